@@ -6,10 +6,18 @@ pub async fn route_pool_manager_message(pool_manager_message: PoolManagerMessage
     match pool_manager_message.message_type {
         MessageType::UpdatePosition 
         | MessageType::RemovePosition 
+        | MessageType::UpdateGroup
+        | MessageType::RemoveGroup
+        | MessageType::BatchResult
+        | MessageType::UpdateRangeOrder
         | MessageType::Stats => {
             let (channel, instruction, frequency) = match pool_manager_message.message_type {
                 MessageType::UpdatePosition => ("managed-position", "update", pool_manager_message.frequency_seconds),
                 MessageType::RemovePosition => ("managed-position", "remove", pool_manager_message.frequency_seconds),
+                MessageType::UpdateGroup => ("position-group", "update", pool_manager_message.frequency_seconds),
+                MessageType::RemoveGroup => ("position-group", "remove", pool_manager_message.frequency_seconds),
+                MessageType::BatchResult => ("batch-open", "result", pool_manager_message.frequency_seconds),
+                MessageType::UpdateRangeOrder => ("range-order", "update", pool_manager_message.frequency_seconds),
                 MessageType::Stats => ("stats", "update", pool_manager_message.frequency_seconds),
             };
         
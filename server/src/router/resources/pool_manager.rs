@@ -2,9 +2,10 @@ use cnctd_server::{
     bad_request, internal_server_error, not_found, unauthorized, success_data, success_msg,
     router::{error::{ErrorCode, ErrorResponse}, response::SuccessResponse, HttpMethod},
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use solana::{pool_manager::{managed_position::{ManagedPosition, PoolType}, new_position::{NewManualPosition, NewPosition}, PoolManager}, services::position_settings::PositionSettings, wallet::Wallet};
+use solana::{pool_manager::{batch_open::BatchOpen, managed_position::{ManagedPosition, PoolType}, new_position::{NewManualPosition, NewPosition}, orca::{zap_in::ZapIn, Orca}, position_manager::position_group::{LadderStrategy, PositionGroup}, range_order::{RangeOrder, RangeOrderSide}, PoolManager}, rpc::{fee_oracle::FeeOracle, provider_registry::ProviderRegistry, rate_limiter::RateLimiter, sidecar::SidecarClient}, services::position_settings::PositionSettings, wallet::{lookup_table::LookupTable, nonce_account::NonceAccounts, transaction_ledger::{LedgerQuery, LedgerStatus, TransactionLedger}, Wallet}};
 
 use crate::router::rest::Resource;
 
@@ -19,6 +20,20 @@ struct DataIn {
     pool_type: Option<PoolType>,
     token_mint_a: Option<String>,
     token_mint_b: Option<String>,
+    target_mint: Option<String>,
+    strategy: Option<LadderStrategy>,
+    side: Option<RangeOrderSide>,
+    range_lower: Option<f64>,
+    range_upper: Option<f64>,
+    amount: Option<u64>,
+    amount_a: Option<u64>,
+    amount_b: Option<u64>,
+    fraction: Option<f64>,
+    slippage: Option<u16>,
+    status: Option<LedgerStatus>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: Option<usize>,
 }
 
 enum Operation {
@@ -26,6 +41,9 @@ enum Operation {
     OpenPosition,
     OpenProgrammaticPosition,
     ClosePosition,
+    CollectFees,
+    IncreaseLiquidity,
+    DecreaseLiquidity,
     SwapTokens,
     ConnectLocalWallet,
     DisconnectLocalWallet,
@@ -34,6 +52,23 @@ enum Operation {
     ToggleAutoRebalance,
     AllPositionSettings,
     PositionSettings,
+    ZapIn,
+    OpenLadder,
+    AllGroups,
+    BatchOpen,
+    BatchResults,
+    RangeOrder,
+    AllRangeOrders,
+    SidecarStatus,
+    RpcProviders,
+    RpcRateLimits,
+    LookupTable,
+    BundleResults,
+    PriorityFees,
+    NonceAccounts,
+    TransactionLedger,
+    SwapRoutes,
+    DiscoverPools,
     Unrecognized,
 }
 
@@ -44,6 +79,9 @@ impl Operation {
             "open-position" => Operation::OpenPosition,
             "open-programmatic-position" => Operation::OpenProgrammaticPosition,
             "close-position" => Operation::ClosePosition,
+            "collect-fees" => Operation::CollectFees,
+            "increase-liquidity" => Operation::IncreaseLiquidity,
+            "decrease-liquidity" => Operation::DecreaseLiquidity,
             "swap-tokens" => Operation::SwapTokens,
             "connect-local-wallet" => Operation::ConnectLocalWallet,
             "disconnect-local-wallet" => Operation::DisconnectLocalWallet,
@@ -52,6 +90,23 @@ impl Operation {
             "toggle-auto-rebalance" => Operation::ToggleAutoRebalance,
             "all-position-settings" => Operation::AllPositionSettings,
             "position-settings" => Operation::PositionSettings,
+            "zap-in" => Operation::ZapIn,
+            "open-ladder" => Operation::OpenLadder,
+            "all-groups" => Operation::AllGroups,
+            "batch-open" => Operation::BatchOpen,
+            "batch-results" => Operation::BatchResults,
+            "range-order" => Operation::RangeOrder,
+            "all-range-orders" => Operation::AllRangeOrders,
+            "sidecar-status" => Operation::SidecarStatus,
+            "rpc-providers" => Operation::RpcProviders,
+            "rpc-rate-limits" => Operation::RpcRateLimits,
+            "lookup-table" => Operation::LookupTable,
+            "bundle-results" => Operation::BundleResults,
+            "priority-fees" => Operation::PriorityFees,
+            "nonce-accounts" => Operation::NonceAccounts,
+            "transaction-ledger" => Operation::TransactionLedger,
+            "swap-routes" => Operation::SwapRoutes,
+            "discover-pools" => Operation::DiscoverPools,
             _ => Operation::Unrecognized,
         }
     }
@@ -66,9 +121,16 @@ impl Operation {
     fn requires_auth(&self) -> bool {
         match self {
             Operation::ClosePosition 
+            | Operation::CollectFees
+            | Operation::IncreaseLiquidity
+            | Operation::DecreaseLiquidity
             | Operation::OpenProgrammaticPosition 
             | Operation::SwapTokens 
             | Operation::OpenPosition
+            | Operation::ZapIn
+            | Operation::OpenLadder
+            | Operation::BatchOpen
+            | Operation::RangeOrder
             | Operation::ToggleAutoRebalance => true,
             _ => false,
         }
@@ -110,6 +172,83 @@ pub async fn route_pool_manager(
 
                 Ok(success_data!(json!(wallet_pubkey.to_string())))
             }
+            Operation::AllGroups => {
+                let position_groups = PoolManager::get_position_groups().await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(position_groups)))
+            }
+            Operation::BatchResults => {
+                let batch_results = PoolManager::get_batch_results().await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(batch_results)))
+            }
+            Operation::AllRangeOrders => {
+                let range_orders = PoolManager::get_range_orders().await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(range_orders)))
+            }
+            Operation::DiscoverPools => {
+                let token_mint_a = data.token_mint_a.ok_or_else(|| bad_request!("Missing token mint A"))?;
+                let token_mint_b = data.token_mint_b.ok_or_else(|| bad_request!("Missing token mint B"))?;
+                let range_factor = data.range_factor.unwrap_or(0.01);
+
+                let pools = Orca::discover_whirlpools(&token_mint_a, &token_mint_b, range_factor).await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(pools)))
+            }
+            Operation::SwapRoutes => {
+                let swap_routes = PoolManager::get_swap_routes().await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(swap_routes)))
+            }
+            Operation::SidecarStatus => {
+                let sidecar_status = SidecarClient::get_status().await;
+
+                Ok(success_data!(json!(sidecar_status)))
+            }
+            Operation::RpcProviders => {
+                let provider_scores = ProviderRegistry::get_scores();
+
+                Ok(success_data!(json!(provider_scores)))
+            }
+            Operation::RpcRateLimits => {
+                let provider_usage = RateLimiter::get_usage();
+
+                Ok(success_data!(json!(provider_usage)))
+            }
+            Operation::LookupTable => {
+                let lookup_table_status = LookupTable::get_status();
+
+                Ok(success_data!(json!(lookup_table_status)))
+            }
+            Operation::BundleResults => {
+                let bundle_results = PoolManager::get_bundle_results().await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(bundle_results)))
+            }
+            Operation::PriorityFees => {
+                let fee_records = FeeOracle::get_records();
+
+                Ok(success_data!(json!(fee_records)))
+            }
+            Operation::NonceAccounts => {
+                let nonce_accounts = NonceAccounts::get_status();
+
+                Ok(success_data!(json!(nonce_accounts)))
+            }
+            Operation::TransactionLedger => {
+                // Filters by position address, status and a created_at window, newest first
+                let query = LedgerQuery {
+                    position_address: data.address,
+                    status: data.status,
+                    from: data.from,
+                    to: data.to,
+                    limit: data.limit,
+                };
+                let ledger_records = TransactionLedger::query(&query);
+
+                Ok(success_data!(json!(ledger_records)))
+            }
             Operation::AllPositionSettings => {
                 let position_settings = PositionSettings::get_all().await.map_err(|e| internal_server_error!(e))?;
 
@@ -155,6 +294,49 @@ pub async fn route_pool_manager(
 
                 Ok(success_data!(json!(swap_instructions)))
             }
+            Operation::OpenLadder => {
+                let pool_address = data.pool_address.ok_or_else(|| bad_request!("Missing pool address"))?;
+                let position_group = PositionGroup::new(
+                    data.name.unwrap_or_else(|| pool_address.clone()),
+                    data.pool_type.ok_or_else(|| bad_request!("Missing pool type"))?,
+                    pool_address,
+                    data.token_mint_a.ok_or_else(|| bad_request!("Missing token mint A"))?,
+                    data.token_mint_b.ok_or_else(|| bad_request!("Missing token mint B"))?,
+                    data.strategy.unwrap_or_else(LadderStrategy::default),
+                ).map_err(|e| bad_request!(e))?;
+
+                PoolManager::queue_group_open(position_group.clone()).await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(position_group)))
+            }
+            Operation::BatchOpen => {
+                let batch_open: BatchOpen = serde_json::from_value(data_val).map_err(|e| bad_request!(e))?;
+
+                PoolManager::queue_batch_open(batch_open).await.map_err(|e| bad_request!(e))?;
+
+                Ok(success_msg!("Queued batch open"))
+            }
+            Operation::RangeOrder => {
+                let range_order = RangeOrder::new(
+                    data.pool_address.ok_or_else(|| bad_request!("Missing pool address"))?,
+                    data.token_mint_a.ok_or_else(|| bad_request!("Missing token mint A"))?,
+                    data.token_mint_b.ok_or_else(|| bad_request!("Missing token mint B"))?,
+                    data.side.ok_or_else(|| bad_request!("Missing side"))?,
+                    data.range_lower.ok_or_else(|| bad_request!("Missing range lower"))?,
+                    data.range_upper.ok_or_else(|| bad_request!("Missing range upper"))?,
+                    data.amount.ok_or_else(|| bad_request!("Missing amount"))?,
+                ).await.map_err(|e| bad_request!(e))?;
+
+                PoolManager::queue_range_order(range_order.clone()).await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(range_order)))
+            }
+            Operation::ZapIn => {
+                let zap_in: ZapIn = serde_json::from_value(data_val).map_err(|e| bad_request!(e))?;
+                let zap_in_instructions = PoolManager::zap_in(zap_in).await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(zap_in_instructions)))
+            }
             Operation::PositionSettings => {
                 let name = data.name.ok_or_else(|| bad_request!("Missing name"))?;
                 let range_factor = data.range_factor.ok_or_else(|| bad_request!("Missing range factor"))?;
@@ -170,9 +352,61 @@ pub async fn route_pool_manager(
                 println!("Closing position with data: {:?}", data_val);
                 let address = data.address.ok_or_else(|| bad_request!("Missing address"))?;    
 
-                PoolManager::queue_programmatic_close(&address).await.map_err(|e| internal_server_error!(e))?;
+                if let Some(target_mint) = data.target_mint {
+                    let exit_instructions = PoolManager::exit_position(&address, target_mint).await.map_err(|e| internal_server_error!(e))?;
+
+                    return match exit_instructions {
+                        Some(instructions) => Ok(success_data!(json!(instructions))),
+                        None => Ok(success_msg!("Queued for exit")),
+                    };
+                }
 
-                Ok(success_msg!("Queued for close"))
+                let close_transaction = PoolManager::close_position(&address).await.map_err(|e| internal_server_error!(e))?;
+
+                match close_transaction {
+                    Some(transaction) => Ok(success_data!(json!(transaction))),
+                    None => Ok(success_msg!("Queued for close")),
+                }
+            }
+            Operation::CollectFees => {
+                let address = data.address.ok_or_else(|| bad_request!("Missing address"))?;
+
+                let collect_transaction = PoolManager::collect_fees(&address).await.map_err(|e| internal_server_error!(e))?;
+
+                match collect_transaction {
+                    Some(transaction) => Ok(success_data!(json!(transaction))),
+                    None => Ok(success_msg!("Fees collected")),
+                }
+            }
+            Operation::IncreaseLiquidity => {
+                let address = data.address.ok_or_else(|| bad_request!("Missing address"))?;
+                let amount_a = data.amount_a.unwrap_or(0);
+                let amount_b = data.amount_b.unwrap_or(0);
+                if amount_a == 0 && amount_b == 0 {
+                    return Err(bad_request!("Missing amount A or amount B"));
+                }
+
+                let liquidity_instructions = PoolManager::increase_liquidity(&address, amount_a, amount_b, data.slippage.unwrap_or(100))
+                    .await
+                    .map_err(|e| internal_server_error!(e))?;
+
+                match liquidity_instructions {
+                    Some(instructions) => Ok(success_data!(json!(instructions))),
+                    None => Ok(success_msg!("Liquidity increased")),
+                }
+            }
+            Operation::DecreaseLiquidity => {
+                let address = data.address.ok_or_else(|| bad_request!("Missing address"))?;
+                let fraction = data.fraction.ok_or_else(|| bad_request!("Missing fraction"))?;
+
+                let liquidity_instructions = PoolManager::decrease_liquidity(&address, fraction, data.slippage.unwrap_or(100))
+                    .await
+                    .map_err(|e| internal_server_error!(e))?;
+
+                match liquidity_instructions {
+                    Some(instructions) => Ok(success_data!(json!(instructions))),
+                    None => Ok(success_msg!("Liquidity decreased")),
+                }
             }
             Operation::ConnectLocalWallet => {
                 let wallet_key_string = data.wallet_key.ok_or_else(|| bad_request!("Missing wallet key"))?;
@@ -210,6 +444,13 @@ pub async fn route_pool_manager(
 
                 Ok(success_msg!("Deleted"))
             }
+            Operation::RangeOrder => {
+                let id = data.id.ok_or_else(|| bad_request!("Missing id"))?;
+
+                PoolManager::cancel_range_order(&id).await.map_err(|e| bad_request!(e))?;
+
+                Ok(success_msg!("Queued for cancel"))
+            }
             _ => Err(bad_request!("Invalid operation for DELETE")),
         },
         _ => Err(bad_request!("Invalid HTTP method")),
@@ -1,10 +1,10 @@
 use cnctd_server::{
-    bad_request, internal_server_error, not_found, unauthorized, success_data, success_msg,
+    bad_request, internal_server_error, not_found, unauthorized, success_data,
     router::{error::{ErrorCode, ErrorResponse}, response::SuccessResponse, HttpMethod},
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use solana::pool_manager::{swap::Swap, PoolManager};
+use solana::pool_manager::{position_manager::managed_position::PoolType, swap_router::{SwapQuoteRequest, SwapRouter}};
 use crate::router::rest::Resource;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,10 +19,15 @@ struct IncomingTokenSwap {
     pub mint_in_address: String,
     pub mint_out_address: String,
     pub slippage_tolerance: Option<u16>,
+    #[serde(default)]
+    pub pool_address: Option<String>,
+    #[serde(default)]
+    pub pool_type: Option<PoolType>,
 }
 
 enum Operation {
     Swap,
+    Quote,
     Unrecognized,
 }
 
@@ -30,6 +35,7 @@ impl Operation {
     fn from_str(s: &str) -> Self {
         match s {
             "swap" => Operation::Swap,
+            "quote" => Operation::Quote,
             _ => Operation::Unrecognized,
         }
     }
@@ -62,24 +68,30 @@ pub async fn route_tokens(
 
     match method {
         HttpMethod::GET => match operation {
+            Operation::Quote => {
+                let swap_quote_request: SwapQuoteRequest = serde_json::from_value(data_val).map_err(|e| bad_request!(e))?;
+                let swap_quote = SwapRouter::quote(swap_quote_request).await.map_err(|e| internal_server_error!(e))?;
+
+                Ok(success_data!(json!(swap_quote)))
+            }
             _ => Err(bad_request!("Invalid operation for GET")),
         },
         HttpMethod::POST => match operation {
             Operation::Swap => {
                 let incoming_token_swap: IncomingTokenSwap = serde_json::from_value(data_val.clone()).map_err(|e| bad_request!(e))?;
 
-                let swap = Swap::new(
-                    incoming_token_swap.mint_in_address.clone(),
-                    incoming_token_swap.mint_out_address.clone(),
-                    incoming_token_swap.amount,
-                    incoming_token_swap.amount_is_in,
-                    incoming_token_swap.slippage_tolerance.unwrap_or(50),
-                    None,
-                );
-
-                PoolManager::queue_programmatic_swap(swap).await.map_err(|e| internal_server_error!(e))?;
+                // The router checks the impact ceiling on the route it is about to execute
+                let swap_route = SwapRouter::execute_request(SwapQuoteRequest {
+                    mint_in_address: incoming_token_swap.mint_in_address,
+                    mint_out_address: incoming_token_swap.mint_out_address,
+                    amount: incoming_token_swap.amount,
+                    amount_is_in: incoming_token_swap.amount_is_in,
+                    slippage_tolerance: incoming_token_swap.slippage_tolerance,
+                    pool_address: incoming_token_swap.pool_address,
+                    pool_type: incoming_token_swap.pool_type,
+                }).await.map_err(|e| internal_server_error!(e))?;
 
-                Ok(success_msg!("initiated swap"))
+                Ok(success_data!(json!(swap_route)))
             }
             _ => Err(bad_request!("Invalid operation for POST")),
         },
@@ -0,0 +1,55 @@
+//! Colored console output shared by the kebtech crates
+
+#[doc(hidden)]
+pub use colored;
+
+#[macro_export]
+macro_rules! red {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::colored::Colorize::red(format!($($arg)*).as_str()))
+    };
+}
+
+#[macro_export]
+macro_rules! green {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::colored::Colorize::green(format!($($arg)*).as_str()))
+    };
+}
+
+#[macro_export]
+macro_rules! blue {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::colored::Colorize::blue(format!($($arg)*).as_str()))
+    };
+}
+
+#[macro_export]
+macro_rules! yellow {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::colored::Colorize::yellow(format!($($arg)*).as_str()))
+    };
+}
+
+#[macro_export]
+macro_rules! magenta {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::colored::Colorize::magenta(format!($($arg)*).as_str()))
+    };
+}
+
+/// Prints `text` from green at a score of 0 through yellow to red at 1 and above
+#[macro_export]
+macro_rules! score_to_color {
+    ($score:expr, $text:expr) => {{
+        let score: f64 = $score;
+        let text = $text;
+        if score < 0.5 {
+            $crate::green!("{}", text)
+        } else if score < 0.8 {
+            $crate::yellow!("{}", text)
+        } else {
+            $crate::red!("{}", text)
+        }
+    }};
+}
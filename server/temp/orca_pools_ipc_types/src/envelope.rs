@@ -0,0 +1,109 @@
+//! Versioned framing for sharing one sidecar connection between concurrent calls. Each line is
+//! a `SidecarEnvelope`, and replies carry the id of the request they answer. The legacy framing,
+//! a bare `Request` answered by a bare `Response` per connection, is what `Request::send` speaks.
+//! `serve` is the sidecar's end, and answers both.
+
+use std::{future::Future, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::{unix::OwnedWriteHalf, UnixListener, UnixStream}, sync::Mutex};
+
+use crate::{request::Request, response::Response};
+
+/// Bumped whenever the envelope or the request and response schema changes. Both sides have to
+/// agree on it in the `Hello` handshake before any call is made.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SidecarEnvelope<T> {
+    pub id: u64,
+    pub version: u32,
+    pub body: T,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SidecarRequest {
+    Hello,
+    Ping,
+    Call(Request),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SidecarReply {
+    Hello { version: u32 },
+    Pong,
+    Response(Response),
+    /// The Solana RPC the sidecar called failed, so the call can be retried against another url
+    RpcError(String),
+    Error(String),
+}
+
+/// Accepts connections on the socket until it fails, serving each one with `serve_connection`
+pub async fn serve<H, F>(socket_path: &str, handler: H) -> std::io::Result<()>
+where
+    H: Fn(Request) -> F + Clone + Send + Sync + 'static,
+    F: Future<Output = SidecarReply> + Send + 'static,
+{
+    // A socket file left behind by an earlier run would make the bind fail
+    let _ = std::fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let _ = serve_connection(stream, handler).await;
+        });
+    }
+}
+
+/// Answers each line on the connection until the client closes it. Envelopes get a reply with
+/// their id, and each call runs on its own task so a slow one doesn't hold up the rest. A bare
+/// `Request` gets a bare `Response`, so legacy clients keep working.
+pub async fn serve_connection<H, F>(stream: UnixStream, handler: H) -> std::io::Result<()>
+where
+    H: Fn(Request) -> F + Clone + Send + Sync + 'static,
+    F: Future<Output = SidecarReply> + Send + 'static,
+{
+    let (reader, writer) = stream.into_split();
+    let writer = Arc::new(Mutex::new(writer));
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if let Ok(envelope) = serde_json::from_str::<SidecarEnvelope<SidecarRequest>>(&line) {
+            let writer = writer.clone();
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let body = match envelope.body {
+                    SidecarRequest::Hello => SidecarReply::Hello { version: PROTOCOL_VERSION },
+                    SidecarRequest::Ping => SidecarReply::Pong,
+                    SidecarRequest::Call(_) if envelope.version != PROTOCOL_VERSION => {
+                        SidecarReply::Error(format!("Protocol v{} is not supported, expected v{}", envelope.version, PROTOCOL_VERSION))
+                    }
+                    SidecarRequest::Call(request) => handler(request).await,
+                };
+                let reply = SidecarEnvelope { id: envelope.id, version: PROTOCOL_VERSION, body };
+                let _ = write_line(&writer, &reply).await;
+            });
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => match handler(request).await {
+                SidecarReply::Response(response) => response,
+                SidecarReply::RpcError(message) | SidecarReply::Error(message) => Response::Error(message),
+                reply => Response::Error(format!("Unexpected reply to call: {:?}", reply)),
+            },
+            Err(e) => Response::Error(format!("Unreadable request: {}", e)),
+        };
+        write_line(&writer, &response).await?;
+    }
+
+    Ok(())
+}
+
+async fn write_line<T: Serialize>(writer: &Mutex<OwnedWriteHalf>, message: &T) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.lock().await.write_all(&line).await
+}
@@ -0,0 +1,8 @@
+//! Requests and responses exchanged with the Orca sidecar over its unix socket
+
+pub mod envelope;
+pub mod request;
+pub mod response;
+pub mod solana;
+
+pub const DEFAULT_SOCKET_PATH: &str = "/tmp/orca_pools_ipc.sock";
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// Pool state the caller already has, so the sidecar can skip fetching it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PriceTickInfo {
+    pub sqrt_price: String,
+    pub tick_current_index: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClosePositionRequest {
+    pub rpc_url: String,
+    pub position_mint: String,
+    pub wallet_key: String,
+    pub price_tick_info: Option<PriceTickInfo>,
+    /// Basis points
+    pub slippage_tolerance: Option<u16>,
+}
+
+impl ClosePositionRequest {
+    pub fn new(
+        rpc_url: String,
+        position_mint: String,
+        wallet_key: String,
+        price_tick_info: Option<PriceTickInfo>,
+        slippage_tolerance: Option<u16>,
+    ) -> Self {
+        Self {
+            rpc_url,
+            position_mint,
+            wallet_key,
+            price_tick_info,
+            slippage_tolerance,
+        }
+    }
+}
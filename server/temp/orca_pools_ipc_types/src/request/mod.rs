@@ -0,0 +1,51 @@
+pub mod close_position_request;
+pub mod new_position_request;
+pub mod swap_request;
+
+use close_position_request::ClosePositionRequest;
+use new_position_request::NewPositionRequest;
+use serde::{Deserialize, Serialize};
+use swap_request::SwapRequest;
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::UnixStream};
+
+use crate::{response::Response, DEFAULT_SOCKET_PATH};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum TokenAmount {
+    TokenA(u64),
+    TokenB(u64),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Request {
+    GetOpenPositionInstruction { new_position_request: NewPositionRequest },
+    GetClosePositionInstruction { close_position_request: ClosePositionRequest },
+    GetSwapInstructions { swap_request: SwapRequest },
+    GetClpPool { rpc_url: String, token_a: String, token_b: String, tick_spacing: u16 },
+    GetPoolTokensAndTick { rpc_url: String, whirlpool_address: String },
+}
+
+impl Request {
+    /// Sends the request on its own connection to the sidecar socket, `ORCA_SIDECAR_SOCKET`,
+    /// and reads back one JSON line
+    pub async fn send(&self) -> anyhow::Result<Response> {
+        let socket_path = std::env::var("ORCA_SIDECAR_SOCKET").unwrap_or_else(|_| DEFAULT_SOCKET_PATH.to_string());
+        self.send_to(&socket_path).await
+    }
+
+    pub async fn send_to(&self, socket_path: &str) -> anyhow::Result<Response> {
+        let mut stream = UnixStream::connect(socket_path).await?;
+
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        stream.write_all(&line).await?;
+
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).await?;
+
+        match serde_json::from_str::<Response>(&reply)? {
+            Response::Error(message) => Err(anyhow::anyhow!(message)),
+            response => Ok(response),
+        }
+    }
+}
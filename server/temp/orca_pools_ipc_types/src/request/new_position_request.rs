@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use super::TokenAmount;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewPositionRequest {
+    pub rpc_url: String,
+    pub wallet_key: String,
+    pub pool_address: String,
+    pub token_amount: TokenAmount,
+    /// Basis points
+    pub slippage_tolerance: Option<u16>,
+    pub range_lower: f64,
+    pub range_upper: f64,
+}
+
+impl NewPositionRequest {
+    pub fn new(
+        rpc_url: String,
+        wallet_key: String,
+        pool_address: String,
+        token_amount: TokenAmount,
+        slippage_tolerance: Option<u16>,
+        range_lower: f64,
+        range_upper: f64,
+    ) -> Self {
+        Self {
+            rpc_url,
+            wallet_key,
+            pool_address,
+            token_amount,
+            slippage_tolerance,
+            range_lower,
+            range_upper,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SwapAmount {
+    ExactIn(u64),
+    ExactOut(u64),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SwapRequest {
+    pub rpc_url: String,
+    pub wallet_key: String,
+    pub pool_address: String,
+    pub amount: SwapAmount,
+    pub mint_out_address: String,
+    /// Basis points
+    pub slippage_tolerance: Option<u16>,
+}
+
+impl SwapRequest {
+    pub fn new(
+        rpc_url: String,
+        wallet_key: String,
+        pool_address: String,
+        amount: SwapAmount,
+        mint_out_address: String,
+        slippage_tolerance: Option<u16>,
+    ) -> Self {
+        Self {
+            rpc_url,
+            wallet_key,
+            pool_address,
+            amount,
+            mint_out_address,
+            slippage_tolerance,
+        }
+    }
+}
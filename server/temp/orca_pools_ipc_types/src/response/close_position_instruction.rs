@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+use crate::solana::SolanaInstruction;
+
+/// Liquidity returned by the close, in raw units
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DecreaseLiquidityQuote {
+    pub liquidity_delta: u128,
+    pub token_est_a: u64,
+    pub token_est_b: u64,
+    pub token_min_a: u64,
+    pub token_min_b: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CollectFeesQuote {
+    pub fee_owed_a: u64,
+    pub fee_owed_b: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrcaClosePositionInstruction {
+    pub instructions: Vec<SolanaInstruction>,
+    pub additional_signers: Vec<String>,
+    pub quote: DecreaseLiquidityQuote,
+    pub fees_quote: CollectFeesQuote,
+    /// Rewards owed in raw units, one entry per pool reward index
+    pub rewards_quote: Vec<u64>,
+}
@@ -0,0 +1,20 @@
+pub mod close_position_instruction;
+pub mod open_position_instruction;
+pub mod orca_pool_info;
+pub mod orca_swap_instructions;
+
+use close_position_instruction::OrcaClosePositionInstruction;
+use open_position_instruction::OrcaOpenPositionInstruction;
+use orca_pool_info::{OrcaPoolInfo, OrcaPoolTokensAndTick};
+use orca_swap_instructions::OrcaSwapInstructions;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Response {
+    OpenPositionInstruction(OrcaOpenPositionInstruction),
+    ClosePositionInstruction(OrcaClosePositionInstruction),
+    SwapInstructions(OrcaSwapInstructions),
+    PoolInfo(OrcaPoolInfo),
+    PoolTokensAndTick(OrcaPoolTokensAndTick),
+    Error(String),
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use crate::solana::SolanaInstruction;
+
+/// Liquidity added by the open, in raw units
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IncreaseLiquidityQuote {
+    pub liquidity_delta: u128,
+    pub token_est_a: u64,
+    pub token_est_b: u64,
+    pub token_max_a: u64,
+    pub token_max_b: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrcaOpenPositionInstruction {
+    pub position_mint: String,
+    pub quote: IncreaseLiquidityQuote,
+    pub instructions: Vec<SolanaInstruction>,
+    /// Base64 keypairs the transaction also has to be signed with, such as the position mint
+    pub additional_signers: Vec<String>,
+    /// Lamports of rent for the tick arrays the open has to initialize
+    pub initialization_cost: u64,
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrcaPoolInfo {
+    pub address: String,
+    pub token_mint_a: String,
+    pub token_mint_b: String,
+    pub tick_spacing: u16,
+    pub tick_current_index: i32,
+    pub sqrt_price: String,
+    pub liquidity: String,
+    /// Token B per token A, adjusted for decimals
+    pub price: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrcaPoolTokensAndTick {
+    pub token_a: String,
+    pub token_b: String,
+    pub tick_spacing: u16,
+    pub tick_current_index: i32,
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::solana::SolanaInstruction;
+
+/// The sidecar's quote for an exact in swap, computed over the pool's tick arrays, in raw units
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExactInSwapQuote {
+    pub token_in: u64,
+    pub token_est_out: u64,
+    /// The output after slippage
+    pub token_min_out: u64,
+    pub trade_fee: u64,
+}
+
+/// The sidecar's quote for an exact out swap, computed over the pool's tick arrays, in raw units
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExactOutSwapQuote {
+    pub token_out: u64,
+    pub token_est_in: u64,
+    /// The input after slippage
+    pub token_max_in: u64,
+    pub trade_fee: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum OrcaSwapQuote {
+    ExactIn(ExactInSwapQuote),
+    ExactOut(ExactOutSwapQuote),
+}
+
+impl OrcaSwapQuote {
+    /// The exact in quote, or an error for an exact out one, which callers that size their
+    /// follow-up instructions off the minimum out can't use
+    pub fn exact_in(&self) -> anyhow::Result<&ExactInSwapQuote> {
+        match self {
+            OrcaSwapQuote::ExactIn(quote) => Ok(quote),
+            OrcaSwapQuote::ExactOut(_) => Err(anyhow::anyhow!("Expected an exact in swap quote from the sidecar, got an exact out quote")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrcaSwapInstructions {
+    pub instructions: Vec<SolanaInstruction>,
+    pub additional_signers: Vec<String>,
+    pub quote: OrcaSwapQuote,
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SolanaAccountMeta {
+    pub pubkey: String,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// An instruction with its keys as base58 strings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SolanaInstruction {
+    pub program_id: String,
+    pub accounts: Vec<SolanaAccountMeta>,
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,240 @@
+use chrono::{DateTime, Utc};
+use helius::types::PriorityLevel;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{rpc::RpcMode, wallet::{programmatic_transaction::TransactionKind, Wallet}};
+
+use super::{new_position::NewPositionData, orca::{zap_in::ZapIn, Orca}, position_manager::managed_position::PoolType, swap_router::SwapRouter};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchLeg {
+    pub pool_type: PoolType,
+    pub pool_address: String,
+    pub weight: f64,
+    pub range_factor: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchOpen {
+    pub funding_mint: String,
+    pub amount: u64,
+    pub legs: Vec<BatchLeg>,
+    pub slippage_tolerance: Option<u16>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum BatchLegStatus {
+    Pending,
+    Opened(String),
+    Failed(String),
+}
+
+/// Pool mint a leg's allocation was swapped into, and the amount the swap guaranteed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchLegFunding {
+    pub mint: String,
+    pub amount: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchLegResult {
+    pub pool_address: String,
+    pub weight: f64,
+    pub allocated_amount: u64,
+    /// Set once the allocation has been swapped, so a retry zaps in from it without swapping again
+    #[serde(default)]
+    pub funding: Option<BatchLegFunding>,
+    pub status: BatchLegStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchOpenResult {
+    pub funding_mint: String,
+    pub amount: u64,
+    pub deployed_amount: u64,
+    pub undeployed_amount: u64,
+    pub legs: Vec<BatchLegResult>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl BatchOpenResult {
+    /// Undeployed amount still held in the funding mint, leaving out what legs that failed after
+    /// their swap now hold in a pool mint
+    fn unfunded_amount(&self) -> u64 {
+        let swapped_amount: u64 = self
+            .legs
+            .iter()
+            .filter(|leg| leg.funding.is_some() && !matches!(leg.status, BatchLegStatus::Opened(_)))
+            .map(|leg| leg.allocated_amount)
+            .sum();
+
+        self.undeployed_amount.saturating_sub(swapped_amount)
+    }
+}
+
+impl BatchOpen {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.legs.is_empty() {
+            return Err(anyhow::anyhow!("Batch open needs at least one leg"));
+        }
+        if self.legs.iter().any(|leg| leg.weight <= 0.0) {
+            return Err(anyhow::anyhow!("Batch leg weights must be positive"));
+        }
+        if self.legs.iter().any(|leg| leg.pool_type != PoolType::Orca) {
+            return Err(anyhow::anyhow!("Batch open only supports Orca pools"));
+        }
+        if self.amount == 0 {
+            return Err(anyhow::anyhow!("Batch amount must be greater than zero"));
+        }
+
+        Ok(())
+    }
+
+    /// Opens every leg in sequence from the funding mint. The share of a failed leg is spread over the
+    /// legs that have not run yet, and failed legs get one more attempt with whatever is left at the end.
+    /// A leg that failed after its swap retries from what the swap returned instead.
+    pub async fn execute(&self) -> anyhow::Result<BatchOpenResult> {
+        self.validate()?;
+        magenta!("opening batch of {} legs with {} of {}", self.legs.len(), self.amount, self.funding_mint);
+
+        let mut result = BatchOpenResult {
+            funding_mint: self.funding_mint.clone(),
+            amount: self.amount,
+            deployed_amount: 0,
+            undeployed_amount: self.amount,
+            legs: self
+                .legs
+                .iter()
+                .map(|leg| BatchLegResult {
+                    pool_address: leg.pool_address.clone(),
+                    weight: leg.weight,
+                    allocated_amount: 0,
+                    funding: None,
+                    status: BatchLegStatus::Pending,
+                })
+                .collect(),
+            started_at: Utc::now(),
+            finished_at: None,
+        };
+
+        let mut remaining_weight: f64 = self.legs.iter().map(|leg| leg.weight).sum();
+
+        for (index, leg) in self.legs.iter().enumerate() {
+            let allocation = (result.unfunded_amount() as f64 * leg.weight / remaining_weight) as u64;
+            remaining_weight -= leg.weight;
+
+            self.open_leg(&mut result, index, allocation).await;
+        }
+
+        let failed_legs: Vec<usize> = (0..self.legs.len())
+            .filter(|index| matches!(result.legs[*index].status, BatchLegStatus::Failed(_)))
+            .collect();
+
+        if !failed_legs.is_empty() && result.undeployed_amount > 0 {
+            yellow!("retrying failed batch legs with {} undeployed", result.undeployed_amount);
+            let mut remaining_weight: f64 = failed_legs
+                .iter()
+                .filter(|index| result.legs[**index].funding.is_none())
+                .map(|index| self.legs[*index].weight)
+                .sum();
+
+            for index in failed_legs {
+                let allocation = if result.legs[index].funding.is_some() {
+                    result.legs[index].allocated_amount
+                } else {
+                    let allocation = (result.unfunded_amount() as f64 * self.legs[index].weight / remaining_weight) as u64;
+                    remaining_weight -= self.legs[index].weight;
+                    allocation
+                };
+
+                self.open_leg(&mut result, index, allocation).await;
+            }
+        }
+
+        result.finished_at = Some(Utc::now());
+        green!("batch open finished: deployed {}, undeployed {}", result.deployed_amount, result.undeployed_amount);
+
+        Ok(result)
+    }
+
+    async fn open_leg(&self, result: &mut BatchOpenResult, index: usize, allocation: u64) {
+        let leg = &self.legs[index];
+        result.legs[index].allocated_amount = allocation;
+
+        match self.zap_into_leg(leg, &mut result.legs[index]).await {
+            Ok(signature) => {
+                green!("opened batch leg {} with {}", leg.pool_address, allocation);
+                result.legs[index].status = BatchLegStatus::Opened(signature);
+                result.deployed_amount += allocation;
+                result.undeployed_amount = result.undeployed_amount.saturating_sub(allocation);
+            }
+            Err(e) => {
+                red!("Failed to open batch leg {}: {:?}", leg.pool_address, e);
+                result.legs[index].status = BatchLegStatus::Failed(e.to_string());
+            }
+        }
+    }
+
+    async fn zap_into_leg(&self, leg: &BatchLeg, leg_result: &mut BatchLegResult) -> anyhow::Result<String> {
+        let (input_mint, amount) = match &leg_result.funding {
+            Some(funding) => {
+                yellow!("zapping into batch leg {} from the {} already swapped", leg.pool_address, funding.amount);
+                (funding.mint.clone(), funding.amount)
+            }
+            None => {
+                if leg_result.allocated_amount == 0 {
+                    return Err(anyhow::anyhow!("Nothing allocated to leg"));
+                }
+
+                let (input_mint, amount) = self.fund_leg(leg, leg_result.allocated_amount).await?;
+                if input_mint != self.funding_mint {
+                    leg_result.funding = Some(BatchLegFunding { mint: input_mint.clone(), amount });
+                }
+                (input_mint, amount)
+            }
+        };
+
+        let price = Orca::get_pool_price(RpcMode::fast(), &leg.pool_address).await?;
+        let (range_lower, range_upper) = match leg.range_factor {
+            Some(range_factor) => (price - (price * range_factor), price + (price * range_factor)),
+            None => NewPositionData::get_ranges(price),
+        };
+
+        let zap_in = ZapIn::new(
+            Wallet::get_programmatic_pubkey()?.to_string(),
+            leg.pool_address.clone(),
+            input_mint,
+            amount,
+            range_lower,
+            range_upper,
+            self.slippage_tolerance,
+        );
+        let zap_in_instructions = zap_in.get_instructions().await?;
+
+        let signature = Orca::perform_orca_transaction(
+            zap_in_instructions.instructions,
+            zap_in_instructions.additional_signers,
+            Some(PriorityLevel::High),
+            TransactionKind::Open,
+        ).await?;
+
+        Ok(signature.to_string())
+    }
+
+    /// The mint and amount to zap into the leg with. Zapping needs one of the pool's own mints, so
+    /// a funding mint outside the pool is swapped into its token B through Jupiter first.
+    async fn fund_leg(&self, leg: &BatchLeg, amount: u64) -> anyhow::Result<(String, u64)> {
+        let pool_tokens = Orca::get_tokens_and_tick(RpcMode::fast(), &leg.pool_address).await?;
+
+        if self.funding_mint == pool_tokens.token_a || self.funding_mint == pool_tokens.token_b {
+            return Ok((self.funding_mint.clone(), amount));
+        }
+
+        yellow!("{} is not in pool {}, swapping into {} first", self.funding_mint, leg.pool_address, pool_tokens.token_b);
+        let swap = SwapRouter::swap_into(&self.funding_mint, &pool_tokens.token_b, amount, self.slippage_tolerance).await?;
+
+        Ok((pool_tokens.token_b, swap.chosen.other_amount_threshold))
+    }
+}
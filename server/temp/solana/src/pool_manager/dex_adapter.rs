@@ -0,0 +1,226 @@
+use std::{future::Future, pin::Pin};
+
+use helius::types::PriorityLevel;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, signature::Signature};
+
+use crate::{rpc::{Rpc, RpcMode}, token::Token, utils::clmm_swap_amount, wallet::programmatic_transaction::{ProgrammaticTransaction, TransactionKind}};
+
+use super::{meteora::Meteora, orca::{token_swap::TokenSwap, Orca}, position_manager::managed_position::{ManagedPosition, PoolType}, raydium::Raydium};
+
+pub type AdapterFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// Instructions returned by any venue, ready for `ProgrammaticTransaction::perform`.
+/// Additional signers are base64 encoded keypairs.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdapterInstructions {
+    pub instructions: Vec<Instruction>,
+    pub additional_signers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DexPoolInfo {
+    pub pool_type: PoolType,
+    pub address: String,
+    pub token_mint_a: String,
+    pub token_mint_b: String,
+    /// Token B per token A, adjusted for decimals
+    pub price: f64,
+    pub tick_spacing: Option<u16>,
+    pub bin_step: Option<u16>,
+    /// Swap fee as a fraction, when the venue exposes it
+    pub fee_rate: Option<f64>,
+}
+
+/// Venue-neutral request to open a position. Venues that size from one side use `token_amount_b`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenPositionRequest {
+    pub wallet_key: String,
+    pub pool_address: String,
+    pub token_amount_a: u64,
+    pub token_amount_b: u64,
+    pub slippage: u16,
+    pub range_lower: f64,
+    pub range_upper: f64,
+}
+
+/// Raw amounts for a swap. `other_amount_threshold` is the minimum output for exact-in swaps and
+/// the maximum input for exact-out swaps.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SwapQuote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub other_amount_threshold: u64,
+    pub fee_rate: f64,
+    /// False for estimates that don't walk the pool's ticks, which overstate what a swap large
+    /// enough to move the price gets
+    pub price_impact_included: bool,
+}
+
+impl SwapQuote {
+    /// Estimates a swap at a fixed raw price (raw token B per raw token A), ignoring price impact.
+    /// `sell_is_a` is true when `token_swap.mint_out_address` is token A.
+    pub fn from_raw_price(token_swap: &TokenSwap, sell_is_a: bool, raw_price: f64, fee_rate: f64) -> Self {
+        let slippage = token_swap.slippage_tolerance.unwrap_or(50) as f64 / 10_000.0;
+        let amount = token_swap.amount as f64;
+
+        if token_swap.amount_is_in {
+            let amount_after_fee = amount * (1.0 - fee_rate);
+            let amount_out = if sell_is_a { amount_after_fee * raw_price } else { amount_after_fee / raw_price };
+
+            Self {
+                amount_in: token_swap.amount,
+                amount_out: amount_out as u64,
+                other_amount_threshold: (amount_out * (1.0 - slippage)) as u64,
+                fee_rate,
+                price_impact_included: false,
+            }
+        } else {
+            let amount_in = if sell_is_a { amount / raw_price } else { amount * raw_price } / (1.0 - fee_rate);
+
+            Self {
+                amount_in: amount_in as u64,
+                amount_out: token_swap.amount,
+                other_amount_threshold: (amount_in * (1.0 + slippage)) as u64,
+                fee_rate,
+                price_impact_included: false,
+            }
+        }
+    }
+
+    /// Estimates a swap against a concentrated liquidity pool's active liquidity, so the price
+    /// moves with the size of the swap. Liquidity is taken as constant, so a swap that crosses an
+    /// initialized tick is overstated and the quote counts as an estimate.
+    /// `sell_is_a` is true when `token_swap.mint_out_address` is token A.
+    pub fn from_clmm_liquidity(token_swap: &TokenSwap, sell_is_a: bool, liquidity: u128, sqrt_price_x64: u128, fee_rate: f64) -> anyhow::Result<Self> {
+        let slippage = token_swap.slippage_tolerance.unwrap_or(50) as f64 / 10_000.0;
+
+        if token_swap.amount_is_in {
+            let amount_after_fee = (token_swap.amount as f64 * (1.0 - fee_rate)) as u64;
+            let amount_out = clmm_swap_amount(liquidity, sqrt_price_x64, amount_after_fee, true, sell_is_a)
+                .ok_or_else(|| anyhow::anyhow!("Pool has no liquidity to swap {}", token_swap.amount))?;
+
+            Ok(Self {
+                amount_in: token_swap.amount,
+                amount_out: amount_out as u64,
+                other_amount_threshold: (amount_out * (1.0 - slippage)) as u64,
+                fee_rate,
+                price_impact_included: false,
+            })
+        } else {
+            let amount_in = clmm_swap_amount(liquidity, sqrt_price_x64, token_swap.amount, false, sell_is_a)
+                .ok_or_else(|| anyhow::anyhow!("Pool has too little liquidity to receive {}", token_swap.amount))?
+                / (1.0 - fee_rate);
+
+            Ok(Self {
+                amount_in: amount_in as u64,
+                amount_out: token_swap.amount,
+                other_amount_threshold: (amount_in * (1.0 + slippage)) as u64,
+                fee_rate,
+                price_impact_included: false,
+            })
+        }
+    }
+}
+
+impl SwapQuote {
+    /// Adjusts a quote for Token-2022 transfer fees: the pool receives the input net of the sold
+    /// mint's fee, and the wallet receives the output net of the bought mint's fee.
+    pub async fn net_of_transfer_fees(self, token_swap: &TokenSwap, mint_bought: &str) -> anyhow::Result<Self> {
+        let token_sold = Token::from_mint_address(&token_swap.mint_out_address).await?;
+        let token_bought = Token::from_mint_address(mint_bought).await?;
+
+        if token_sold.transfer_fee_config.is_none() && token_bought.transfer_fee_config.is_none() {
+            return Ok(self);
+        }
+
+        let epoch = Rpc::get_epoch(RpcMode::fast()).await?;
+
+        if token_swap.amount_is_in {
+            let amount_received = token_sold.amount_after_transfer_fee(self.amount_in, epoch);
+            let scale = amount_received as f64 / self.amount_in.max(1) as f64;
+
+            Ok(Self {
+                amount_out: token_bought.amount_after_transfer_fee((self.amount_out as f64 * scale) as u64, epoch),
+                other_amount_threshold: token_bought.amount_after_transfer_fee((self.other_amount_threshold as f64 * scale) as u64, epoch),
+                ..self
+            })
+        } else {
+            let amount_sent = token_bought.amount_before_transfer_fee(self.amount_out, epoch);
+            let scale = amount_sent as f64 / self.amount_out.max(1) as f64;
+
+            Ok(Self {
+                amount_in: token_sold.amount_before_transfer_fee((self.amount_in as f64 * scale) as u64, epoch),
+                other_amount_threshold: token_sold.amount_before_transfer_fee((self.other_amount_threshold as f64 * scale) as u64, epoch),
+                ..self
+            })
+        }
+    }
+}
+
+/// Everything the pool manager needs from a DEX. Each venue implements this in its own module
+/// and is looked up through `DexRegistry` by `PoolType`.
+pub trait DexAdapter: Send + Sync {
+    fn pool_type(&self) -> PoolType;
+
+    /// Positions owned by the wallet, priced and ready to be tracked. `created_at` is left at the
+    /// time of the call, the pool manager fills it in for positions it has not seen before.
+    fn list_positions<'a>(&'a self, wallet_key: &'a str) -> AdapterFuture<'a, Vec<ManagedPosition>>;
+
+    fn pool_info<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, DexPoolInfo>;
+
+    fn pool_price<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, f64>;
+
+    fn open_position<'a>(&'a self, request: &'a OpenPositionRequest) -> AdapterFuture<'a, AdapterInstructions>;
+
+    /// Withdraws everything, collects fees and rewards, and closes the position
+    fn close_position<'a>(&'a self, position: &'a ManagedPosition) -> AdapterFuture<'a, AdapterInstructions>;
+
+    /// Collects fees and rewards while leaving liquidity in place
+    fn collect_fees<'a>(&'a self, position: &'a ManagedPosition) -> AdapterFuture<'a, AdapterInstructions>;
+
+    fn swap<'a>(&'a self, token_swap: &'a TokenSwap) -> AdapterFuture<'a, AdapterInstructions>;
+
+    fn quote<'a>(&'a self, token_swap: &'a TokenSwap) -> AdapterFuture<'a, SwapQuote>;
+
+    /// Mint of each of the pool's reward slots by reward index, `None` for slots that are not
+    /// initialized. Venues without rewards return none at all.
+    fn reward_mints<'a>(&'a self, _pool_address: &'a str) -> AdapterFuture<'a, Vec<Option<String>>> {
+        Box::pin(async { Ok(vec![]) })
+    }
+
+    /// Builds and sends a swap signed by the programmatic wallet
+    fn execute_swap<'a>(&'a self, token_swap: TokenSwap) -> AdapterFuture<'a, Signature> {
+        Box::pin(async move {
+            let swap_instructions = self.swap(&token_swap).await?;
+
+            ProgrammaticTransaction::perform(
+                swap_instructions.instructions,
+                swap_instructions.additional_signers,
+                Some(PriorityLevel::High),
+                TransactionKind::Swap,
+            ).await
+        })
+    }
+}
+
+pub struct DexRegistry;
+
+impl DexRegistry {
+    pub fn get(pool_type: &PoolType) -> anyhow::Result<&'static dyn DexAdapter> {
+        match pool_type {
+            PoolType::Orca => Ok(&Orca),
+            PoolType::Raydium => Ok(&Raydium),
+            PoolType::Meteora => Ok(&Meteora),
+            _ => Err(anyhow::anyhow!("No DEX adapter registered for {:?}", pool_type)),
+        }
+    }
+
+    pub fn all() -> Vec<&'static dyn DexAdapter> {
+        vec![&Orca, &Raydium, &Meteora]
+    }
+
+    pub fn is_supported(pool_type: &PoolType) -> bool {
+        DexRegistry::get(pool_type).is_ok()
+    }
+}
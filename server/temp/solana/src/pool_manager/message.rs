@@ -7,16 +7,25 @@ use super::PoolManager;
 pub enum MessageType {
     UpdatePosition,
     RemovePosition,
+    UpdateGroup,
+    RemoveGroup,
+    BatchResult,
+    UpdateRangeOrder,
     Stats,
 }
 
-impl MessageType {
-    pub fn to_string(&self) -> String {
-        match self {
-            MessageType::UpdatePosition => "update-position".to_string(),
-            MessageType::RemovePosition => "remove-position".to_string(),
-            MessageType::Stats => "stats".to_string(),
-        }
+impl std::fmt::Display for MessageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message_type = match self {
+            MessageType::UpdatePosition => "update-position",
+            MessageType::RemovePosition => "remove-position",
+            MessageType::UpdateGroup => "update-group",
+            MessageType::RemoveGroup => "remove-group",
+            MessageType::BatchResult => "batch-result",
+            MessageType::UpdateRangeOrder => "update-range-order",
+            MessageType::Stats => "stats",
+        };
+        write!(f, "{}", message_type)
     }
 }
 
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::utils::*;
+
+pub const LB_PAIR_LEN: usize = 904;
+pub const POSITION_V2_LEN: usize = 8120;
+pub const MAX_BINS_PER_POSITION: i32 = 70;
+pub const BINS_PER_ARRAY: i32 = 70;
+const BIN_LEN: usize = 144;
+const REWARD_INFO_LEN: usize = 144;
+pub const POSITION_OWNER_OFFSET: usize = 40;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MeteoraRewardInfo {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+}
+
+/// Decoded DLMM `LbPair`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MeteoraLbPair {
+    pub address: Pubkey,
+    pub base_factor: u16,
+    pub base_fee_power_factor: u8,
+    pub active_id: i32,
+    pub bin_step: u16,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub oracle: Pubkey,
+    pub reward_infos: Vec<MeteoraRewardInfo>,
+}
+
+impl MeteoraLbPair {
+    pub fn from_account_data(address: Pubkey, data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < LB_PAIR_LEN {
+            return Err(anyhow::anyhow!("Account {} is not a Meteora DLMM pair", address));
+        }
+
+        let mut reward_infos = Vec::with_capacity(2);
+        for index in 0..2 {
+            let offset = 264 + index * REWARD_INFO_LEN;
+            reward_infos.push(MeteoraRewardInfo {
+                mint: read_pubkey(data, offset)?,
+                vault: read_pubkey(data, offset + 32)?,
+            });
+        }
+
+        Ok(Self {
+            address,
+            base_factor: read_u16(data, 8)?,
+            base_fee_power_factor: read_u8(data, 34)?,
+            active_id: read_i32(data, 76)?,
+            bin_step: read_u16(data, 80)?,
+            token_x_mint: read_pubkey(data, 88)?,
+            token_y_mint: read_pubkey(data, 120)?,
+            reserve_x: read_pubkey(data, 152)?,
+            reserve_y: read_pubkey(data, 184)?,
+            oracle: read_pubkey(data, 552)?,
+            reward_infos,
+        })
+    }
+
+    pub fn price(&self, decimals_x: u8, decimals_y: u8) -> f64 {
+        bin_id_to_price(self.active_id, self.bin_step, decimals_x, decimals_y)
+    }
+
+    /// Base swap fee as a fraction, ignoring the volatility component
+    pub fn base_fee_rate(&self) -> f64 {
+        self.base_factor as f64 * self.bin_step as f64 * 10.0 * 10f64.powi(self.base_fee_power_factor as i32) / 1_000_000_000.0
+    }
+}
+
+/// Decoded DLMM `PositionV2`. Shares and fee checkpoints are indexed from `lower_bin_id`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MeteoraPosition {
+    pub address: Pubkey,
+    pub lb_pair: Pubkey,
+    pub owner: Pubkey,
+    pub liquidity_shares: Vec<u128>,
+    pub fee_infos: Vec<MeteoraPositionFeeInfo>,
+    pub lower_bin_id: i32,
+    pub upper_bin_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MeteoraPositionFeeInfo {
+    pub fee_x_per_token_complete: u128,
+    pub fee_y_per_token_complete: u128,
+    pub fee_x_pending: u64,
+    pub fee_y_pending: u64,
+}
+
+impl MeteoraPosition {
+    pub fn from_account_data(address: Pubkey, data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < POSITION_V2_LEN {
+            return Err(anyhow::anyhow!("Account {} is not a Meteora DLMM position", address));
+        }
+
+        let mut liquidity_shares = Vec::with_capacity(MAX_BINS_PER_POSITION as usize);
+        let mut fee_infos = Vec::with_capacity(MAX_BINS_PER_POSITION as usize);
+        for index in 0..MAX_BINS_PER_POSITION as usize {
+            liquidity_shares.push(read_u128(data, 72 + index * 16)?);
+
+            let offset = 4552 + index * 48;
+            fee_infos.push(MeteoraPositionFeeInfo {
+                fee_x_per_token_complete: read_u128(data, offset)?,
+                fee_y_per_token_complete: read_u128(data, offset + 16)?,
+                fee_x_pending: read_u64(data, offset + 32)?,
+                fee_y_pending: read_u64(data, offset + 40)?,
+            });
+        }
+
+        Ok(Self {
+            address,
+            lb_pair: read_pubkey(data, 8)?,
+            owner: read_pubkey(data, POSITION_OWNER_OFFSET)?,
+            liquidity_shares,
+            fee_infos,
+            lower_bin_id: read_i32(data, 7912)?,
+            upper_bin_id: read_i32(data, 7916)?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MeteoraBin {
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub liquidity_supply: u128,
+    pub fee_amount_x_per_token_stored: u128,
+    pub fee_amount_y_per_token_stored: u128,
+}
+
+/// Index of the bin array holding the given bin
+pub fn bin_array_index(bin_id: i32) -> i64 {
+    bin_id.div_euclid(BINS_PER_ARRAY) as i64
+}
+
+/// Reads a bin out of `BinArray` data, returning an empty bin when the array does not exist
+pub fn read_bin(data: Option<&[u8]>, bin_id: i32) -> anyhow::Result<MeteoraBin> {
+    let data = match data {
+        Some(data) => data,
+        None => return Ok(MeteoraBin::default()),
+    };
+
+    let index = read_i64(data, 8)?;
+    if index != bin_array_index(bin_id) {
+        return Err(anyhow::anyhow!("Bin array index mismatch for bin {}", bin_id));
+    }
+
+    let offset = 56 + (bin_id - index as i32 * BINS_PER_ARRAY) as usize * BIN_LEN;
+
+    Ok(MeteoraBin {
+        amount_x: read_u64(data, offset)?,
+        amount_y: read_u64(data, offset + 8)?,
+        liquidity_supply: read_u128(data, offset + 32)?,
+        fee_amount_x_per_token_stored: read_u128(data, offset + 80)?,
+        fee_amount_y_per_token_stored: read_u128(data, offset + 96)?,
+    })
+}
@@ -0,0 +1,107 @@
+use chrono::Utc;
+
+use crate::{pool_manager::{dex_adapter::{AdapterFuture, AdapterInstructions, DexAdapter, DexPoolInfo, OpenPositionRequest, SwapQuote}, orca::token_swap::TokenSwap, position_manager::managed_position::{ManagedPosition, PoolType}}, rpc::RpcMode, token::Token};
+
+use super::{accounts::MeteoraLbPair, Meteora, MeteoraInstructions};
+
+impl From<MeteoraInstructions> for AdapterInstructions {
+    fn from(meteora_instructions: MeteoraInstructions) -> Self {
+        Self {
+            instructions: meteora_instructions.instructions,
+            additional_signers: meteora_instructions.additional_signers,
+        }
+    }
+}
+
+impl DexAdapter for Meteora {
+    fn pool_type(&self) -> PoolType {
+        PoolType::Meteora
+    }
+
+    fn list_positions<'a>(&'a self, wallet_key: &'a str) -> AdapterFuture<'a, Vec<ManagedPosition>> {
+        Box::pin(async move {
+            let positions = Meteora::get_positions_for_wallet(wallet_key.to_string()).await?;
+            let mut pairs: Vec<MeteoraLbPair> = vec![];
+            let mut managed_positions = vec![];
+
+            for position in positions {
+                let pair = match pairs.iter().find(|pair| pair.address == position.lb_pair) {
+                    Some(pair) => pair.clone(),
+                    None => {
+                        let pair = Meteora::get_lb_pair(RpcMode::conservative(), &position.lb_pair.to_string()).await?;
+                        pairs.push(pair.clone());
+                        pair
+                    }
+                };
+                let amounts = Meteora::get_position_amounts(RpcMode::conservative(), &pair, &position).await?;
+
+                let mut managed_position = ManagedPosition::from_meteora_position(position.clone(), Utc::now());
+                managed_position.update_meteora_prices(&pair, position, amounts).await?;
+                managed_positions.push(managed_position);
+            }
+
+            Ok(managed_positions)
+        })
+    }
+
+    fn pool_info<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, DexPoolInfo> {
+        Box::pin(async move {
+            let pair = Meteora::get_lb_pair(RpcMode::fast(), pool_address).await?;
+            let token_x = Token::from_mint_address(&pair.token_x_mint.to_string()).await?;
+            let token_y = Token::from_mint_address(&pair.token_y_mint.to_string()).await?;
+
+            Ok(DexPoolInfo {
+                pool_type: PoolType::Meteora,
+                address: pool_address.to_string(),
+                token_mint_a: pair.token_x_mint.to_string(),
+                token_mint_b: pair.token_y_mint.to_string(),
+                price: pair.price(token_x.decimals, token_y.decimals),
+                tick_spacing: None,
+                bin_step: Some(pair.bin_step),
+                fee_rate: Some(pair.base_fee_rate()),
+            })
+        })
+    }
+
+    fn pool_price<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, f64> {
+        Box::pin(Meteora::get_pool_price(RpcMode::fast(), pool_address))
+    }
+
+    fn open_position<'a>(&'a self, request: &'a OpenPositionRequest) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            let open_position_instructions = Meteora::get_open_position_instructions(
+                &request.wallet_key,
+                &request.pool_address,
+                request.token_amount_a,
+                request.token_amount_b,
+                request.slippage,
+                request.range_lower,
+                request.range_upper,
+            ).await?;
+
+            Ok(open_position_instructions.into())
+        })
+    }
+
+    fn close_position<'a>(&'a self, position: &'a ManagedPosition) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            Ok(Meteora::get_close_position_instructions(&position.wallet_key, &position.address).await?.into())
+        })
+    }
+
+    fn collect_fees<'a>(&'a self, position: &'a ManagedPosition) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            Ok(Meteora::get_collect_fees_instructions(&position.wallet_key, &position.address).await?.into())
+        })
+    }
+
+    fn swap<'a>(&'a self, token_swap: &'a TokenSwap) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            Ok(Meteora::get_swap_instructions(token_swap.clone()).await?.into())
+        })
+    }
+
+    fn quote<'a>(&'a self, token_swap: &'a TokenSwap) -> AdapterFuture<'a, SwapQuote> {
+        Box::pin(Meteora::get_swap_quote(token_swap))
+    }
+}
@@ -0,0 +1,243 @@
+use solana_sdk::{instruction::{AccountMeta, Instruction}, pubkey::Pubkey, sysvar};
+use solana_system_interface::program as system_program;
+
+use crate::utils::anchor_discriminator;
+
+use super::{accounts::MeteoraLbPair, Meteora};
+
+/// `StrategyType::SpotImBalanced`, an even spread that accepts any mix of X and Y
+pub const STRATEGY_SPOT_IMBALANCED: u8 = 6;
+
+/// Accounts shared by the liquidity and close builders for one position
+pub struct MeteoraPositionAccounts {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub bin_array_lower: Pubkey,
+    pub bin_array_upper: Pubkey,
+    pub user_token_x: Pubkey,
+    pub user_token_y: Pubkey,
+    pub token_x_program: Pubkey,
+    pub token_y_program: Pubkey,
+}
+
+fn event_accounts() -> anyhow::Result<Vec<AccountMeta>> {
+    Ok(vec![
+        AccountMeta::new_readonly(Meteora::get_event_authority_address()?, false),
+        AccountMeta::new_readonly(Meteora::program_id()?, false),
+    ])
+}
+
+pub fn initialize_bin_array(pair: &MeteoraLbPair, index: i64, funder: &Pubkey) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("initialize_bin_array").to_vec();
+    data.extend_from_slice(&index.to_le_bytes());
+
+    Ok(Instruction {
+        program_id: Meteora::program_id()?,
+        accounts: vec![
+            AccountMeta::new_readonly(pair.address, false),
+            AccountMeta::new(Meteora::get_bin_array_address(&pair.address, index)?, false),
+            AccountMeta::new(*funder, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    })
+}
+
+pub fn initialize_position(pair: &MeteoraLbPair, owner: &Pubkey, position: &Pubkey, lower_bin_id: i32, width: i32) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("initialize_position").to_vec();
+    data.extend_from_slice(&lower_bin_id.to_le_bytes());
+    data.extend_from_slice(&width.to_le_bytes());
+
+    let mut accounts = vec![
+        AccountMeta::new(*owner, true),
+        AccountMeta::new(*position, true),
+        AccountMeta::new_readonly(pair.address, false),
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    accounts.extend(event_accounts()?);
+
+    Ok(Instruction {
+        program_id: Meteora::program_id()?,
+        accounts,
+        data,
+    })
+}
+
+/// Account list shared by `add_liquidity_by_strategy` and `remove_all_liquidity`. The optional
+/// bitmap extension is passed as the program id.
+fn liquidity_accounts(pair: &MeteoraLbPair, accounts: &MeteoraPositionAccounts) -> anyhow::Result<Vec<AccountMeta>> {
+    let mut account_metas = vec![
+        AccountMeta::new(accounts.position, false),
+        AccountMeta::new(pair.address, false),
+        AccountMeta::new_readonly(Meteora::program_id()?, false),
+        AccountMeta::new(accounts.user_token_x, false),
+        AccountMeta::new(accounts.user_token_y, false),
+        AccountMeta::new(pair.reserve_x, false),
+        AccountMeta::new(pair.reserve_y, false),
+        AccountMeta::new_readonly(pair.token_x_mint, false),
+        AccountMeta::new_readonly(pair.token_y_mint, false),
+        AccountMeta::new(accounts.bin_array_lower, false),
+        AccountMeta::new(accounts.bin_array_upper, false),
+        AccountMeta::new_readonly(accounts.owner, true),
+        AccountMeta::new_readonly(accounts.token_x_program, false),
+        AccountMeta::new_readonly(accounts.token_y_program, false),
+    ];
+    account_metas.extend(event_accounts()?);
+
+    Ok(account_metas)
+}
+
+pub struct AddLiquidityArgs {
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub active_id: i32,
+    pub max_active_bin_slippage: i32,
+    pub min_bin_id: i32,
+    pub max_bin_id: i32,
+    pub strategy_type: u8,
+}
+
+pub fn add_liquidity_by_strategy(pair: &MeteoraLbPair, accounts: &MeteoraPositionAccounts, args: &AddLiquidityArgs) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("add_liquidity_by_strategy").to_vec();
+    data.extend_from_slice(&args.amount_x.to_le_bytes());
+    data.extend_from_slice(&args.amount_y.to_le_bytes());
+    data.extend_from_slice(&args.active_id.to_le_bytes());
+    data.extend_from_slice(&args.max_active_bin_slippage.to_le_bytes());
+    data.extend_from_slice(&args.min_bin_id.to_le_bytes());
+    data.extend_from_slice(&args.max_bin_id.to_le_bytes());
+    data.push(args.strategy_type);
+    data.extend_from_slice(&[0u8; 64]);
+
+    Ok(Instruction {
+        program_id: Meteora::program_id()?,
+        accounts: liquidity_accounts(pair, accounts)?,
+        data,
+    })
+}
+
+pub fn remove_all_liquidity(pair: &MeteoraLbPair, accounts: &MeteoraPositionAccounts) -> anyhow::Result<Instruction> {
+    Ok(Instruction {
+        program_id: Meteora::program_id()?,
+        accounts: liquidity_accounts(pair, accounts)?,
+        data: anchor_discriminator("remove_all_liquidity").to_vec(),
+    })
+}
+
+pub fn claim_fee(pair: &MeteoraLbPair, accounts: &MeteoraPositionAccounts) -> anyhow::Result<Instruction> {
+    let mut account_metas = vec![
+        AccountMeta::new(pair.address, false),
+        AccountMeta::new(accounts.position, false),
+        AccountMeta::new(accounts.bin_array_lower, false),
+        AccountMeta::new(accounts.bin_array_upper, false),
+        AccountMeta::new_readonly(accounts.owner, true),
+        AccountMeta::new(pair.reserve_x, false),
+        AccountMeta::new(pair.reserve_y, false),
+        AccountMeta::new(accounts.user_token_x, false),
+        AccountMeta::new(accounts.user_token_y, false),
+        AccountMeta::new_readonly(pair.token_x_mint, false),
+        AccountMeta::new_readonly(pair.token_y_mint, false),
+        AccountMeta::new_readonly(accounts.token_x_program, false),
+    ];
+    account_metas.extend(event_accounts()?);
+
+    Ok(Instruction {
+        program_id: Meteora::program_id()?,
+        accounts: account_metas,
+        data: anchor_discriminator("claim_fee").to_vec(),
+    })
+}
+
+pub fn claim_reward(
+    pair: &MeteoraLbPair,
+    accounts: &MeteoraPositionAccounts,
+    reward_index: u64,
+    user_token_account: &Pubkey,
+    token_program: &Pubkey,
+) -> anyhow::Result<Instruction> {
+    let reward_info = pair
+        .reward_infos
+        .get(reward_index as usize)
+        .ok_or_else(|| anyhow::anyhow!("Invalid reward index {}", reward_index))?;
+
+    let mut data = anchor_discriminator("claim_reward").to_vec();
+    data.extend_from_slice(&reward_index.to_le_bytes());
+
+    let mut account_metas = vec![
+        AccountMeta::new(pair.address, false),
+        AccountMeta::new(accounts.position, false),
+        AccountMeta::new(accounts.bin_array_lower, false),
+        AccountMeta::new(accounts.bin_array_upper, false),
+        AccountMeta::new_readonly(accounts.owner, true),
+        AccountMeta::new(reward_info.vault, false),
+        AccountMeta::new_readonly(reward_info.mint, false),
+        AccountMeta::new(*user_token_account, false),
+        AccountMeta::new_readonly(*token_program, false),
+    ];
+    account_metas.extend(event_accounts()?);
+
+    Ok(Instruction {
+        program_id: Meteora::program_id()?,
+        accounts: account_metas,
+        data,
+    })
+}
+
+pub fn close_position(pair: &MeteoraLbPair, accounts: &MeteoraPositionAccounts) -> anyhow::Result<Instruction> {
+    let mut account_metas = vec![
+        AccountMeta::new(accounts.position, false),
+        AccountMeta::new(pair.address, false),
+        AccountMeta::new(accounts.bin_array_lower, false),
+        AccountMeta::new(accounts.bin_array_upper, false),
+        AccountMeta::new_readonly(accounts.owner, true),
+        AccountMeta::new(accounts.owner, false),
+    ];
+    account_metas.extend(event_accounts()?);
+
+    Ok(Instruction {
+        program_id: Meteora::program_id()?,
+        accounts: account_metas,
+        data: anchor_discriminator("close_position").to_vec(),
+    })
+}
+
+pub struct SwapAccounts {
+    pub user: Pubkey,
+    pub user_token_in: Pubkey,
+    pub user_token_out: Pubkey,
+    pub token_x_program: Pubkey,
+    pub token_y_program: Pubkey,
+}
+
+/// Exact-in swap. `bin_arrays` are the arrays the swap walks through, starting at the active bin.
+pub fn swap(pair: &MeteoraLbPair, accounts: &SwapAccounts, amount_in: u64, min_amount_out: u64, bin_arrays: &[Pubkey]) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("swap").to_vec();
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&min_amount_out.to_le_bytes());
+
+    let program_id = Meteora::program_id()?;
+    let mut account_metas = vec![
+        AccountMeta::new(pair.address, false),
+        AccountMeta::new_readonly(program_id, false),
+        AccountMeta::new(pair.reserve_x, false),
+        AccountMeta::new(pair.reserve_y, false),
+        AccountMeta::new(accounts.user_token_in, false),
+        AccountMeta::new(accounts.user_token_out, false),
+        AccountMeta::new_readonly(pair.token_x_mint, false),
+        AccountMeta::new_readonly(pair.token_y_mint, false),
+        AccountMeta::new(pair.oracle, false),
+        AccountMeta::new_readonly(program_id, false),
+        AccountMeta::new_readonly(accounts.user, true),
+        AccountMeta::new_readonly(accounts.token_x_program, false),
+        AccountMeta::new_readonly(accounts.token_y_program, false),
+    ];
+    account_metas.extend(event_accounts()?);
+    account_metas.extend(bin_arrays.iter().map(|bin_array| AccountMeta::new(*bin_array, false)));
+
+    Ok(Instruction {
+        program_id,
+        accounts: account_metas,
+        data,
+    })
+}
@@ -0,0 +1,415 @@
+pub mod accounts;
+pub mod adapter;
+pub mod instructions;
+
+use std::str::FromStr;
+
+use accounts::{bin_array_index, read_bin, MeteoraLbPair, MeteoraPosition, MAX_BINS_PER_POSITION, POSITION_OWNER_OFFSET, POSITION_V2_LEN};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use instructions::{AddLiquidityArgs, MeteoraPositionAccounts, SwapAccounts, STRATEGY_SPOT_IMBALANCED};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
+
+use crate::{rpc::{Rpc, RpcMode}, token::Token, utils::*};
+
+use super::{dex_adapter::SwapQuote, orca::token_swap::TokenSwap};
+
+pub const DLMM_PROGRAM_ID: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Meteora;
+
+/// Instructions built locally for the DLMM program. Additional signers are base64 encoded keypairs.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MeteoraInstructions {
+    pub instructions: Vec<Instruction>,
+    pub additional_signers: Vec<String>,
+}
+
+/// Raw token amounts held by a position and the fees it has earned
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MeteoraPositionAmounts {
+    pub amount_x: f64,
+    pub amount_y: f64,
+    pub fee_x: f64,
+    pub fee_y: f64,
+}
+
+impl Meteora {
+    pub fn program_id() -> anyhow::Result<Pubkey> {
+        Ok(Pubkey::from_str(DLMM_PROGRAM_ID)?)
+    }
+
+    pub fn get_event_authority_address() -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(&[b"__event_authority"], &Meteora::program_id()?);
+        Ok(address)
+    }
+
+    pub fn get_bin_array_address(lb_pair: &Pubkey, index: i64) -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(
+            &[b"bin_array", lb_pair.as_ref(), &index.to_le_bytes()],
+            &Meteora::program_id()?,
+        );
+        Ok(address)
+    }
+
+    pub async fn get_lb_pair(rpc_mode: RpcMode, pair_address: &str) -> anyhow::Result<MeteoraLbPair> {
+        let pair_pubkey = Pubkey::from_str(pair_address)?;
+        let account = Rpc::get_account(rpc_mode, &pair_pubkey, Some(10000)).await?;
+
+        if account.owner != Meteora::program_id()? {
+            return Err(anyhow::anyhow!("Pair {} is not owned by the Meteora DLMM program", pair_address));
+        }
+
+        MeteoraLbPair::from_account_data(pair_pubkey, &account.data)
+    }
+
+    pub async fn get_pool_price(rpc_mode: RpcMode, pair_address: &str) -> anyhow::Result<f64> {
+        let pair = Meteora::get_lb_pair(rpc_mode, pair_address).await?;
+        let token_x = Token::from_mint_address(&pair.token_x_mint.to_string()).await?;
+        let token_y = Token::from_mint_address(&pair.token_y_mint.to_string()).await?;
+
+        Ok(pair.price(token_x.decimals, token_y.decimals))
+    }
+
+    pub async fn get_positions_for_wallet(wallet_key: String) -> anyhow::Result<Vec<MeteoraPosition>> {
+        let owner = Pubkey::from_str(&wallet_key)?;
+        let filters = vec![
+            RpcFilterType::DataSize(POSITION_V2_LEN as u64),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(POSITION_OWNER_OFFSET, &owner.to_bytes())),
+        ];
+
+        let accounts = Rpc::get_program_accounts(RpcMode::conservative(), &Meteora::program_id()?, filters, Some(20000)).await?;
+
+        accounts
+            .into_iter()
+            .map(|(address, account)| MeteoraPosition::from_account_data(address, &account.data))
+            .collect()
+    }
+
+    pub async fn get_position(rpc_mode: RpcMode, position_address: &str) -> anyhow::Result<MeteoraPosition> {
+        let address = Pubkey::from_str(position_address)?;
+        let account = Rpc::get_account(rpc_mode, &address, Some(10000)).await?;
+
+        MeteoraPosition::from_account_data(address, &account.data)
+    }
+
+    fn get_position_bin_arrays(pair: &MeteoraLbPair, lower_bin_id: i32, upper_bin_id: i32) -> anyhow::Result<(i64, i64, Pubkey, Pubkey)> {
+        let lower_index = bin_array_index(lower_bin_id);
+        let upper_index = bin_array_index(upper_bin_id);
+
+        Ok((
+            lower_index,
+            upper_index,
+            Meteora::get_bin_array_address(&pair.address, lower_index)?,
+            Meteora::get_bin_array_address(&pair.address, upper_index)?,
+        ))
+    }
+
+    /// Sums the position's share of every bin in its range, plus pending and unclaimed fees
+    pub async fn get_position_amounts(rpc_mode: RpcMode, pair: &MeteoraLbPair, position: &MeteoraPosition) -> anyhow::Result<MeteoraPositionAmounts> {
+        let (lower_index, _, lower_address, upper_address) =
+            Meteora::get_position_bin_arrays(pair, position.lower_bin_id, position.upper_bin_id)?;
+        let bin_arrays = Rpc::get_multiple_accounts(rpc_mode, &[lower_address, upper_address], Some(10000)).await?;
+
+        let mut amounts = MeteoraPositionAmounts::default();
+
+        for bin_id in position.lower_bin_id..=position.upper_bin_id {
+            let offset = (bin_id - position.lower_bin_id) as usize;
+            let share = position.liquidity_shares.get(offset).copied().unwrap_or(0);
+            let fee_info = &position.fee_infos[offset];
+
+            let bin_array = if bin_array_index(bin_id) == lower_index { &bin_arrays[0] } else { &bin_arrays[1] };
+            let bin = read_bin(bin_array.as_ref().map(|account| account.data.as_slice()), bin_id)?;
+
+            if share > 0 && bin.liquidity_supply > 0 {
+                let share_ratio = share as f64 / bin.liquidity_supply as f64;
+                amounts.amount_x += bin.amount_x as f64 * share_ratio;
+                amounts.amount_y += bin.amount_y as f64 * share_ratio;
+            }
+
+            // Shares and fee growth are both Q64 scaled
+            let share_units = share as f64 / Q64;
+            amounts.fee_x += fee_info.fee_x_pending as f64
+                + share_units * bin.fee_amount_x_per_token_stored.wrapping_sub(fee_info.fee_x_per_token_complete) as f64 / Q64;
+            amounts.fee_y += fee_info.fee_y_pending as f64
+                + share_units * bin.fee_amount_y_per_token_stored.wrapping_sub(fee_info.fee_y_per_token_complete) as f64 / Q64;
+        }
+
+        Ok(amounts)
+    }
+
+    /// Bin range for a price range, narrowed around the active bin when it is wider than one position allows
+    pub fn get_bin_range(pair: &MeteoraLbPair, decimals_x: u8, decimals_y: u8, range_lower: f64, range_upper: f64) -> (i32, i32) {
+        let mut lower_bin_id = price_to_bin_id(range_lower, pair.bin_step, decimals_x, decimals_y);
+        let mut upper_bin_id = price_to_bin_id(range_upper, pair.bin_step, decimals_x, decimals_y).max(lower_bin_id);
+
+        if upper_bin_id - lower_bin_id + 1 > MAX_BINS_PER_POSITION {
+            let center = pair.active_id.clamp(lower_bin_id, upper_bin_id);
+            lower_bin_id = (center - MAX_BINS_PER_POSITION / 2).max(lower_bin_id);
+            upper_bin_id = lower_bin_id + MAX_BINS_PER_POSITION - 1;
+        }
+
+        (lower_bin_id, upper_bin_id)
+    }
+
+    pub async fn get_open_position_instructions(
+        wallet_key: &str,
+        pair_address: &str,
+        amount_x: u64,
+        amount_y: u64,
+        slippage: u16,
+        range_lower: f64,
+        range_upper: f64,
+    ) -> anyhow::Result<MeteoraInstructions> {
+        let owner = Pubkey::from_str(wallet_key)?;
+        let pair = Meteora::get_lb_pair(RpcMode::fast(), pair_address).await?;
+        let token_x = Token::from_mint_address(&pair.token_x_mint.to_string()).await?;
+        let token_y = Token::from_mint_address(&pair.token_y_mint.to_string()).await?;
+        let token_programs = Token::get_token_programs(RpcMode::fast(), &[pair.token_x_mint, pair.token_y_mint]).await?;
+
+        let (lower_bin_id, upper_bin_id) = Meteora::get_bin_range(&pair, token_x.decimals, token_y.decimals, range_lower, range_upper);
+        let (lower_index, upper_index, bin_array_lower, bin_array_upper) =
+            Meteora::get_position_bin_arrays(&pair, lower_bin_id, upper_bin_id)?;
+
+        let position = Keypair::new();
+        let position_accounts = MeteoraPositionAccounts {
+            owner,
+            position: position.pubkey(),
+            bin_array_lower,
+            bin_array_upper,
+            user_token_x: Token::get_associated_token_address(&owner, &pair.token_x_mint, &token_programs[0])?,
+            user_token_y: Token::get_associated_token_address(&owner, &pair.token_y_mint, &token_programs[1])?,
+            token_x_program: token_programs[0],
+            token_y_program: token_programs[1],
+        };
+
+        let mut instructions = vec![];
+        let mut closing_instructions = vec![];
+
+        // Bin arrays have to exist before liquidity can be added to them
+        let bin_arrays = Rpc::get_multiple_accounts(RpcMode::fast(), &[bin_array_lower, bin_array_upper], Some(10000)).await?;
+        if bin_arrays[0].is_none() {
+            instructions.push(instructions::initialize_bin_array(&pair, lower_index, &owner)?);
+        }
+        if bin_arrays[1].is_none() && upper_index != lower_index {
+            instructions.push(instructions::initialize_bin_array(&pair, upper_index, &owner)?);
+        }
+
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+        for (mint, token_program, token_account, amount) in [
+            (pair.token_x_mint, token_programs[0], position_accounts.user_token_x, amount_x),
+            (pair.token_y_mint, token_programs[1], position_accounts.user_token_y, amount_y),
+        ] {
+            instructions.push(Token::create_associated_token_account_idempotent(&owner, &owner, &mint, &token_program)?);
+            if mint == wsol_mint {
+                instructions.extend(Token::wrap_sol_instructions(&owner, &token_account, amount)?);
+                closing_instructions.push(Token::unwrap_sol_instruction(&owner, &token_account)?);
+            }
+        }
+
+        instructions.push(instructions::initialize_position(
+            &pair,
+            &owner,
+            &position.pubkey(),
+            lower_bin_id,
+            upper_bin_id - lower_bin_id + 1,
+        )?);
+
+        // Slippage is expressed to the program as how many bins the active bin may move
+        let max_active_bin_slippage = ((slippage as f64 / pair.bin_step as f64).ceil() as i32).max(1);
+        instructions.push(instructions::add_liquidity_by_strategy(
+            &pair,
+            &position_accounts,
+            &AddLiquidityArgs {
+                amount_x,
+                amount_y,
+                active_id: pair.active_id,
+                max_active_bin_slippage,
+                min_bin_id: lower_bin_id,
+                max_bin_id: upper_bin_id,
+                strategy_type: STRATEGY_SPOT_IMBALANCED,
+            },
+        )?);
+        instructions.extend(closing_instructions);
+
+        Ok(MeteoraInstructions {
+            instructions,
+            additional_signers: vec![BASE64_STANDARD.encode(position.to_bytes())],
+        })
+    }
+
+    /// Removes all liquidity, claims fees and rewards, and closes the position account
+    pub async fn get_close_position_instructions(wallet_key: &str, position_address: &str) -> anyhow::Result<MeteoraInstructions> {
+        Meteora::get_claim_instructions(wallet_key, position_address, true).await
+    }
+
+    /// Claims fees and rewards, leaving liquidity in place
+    pub async fn get_collect_fees_instructions(wallet_key: &str, position_address: &str) -> anyhow::Result<MeteoraInstructions> {
+        Meteora::get_claim_instructions(wallet_key, position_address, false).await
+    }
+
+    async fn get_claim_instructions(wallet_key: &str, position_address: &str, close: bool) -> anyhow::Result<MeteoraInstructions> {
+        let owner = Pubkey::from_str(wallet_key)?;
+        let position = Meteora::get_position(RpcMode::fast(), position_address).await?;
+        let pair = Meteora::get_lb_pair(RpcMode::fast(), &position.lb_pair.to_string()).await?;
+
+        let rewards: Vec<(u64, Pubkey)> = pair
+            .reward_infos
+            .iter()
+            .enumerate()
+            .filter(|(_, reward)| reward.mint != Pubkey::default())
+            .map(|(index, reward)| (index as u64, reward.mint))
+            .collect();
+        let mut mints = vec![pair.token_x_mint, pair.token_y_mint];
+        mints.extend(rewards.iter().map(|(_, mint)| *mint));
+        let token_programs = Token::get_token_programs(RpcMode::fast(), &mints).await?;
+
+        let (_, _, bin_array_lower, bin_array_upper) =
+            Meteora::get_position_bin_arrays(&pair, position.lower_bin_id, position.upper_bin_id)?;
+        let position_accounts = MeteoraPositionAccounts {
+            owner,
+            position: position.address,
+            bin_array_lower,
+            bin_array_upper,
+            user_token_x: Token::get_associated_token_address(&owner, &pair.token_x_mint, &token_programs[0])?,
+            user_token_y: Token::get_associated_token_address(&owner, &pair.token_y_mint, &token_programs[1])?,
+            token_x_program: token_programs[0],
+            token_y_program: token_programs[1],
+        };
+
+        let mut instructions = vec![
+            Token::create_associated_token_account_idempotent(&owner, &owner, &pair.token_x_mint, &token_programs[0])?,
+            Token::create_associated_token_account_idempotent(&owner, &owner, &pair.token_y_mint, &token_programs[1])?,
+        ];
+
+        if close && position.liquidity_shares.iter().any(|share| *share > 0) {
+            instructions.push(instructions::remove_all_liquidity(&pair, &position_accounts)?);
+        }
+        instructions.push(instructions::claim_fee(&pair, &position_accounts)?);
+
+        for ((reward_index, reward_mint), token_program) in rewards.iter().zip(token_programs.iter().skip(2)) {
+            let user_token_account = Token::get_associated_token_address(&owner, reward_mint, token_program)?;
+            instructions.push(Token::create_associated_token_account_idempotent(&owner, &owner, reward_mint, token_program)?);
+            instructions.push(instructions::claim_reward(&pair, &position_accounts, *reward_index, &user_token_account, token_program)?);
+        }
+
+        if close {
+            instructions.push(instructions::close_position(&pair, &position_accounts)?);
+        }
+
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+        if pair.token_x_mint == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &position_accounts.user_token_x)?);
+        } else if pair.token_y_mint == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &position_accounts.user_token_y)?);
+        }
+
+        Ok(MeteoraInstructions {
+            instructions,
+            additional_signers: vec![],
+        })
+    }
+
+    /// Spot estimate of an exact-in swap at the active bin and base fee, without price impact
+    pub async fn get_swap_quote(token_swap: &TokenSwap) -> anyhow::Result<SwapQuote> {
+        let pair = Meteora::get_lb_pair(RpcMode::fast(), &token_swap.pool_address).await?;
+        let sell_mint = Pubkey::from_str(&token_swap.mint_out_address)?;
+
+        Meteora::quote_with_pair(&pair, token_swap, &sell_mint).await
+    }
+
+    async fn quote_with_pair(pair: &MeteoraLbPair, token_swap: &TokenSwap, sell_mint: &Pubkey) -> anyhow::Result<SwapQuote> {
+        if !token_swap.amount_is_in {
+            return Err(anyhow::anyhow!("Meteora swaps only support an exact input amount"));
+        }
+
+        let (swap_for_y, buy_mint) = if *sell_mint == pair.token_x_mint {
+            (true, pair.token_y_mint)
+        } else if *sell_mint == pair.token_y_mint {
+            (false, pair.token_x_mint)
+        } else {
+            return Err(anyhow::anyhow!("Mint {} is not part of pair {}", sell_mint, token_swap.pool_address));
+        };
+
+        // Raw token Y per raw token X at the active bin
+        let raw_price = (1.0 + pair.bin_step as f64 / 10_000.0).powi(pair.active_id);
+
+        SwapQuote::from_raw_price(token_swap, swap_for_y, raw_price, pair.base_fee_rate())
+            .net_of_transfer_fees(token_swap, &buy_mint.to_string())
+            .await
+    }
+
+    /// Exact-in swap on a DLMM pair. `mint_out_address` is the mint being sold, matching how swaps
+    /// are requested from the Orca sidecar.
+    pub async fn get_swap_instructions(token_swap: TokenSwap) -> anyhow::Result<MeteoraInstructions> {
+        let owner = Pubkey::from_str(&token_swap.wallet_key)?;
+        let pair = Meteora::get_lb_pair(RpcMode::fast(), &token_swap.pool_address).await?;
+        let sell_mint = Pubkey::from_str(&token_swap.mint_out_address)?;
+
+        let min_amount_out = Meteora::quote_with_pair(&pair, &token_swap, &sell_mint).await?.other_amount_threshold;
+        let swap_for_y = sell_mint == pair.token_x_mint;
+
+        let token_programs = Token::get_token_programs(RpcMode::fast(), &[pair.token_x_mint, pair.token_y_mint]).await?;
+        let user_token_x = Token::get_associated_token_address(&owner, &pair.token_x_mint, &token_programs[0])?;
+        let user_token_y = Token::get_associated_token_address(&owner, &pair.token_y_mint, &token_programs[1])?;
+        let (user_token_in, user_token_out) = if swap_for_y { (user_token_x, user_token_y) } else { (user_token_y, user_token_x) };
+
+        // Selling X walks the price down through lower bins, selling Y walks it up
+        let active_index = bin_array_index(pair.active_id);
+        let candidates = (0..4)
+            .map(|step| {
+                let index = if swap_for_y { active_index - step } else { active_index + step };
+                Meteora::get_bin_array_address(&pair.address, index)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let candidate_accounts = Rpc::get_multiple_accounts(RpcMode::fast(), &candidates, Some(10000)).await?;
+        let bin_arrays: Vec<Pubkey> = candidates
+            .into_iter()
+            .zip(candidate_accounts)
+            .filter(|(_, account)| account.is_some())
+            .map(|(address, _)| address)
+            .take(3)
+            .collect();
+
+        if bin_arrays.is_empty() {
+            return Err(anyhow::anyhow!("No bin arrays found around the active bin of pair {}", token_swap.pool_address));
+        }
+
+        let mut instructions = vec![
+            Token::create_associated_token_account_idempotent(&owner, &owner, &pair.token_x_mint, &token_programs[0])?,
+            Token::create_associated_token_account_idempotent(&owner, &owner, &pair.token_y_mint, &token_programs[1])?,
+        ];
+
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+        if sell_mint == wsol_mint {
+            instructions.extend(Token::wrap_sol_instructions(&owner, &user_token_in, token_swap.amount)?);
+        }
+
+        instructions.push(instructions::swap(
+            &pair,
+            &SwapAccounts {
+                user: owner,
+                user_token_in,
+                user_token_out,
+                token_x_program: token_programs[0],
+                token_y_program: token_programs[1],
+            },
+            token_swap.amount,
+            min_amount_out,
+            &bin_arrays,
+        )?);
+
+        if sell_mint == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &user_token_in)?);
+        } else if pair.token_x_mint == wsol_mint || pair.token_y_mint == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &user_token_out)?);
+        }
+
+        Ok(MeteoraInstructions {
+            instructions,
+            additional_signers: vec![],
+        })
+    }
+}
@@ -1,28 +1,47 @@
-use std::{str::FromStr, sync::Arc, time::Duration};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
 
 use chrono::{DateTime, Utc};
+use helius::types::PriorityLevel;
 use kebtech_utils::*;
+use batch_open::{BatchOpen, BatchOpenResult};
 use message::{MessageType, PoolManagerMessage};
-use new_position::{NewPosition, NewPositionData, NewProgrammaticPosition};
-use orca::{token_swap::TokenSwap, Orca};
-use orca_pools_ipc_types::response::{close_position_instruction::OrcaClosePositionInstruction, open_position_instruction::OrcaOpenPositionInstruction, orca_position_info::OrcaPositionInfo, orca_swap_instructions::OrcaSwapInstructions};
-use position_manager::managed_position::{ManagedPosition, PoolType};
+use range_order::{RangeOrder, RangeOrderStatus};
+use rebalance_bundle::RebalanceBundle;
+use swap_router::SwapRouteRecord;
+use new_position::{NewPosition, NewPositionData, NewProgrammaticPosition, PreparedOpen};
+use orca::{liquidity::OrcaLiquidityInstructions, token_swap::TokenSwap, zap_in::{ZapIn, ZapInInstructions}, Orca};
+use orca_pools_ipc_types::response::{open_position_instruction::OrcaOpenPositionInstruction, orca_swap_instructions::OrcaSwapInstructions};
+use dex_adapter::DexRegistry;
+use position_manager::{exit_to_token::{ExitToTokenInstructions, PositionExit}, managed_position::{LiquidityChangeKind, ManagedPosition, PoolType}, position_group::PositionGroup};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use solana_sdk::pubkey::Pubkey;
 use state::InitCell;
 use tokio::{sync::Mutex, time::interval};
 
-use crate::{price_info::{coinbase::{ticker::TickerState, websocket::CoinbaseWebsocket}, price_checker::PriceChecker}, rpc::{Rpc, RpcMode}, token::Token, wallet::Wallet};
+use crate::{price_info::{coinbase::{ticker::TickerState, websocket::CoinbaseWebsocket}, price_checker::PriceChecker}, rpc::{jito::{BundleResult, Jito}, provider_registry::ProviderRegistry, sidecar::SidecarClient, Rpc, RpcMode}, token::Token, wallet::{lookup_table::LookupTable, nonce_account::NonceAccounts, prepared_transaction::PreparedTransaction, programmatic_transaction::{ProgrammaticTransaction, TransactionKind}, unsigned_transaction::UnsignedTransaction, Wallet}};
 
 pub mod position_manager;
+pub mod dex_adapter;
+pub mod batch_open;
+pub mod range_order;
+pub mod rebalance_bundle;
+pub mod swap_router;
 pub mod message;
 pub mod new_position;
 pub mod orca;
 pub mod raydium;
+pub mod meteora;
 
 pub static POOL_MANAGER: InitCell<Arc<Mutex<PoolManager>>> = InitCell::new();
 
+const MAX_BUNDLE_RESULTS: usize = 50;
+/// Prepared closes and opens are rebuilt after this long, as their amounts go stale with the price
+const PREPARED_CLOSE_MAX_AGE_SECONDS: i64 = 60;
+/// Prepared opens are only dropped after this long, as they are sent once the close has landed.
+/// Sending one checks the price is still where its range was centred.
+const PREPARED_OPEN_MAX_AGE_SECONDS: i64 = 300;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PoolManager {
     pub created: DateTime<Utc>,
@@ -33,9 +52,31 @@ pub struct PoolManager {
     pub programmatic_wallet_pubkey: Option<Pubkey>,
     pub position_to_open: Option<NewProgrammaticPosition>,
     pub position_to_close: Option<ManagedPosition>,
+    pub position_to_exit: Option<PositionExit>,
+    pub position_groups: Vec<PositionGroup>,
+    pub group_to_open: Option<PositionGroup>,
+    pub group_to_rebalance: Option<PositionGroup>,
+    pub batch_to_open: Option<BatchOpen>,
+    pub batch_results: Vec<BatchOpenResult>,
+    pub range_orders: Vec<RangeOrder>,
+    pub range_order_to_place: Option<RangeOrder>,
+    pub range_order_to_close: Option<RangeOrder>,
+    pub swap_routes: Vec<SwapRouteRecord>,
+    pub bundle_results: Vec<BundleResult>,
+    /// Closes signed against a durable nonce, by position address
+    pub prepared_closes: HashMap<String, PreparedTransaction>,
+    /// Opens signed against a durable nonce, by pool address
+    #[serde(default)]
+    pub prepared_opens: HashMap<String, PreparedOpen>,
     pub message_queue: Vec<PoolManagerMessage>,
 }
 
+impl Default for PoolManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PoolManager {
     pub fn new() -> Self {
         let programmatic_wallet_pubkey = match Wallet::get_programmatic_pubkey() {
@@ -64,6 +105,19 @@ impl PoolManager {
             position_to_open: None,
             message_queue: Vec::new(),
             position_to_close: None,
+            position_to_exit: None,
+            position_groups: Vec::new(),
+            group_to_open: None,
+            group_to_rebalance: None,
+            batch_to_open: None,
+            batch_results: Vec::new(),
+            range_orders: Vec::new(),
+            range_order_to_place: None,
+            range_order_to_close: None,
+            swap_routes: Vec::new(),
+            bundle_results: Vec::new(),
+            prepared_closes: HashMap::new(),
+            prepared_opens: HashMap::new(),
         }
     }
 
@@ -74,6 +128,10 @@ impl PoolManager {
         TickerState::init();
         PriceChecker::init();
         NewPositionData::init();
+        SidecarClient::init();
+        ProviderRegistry::start_slot_monitor();
+        LookupTable::start_maintenance();
+        NonceAccounts::start_maintenance();
         
         tokio::spawn(async {
             match CoinbaseWebsocket::start().await {
@@ -146,6 +204,10 @@ impl PoolManager {
                 }
 
                 if let Some(position_to_close) = pool_manager.position_to_close.clone() {
+                    if Jito::enabled() && PoolManager::rebalance_with_bundle(&position_to_close).await {
+                        continue;
+                    }
+
                     match position_to_close.close().await {
                         Ok(_) => {
                             let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
@@ -171,6 +233,138 @@ impl PoolManager {
                 }
             }
 
+            {
+                let pool_manager_lock = POOL_MANAGER.get().lock().await;
+                let pool_manager = pool_manager_lock.clone();
+                drop(pool_manager_lock);
+
+                if !pool_manager.active {
+                    continue;
+                }
+
+                if let Some(mut group_to_rebalance) = pool_manager.group_to_rebalance.clone() {
+                    match group_to_rebalance.close(&pool_manager.managed_positions).await {
+                        Ok(_) => {
+                            group_to_rebalance.rebalance_count += 1;
+                            let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                            pool_manager_lock.group_to_rebalance = None;
+                            pool_manager_lock.group_to_open = Some(group_to_rebalance);
+                            drop(pool_manager_lock);
+                            println!("moved position group from rebalance queue to open queue");
+                        },
+                        Err(e) => {
+                            red!("Failed to close position group: {:?}", e);
+                            // Keep the legs that did close out of the retry, their PnL is realized
+                            let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                            pool_manager_lock.group_to_rebalance = Some(group_to_rebalance);
+                        },
+                    }
+                }
+
+                if let Some(mut group_to_open) = pool_manager.group_to_open.clone() {
+                    match group_to_open.open().await {
+                        Ok(_) => {
+                            let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                            pool_manager_lock.group_to_open = None;
+                            pool_manager_lock.position_groups.retain(|group| group.id != group_to_open.id);
+                            pool_manager_lock.position_groups.push(group_to_open);
+                            drop(pool_manager_lock);
+                            PoolManager::fetch_and_update_managed_positions(0).await?;
+                        },
+                        Err(e) => {
+                            red!("Failed to open position group: {:?}", e);
+                            // Keep the legs that did open so the retry starts at the first missing one
+                            let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                            pool_manager_lock.group_to_open = Some(group_to_open);
+                        },
+                    }
+                }
+
+                if let Some(batch_to_open) = pool_manager.batch_to_open.clone() {
+                    let batch_result = match batch_to_open.execute().await {
+                        Ok(batch_result) => Some(batch_result),
+                        Err(e) => {
+                            red!("Failed to open batch: {:?}", e);
+                            None
+                        },
+                    };
+
+                    let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                    pool_manager_lock.batch_to_open = None;
+                    if let Some(batch_result) = batch_result {
+                        pool_manager_lock.batch_results.push(batch_result.clone());
+                        pool_manager_lock.message_queue.push(PoolManagerMessage::new(
+                            MessageType::BatchResult,
+                            Some(json!(batch_result)),
+                            0,
+                        ));
+                    }
+                    drop(pool_manager_lock);
+                    PoolManager::fetch_and_update_managed_positions(0).await?;
+                }
+
+                if let Some(mut range_order) = pool_manager.range_order_to_place.clone() {
+                    if let Err(e) = range_order.place().await {
+                        red!("Failed to place range order: {:?}", e);
+                        range_order.status = RangeOrderStatus::Failed(e.to_string());
+                    }
+
+                    let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                    pool_manager_lock.range_order_to_place = None;
+                    pool_manager_lock.range_orders.push(range_order.clone());
+                    pool_manager_lock.message_queue.push(PoolManagerMessage::new(
+                        MessageType::UpdateRangeOrder,
+                        Some(json!(range_order)),
+                        0,
+                    ));
+                    drop(pool_manager_lock);
+                    PoolManager::fetch_and_update_managed_positions(0).await?;
+                }
+
+                if let Some(mut range_order) = pool_manager.range_order_to_close.clone() {
+                    let status = if range_order.status == RangeOrderStatus::Filled {
+                        RangeOrderStatus::Closed
+                    } else {
+                        RangeOrderStatus::Cancelled
+                    };
+
+                    match range_order.close(&pool_manager.managed_positions, status).await {
+                        Ok(_) => {
+                            let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                            pool_manager_lock.range_order_to_close = None;
+                            if let Some(existing_order) = pool_manager_lock.range_orders.iter_mut().find(|o| o.id == range_order.id) {
+                                *existing_order = range_order.clone();
+                            }
+                            pool_manager_lock.message_queue.push(PoolManagerMessage::new(
+                                MessageType::UpdateRangeOrder,
+                                Some(json!(range_order)),
+                                0,
+                            ));
+                            drop(pool_manager_lock);
+                            PoolManager::fetch_and_update_managed_positions(0).await?;
+                        },
+                        Err(e) => red!("Failed to close range order: {:?}", e),
+                    }
+                }
+
+                if let Some(position_to_exit) = pool_manager.position_to_exit.clone() {
+                    match position_to_exit.execute().await {
+                        Ok(_) => {
+                            let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                            pool_manager_lock.position_to_exit = None;
+                            drop(pool_manager_lock);
+                            println!("removed position from exit queue");
+                            PoolManager::fetch_and_update_managed_positions(0).await?;
+                        },
+                        Err(e) => {
+                            red!("Failed to exit position: {:?}", e);
+                            let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                            pool_manager_lock.position_to_exit = None;
+                        },
+                    }
+                }
+            }
+
             {
                 let pool_manager_lock = POOL_MANAGER.get().lock().await;
                 let pool_manager = pool_manager_lock.clone();
@@ -206,7 +400,7 @@ impl PoolManager {
         blue!("\nChecking for new positions...\n");
     
         // Clone necessary data under a scoped lock
-        let (local_wallet_pubkey, programmatic_wallet_pubkey, mut managed_positions) = {
+        let (local_wallet_pubkey, programmatic_wallet_pubkey, mut managed_positions, mut position_groups) = {
             let pool_manager_lock = POOL_MANAGER.get().lock().await;
             let pool_manager = pool_manager_lock.clone();
 
@@ -216,96 +410,107 @@ impl PoolManager {
                 pool_manager.local_wallet_pubkey,
                 pool_manager.programmatic_wallet_pubkey,
                 pool_manager.managed_positions.clone(),
+                pool_manager.position_groups.clone(),
             )
         };
     
-        // Fetch positions for wallets
-        let mut orca_positions: Vec<OrcaPositionInfo> = vec![];
-    
-        if let Some(pubkey) = local_wallet_pubkey {
-            let local_orca_positions = Orca::get_positions_for_wallet(pubkey.to_string()).await?;
-            orca_positions.extend(local_orca_positions);
-        }
-    
-        if let Some(pubkey) = programmatic_wallet_pubkey {
-            let programmatic_orca_positions = Orca::get_positions_for_wallet(pubkey.to_string()).await?;
-            orca_positions.extend(programmatic_orca_positions);
+        // Fetch positions for wallets from every registered venue. A venue or wallet that fails
+        // is skipped for this refresh rather than holding up the others.
+        let mut fetched_positions: Vec<ManagedPosition> = vec![];
+        let mut failed_fetches: Vec<(PoolType, String)> = vec![];
+
+        for adapter in DexRegistry::all() {
+            for pubkey in [local_wallet_pubkey, programmatic_wallet_pubkey].into_iter().flatten() {
+                match adapter.list_positions(&pubkey.to_string()).await {
+                    Ok(wallet_positions) => fetched_positions.extend(wallet_positions),
+                    Err(e) => {
+                        red!("Failed to fetch {:?} positions for {}: {:?}", adapter.pool_type(), pubkey, e);
+                        failed_fetches.push((adapter.pool_type(), pubkey.to_string()));
+                    }
+                }
+            }
         }
     
         let mut message_queue = vec![];
     
         // Retain and process existing positions
         managed_positions.retain(|position| {
-            if position.pool_type == PoolType::Orca {
-                let exists_in_orca = orca_positions.iter().any(|orca_position| orca_position.address == position.address);
-    
-                if !exists_in_orca {
-                    message_queue.push(PoolManagerMessage {
-                        message_type: MessageType::RemovePosition,
-                        data: Some(json!(position.clone())),
-                        frequency_seconds,
-                    });
-                    false // Remove this position
-                } else {
-                    true // Retain this position
-                }
-            } else {
-                true // Retain non-Orca positions
+            if !DexRegistry::is_supported(&position.pool_type) {
+                return true; // Retain positions from pools that are not tracked yet
             }
-        });
-    
-        // Process new or updated positions
-        for position in orca_positions {
-            if let Some(existing_position) = managed_positions.iter_mut().find(|p| p.address == position.address) {
-                // println!("Position exists in PoolManager");
-                let pool = Orca::get_clp_pool(
-                    RpcMode::conservative(),
-                    &existing_position.token_a.as_ref().unwrap().address,
-                    &existing_position.token_b.as_ref().unwrap().address,
-                    existing_position.tick_spacing,
-                )
-                .await?;
-                existing_position.update_prices(pool, position.clone()).await?;
-    
+
+            // Keep positions whose fetch failed until a refresh can confirm they are gone
+            if failed_fetches.iter().any(|(pool_type, wallet_key)| pool_type == &position.pool_type && wallet_key == &position.wallet_key) {
+                return true;
+            }
+
+            let exists = fetched_positions.iter().any(|fetched_position| fetched_position.address == position.address);
+
+            if !exists {
                 message_queue.push(PoolManagerMessage {
-                    message_type: MessageType::UpdatePosition,
-                    data: Some(json!(existing_position.clone())),
+                    message_type: MessageType::RemovePosition,
+                    data: Some(json!(position.clone())),
                     frequency_seconds,
                 });
+            }
+
+            exists
+        });
+    
+        // Process new or updated positions
+        for position in fetched_positions {
+            let managed_position = if let Some(existing_position) = managed_positions.iter_mut().find(|p| p.address == position.address) {
+                existing_position.refresh_from(position);
+                existing_position.clone()
             } else {
                 // New position
-                let tokens_and_tick = Orca::get_tokens_and_tick(RpcMode::conservative(), &position.whirlpool_address).await?;
-                let pool = Orca::get_clp_pool(
-                    RpcMode::conservative(),
-                    &tokens_and_tick.token_a,
-                    &tokens_and_tick.token_b,
-                    tokens_and_tick.tick_spacing,
-                )
-                .await?;
-
-                let created_at = Rpc::get_account_creation_date(
+                let mut managed_position = position;
+                managed_position.created_at = Rpc::get_account_creation_date(
                     RpcMode::conservative(), 
-                    &position.address, 
+                    &managed_position.address, 
                     Some(10000)
                 ).await.unwrap_or(Utc::now());
-    
-                let mut managed_position = ManagedPosition::from_orca_position_info(position.clone(), created_at);
-                managed_position = managed_position.update_prices(pool, position.clone()).await?;
+                managed_position.update_accounting();
 
                 managed_positions.push(managed_position.clone());
+
+                managed_position
+            };
+
+            message_queue.push(PoolManagerMessage {
+                message_type: MessageType::UpdatePosition,
+                data: Some(json!(managed_position)),
+                frequency_seconds,
+            });
+        }
     
-                message_queue.push(PoolManagerMessage {
-                    message_type: MessageType::UpdatePosition,
-                    data: Some(json!(managed_position.clone())),
-                    frequency_seconds,
-                });
-            }
+        for group in position_groups.iter_mut() {
+            group.update_aggregates(&managed_positions);
+
+            message_queue.push(PoolManagerMessage {
+                message_type: MessageType::UpdateGroup,
+                data: Some(json!(group.clone())),
+                frequency_seconds,
+            });
         }
     
         // Update the pool manager state and message queue under a scoped lock
         {
             let mut pool_manager = POOL_MANAGER.get().lock().await;
             pool_manager.managed_positions = managed_positions;
+            for group in position_groups {
+                if let Some(existing_group) = pool_manager.position_groups.iter_mut().find(|g| g.id == group.id) {
+                    existing_group.balance_token_a = group.balance_token_a;
+                    existing_group.balance_token_b = group.balance_token_b;
+                    existing_group.balance_total_usd = group.balance_total_usd;
+                    existing_group.yield_total_usd = group.yield_total_usd;
+                    existing_group.range_lower = group.range_lower;
+                    existing_group.range_upper = group.range_upper;
+                    existing_group.cost_basis_usd = group.cost_basis_usd;
+                    existing_group.pnl_usd = group.pnl_usd;
+                    existing_group.updated_at = group.updated_at;
+                }
+            }
             pool_manager.message_queue.extend(message_queue);
             pool_manager.updated = Utc::now();
 
@@ -322,11 +527,14 @@ impl PoolManager {
         let pool_manager_lock = POOL_MANAGER.get().lock().await;
         let pool_manager = pool_manager_lock.clone();
         drop(pool_manager_lock);
-        if pool_manager.position_to_close.is_some() || pool_manager.position_to_open.is_some() {
-            return true;
-        } else {
-            return false
-        }
+        pool_manager.position_to_close.is_some()
+            || pool_manager.position_to_open.is_some()
+            || pool_manager.position_to_exit.is_some()
+            || pool_manager.group_to_open.is_some()
+            || pool_manager.group_to_rebalance.is_some()
+            || pool_manager.batch_to_open.is_some()
+            || pool_manager.range_order_to_place.is_some()
+            || pool_manager.range_order_to_close.is_some()
     }
     
     pub async fn add_to_message_queue(message: PoolManagerMessage) -> anyhow::Result<()> {
@@ -356,30 +564,28 @@ impl PoolManager {
         Ok(pool_manager.managed_positions)
     }
 
+    pub async fn get_managed_position(address: &str) -> anyhow::Result<ManagedPosition> {
+        let managed_positions = PoolManager::get_managed_positions().await?;
+
+        managed_positions
+            .into_iter()
+            .find(|p| p.address == address)
+            .ok_or_else(|| anyhow::anyhow!("Managed position not found: {}", address))
+    }
+
     pub async fn get_positions_for_wallet(wallet_key: &str) -> anyhow::Result<Vec<ManagedPosition>> {
-        let orca_positions = Orca::get_positions_for_wallet(wallet_key.to_string()).await?;
-        
         let mut managed_positions = vec![];
         
-        for position in orca_positions {
-            let tokens_and_tick = Orca::get_tokens_and_tick(RpcMode::conservative(), &position.whirlpool_address).await?;
-            let pool = Orca::get_clp_pool(
-                RpcMode::conservative(),
-                &tokens_and_tick.token_a,
-                &tokens_and_tick.token_b,
-                tokens_and_tick.tick_spacing,
-            )
-            .await?;
-
-            let created_at = Rpc::get_account_creation_date(
-                RpcMode::conservative(), 
-                &position.address, 
-                Some(10000)
-            ).await.unwrap_or(Utc::now());
+        for adapter in DexRegistry::all() {
+            for mut managed_position in adapter.list_positions(wallet_key).await? {
+                managed_position.created_at = Rpc::get_account_creation_date(
+                    RpcMode::conservative(), 
+                    &managed_position.address, 
+                    Some(10000)
+                ).await.unwrap_or(Utc::now());
 
-            let mut managed_position = ManagedPosition::from_orca_position_info(position.clone(), created_at);
-            managed_position = managed_position.update_prices(pool, position.clone()).await?;
-            managed_positions.push(managed_position);
+                managed_positions.push(managed_position);
+            }
         }
 
         Ok(managed_positions)
@@ -411,8 +617,52 @@ impl PoolManager {
             return Ok(());
         }
         let managed_positions = PoolManager::get_managed_positions().await?;
+        let position_groups = PoolManager::get_position_groups().await?;
+        let mut range_orders = PoolManager::get_range_orders().await?;
+
+        for range_order in range_orders.iter_mut().filter(|order| order.is_active()) {
+            if range_order.update_status(&managed_positions).await? {
+                PoolManagerMessage::new(MessageType::UpdateRangeOrder, Some(json!(range_order.clone())), 1).add_to_queue().await?;
+
+                let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                if let Some(existing_order) = pool_manager_lock.range_orders.iter_mut().find(|o| o.id == range_order.id) {
+                    existing_order.status = range_order.status.clone();
+                    existing_order.fill_ratio = range_order.fill_ratio;
+                    existing_order.updated_at = range_order.updated_at;
+                }
+                drop(pool_manager_lock);
+            }
+
+            if range_order.status == RangeOrderStatus::Filled {
+                println!("Range order filled, closing: {}", range_order.id);
+                PoolManager::queue_range_order_close(range_order.clone()).await?;
+                return Ok(());
+            }
+        }
+
+        for group in position_groups.iter() {
+            if group.strategy.auto_rebalance && group.should_rebalance(&managed_positions).await? {
+                println!("Rebalancing position group: {}", group.name);
+                PoolManager::queue_group_rebalance(group.clone()).await?;
+                return Ok(());
+            }
+        }
         
+        if NonceAccounts::enabled() {
+            PoolManager::prune_prepared_closes(&managed_positions).await;
+        }
+
         for mut position in managed_positions {
+            // Grouped positions are rebalanced together with the rest of their ladder
+            if position_groups.iter().any(|group| group.contains(&position.address)) {
+                continue;
+            }
+
+            // Range order positions are closed once filled instead of being rebalanced
+            if range_orders.iter().any(|order| order.position_address.as_deref() == Some(position.address.as_str())) {
+                continue;
+            }
+
             if Wallet::is_programmatic_wallet(&position.wallet_key.clone())? {
                 if position.should_rebalance().await? {
                     println!("Closing position for wallet: {}", position.wallet_key);
@@ -428,6 +678,8 @@ impl PoolManager {
 
                     // println!("Rebalancing position for wallet: {}", position.wallet_key);
                     // position.rebalance().await?;
+                } else if NonceAccounts::enabled() && position.near_range_edge() {
+                    PoolManager::prepare_close_ahead(&position).await;
                 }
             }
         }
@@ -437,7 +689,7 @@ impl PoolManager {
     pub async fn unset_local_wallet_pubkey() -> anyhow::Result<Vec<ManagedPosition>> {
         let mut pool_manager = POOL_MANAGER.get().lock().await;
 
-        let wallet_key = pool_manager.local_wallet_pubkey.clone().ok_or_else(|| anyhow::anyhow!("Local wallet pubkey not set"))?.to_string();
+        let wallet_key = pool_manager.local_wallet_pubkey.ok_or_else(|| anyhow::anyhow!("Local wallet pubkey not set"))?.to_string();
         pool_manager.local_wallet_pubkey = None;
         let positions_to_remove = pool_manager.managed_positions.iter().filter(|p| p.wallet_key == wallet_key).cloned().collect();
 
@@ -450,6 +702,9 @@ impl PoolManager {
 
     pub async fn open_position(new_position: NewPosition) -> anyhow::Result<OrcaOpenPositionInstruction> {
         blue!("Opening position with data: {:?}", new_position);
+        if new_position.pool_type != PoolType::Orca {
+            return Err(anyhow::anyhow!("Open instructions for local wallets are only available for Orca pools"));
+        }
 
         let open_position_instruction = Orca::get_open_position_instructions(new_position).await?;
 
@@ -469,6 +724,14 @@ impl PoolManager {
     }
 
     pub async fn queue_programmatic_close(managed_position: ManagedPosition) -> anyhow::Result<()> {
+        if !Wallet::is_programmatic_wallet(&managed_position.wallet_key)? {
+            return Err(anyhow::anyhow!(
+                "Position {} is owned by {}, which the pool manager can't sign for",
+                managed_position.address,
+                managed_position.wallet_key,
+            ));
+        }
+
         // println!("closing prog position ");
         
         // managed_position.close().await?;
@@ -483,17 +746,433 @@ impl PoolManager {
         Ok(())
     }
 
-    pub async fn close_position(managed_position: ManagedPosition) -> anyhow::Result<OrcaClosePositionInstruction> {
-        blue!("Closing position with data: {:?}", managed_position);
+    pub async fn get_position_groups() -> anyhow::Result<Vec<PositionGroup>> {
+        let pool_manager_lock = POOL_MANAGER.get().lock().await;
+        let position_groups = pool_manager_lock.position_groups.clone();
+        drop(pool_manager_lock);
+
+        Ok(position_groups)
+    }
+
+    pub async fn queue_group_open(position_group: PositionGroup) -> anyhow::Result<()> {
+        blue!("queuing new position group: {}", position_group.name);
+        let mut pool_manager = POOL_MANAGER.get().lock().await;
+
+        pool_manager.group_to_open = Some(position_group);
+
+        drop(pool_manager);
+
+        Ok(())
+    }
+
+    pub async fn queue_group_rebalance(position_group: PositionGroup) -> anyhow::Result<()> {
+        blue!("adding position group to rebalance queue: {}", position_group.name);
+        let mut pool_manager = POOL_MANAGER.get().lock().await;
+
+        pool_manager.group_to_rebalance = Some(position_group);
+
+        drop(pool_manager);
+
+        Ok(())
+    }
+
+    pub async fn queue_batch_open(batch_open: BatchOpen) -> anyhow::Result<()> {
+        batch_open.validate()?;
+        blue!("queuing batch open of {} legs", batch_open.legs.len());
+        let mut pool_manager = POOL_MANAGER.get().lock().await;
+
+        if pool_manager.batch_to_open.is_some() {
+            return Err(anyhow::anyhow!("A batch open is already in progress"));
+        }
+        pool_manager.batch_to_open = Some(batch_open);
+
+        drop(pool_manager);
 
-        let close_position_instruction = Orca::get_close_position_instructions(
-            RpcMode::fast(),
-            managed_position.address.clone(),
-            managed_position.wallet_key.clone(),
-            None
+        Ok(())
+    }
+
+    pub async fn get_batch_results() -> anyhow::Result<Vec<BatchOpenResult>> {
+        let pool_manager_lock = POOL_MANAGER.get().lock().await;
+        let batch_results = pool_manager_lock.batch_results.clone();
+        drop(pool_manager_lock);
+
+        Ok(batch_results)
+    }
+
+    pub async fn get_swap_routes() -> anyhow::Result<Vec<SwapRouteRecord>> {
+        let pool_manager_lock = POOL_MANAGER.get().lock().await;
+        let swap_routes = pool_manager_lock.swap_routes.clone();
+        drop(pool_manager_lock);
+
+        Ok(swap_routes)
+    }
+
+    pub async fn get_bundle_results() -> anyhow::Result<Vec<BundleResult>> {
+        let pool_manager_lock = POOL_MANAGER.get().lock().await;
+        let bundle_results = pool_manager_lock.bundle_results.clone();
+        drop(pool_manager_lock);
+
+        Ok(bundle_results)
+    }
+
+    /// Rebalances the queued position through a Jito bundle. Returns false when the bundle could
+    /// not be sent or did not land, so the caller falls back to sending each step normally. A
+    /// bundle whose outcome is unknown is never followed by a normal rebalance.
+    async fn rebalance_with_bundle(position_to_close: &ManagedPosition) -> bool {
+        match position_to_close.clone().should_rebalance().await {
+            Ok(true) => (),
+            // The normal close re-checks the range and clears the queue
+            _ => return false,
+        }
+
+        // Errors only come from before the bundle was accepted, so nothing of it can land
+        let bundle_result = match RebalanceBundle::execute(position_to_close).await {
+            Ok(bundle_result) => bundle_result,
+            Err(e) => {
+                red!("Failed to send rebalance bundle, sending normally: {:?}", e);
+                return false;
+            }
+        };
+
+        let landed = bundle_result.landed();
+        let can_resend = bundle_result.can_resend();
+        let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+        pool_manager_lock.bundle_results.push(bundle_result);
+        let overflow = pool_manager_lock.bundle_results.len().saturating_sub(MAX_BUNDLE_RESULTS);
+        pool_manager_lock.bundle_results.drain(..overflow);
+        // A bundle that may still land must not be followed by a normal close. The refresh below
+        // shows whether the position is still open, and it is queued again if it is.
+        if !can_resend {
+            pool_manager_lock.position_to_close = None;
+        }
+        drop(pool_manager_lock);
+
+        if can_resend {
+            yellow!("Rebalance bundle for {} did not land, sending normally", position_to_close.address);
+            return false;
+        }
+
+        if !landed {
+            red!("Rebalance bundle for {} was sent but its outcome is unknown, not sending it again", position_to_close.address);
+        }
+
+        if let Err(e) = PoolManager::fetch_and_update_managed_positions(0).await {
+            red!("Failed to update managed positions: {:?}", e);
+        }
+
+        true
+    }
+
+    /// Signs a close for a position nearing its range edge, and the open that follows it, so they
+    /// can go out as soon as the rebalance triggers. Ones older than `PREPARED_CLOSE_MAX_AGE_SECONDS`
+    /// are rebuilt.
+    async fn prepare_close_ahead(position: &ManagedPosition) {
+        let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+        let close_ready = match pool_manager_lock.prepared_closes.get(&position.address) {
+            // One that was broadcast may still land, so it is kept rather than replaced
+            Some(prepared_close) if prepared_close.broadcast || Utc::now().signed_duration_since(prepared_close.prepared_at).num_seconds() < PREPARED_CLOSE_MAX_AGE_SECONDS => true,
+            Some(prepared_close) => {
+                prepared_close.discard();
+                pool_manager_lock.prepared_closes.remove(&position.address);
+                false
+            }
+            None => false,
+        };
+        let open_ready = match pool_manager_lock.prepared_opens.get(&position.pool_address) {
+            Some(prepared_open) if prepared_open.transaction.broadcast || Utc::now().signed_duration_since(prepared_open.transaction.prepared_at).num_seconds() < PREPARED_CLOSE_MAX_AGE_SECONDS => true,
+            Some(prepared_open) => {
+                prepared_open.transaction.discard();
+                pool_manager_lock.prepared_opens.remove(&position.pool_address);
+                false
+            }
+            None => false,
+        };
+        drop(pool_manager_lock);
+
+        if !close_ready {
+            match position.prepare_close().await {
+                Ok(prepared_close) => {
+                    let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                    pool_manager_lock.prepared_closes.insert(position.address.clone(), prepared_close);
+                    drop(pool_manager_lock);
+                }
+                Err(e) => yellow!("Failed to prepare close for {}: {:?}", position.address, e),
+            }
+        }
+
+        if !open_ready {
+            match NewProgrammaticPosition::prepare_open(position).await {
+                Ok(prepared_open) => {
+                    let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+                    pool_manager_lock.prepared_opens.insert(position.pool_address.clone(), prepared_open);
+                    drop(pool_manager_lock);
+                }
+                Err(e) => yellow!("Failed to prepare open for {}: {:?}", position.pool_address, e),
+            }
+        }
+    }
+
+    /// Drops prepared closes for positions that are no longer managed and prepared opens nothing
+    /// has used for `PREPARED_OPEN_MAX_AGE_SECONDS`, freeing their nonces
+    async fn prune_prepared_closes(managed_positions: &[ManagedPosition]) {
+        let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+        pool_manager_lock.prepared_closes.retain(|address, prepared_close| {
+            let managed = managed_positions.iter().any(|position| &position.address == address);
+            if !managed {
+                prepared_close.discard();
+            }
+            managed
+        });
+        pool_manager_lock.prepared_opens.retain(|_, prepared_open| {
+            let fresh = prepared_open.transaction.broadcast
+                || Utc::now().signed_duration_since(prepared_open.transaction.prepared_at).num_seconds() < PREPARED_OPEN_MAX_AGE_SECONDS;
+            if !fresh {
+                prepared_open.transaction.discard();
+            }
+            fresh
+        });
+        drop(pool_manager_lock);
+    }
+
+    pub async fn take_prepared_close(address: &str) -> Option<PreparedTransaction> {
+        let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+        let prepared_close = pool_manager_lock.prepared_closes.remove(address);
+        drop(pool_manager_lock);
+
+        prepared_close
+    }
+
+    /// Puts back a prepared close that was sent but may still land, so the next close resends it
+    pub async fn restore_prepared_close(address: &str, prepared_close: PreparedTransaction) {
+        let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+        pool_manager_lock.prepared_closes.insert(address.to_string(), prepared_close);
+        drop(pool_manager_lock);
+    }
+
+    pub async fn take_prepared_open(pool_address: &str) -> Option<PreparedOpen> {
+        let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+        let prepared_open = pool_manager_lock.prepared_opens.remove(pool_address);
+        drop(pool_manager_lock);
+
+        prepared_open
+    }
+
+    /// Puts back a prepared open that was sent but may still land, so the next open resends it
+    pub async fn restore_prepared_open(pool_address: &str, prepared_open: PreparedOpen) {
+        let mut pool_manager_lock = POOL_MANAGER.get().lock().await;
+        pool_manager_lock.prepared_opens.insert(pool_address.to_string(), prepared_open);
+        drop(pool_manager_lock);
+    }
+
+    pub async fn get_range_orders() -> anyhow::Result<Vec<RangeOrder>> {
+        let pool_manager_lock = POOL_MANAGER.get().lock().await;
+        let range_orders = pool_manager_lock.range_orders.clone();
+        drop(pool_manager_lock);
+
+        Ok(range_orders)
+    }
+
+    pub async fn queue_range_order(range_order: RangeOrder) -> anyhow::Result<()> {
+        blue!("queuing range order: {}", range_order.id);
+        let mut pool_manager = POOL_MANAGER.get().lock().await;
+
+        pool_manager.range_order_to_place = Some(range_order);
+
+        drop(pool_manager);
+
+        Ok(())
+    }
+
+    pub async fn queue_range_order_close(range_order: RangeOrder) -> anyhow::Result<()> {
+        blue!("adding range order to close queue: {}", range_order.id);
+        let mut pool_manager = POOL_MANAGER.get().lock().await;
+
+        pool_manager.range_order_to_close = Some(range_order);
+
+        drop(pool_manager);
+
+        Ok(())
+    }
+
+    pub async fn cancel_range_order(id: &str) -> anyhow::Result<()> {
+        let range_order = PoolManager::get_range_orders()
+            .await?
+            .into_iter()
+            .find(|order| order.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Range order not found: {}", id))?;
+
+        if !range_order.is_active() {
+            return Err(anyhow::anyhow!("Range order is already {:?}", range_order.status));
+        }
+
+        PoolManager::queue_range_order_close(range_order).await
+    }
+
+    pub async fn queue_programmatic_exit(position_exit: PositionExit) -> anyhow::Result<()> {
+        blue!("adding to exit queue");
+        let mut pool_manager = POOL_MANAGER.get().lock().await;
+
+        pool_manager.position_to_exit = Some(position_exit);
+
+        drop(pool_manager);
+
+        Ok(())
+    }
+
+    /// Exits a position into a single mint. Programmatic positions are queued and executed by the
+    /// main loop, local wallet positions get the unsigned close and swap instructions back.
+    pub async fn exit_position(address: &str, target_mint: String) -> anyhow::Result<Option<ExitToTokenInstructions>> {
+        let managed_position = PoolManager::get_managed_position(address).await?;
+        let is_programmatic = Wallet::is_programmatic_wallet(&managed_position.wallet_key)?;
+        let position_exit = PositionExit::new(managed_position, target_mint)?;
+
+        if is_programmatic {
+            PoolManager::queue_programmatic_exit(position_exit).await?;
+            Ok(None)
+        } else {
+            let exit_instructions = position_exit.get_instructions().await?;
+            Ok(Some(exit_instructions))
+        }
+    }
+
+    /// Adds liquidity to an Orca position inside its existing range. Programmatic positions are
+    /// executed straight away, local wallet positions get the unsigned instructions back.
+    pub async fn increase_liquidity(address: &str, amount_a: u64, amount_b: u64, slippage: u16) -> anyhow::Result<Option<OrcaLiquidityInstructions>> {
+        let managed_position = PoolManager::get_managed_position(address).await?;
+        if managed_position.pool_type != PoolType::Orca {
+            return Err(anyhow::anyhow!("Liquidity changes are only available for Orca positions"));
+        }
+
+        let liquidity_instructions = Orca::get_increase_liquidity_instructions(
+            &managed_position.wallet_key,
+            address,
+            amount_a,
+            amount_b,
+            slippage,
+        ).await?;
+
+        PoolManager::change_liquidity(managed_position, LiquidityChangeKind::Increase, liquidity_instructions).await
+    }
+
+    /// Withdraws `fraction` of an Orca position's liquidity, leaving it open in the same range
+    pub async fn decrease_liquidity(address: &str, fraction: f64, slippage: u16) -> anyhow::Result<Option<OrcaLiquidityInstructions>> {
+        let managed_position = PoolManager::get_managed_position(address).await?;
+        if managed_position.pool_type != PoolType::Orca {
+            return Err(anyhow::anyhow!("Liquidity changes are only available for Orca positions"));
+        }
+
+        let liquidity_instructions = Orca::get_decrease_liquidity_instructions(
+            &managed_position.wallet_key,
+            address,
+            fraction,
+            slippage,
+        ).await?;
+
+        PoolManager::change_liquidity(managed_position, LiquidityChangeKind::Decrease, liquidity_instructions).await
+    }
+
+    async fn change_liquidity(
+        managed_position: ManagedPosition,
+        kind: LiquidityChangeKind,
+        liquidity_instructions: OrcaLiquidityInstructions,
+    ) -> anyhow::Result<Option<OrcaLiquidityInstructions>> {
+        // Local wallet changes are picked up from the position's liquidity on the next refresh
+        if !Wallet::is_programmatic_wallet(&managed_position.wallet_key)? {
+            return Ok(Some(liquidity_instructions));
+        }
+
+        if PoolManager::is_rebalancing().await {
+            return Err(anyhow::anyhow!("Pool manager is busy, try the liquidity change again shortly"));
+        }
+
+        magenta!("{:?} liquidity of position {} by {}", kind, managed_position.address, liquidity_instructions.liquidity_delta);
+        let signature = ProgrammaticTransaction::perform_for_position(
+            liquidity_instructions.instructions,
+            liquidity_instructions.additional_signers,
+            Some(PriorityLevel::High),
+            TransactionKind::Liquidity,
+            Some(managed_position.address.clone()),
+        ).await?;
+        green!("Changed liquidity of position {}: {}", managed_position.address, signature);
+
+        let token_a = managed_position.token_a.clone().ok_or_else(|| anyhow::anyhow!("Token A not found in managed position"))?;
+        let token_b = managed_position.token_b.clone().ok_or_else(|| anyhow::anyhow!("Token B not found in managed position"))?;
+        let value_usd = managed_position.amounts_value_usd(
+            liquidity_instructions.estimated_amount_a as f64 / 10f64.powi(token_a.decimals as i32),
+            liquidity_instructions.estimated_amount_b as f64 / 10f64.powi(token_b.decimals as i32),
+        );
+
+        let mut pool_manager = POOL_MANAGER.get().lock().await;
+        if let Some(position) = pool_manager.managed_positions.iter_mut().find(|p| p.address == managed_position.address) {
+            position.record_sent_liquidity_change(
+                kind,
+                liquidity_instructions.liquidity_delta,
+                liquidity_instructions.liquidity_before,
+                value_usd,
+                signature.to_string(),
+            );
+        }
+
+        drop(pool_manager);
+
+        Ok(None)
+    }
+
+    /// Closes a position on any venue. Programmatic positions are queued and closed by the main
+    /// loop, positions owned by another wallet get the instructions and an unsigned transaction back.
+    pub async fn close_position(address: &str) -> anyhow::Result<Option<UnsignedTransaction>> {
+        let managed_position = PoolManager::get_managed_position(address).await?;
+
+        if Wallet::is_programmatic_wallet(&managed_position.wallet_key)? {
+            PoolManager::queue_programmatic_close(managed_position).await?;
+            return Ok(None);
+        }
+
+        blue!("Building close instructions for {} owned by {}", managed_position.address, managed_position.wallet_key);
+        let close_instructions = DexRegistry::get(&managed_position.pool_type)?.close_position(&managed_position).await?;
+        let unsigned_transaction = UnsignedTransaction::new(
+            &managed_position.wallet_key,
+            close_instructions.instructions,
+            close_instructions.additional_signers,
+            TransactionKind::Close,
+            Some(managed_position.address.clone()),
         ).await?;
 
-        Ok(close_position_instruction)
+        Ok(Some(unsigned_transaction))
+    }
+
+    /// Collects fees and rewards without touching liquidity. Programmatic positions are collected
+    /// straight away, other wallets get the instructions and an unsigned transaction back.
+    pub async fn collect_fees(address: &str) -> anyhow::Result<Option<UnsignedTransaction>> {
+        let managed_position = PoolManager::get_managed_position(address).await?;
+        let collect_instructions = DexRegistry::get(&managed_position.pool_type)?.collect_fees(&managed_position).await?;
+
+        if !Wallet::is_programmatic_wallet(&managed_position.wallet_key)? {
+            let unsigned_transaction = UnsignedTransaction::new(
+                &managed_position.wallet_key,
+                collect_instructions.instructions,
+                collect_instructions.additional_signers,
+                TransactionKind::CollectFees,
+                Some(managed_position.address.clone()),
+            ).await?;
+
+            return Ok(Some(unsigned_transaction));
+        }
+
+        if PoolManager::is_rebalancing().await {
+            return Err(anyhow::anyhow!("Pool manager is busy, try collecting fees again shortly"));
+        }
+
+        let signature = ProgrammaticTransaction::perform_for_position(
+            collect_instructions.instructions,
+            collect_instructions.additional_signers,
+            Some(PriorityLevel::High),
+            TransactionKind::CollectFees,
+            Some(managed_position.address.clone()),
+        ).await?;
+        green!("Collected fees for position {}: {}", managed_position.address, signature);
+
+        Ok(None)
     }
 
     pub async fn swap_tokens(token_swap: TokenSwap) -> anyhow::Result<OrcaSwapInstructions> {
@@ -503,6 +1182,14 @@ impl PoolManager {
 
         Ok(swap_instructions)
     }
+
+    pub async fn zap_in(zap_in: ZapIn) -> anyhow::Result<ZapInInstructions> {
+        blue!("Zapping in with data: {:?}", zap_in);
+
+        let zap_in_instructions = zap_in.get_instructions().await?;
+
+        Ok(zap_in_instructions)
+    }
     
 }
 
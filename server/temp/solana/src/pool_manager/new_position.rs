@@ -1,22 +1,35 @@
-use std::{sync::Arc, time::Instant};
+use std::sync::Arc;
 
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use figlet_rs::FIGfont;
 use helius::types::PriorityLevel;
-use orca_pools_ipc_types::response::open_position_instruction::OrcaOpenPositionInstruction;
 use serde::{Deserialize, Serialize};
-use solana_client::rpc_response::RpcSimulateTransactionResult;
-use solana_sdk::{instruction::Instruction, signer::Signer, hash::Hash};
+use solana_sdk::{signature::Signature, signer::Signer};
 use kebtech_utils::*;
 use state::InitCell;
 use tokio::sync::Mutex;
 
-use crate::{pool_manager::{new_position, orca::token_swap::TokenSwap, PoolManager}, rpc::{Rpc, RpcMode}, token::Token, wallet::Wallet};
+use crate::{pool_manager::{orca::token_swap::TokenSwap, PoolManager}, rpc::{ComputeUnitLimit, RpcMode}, token::Token, wallet::{prepared_transaction::{PreparedSendError, PreparedTransaction}, programmatic_transaction::{ProgrammaticTransaction, TransactionKind}, transaction_policy::{ComputePolicy, TransactionPolicy}, Wallet}};
 
-use super::{orca::Orca, position_manager::managed_position::{ManagedPosition, PoolType}, POOL_MANAGER};
+use super::{dex_adapter::{DexRegistry, OpenPositionRequest}, position_manager::managed_position::{ManagedPosition, PoolType}, swap_router::SwapRouter, POOL_MANAGER};
 
 pub static NEW_POSITION_DATA: InitCell<Arc<Mutex<NewPositionData>>> = InitCell::new();
 
+/// SOL kept back for fees when balancing and opening
+const SOL_FEE_RESERVE: f64 = 0.1;
+/// Prepared opens are sized before the close and swap they depend on, so they keep more back
+/// than the 7.5% a regular open does
+const PREPARED_OPEN_BUFFER_PERCENT: f64 = 0.15;
+/// How far the pool price may move from a prepared open's before its range is off centre
+const PREPARED_OPEN_MAX_DRIFT: f64 = 0.002;
+
+/// An open signed ahead of a rebalance against a durable nonce, for the range centred on `price`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreparedOpen {
+    pub transaction: PreparedTransaction,
+    pub price: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewProgrammaticPosition {
     pub pool_type: PoolType,
@@ -25,8 +38,8 @@ pub struct NewProgrammaticPosition {
     pub token_mint_b: String,
 }
 
-impl NewProgrammaticPosition {
-    pub fn default() -> Self {
+impl Default for NewProgrammaticPosition {
+    fn default() -> Self {
         Self {
             pool_type: PoolType::Orca,
             pool_address: "Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE".to_string(),
@@ -34,41 +47,47 @@ impl NewProgrammaticPosition {
             token_mint_b: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
         }
     }
+}
 
+impl NewProgrammaticPosition {
     pub async fn open(&self) -> anyhow::Result<()> {
         // let mut new_position_data_lock = NEW_POSITION_DATA.get().lock().await;
         magenta!("opening new position: {:?}", self);
         
-        let (_token_amount_a, token_amount_b, range_lower, range_upper) = self.balance_tokens().await?;
+        let (token_amount_a, token_amount_b, range_lower, range_upper) = self.balance_tokens().await?;
         println!("finished balancing tokens");
         let buffer_percent = 0.075;
         let token_amount_b_with_buffer = token_amount_b.saturating_sub((token_amount_b as f64 * buffer_percent) as u64);
+        let token_amount_a_with_buffer = token_amount_a.saturating_sub((token_amount_a as f64 * buffer_percent) as u64);
+
+        if self.send_prepared_open((range_lower + range_upper) / 2.0).await?.is_none() {
+            let start = Utc::now();
+            blue!("getting open position instructions");
+            let open_position_request = OpenPositionRequest {
+                wallet_key: Wallet::get_programmatic_pubkey()?.to_string(),
+                pool_address: self.pool_address.clone(),
+                token_amount_a: token_amount_a_with_buffer,
+                token_amount_b: token_amount_b_with_buffer,
+                slippage: 500,
+                range_lower,
+                range_upper,
+            };
+            let open_position_instructions = DexRegistry::get(&self.pool_type)?.open_position(&open_position_request).await?;
 
-        let start = Utc::now();
-        blue!("getting open position instructions");
-        let open_position_instructions = Orca::get_prog_open_position_instructions(
-            &self.pool_address,
-            token_amount_b_with_buffer,
-            500,
-            range_lower,
-            range_upper
-        ).await?;
-
-        green!("got open position instructions in {:?}ms", start.signed_duration_since(Utc::now()).num_milliseconds());
-
-        magenta!("open position instructions: {:?}", open_position_instructions);
+            green!("got open position instructions in {:?}ms", start.signed_duration_since(Utc::now()).num_milliseconds());
 
-        yellow!("token amount b with buffer: {}", token_amount_b_with_buffer);
+            yellow!("token amount a with buffer: {} - token amount b with buffer: {}", token_amount_a_with_buffer, token_amount_b_with_buffer);
 
-        let start = Utc::now();
-        blue!("performing open position transaction");
-        let _signature = Orca::perform_orca_transaction(
-            open_position_instructions.instructions,
-            open_position_instructions.additional_signers,
-            Some(PriorityLevel::High),
-        ).await?;
-
-        green!("performed open position transaction in {:?}ms", start.signed_duration_since(Utc::now()).num_milliseconds());
+            let start = Utc::now();
+            blue!("performing open position transaction");
+            let _signature = ProgrammaticTransaction::perform(
+                open_position_instructions.instructions,
+                open_position_instructions.additional_signers,
+                Some(PriorityLevel::High),
+                TransactionKind::Open,
+            ).await?;
+            green!("performed open position transaction in {:?}ms", start.signed_duration_since(Utc::now()).num_milliseconds());
+        }
 
         let font = FIGfont::standard().unwrap();
         let banner = font.convert("Opened Position").unwrap();
@@ -79,6 +98,89 @@ impl NewProgrammaticPosition {
         Ok(())
     }
 
+    /// Sends the pool's prepared open if the price is still where its range was centred. `None`
+    /// when there is none to use and the open has to be built now.
+    async fn send_prepared_open(&self, price: f64) -> anyhow::Result<Option<Signature>> {
+        let Some(prepared_open) = PoolManager::take_prepared_open(&self.pool_address).await else {
+            return Ok(None);
+        };
+
+        // One that was broadcast may still land, so it is resent whatever the price
+        if !prepared_open.transaction.broadcast && (prepared_open.price - price).abs() / price > PREPARED_OPEN_MAX_DRIFT {
+            yellow!("Price moved from {} to {} since the open for {} was prepared, building a new one", prepared_open.price, price, self.pool_address);
+            prepared_open.transaction.discard();
+            return Ok(None);
+        }
+
+        match prepared_open.transaction.send().await {
+            Ok(signature) => {
+                green!("Opened position in {} with prepared transaction {}", self.pool_address, signature);
+                Ok(Some(signature))
+            }
+            Err(PreparedSendError::Dropped(e)) => {
+                yellow!("Prepared open for {} was not used, building a new one: {:?}", self.pool_address, e);
+                Ok(None)
+            }
+            Err(PreparedSendError::Unresolved(transaction, e)) => {
+                PoolManager::restore_prepared_open(&self.pool_address, PreparedOpen { transaction: *transaction, price: prepared_open.price }).await;
+                Err(anyhow::anyhow!("Prepared open for {} is unresolved: {:?}", self.pool_address, e))
+            }
+        }
+    }
+
+    /// Signs the open a rebalance of `position` would send, sized from what the wallet will hold
+    /// once the position is closed and its tokens are balanced 50/50. It can't simulate before
+    /// then, so its compute limit is fixed.
+    pub async fn prepare_open(position: &ManagedPosition) -> anyhow::Result<PreparedOpen> {
+        let new_position = NewProgrammaticPosition::from_managed_position(position)?;
+        let adapter = DexRegistry::get(&position.pool_type)?;
+        let token_a = position.token_a.clone().ok_or_else(|| anyhow::anyhow!("Token A not found in managed position"))?;
+        let token_b = position.token_b.clone().ok_or_else(|| anyhow::anyhow!("Token B not found in managed position"))?;
+        let scale_a = 10f64.powi(token_a.decimals as i32);
+        let scale_b = 10f64.powi(token_b.decimals as i32);
+
+        let mut amount_a = NewPositionData::fetch_balance_a_amount(&new_position).await?
+            + ((position.balance_token_a + position.yield_token_a) * scale_a) as u64;
+        let amount_b = NewPositionData::fetch_balance_b_amount(&new_position).await?
+            + ((position.balance_token_b + position.yield_token_b) * scale_b) as u64;
+        if token_a.address == Token::solana().address {
+            amount_a = amount_a.saturating_sub((SOL_FEE_RESERVE * scale_a) as u64);
+        }
+
+        let price = adapter.pool_price(&position.pool_address).await?;
+        let half_value = (amount_a as f64 / scale_a * price + amount_b as f64 / scale_b) / 2.0;
+        if half_value <= 0.0 {
+            return Err(anyhow::anyhow!("Nothing to reopen position {} with", position.address));
+        }
+
+        let (range_lower, range_upper) = NewPositionData::get_ranges(price);
+        let open_position_request = OpenPositionRequest {
+            wallet_key: Wallet::get_programmatic_pubkey()?.to_string(),
+            pool_address: position.pool_address.clone(),
+            token_amount_a: (half_value / price * scale_a * (1.0 - PREPARED_OPEN_BUFFER_PERCENT)) as u64,
+            token_amount_b: (half_value * scale_b * (1.0 - PREPARED_OPEN_BUFFER_PERCENT)) as u64,
+            slippage: 500,
+            range_lower,
+            range_upper,
+        };
+        let open_position_instructions = adapter.open_position(&open_position_request).await?;
+
+        let policy = TransactionPolicy {
+            compute: ComputePolicy::Fixed(ComputeUnitLimit::HighUsage),
+            ..TransactionPolicy::for_kind(TransactionKind::Open)
+        };
+        let transaction = PreparedTransaction::prepare_with_policy(
+            open_position_instructions.instructions,
+            open_position_instructions.additional_signers,
+            Some(PriorityLevel::High),
+            TransactionKind::Open,
+            None,
+            &policy,
+        ).await?;
+
+        Ok(PreparedOpen { transaction, price })
+    }
+
     pub async fn balance_tokens(&self) -> anyhow::Result<(u64, u64, f64, f64)> {
         loop {
             let (balance_a, balance_b, range_lower, range_upper, swapped) = self.balance_tokens_core().await?;
@@ -95,15 +197,14 @@ impl NewProgrammaticPosition {
         let token_b = Token::from_mint_address(&self.token_mint_b).await?;
         let wallet = Wallet::get_programmatic_keypair()?;
     
-        let mut balance_a_amount = NewPositionData::get_balance_a_amount(&self).await?;
-        let mut balance_b_amount = NewPositionData::get_balance_b_amount(&self).await?;
+        let mut balance_a_amount = NewPositionData::get_balance_a_amount(self).await?;
+        let balance_b_amount = NewPositionData::get_balance_b_amount(self).await?;
         let decimals_a = 10u64.pow(token_a.decimals as u32);
         let decimals_b = 10u64.pow(token_b.decimals as u32);
     
         // Adjust for SOL fees
-        let sol_fee_reserve = 0.1; // Reserve 0.1 SOL for fees
         if token_a.address == Token::solana().address {
-            balance_a_amount = balance_a_amount.saturating_sub((sol_fee_reserve * decimals_a as f64) as u64);
+            balance_a_amount = balance_a_amount.saturating_sub((SOL_FEE_RESERVE * decimals_a as f64) as u64);
         }
     
         let current_price = NewPositionData::get_pool_price(self).await?;
@@ -148,19 +249,18 @@ impl NewProgrammaticPosition {
             let excess_usd = value_b_usd - total_value_usd / 2.0;
             let swap_amount = excess_usd * decimals_b as f64;
             println!("Swapping {} Token B to balance 50/50.", swap_amount);
-            TokenSwap::new(
+            let token_swap = TokenSwap::new(
                 wallet.pubkey().to_string(),
                 self.pool_address.clone(),
                 swap_amount as u64,
                 true,
                 token_b.address.clone(),
                 Some(50),
-            )
-            .swap()
-            .await?;
+            );
+            SwapRouter::execute(token_swap, &self.pool_type).await?;
         }
         
-        NewPositionData::set_token_amounts(&self).await?;
+        NewPositionData::set_token_amounts(self).await?;
         // Indicate that a swap was performed
         Ok((balance_a_amount, balance_b_amount, 0.0, 0.0, true))
     }
@@ -205,6 +305,12 @@ pub struct NewPositionData {
     pub loop_active: bool,
 }
 
+impl Default for NewPositionData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NewPositionData {
     pub fn new() -> Self {
         Self {
@@ -230,7 +336,6 @@ impl NewPositionData {
     pub async fn pool_price_loop(position: &NewProgrammaticPosition) {
         let new_position_data = NEW_POSITION_DATA.get().lock().await.clone();
         if new_position_data.loop_active {
-            drop(new_position_data);
             return;
         }
 
@@ -242,7 +347,6 @@ impl NewPositionData {
             drop(new_position_data_lock);
             interval.tick().await;
             // blue!("fetching pool price");
-            let start = Instant::now();
             let price = match Self::fetch_pool_price(position).await {
                 Ok(price) => price,
                 Err(e) => {
@@ -283,8 +387,17 @@ impl NewPositionData {
         Ok(())
     }
 
+    pub async fn set_pool_price(position: &NewProgrammaticPosition) -> anyhow::Result<()> {
+        let price = Self::fetch_pool_price(position).await?;
+        let mut new_position_data_lock = NEW_POSITION_DATA.get().lock().await;
+        new_position_data_lock.pool_price = Some(price);
+        drop(new_position_data_lock);
+
+        Ok(())
+    }
+
     pub async fn fetch_pool_price(position: &NewProgrammaticPosition) -> anyhow::Result<f64> {
-        let price = Orca::get_pool_price(RpcMode::fast(), &position.pool_address).await?;
+        let price = DexRegistry::get(&position.pool_type)?.pool_price(&position.pool_address).await?;
        
         Ok(price)
     }
@@ -377,7 +490,7 @@ impl NewPositionData {
         if let Some(pool_price) = new_position_data.pool_price {
             Ok(pool_price)
         } else {
-            let price = Orca::get_pool_price(RpcMode::fast(), &position.pool_address).await?;
+            let price = Self::fetch_pool_price(position).await?;
             let mut new_position_data_lock = NEW_POSITION_DATA.get().lock().await;
             new_position_data_lock.pool_price = Some(price);
             drop(new_position_data_lock);
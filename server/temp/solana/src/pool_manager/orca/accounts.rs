@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::utils::*;
+
+pub const WHIRLPOOL_LEN: usize = 653;
+pub const POSITION_LEN: usize = 216;
+pub const TICK_ARRAY_SIZE: i32 = 88;
+const TICK_LEN: usize = 113;
+/// Fixed tick arrays store every tick in full after the start index
+const FIXED_TICKS_OFFSET: usize = 12;
+/// Dynamic tick arrays store a whirlpool key and tick bitmap after the start index, then each
+/// tick as a one byte tag, followed by its data only when initialized
+const DYNAMIC_TICKS_OFFSET: usize = 60;
+const REWARD_INFO_LEN: usize = 128;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WhirlpoolRewardInfo {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub growth_global_x64: u128,
+}
+
+/// Decoded `Whirlpool`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WhirlpoolState {
+    pub address: Pubkey,
+    pub tick_spacing: u16,
+    /// Hundredths of a bip
+    pub fee_rate: u16,
+    pub liquidity: u128,
+    pub sqrt_price: u128,
+    pub tick_current_index: i32,
+    pub token_mint_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub fee_growth_global_a: u128,
+    pub token_mint_b: Pubkey,
+    pub token_vault_b: Pubkey,
+    pub fee_growth_global_b: u128,
+    pub reward_infos: Vec<WhirlpoolRewardInfo>,
+}
+
+impl WhirlpoolState {
+    pub fn from_account_data(address: Pubkey, data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < WHIRLPOOL_LEN {
+            return Err(anyhow::anyhow!("Account {} is not a Whirlpool", address));
+        }
+
+        let mut reward_infos = Vec::with_capacity(3);
+        for index in 0..3 {
+            let offset = 269 + index * REWARD_INFO_LEN;
+            reward_infos.push(WhirlpoolRewardInfo {
+                mint: read_pubkey(data, offset)?,
+                vault: read_pubkey(data, offset + 32)?,
+                growth_global_x64: read_u128(data, offset + 112)?,
+            });
+        }
+
+        Ok(Self {
+            address,
+            tick_spacing: read_u16(data, 41)?,
+            fee_rate: read_u16(data, 45)?,
+            liquidity: read_u128(data, 49)?,
+            sqrt_price: read_u128(data, 65)?,
+            tick_current_index: read_i32(data, 81)?,
+            token_mint_a: read_pubkey(data, 101)?,
+            token_vault_a: read_pubkey(data, 133)?,
+            fee_growth_global_a: read_u128(data, 165)?,
+            token_mint_b: read_pubkey(data, 181)?,
+            token_vault_b: read_pubkey(data, 213)?,
+            fee_growth_global_b: read_u128(data, 245)?,
+            reward_infos,
+        })
+    }
+
+    pub fn price(&self, decimals_a: u8, decimals_b: u8) -> f64 {
+        sqrt_price_x64_to_price(self.sqrt_price, decimals_a, decimals_b)
+    }
+
+    /// Swap fee as a fraction
+    pub fn fee_rate(&self) -> f64 {
+        self.fee_rate as f64 / 1_000_000.0
+    }
+
+    /// Start index of the tick array holding the given tick
+    pub fn tick_array_start_index(&self, tick_index: i32) -> i32 {
+        let ticks_in_array = self.tick_spacing as i32 * TICK_ARRAY_SIZE;
+        tick_index.div_euclid(ticks_in_array) * ticks_in_array
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WhirlpoolPositionRewardInfo {
+    pub growth_inside_checkpoint: u128,
+    pub amount_owed: u64,
+}
+
+/// Decoded Whirlpool `Position`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WhirlpoolPosition {
+    pub address: Pubkey,
+    pub wallet_key: Pubkey,
+    pub whirlpool: Pubkey,
+    pub position_mint: Pubkey,
+    pub liquidity: u128,
+    pub tick_lower_index: i32,
+    pub tick_upper_index: i32,
+    pub fee_growth_checkpoint_a: u128,
+    pub fee_owed_a: u64,
+    pub fee_growth_checkpoint_b: u128,
+    pub fee_owed_b: u64,
+    pub reward_infos: Vec<WhirlpoolPositionRewardInfo>,
+}
+
+impl WhirlpoolPosition {
+    pub fn from_account_data(address: Pubkey, wallet_key: Pubkey, data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < POSITION_LEN {
+            return Err(anyhow::anyhow!("Account {} is not a Whirlpool position", address));
+        }
+
+        let mut reward_infos = Vec::with_capacity(3);
+        for index in 0..3 {
+            let offset = 144 + index * 24;
+            reward_infos.push(WhirlpoolPositionRewardInfo {
+                growth_inside_checkpoint: read_u128(data, offset)?,
+                amount_owed: read_u64(data, offset + 16)?,
+            });
+        }
+
+        Ok(Self {
+            address,
+            wallet_key,
+            whirlpool: read_pubkey(data, 8)?,
+            position_mint: read_pubkey(data, 40)?,
+            liquidity: read_u128(data, 72)?,
+            tick_lower_index: read_i32(data, 88)?,
+            tick_upper_index: read_i32(data, 92)?,
+            fee_growth_checkpoint_a: read_u128(data, 96)?,
+            fee_owed_a: read_u64(data, 112)?,
+            fee_growth_checkpoint_b: read_u128(data, 120)?,
+            fee_owed_b: read_u64(data, 136)?,
+            reward_infos,
+        })
+    }
+}
+
+/// The growth fields of a Whirlpool `Tick` needed to work out what a range has earned
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WhirlpoolTick {
+    pub initialized: bool,
+    pub fee_growth_outside_a: u128,
+    pub fee_growth_outside_b: u128,
+    pub reward_growths_outside: [u128; 3],
+}
+
+impl WhirlpoolTick {
+    /// Reads a tick out of fixed `TickArray` or `DynamicTickArray` data, returning the default
+    /// (uninitialized) tick when the array has not been created yet
+    pub fn from_tick_array_data(data: Option<&[u8]>, start_index: i32, tick_index: i32, tick_spacing: u16) -> anyhow::Result<Self> {
+        let data = match data {
+            Some(data) => data,
+            None => return Ok(Self::default()),
+        };
+
+        if read_i32(data, 8)? != start_index {
+            return Err(anyhow::anyhow!("Tick array start index mismatch for tick {}", tick_index));
+        }
+
+        let tick_position = (tick_index - start_index) / tick_spacing as i32;
+        if !(0..TICK_ARRAY_SIZE).contains(&tick_position) {
+            return Err(anyhow::anyhow!("Tick {} is outside the tick array starting at {}", tick_index, start_index));
+        }
+
+        let discriminator = read_bytes::<8>(data, 0)?;
+        let offset = if discriminator == anchor_account_discriminator("TickArray") {
+            FIXED_TICKS_OFFSET + tick_position as usize * TICK_LEN
+        } else if discriminator == anchor_account_discriminator("DynamicTickArray") {
+            let mut offset = DYNAMIC_TICKS_OFFSET;
+            for _ in 0..tick_position {
+                offset += if read_u8(data, offset)? == 0 { 1 } else { TICK_LEN };
+            }
+            offset
+        } else {
+            return Err(anyhow::anyhow!("Account for tick {} is not a Whirlpool tick array", tick_index));
+        };
+
+        // Uninitialized dynamic ticks are only their tag, and cleared fixed ticks are all zero
+        if read_u8(data, offset)? == 0 {
+            return Ok(Self::default());
+        }
+
+        Ok(Self {
+            initialized: true,
+            fee_growth_outside_a: read_u128(data, offset + 33)?,
+            fee_growth_outside_b: read_u128(data, offset + 49)?,
+            reward_growths_outside: [
+                read_u128(data, offset + 65)?,
+                read_u128(data, offset + 81)?,
+                read_u128(data, offset + 97)?,
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick_data(fee_growth_outside_a: u128) -> Vec<u8> {
+        let mut data = vec![1u8];
+        data.extend_from_slice(&[0u8; 32]);
+        data.extend_from_slice(&fee_growth_outside_a.to_le_bytes());
+        data.extend_from_slice(&[0u8; 64]);
+        data
+    }
+
+    fn tick_array_header(account_name: &str, start_index: i32) -> Vec<u8> {
+        let mut data = anchor_account_discriminator(account_name).to_vec();
+        data.extend_from_slice(&start_index.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn reads_fixed_tick_array() {
+        let mut data = tick_array_header("TickArray", -5632);
+        for position in 0..TICK_ARRAY_SIZE {
+            if position == 3 {
+                data.extend(tick_data(42));
+            } else {
+                data.extend_from_slice(&[0u8; TICK_LEN]);
+            }
+        }
+
+        let tick = WhirlpoolTick::from_tick_array_data(Some(&data), -5632, -5632 + 3 * 64, 64).unwrap();
+        assert!(tick.initialized);
+        assert_eq!(tick.fee_growth_outside_a, 42);
+
+        let tick = WhirlpoolTick::from_tick_array_data(Some(&data), -5632, -5632 + 4 * 64, 64).unwrap();
+        assert!(!tick.initialized);
+    }
+
+    #[test]
+    fn reads_dynamic_tick_array() {
+        let mut data = tick_array_header("DynamicTickArray", 0);
+        data.extend_from_slice(&[0u8; 32 + 16]);
+        data.push(0);
+        data.extend(tick_data(7));
+        data.push(0);
+        data.extend(tick_data(9));
+
+        let tick = WhirlpoolTick::from_tick_array_data(Some(&data), 0, 1, 1).unwrap();
+        assert_eq!(tick.fee_growth_outside_a, 7);
+
+        let tick = WhirlpoolTick::from_tick_array_data(Some(&data), 0, 2, 1).unwrap();
+        assert!(!tick.initialized);
+
+        let tick = WhirlpoolTick::from_tick_array_data(Some(&data), 0, 3, 1).unwrap();
+        assert_eq!(tick.fee_growth_outside_a, 9);
+    }
+
+    #[test]
+    fn rejects_other_accounts() {
+        let mut data = tick_array_header("Whirlpool", 0);
+        data.extend_from_slice(&[0u8; TICK_LEN]);
+
+        assert!(WhirlpoolTick::from_tick_array_data(Some(&data), 0, 0, 1).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::Utc;
+use orca_pools_ipc_types::request::TokenAmount;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{pool_manager::{dex_adapter::{AdapterFuture, AdapterInstructions, DexAdapter, DexPoolInfo, OpenPositionRequest, SwapQuote}, position_manager::managed_position::{ManagedPosition, PoolType}}, rpc::RpcMode, token::Token};
+
+use super::{accounts::WhirlpoolState, token_swap::TokenSwap, Orca};
+
+impl DexAdapter for Orca {
+    fn pool_type(&self) -> PoolType {
+        PoolType::Orca
+    }
+
+    fn list_positions<'a>(&'a self, wallet_key: &'a str) -> AdapterFuture<'a, Vec<ManagedPosition>> {
+        Box::pin(async move {
+            let positions = Orca::get_positions_for_wallet(wallet_key.to_string()).await?;
+            let mut pools: Vec<WhirlpoolState> = vec![];
+            let mut managed_positions = vec![];
+
+            for position in positions {
+                let pool = match pools.iter().find(|pool| pool.address == position.whirlpool) {
+                    Some(pool) => pool.clone(),
+                    None => {
+                        let pool = Orca::get_whirlpool(RpcMode::conservative(), &position.whirlpool.to_string()).await?;
+                        pools.push(pool.clone());
+                        pool
+                    }
+                };
+                let amounts_owed = Orca::get_amounts_owed(RpcMode::conservative(), &pool, &position).await?;
+
+                let mut managed_position = ManagedPosition::from_whirlpool_position(position.clone(), Utc::now());
+                managed_position.update_prices(&pool, position, amounts_owed).await?;
+                managed_positions.push(managed_position);
+            }
+
+            Ok(managed_positions)
+        })
+    }
+
+    fn pool_info<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, DexPoolInfo> {
+        Box::pin(async move {
+            let pool = Orca::get_whirlpool(RpcMode::fast(), pool_address).await?;
+            let token_a = Token::from_mint_address(&pool.token_mint_a.to_string()).await?;
+            let token_b = Token::from_mint_address(&pool.token_mint_b.to_string()).await?;
+
+            Ok(DexPoolInfo {
+                pool_type: PoolType::Orca,
+                address: pool_address.to_string(),
+                token_mint_a: pool.token_mint_a.to_string(),
+                token_mint_b: pool.token_mint_b.to_string(),
+                price: pool.price(token_a.decimals, token_b.decimals),
+                tick_spacing: Some(pool.tick_spacing),
+                bin_step: None,
+                fee_rate: Some(pool.fee_rate()),
+            })
+        })
+    }
+
+    fn pool_price<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, f64> {
+        Box::pin(Orca::get_pool_price(RpcMode::fast(), pool_address))
+    }
+
+    fn open_position<'a>(&'a self, request: &'a OpenPositionRequest) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            let open_position_instruction = Orca::get_wallet_open_position_instructions(
+                &request.wallet_key,
+                &request.pool_address,
+                TokenAmount::TokenB(request.token_amount_b),
+                request.slippage,
+                request.range_lower,
+                request.range_upper,
+            ).await?;
+
+            Ok(AdapterInstructions {
+                instructions: Orca::solana_instructions_to_instructions(&open_position_instruction.instructions)?,
+                additional_signers: open_position_instruction.additional_signers,
+            })
+        })
+    }
+
+    fn close_position<'a>(&'a self, position: &'a ManagedPosition) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            let close_position_instruction = Orca::get_close_position_instructions(
+                RpcMode::fast(),
+                position.position_mint.clone(),
+                position.wallet_key.clone(),
+                None,
+            ).await?;
+
+            Ok(AdapterInstructions {
+                instructions: Orca::solana_instructions_to_instructions(&close_position_instruction.instructions)?,
+                additional_signers: close_position_instruction.additional_signers,
+            })
+        })
+    }
+
+    fn collect_fees<'a>(&'a self, position: &'a ManagedPosition) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(Orca::get_collect_fees_instructions(&position.wallet_key, &position.address))
+    }
+
+    fn swap<'a>(&'a self, token_swap: &'a TokenSwap) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            let swap_instructions = Orca::get_swap_instructions(token_swap.clone()).await?;
+
+            Ok(AdapterInstructions {
+                instructions: Orca::solana_instructions_to_instructions(&swap_instructions.instructions)?,
+                additional_signers: swap_instructions.additional_signers,
+            })
+        })
+    }
+
+    /// The sidecar's quote, which walks the pool's tick arrays so price impact and Token-2022
+    /// transfer fees are included
+    fn quote<'a>(&'a self, token_swap: &'a TokenSwap) -> AdapterFuture<'a, SwapQuote> {
+        Box::pin(async move {
+            let swap_instructions = Orca::get_swap_instructions(token_swap.clone()).await?;
+            let quote = swap_instructions.quote.exact_in()?;
+
+            Ok(SwapQuote {
+                amount_in: quote.token_in,
+                amount_out: quote.token_est_out,
+                other_amount_threshold: quote.token_min_out,
+                fee_rate: quote.trade_fee as f64 / quote.token_in.max(1) as f64,
+                price_impact_included: true,
+            })
+        })
+    }
+
+    fn reward_mints<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, Vec<Option<String>>> {
+        Box::pin(async move {
+            let pool = Orca::get_whirlpool(RpcMode::fast(), pool_address).await?;
+
+            Ok(pool
+                .reward_infos
+                .iter()
+                .map(|reward| (reward.mint != Pubkey::default()).then(|| reward.mint.to_string()))
+                .collect())
+        })
+    }
+}
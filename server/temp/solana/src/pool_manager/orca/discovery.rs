@@ -0,0 +1,216 @@
+use std::str::FromStr;
+
+use futures_util::future::join_all;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_client::client_error::reqwest;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{rpc::{Rpc, RpcMode}, token::Token, utils::*};
+
+use super::{accounts::WhirlpoolState, Orca};
+
+pub const WHIRLPOOLS_CONFIG: &str = "2LecshUwdy9xi7meFgHtFJQNSKk4KdTrxF9xA4JWqEG9";
+/// Tick spacings of the mainnet fee tiers, including the splash pool tier
+pub const FEE_TIER_TICK_SPACINGS: [u16; 11] = [1, 2, 4, 8, 16, 32, 64, 96, 128, 256, 32896];
+
+const DEFAULT_ORCA_API_URL: &str = "https://api.orca.so/v2/solana";
+/// Liquidity used to size a hypothetical position when estimating APR
+const REFERENCE_LIQUIDITY: u128 = 1_000_000_000_000;
+
+/// A Whirlpool for a mint pair with the numbers needed to pick between fee tiers.
+/// `tvl_b` and `liquidity` come from chain, the USD figures from the Orca API when it answers.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WhirlpoolRanking {
+    pub address: String,
+    pub token_mint_a: String,
+    pub token_mint_b: String,
+    pub tick_spacing: u16,
+    pub fee_rate: f64,
+    /// Token B per token A
+    pub price: f64,
+    /// Active liquidity at the current tick
+    pub liquidity: u128,
+    /// Vault balances valued in token B
+    pub tvl_b: f64,
+    pub tvl_usd: Option<f64>,
+    pub volume_24h_usd: Option<f64>,
+    pub fees_24h_usd: Option<f64>,
+    /// Range width the APR was estimated for, as a fraction of price either side
+    pub range_factor: f64,
+    /// Annualised fees for a small position over `range_factor`, as a fraction of its value
+    pub estimated_fee_apr: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct WhirlpoolStats {
+    tvl_usd: Option<f64>,
+    volume_24h_usd: Option<f64>,
+    fees_24h_usd: Option<f64>,
+}
+
+impl Orca {
+    pub fn get_whirlpool_address(mint_a: &Pubkey, mint_b: &Pubkey, tick_spacing: u16) -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(
+            &[
+                b"whirlpool",
+                Pubkey::from_str(WHIRLPOOLS_CONFIG)?.as_ref(),
+                mint_a.as_ref(),
+                mint_b.as_ref(),
+                &tick_spacing.to_le_bytes(),
+            ],
+            &Orca::program_id()?,
+        );
+        Ok(address)
+    }
+
+    /// Every Whirlpool for the pair across fee tiers, best estimated fee APR first.
+    /// Pools without an estimate are ranked after, by on-chain TVL.
+    pub async fn discover_whirlpools(token_mint_a: &str, token_mint_b: &str, range_factor: f64) -> anyhow::Result<Vec<WhirlpoolRanking>> {
+        if range_factor <= 0.0 || range_factor >= 1.0 {
+            return Err(anyhow::anyhow!("Range factor must be between 0 and 1"));
+        }
+
+        let mint_a = Pubkey::from_str(token_mint_a)?;
+        let mint_b = Pubkey::from_str(token_mint_b)?;
+
+        // The program orders the mints, so check both ways round
+        let mut addresses = vec![];
+        for tick_spacing in FEE_TIER_TICK_SPACINGS {
+            addresses.push(Orca::get_whirlpool_address(&mint_a, &mint_b, tick_spacing)?);
+            addresses.push(Orca::get_whirlpool_address(&mint_b, &mint_a, tick_spacing)?);
+        }
+
+        let accounts = Rpc::get_multiple_accounts(RpcMode::fast(), &addresses, Some(10000)).await?;
+        let program_id = Orca::program_id()?;
+
+        let pools: Vec<WhirlpoolState> = addresses
+            .into_iter()
+            .zip(accounts)
+            .filter_map(|(address, account)| {
+                let account = account.filter(|account| account.owner == program_id)?;
+                WhirlpoolState::from_account_data(address, &account.data).ok()
+            })
+            .collect();
+
+        blue!("Found {} Whirlpools for {} / {}", pools.len(), token_mint_a, token_mint_b);
+
+        let rankings = join_all(pools.iter().map(|pool| Orca::rank_whirlpool(pool, range_factor))).await;
+
+        let mut rankings: Vec<WhirlpoolRanking> = rankings
+            .into_iter()
+            .filter_map(|ranking| match ranking {
+                Ok(ranking) => Some(ranking),
+                Err(e) => {
+                    red!("Failed to rank Whirlpool: {:?}", e);
+                    None
+                }
+            })
+            .collect();
+
+        rankings.sort_by(|a, b| match (a.estimated_fee_apr, b.estimated_fee_apr) {
+            (Some(apr_a), Some(apr_b)) => apr_b.total_cmp(&apr_a),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => b.tvl_b.total_cmp(&a.tvl_b),
+        });
+
+        Ok(rankings)
+    }
+
+    async fn rank_whirlpool(pool: &WhirlpoolState, range_factor: f64) -> anyhow::Result<WhirlpoolRanking> {
+        let token_a = Token::from_mint_address(&pool.token_mint_a.to_string()).await?;
+        let token_b = Token::from_mint_address(&pool.token_mint_b.to_string()).await?;
+        let price = pool.price(token_a.decimals, token_b.decimals);
+
+        let vault_addresses = [pool.token_vault_a, pool.token_vault_b];
+        let pool_address = pool.address.to_string();
+        let (vaults, stats) = tokio::join!(
+            Rpc::get_multiple_accounts(RpcMode::fast(), &vault_addresses, Some(10000)),
+            Orca::get_whirlpool_stats(&pool_address),
+        );
+        let vaults = vaults?;
+        let stats = stats.unwrap_or_else(|e| {
+            yellow!("No Orca API stats for {}: {:?}", pool.address, e);
+            WhirlpoolStats::default()
+        });
+
+        // Token account amount sits at the same offset for both token programs
+        let vault_amount = |index: usize| -> f64 {
+            vaults[index]
+                .as_ref()
+                .and_then(|account| read_u64(&account.data, 64).ok())
+                .unwrap_or(0) as f64
+        };
+        let tvl_b = vault_amount(0) / 10f64.powi(token_a.decimals as i32) * price
+            + vault_amount(1) / 10f64.powi(token_b.decimals as i32);
+
+        // Fees per day in token B, converted through the pool's own TVL
+        let fees_24h_usd = stats.fees_24h_usd.or_else(|| stats.volume_24h_usd.map(|volume| volume * pool.fee_rate()));
+        let fees_24h_b = match (fees_24h_usd, stats.tvl_usd) {
+            (Some(fees), Some(tvl_usd)) if tvl_usd > 0.0 => Some(fees * tvl_b / tvl_usd),
+            _ => None,
+        };
+
+        let estimated_fee_apr = fees_24h_b.and_then(|fees_24h_b| {
+            let tick_lower = price_to_tick_index(price - price * range_factor, token_a.decimals, token_b.decimals);
+            let tick_upper = price_to_tick_index(price + price * range_factor, token_a.decimals, token_b.decimals).max(tick_lower + 1);
+            let (amount_a, amount_b) = clmm_amounts_from_liquidity(REFERENCE_LIQUIDITY, pool.sqrt_price, tick_lower, tick_upper);
+            let reference_value_b = amount_a / 10f64.powi(token_a.decimals as i32) * price
+                + amount_b / 10f64.powi(token_b.decimals as i32);
+
+            if pool.liquidity == 0 || reference_value_b <= 0.0 {
+                return None;
+            }
+
+            // Share of the active liquidity a position worth one token B would hold
+            let liquidity_per_b = REFERENCE_LIQUIDITY as f64 / reference_value_b;
+            let share = liquidity_per_b / (pool.liquidity as f64 + liquidity_per_b);
+
+            Some(fees_24h_b * share * 365.0)
+        });
+
+        Ok(WhirlpoolRanking {
+            address: pool.address.to_string(),
+            token_mint_a: pool.token_mint_a.to_string(),
+            token_mint_b: pool.token_mint_b.to_string(),
+            tick_spacing: pool.tick_spacing,
+            fee_rate: pool.fee_rate(),
+            price,
+            liquidity: pool.liquidity,
+            tvl_b,
+            tvl_usd: stats.tvl_usd,
+            volume_24h_usd: stats.volume_24h_usd,
+            fees_24h_usd,
+            range_factor,
+            estimated_fee_apr,
+        })
+    }
+
+    async fn get_whirlpool_stats(pool_address: &str) -> anyhow::Result<WhirlpoolStats> {
+        let base_url = std::env::var("ORCA_API_URL").unwrap_or_else(|_| DEFAULT_ORCA_API_URL.to_string());
+        let response: Value = reqwest::Client::new()
+            .get(format!("{}/pools/{}", base_url, pool_address))
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // The API returns numbers as strings
+        let as_f64 = |value: &Value| match value {
+            Value::String(value) => value.parse::<f64>().ok(),
+            value => value.as_f64(),
+        };
+        let pool = &response["data"];
+        let day = &pool["stats"]["24h"];
+
+        Ok(WhirlpoolStats {
+            tvl_usd: as_f64(&pool["tvlUsdc"]),
+            volume_24h_usd: as_f64(&day["volume"]),
+            fees_24h_usd: as_f64(&day["fees"]),
+        })
+    }
+}
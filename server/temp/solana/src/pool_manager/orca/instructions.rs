@@ -0,0 +1,156 @@
+use std::str::FromStr;
+
+use solana_sdk::{instruction::{AccountMeta, Instruction}, pubkey::Pubkey};
+
+use crate::{token::MEMO_PROGRAM_ID, utils::anchor_discriminator};
+
+use super::{accounts::WhirlpoolState, Orca};
+
+/// Accounts shared by the liquidity builders for one position
+pub struct WhirlpoolPositionAccounts {
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    pub position_token_account: Pubkey,
+    pub tick_array_lower: Pubkey,
+    pub tick_array_upper: Pubkey,
+    pub token_owner_account_a: Pubkey,
+    pub token_owner_account_b: Pubkey,
+    pub token_program_a: Pubkey,
+    pub token_program_b: Pubkey,
+}
+
+/// Account list shared by `increase_liquidity_v2` and `decrease_liquidity_v2`
+fn modify_liquidity_accounts(pool: &WhirlpoolState, accounts: &WhirlpoolPositionAccounts) -> anyhow::Result<Vec<AccountMeta>> {
+    Ok(vec![
+        AccountMeta::new(pool.address, false),
+        AccountMeta::new_readonly(accounts.token_program_a, false),
+        AccountMeta::new_readonly(accounts.token_program_b, false),
+        AccountMeta::new_readonly(Pubkey::from_str(MEMO_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(accounts.owner, true),
+        AccountMeta::new(accounts.position, false),
+        AccountMeta::new_readonly(accounts.position_token_account, false),
+        AccountMeta::new_readonly(pool.token_mint_a, false),
+        AccountMeta::new_readonly(pool.token_mint_b, false),
+        AccountMeta::new(accounts.token_owner_account_a, false),
+        AccountMeta::new(accounts.token_owner_account_b, false),
+        AccountMeta::new(pool.token_vault_a, false),
+        AccountMeta::new(pool.token_vault_b, false),
+        AccountMeta::new(accounts.tick_array_lower, false),
+        AccountMeta::new(accounts.tick_array_upper, false),
+    ])
+}
+
+/// `token_max_a` and `token_max_b` include any Token-2022 transfer fee
+pub fn increase_liquidity_v2(
+    pool: &WhirlpoolState,
+    accounts: &WhirlpoolPositionAccounts,
+    liquidity: u128,
+    token_max_a: u64,
+    token_max_b: u64,
+) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("increase_liquidity_v2").to_vec();
+    data.extend_from_slice(&liquidity.to_le_bytes());
+    data.extend_from_slice(&token_max_a.to_le_bytes());
+    data.extend_from_slice(&token_max_b.to_le_bytes());
+    // No remaining accounts, transfer hooks are not supported
+    data.push(0);
+
+    Ok(Instruction {
+        program_id: Orca::program_id()?,
+        accounts: modify_liquidity_accounts(pool, accounts)?,
+        data,
+    })
+}
+
+/// `token_min_a` and `token_min_b` are checked after any Token-2022 transfer fee
+pub fn decrease_liquidity_v2(
+    pool: &WhirlpoolState,
+    accounts: &WhirlpoolPositionAccounts,
+    liquidity: u128,
+    token_min_a: u64,
+    token_min_b: u64,
+) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("decrease_liquidity_v2").to_vec();
+    data.extend_from_slice(&liquidity.to_le_bytes());
+    data.extend_from_slice(&token_min_a.to_le_bytes());
+    data.extend_from_slice(&token_min_b.to_le_bytes());
+    data.push(0);
+
+    Ok(Instruction {
+        program_id: Orca::program_id()?,
+        accounts: modify_liquidity_accounts(pool, accounts)?,
+        data,
+    })
+}
+
+/// Brings the position's owed fees and rewards up to date. Fails on positions with no liquidity.
+pub fn update_fees_and_rewards(pool: &WhirlpoolState, accounts: &WhirlpoolPositionAccounts) -> anyhow::Result<Instruction> {
+    Ok(Instruction {
+        program_id: Orca::program_id()?,
+        accounts: vec![
+            AccountMeta::new(pool.address, false),
+            AccountMeta::new(accounts.position, false),
+            AccountMeta::new_readonly(accounts.tick_array_lower, false),
+            AccountMeta::new_readonly(accounts.tick_array_upper, false),
+        ],
+        data: anchor_discriminator("update_fees_and_rewards").to_vec(),
+    })
+}
+
+pub fn collect_fees_v2(pool: &WhirlpoolState, accounts: &WhirlpoolPositionAccounts) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("collect_fees_v2").to_vec();
+    data.push(0);
+
+    Ok(Instruction {
+        program_id: Orca::program_id()?,
+        accounts: vec![
+            AccountMeta::new_readonly(pool.address, false),
+            AccountMeta::new_readonly(accounts.owner, true),
+            AccountMeta::new(accounts.position, false),
+            AccountMeta::new_readonly(accounts.position_token_account, false),
+            AccountMeta::new_readonly(pool.token_mint_a, false),
+            AccountMeta::new_readonly(pool.token_mint_b, false),
+            AccountMeta::new(accounts.token_owner_account_a, false),
+            AccountMeta::new(pool.token_vault_a, false),
+            AccountMeta::new(accounts.token_owner_account_b, false),
+            AccountMeta::new(pool.token_vault_b, false),
+            AccountMeta::new_readonly(accounts.token_program_a, false),
+            AccountMeta::new_readonly(accounts.token_program_b, false),
+            AccountMeta::new_readonly(Pubkey::from_str(MEMO_PROGRAM_ID)?, false),
+        ],
+        data,
+    })
+}
+
+pub fn collect_reward_v2(
+    pool: &WhirlpoolState,
+    accounts: &WhirlpoolPositionAccounts,
+    reward_index: u8,
+    reward_owner_account: &Pubkey,
+    reward_token_program: &Pubkey,
+) -> anyhow::Result<Instruction> {
+    let reward = pool
+        .reward_infos
+        .get(reward_index as usize)
+        .ok_or_else(|| anyhow::anyhow!("Whirlpool {} has no reward {}", pool.address, reward_index))?;
+
+    let mut data = anchor_discriminator("collect_reward_v2").to_vec();
+    data.push(reward_index);
+    data.push(0);
+
+    Ok(Instruction {
+        program_id: Orca::program_id()?,
+        accounts: vec![
+            AccountMeta::new_readonly(pool.address, false),
+            AccountMeta::new_readonly(accounts.owner, true),
+            AccountMeta::new(accounts.position, false),
+            AccountMeta::new_readonly(accounts.position_token_account, false),
+            AccountMeta::new(*reward_owner_account, false),
+            AccountMeta::new_readonly(reward.mint, false),
+            AccountMeta::new(reward.vault, false),
+            AccountMeta::new_readonly(*reward_token_program, false),
+            AccountMeta::new_readonly(Pubkey::from_str(MEMO_PROGRAM_ID)?, false),
+        ],
+        data,
+    })
+}
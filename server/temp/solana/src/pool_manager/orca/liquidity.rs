@@ -0,0 +1,246 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+
+use crate::{pool_manager::dex_adapter::AdapterInstructions, rpc::{Rpc, RpcMode}, token::Token, utils::*};
+
+use super::{accounts::{WhirlpoolPosition, WhirlpoolState}, instructions::{self, WhirlpoolPositionAccounts}, Orca};
+
+/// Instructions that change the liquidity of an existing position, with the raw amounts they are
+/// expected to move. Token limits are maxima when increasing and minima when decreasing.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrcaLiquidityInstructions {
+    pub instructions: Vec<Instruction>,
+    pub additional_signers: Vec<String>,
+    pub position_address: String,
+    #[serde(
+        serialize_with = "u128_to_string",
+        deserialize_with = "string_to_u128"
+    )]
+    pub liquidity_before: u128,
+    #[serde(
+        serialize_with = "u128_to_string",
+        deserialize_with = "string_to_u128"
+    )]
+    pub liquidity_delta: u128,
+    pub estimated_amount_a: u64,
+    pub estimated_amount_b: u64,
+    pub token_limit_a: u64,
+    pub token_limit_b: u64,
+}
+
+impl Orca {
+    /// Adds liquidity to a position inside its existing range. The amounts are the most the
+    /// wallet wants to deposit of each token before slippage; the pool price decides the mix.
+    pub async fn get_increase_liquidity_instructions(
+        wallet_key: &str,
+        position_address: &str,
+        amount_a: u64,
+        amount_b: u64,
+        slippage: u16,
+    ) -> anyhow::Result<OrcaLiquidityInstructions> {
+        let (position, pool) = Orca::get_position_and_pool(wallet_key, position_address).await?;
+        let (accounts, mints, token_a, token_b) = Orca::get_position_accounts(&position, &pool).await?;
+        let epoch = Rpc::get_epoch(RpcMode::fast()).await?;
+
+        // Size against what actually reaches the vaults after transfer fees
+        let liquidity_delta = clmm_liquidity_from_amounts(
+            token_a.amount_after_transfer_fee(amount_a, epoch),
+            token_b.amount_after_transfer_fee(amount_b, epoch),
+            pool.sqrt_price,
+            position.tick_lower_index,
+            position.tick_upper_index,
+        );
+        if liquidity_delta == 0 {
+            return Err(anyhow::anyhow!("Amounts are too small to add liquidity to position {}", position_address));
+        }
+
+        let (estimated_a, estimated_b) = clmm_amounts_from_liquidity(
+            liquidity_delta,
+            pool.sqrt_price,
+            position.tick_lower_index,
+            position.tick_upper_index,
+        );
+        let with_slippage = |amount: f64| (amount.ceil() * (10_000 + slippage as u64) as f64 / 10_000.0).ceil() as u64;
+        let token_max_a = token_a.amount_before_transfer_fee(with_slippage(estimated_a), epoch);
+        let token_max_b = token_b.amount_before_transfer_fee(with_slippage(estimated_b), epoch);
+
+        let mut instructions = vec![];
+        let mut closing_instructions = vec![];
+
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+        for (mint, token_program, token_account, token_max) in [
+            (mints[0], accounts.token_program_a, accounts.token_owner_account_a, token_max_a),
+            (mints[1], accounts.token_program_b, accounts.token_owner_account_b, token_max_b),
+        ] {
+            instructions.push(Token::create_associated_token_account_idempotent(&accounts.owner, &accounts.owner, &mint, &token_program)?);
+            if mint == wsol_mint {
+                instructions.extend(Token::wrap_sol_instructions(&accounts.owner, &token_account, token_max)?);
+                closing_instructions.push(Token::unwrap_sol_instruction(&accounts.owner, &token_account)?);
+            }
+        }
+
+        instructions.push(instructions::increase_liquidity_v2(&pool, &accounts, liquidity_delta, token_max_a, token_max_b)?);
+        instructions.extend(closing_instructions);
+
+        Ok(OrcaLiquidityInstructions {
+            instructions,
+            additional_signers: vec![],
+            position_address: position_address.to_string(),
+            liquidity_before: position.liquidity,
+            liquidity_delta,
+            estimated_amount_a: estimated_a.ceil() as u64,
+            estimated_amount_b: estimated_b.ceil() as u64,
+            token_limit_a: token_max_a,
+            token_limit_b: token_max_b,
+        })
+    }
+
+    /// Withdraws `fraction` of a position's liquidity, leaving the position and its range open.
+    /// Fees and rewards stay in the position until they are collected.
+    pub async fn get_decrease_liquidity_instructions(
+        wallet_key: &str,
+        position_address: &str,
+        fraction: f64,
+        slippage: u16,
+    ) -> anyhow::Result<OrcaLiquidityInstructions> {
+        if fraction <= 0.0 || fraction > 1.0 {
+            return Err(anyhow::anyhow!("Fraction must be above 0 and at most 1"));
+        }
+
+        let (position, pool) = Orca::get_position_and_pool(wallet_key, position_address).await?;
+        if position.liquidity == 0 {
+            return Err(anyhow::anyhow!("Position {} has no liquidity to withdraw", position_address));
+        }
+
+        let (accounts, mints, token_a, token_b) = Orca::get_position_accounts(&position, &pool).await?;
+        let epoch = Rpc::get_epoch(RpcMode::fast()).await?;
+
+        let liquidity_delta = if fraction == 1.0 {
+            position.liquidity
+        } else {
+            ((position.liquidity as f64 * fraction) as u128).min(position.liquidity)
+        };
+
+        let (estimated_a, estimated_b) = clmm_amounts_from_liquidity(
+            liquidity_delta,
+            pool.sqrt_price,
+            position.tick_lower_index,
+            position.tick_upper_index,
+        );
+        let estimated_a = token_a.amount_after_transfer_fee(estimated_a as u64, epoch);
+        let estimated_b = token_b.amount_after_transfer_fee(estimated_b as u64, epoch);
+        let token_min_a = (estimated_a as f64 * (10_000 - slippage.min(10_000)) as f64 / 10_000.0) as u64;
+        let token_min_b = (estimated_b as f64 * (10_000 - slippage.min(10_000)) as f64 / 10_000.0) as u64;
+
+        let mut instructions = vec![
+            Token::create_associated_token_account_idempotent(&accounts.owner, &accounts.owner, &mints[0], &accounts.token_program_a)?,
+            Token::create_associated_token_account_idempotent(&accounts.owner, &accounts.owner, &mints[1], &accounts.token_program_b)?,
+        ];
+        instructions.push(instructions::decrease_liquidity_v2(&pool, &accounts, liquidity_delta, token_min_a, token_min_b)?);
+
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+        if mints[0] == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&accounts.owner, &accounts.token_owner_account_a)?);
+        } else if mints[1] == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&accounts.owner, &accounts.token_owner_account_b)?);
+        }
+
+        Ok(OrcaLiquidityInstructions {
+            instructions,
+            additional_signers: vec![],
+            position_address: position_address.to_string(),
+            liquidity_before: position.liquidity,
+            liquidity_delta,
+            estimated_amount_a: estimated_a,
+            estimated_amount_b: estimated_b,
+            token_limit_a: token_min_a,
+            token_limit_b: token_min_b,
+        })
+    }
+
+    /// Collects fees and any initialized rewards, leaving liquidity in place
+    pub async fn get_collect_fees_instructions(wallet_key: &str, position_address: &str) -> anyhow::Result<AdapterInstructions> {
+        let (position, pool) = Orca::get_position_and_pool(wallet_key, position_address).await?;
+        let (accounts, mints, _, _) = Orca::get_position_accounts(&position, &pool).await?;
+        let owner = accounts.owner;
+
+        let mut instructions = vec![
+            Token::create_associated_token_account_idempotent(&owner, &owner, &mints[0], &accounts.token_program_a)?,
+            Token::create_associated_token_account_idempotent(&owner, &owner, &mints[1], &accounts.token_program_b)?,
+        ];
+
+        // Owed amounts only move past the last checkpoint while the position has liquidity
+        if position.liquidity > 0 {
+            instructions.push(instructions::update_fees_and_rewards(&pool, &accounts)?);
+        }
+        instructions.push(instructions::collect_fees_v2(&pool, &accounts)?);
+
+        let rewards: Vec<(u8, Pubkey)> = pool
+            .reward_infos
+            .iter()
+            .enumerate()
+            .filter(|(_, reward)| reward.mint != Pubkey::default())
+            .map(|(index, reward)| (index as u8, reward.mint))
+            .collect();
+        let reward_mints: Vec<Pubkey> = rewards.iter().map(|(_, mint)| *mint).collect();
+        let reward_programs = if reward_mints.is_empty() {
+            vec![]
+        } else {
+            Token::get_token_programs(RpcMode::fast(), &reward_mints).await?
+        };
+
+        for ((reward_index, reward_mint), reward_program) in rewards.into_iter().zip(reward_programs) {
+            instructions.push(Token::create_associated_token_account_idempotent(&owner, &owner, &reward_mint, &reward_program)?);
+            let reward_account = Token::get_associated_token_address(&owner, &reward_mint, &reward_program)?;
+            instructions.push(instructions::collect_reward_v2(&pool, &accounts, reward_index, &reward_account, &reward_program)?);
+        }
+
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+        if mints[0] == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &accounts.token_owner_account_a)?);
+        } else if mints[1] == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &accounts.token_owner_account_b)?);
+        }
+
+        Ok(AdapterInstructions {
+            instructions,
+            additional_signers: vec![],
+        })
+    }
+
+    async fn get_position_and_pool(wallet_key: &str, position_address: &str) -> anyhow::Result<(WhirlpoolPosition, WhirlpoolState)> {
+        let position = Orca::get_position(RpcMode::fast(), wallet_key, position_address).await?;
+        let pool = Orca::get_whirlpool(RpcMode::fast(), &position.whirlpool.to_string()).await?;
+
+        Ok((position, pool))
+    }
+
+    async fn get_position_accounts(
+        position: &WhirlpoolPosition,
+        pool: &WhirlpoolState,
+    ) -> anyhow::Result<(WhirlpoolPositionAccounts, [Pubkey; 2], Token, Token)> {
+        let owner = position.wallet_key;
+        let token_a = Token::from_mint_address(&pool.token_mint_a.to_string()).await?;
+        let token_b = Token::from_mint_address(&pool.token_mint_b.to_string()).await?;
+        let token_programs = Token::get_token_programs(
+            RpcMode::fast(),
+            &[position.position_mint, pool.token_mint_a, pool.token_mint_b],
+        ).await?;
+
+        let accounts = WhirlpoolPositionAccounts {
+            owner,
+            position: position.address,
+            position_token_account: Token::get_associated_token_address(&owner, &position.position_mint, &token_programs[0])?,
+            tick_array_lower: Orca::get_tick_array_address(&pool.address, pool.tick_array_start_index(position.tick_lower_index))?,
+            tick_array_upper: Orca::get_tick_array_address(&pool.address, pool.tick_array_start_index(position.tick_upper_index))?,
+            token_owner_account_a: Token::get_associated_token_address(&owner, &pool.token_mint_a, &token_programs[1])?,
+            token_owner_account_b: Token::get_associated_token_address(&owner, &pool.token_mint_b, &token_programs[2])?,
+            token_program_a: token_programs[1],
+            token_program_b: token_programs[2],
+        };
+
+        Ok((accounts, [pool.token_mint_a, pool.token_mint_b], token_a, token_b))
+    }
+}
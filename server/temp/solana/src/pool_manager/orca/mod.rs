@@ -1,27 +1,159 @@
+pub mod accounts;
+pub mod adapter;
+pub mod discovery;
+pub mod instructions;
+pub mod liquidity;
 pub mod token_swap;
+pub mod zap_in;
 
-use std::{str::FromStr, time::Instant};
+use std::str::FromStr;
 
-use chrono::Utc;
+use accounts::{WhirlpoolPosition, WhirlpoolState, WhirlpoolTick, POSITION_LEN};
 use helius::types::PriorityLevel;
-use kebtech_utils::*;
-use base64::{prelude::BASE64_STANDARD, Engine};
-use orca_pools_ipc_types::{request::{close_position_request::{ClosePositionRequest, PriceTickInfo}, new_position_request::NewPositionRequest, swap_request::{SwapAmount, SwapRequest}, Request, TokenAmount}, response::{close_position_instruction::OrcaClosePositionInstruction, open_position_instruction::OrcaOpenPositionInstruction, orca_pool_info::{OrcaPoolInfo, OrcaPoolTokensAndTick}, orca_position_info::OrcaPositionInfo, orca_swap_instructions::OrcaSwapInstructions, Response}, solana::SolanaInstruction};
-use solana_sdk::{instruction::{AccountMeta, Instruction}, pubkey::Pubkey, signature::{Keypair, Signature}, signer::Signer};
+use orca_pools_ipc_types::{request::{close_position_request::{ClosePositionRequest, PriceTickInfo}, new_position_request::NewPositionRequest, swap_request::{SwapAmount, SwapRequest}, Request, TokenAmount}, response::{close_position_instruction::OrcaClosePositionInstruction, open_position_instruction::OrcaOpenPositionInstruction, orca_pool_info::{OrcaPoolInfo, OrcaPoolTokensAndTick}, orca_swap_instructions::OrcaSwapInstructions, Response}, solana::SolanaInstruction};
+use solana_sdk::{instruction::{AccountMeta, Instruction}, pubkey::Pubkey, signature::Signature, signer::Signer};
+use serde::{Deserialize, Serialize};
 use token_swap::TokenSwap;
 
-use crate::{rpc::{Rpc, RpcMode}, wallet::{programmatic_transaction::ProgrammaticTransaction, Wallet}, };
+use crate::{rpc::{Rpc, RpcMode}, token::Token, utils::*, wallet::{programmatic_transaction::{ProgrammaticTransaction, TransactionKind}, Wallet}, };
 
 use super::new_position::NewPosition;
 
+pub const WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+
 pub struct Orca;
 
+/// Fees and rewards a Whirlpool position has earned, including growth since its last on-chain
+/// checkpoint, in raw units
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WhirlpoolAmountsOwed {
+    pub fee_owed_a: u64,
+    pub fee_owed_b: u64,
+    pub rewards_owed: Vec<u64>,
+}
+
 impl Orca {
+    pub fn program_id() -> anyhow::Result<Pubkey> {
+        Ok(Pubkey::from_str(WHIRLPOOL_PROGRAM_ID)?)
+    }
+
+    pub fn get_position_address(position_mint: &Pubkey) -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(&[b"position", position_mint.as_ref()], &Orca::program_id()?);
+        Ok(address)
+    }
+
+    /// Address of the position an open instruction set creates. The position mint is a new
+    /// keypair among the additional signers, so this doesn't have to wait for RPCs to index it.
+    pub fn get_opened_position_address(open_position_instructions: &OrcaOpenPositionInstruction) -> anyhow::Result<Pubkey> {
+        let signers = ProgrammaticTransaction::get_additional_signers(open_position_instructions.additional_signers.clone())?;
+
+        signers
+            .iter()
+            .filter_map(|signer| Orca::get_position_address(&signer.pubkey()).ok())
+            .find(|address| {
+                let address = address.to_string();
+                open_position_instructions.instructions.iter().any(|instruction| {
+                    instruction.accounts.iter().any(|account| account.pubkey == address)
+                })
+            })
+            .ok_or_else(|| anyhow::anyhow!("Open position instructions don't create a position from any of their signers"))
+    }
+
+    /// Tick array PDAs are seeded with the start index as a decimal string
+    pub fn get_tick_array_address(whirlpool: &Pubkey, start_index: i32) -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(
+            &[b"tick_array", whirlpool.as_ref(), start_index.to_string().as_bytes()],
+            &Orca::program_id()?,
+        );
+        Ok(address)
+    }
+
+    pub async fn get_whirlpool(rpc_mode: RpcMode, pool_address: &str) -> anyhow::Result<WhirlpoolState> {
+        let pool_pubkey = Pubkey::from_str(pool_address)?;
+        let account = Rpc::get_account(rpc_mode, &pool_pubkey, Some(10000)).await?;
+
+        if account.owner != Orca::program_id()? {
+            return Err(anyhow::anyhow!("Pool {} is not owned by the Whirlpool program", pool_address));
+        }
+
+        WhirlpoolState::from_account_data(pool_pubkey, &account.data)
+    }
+
+    pub async fn get_position(rpc_mode: RpcMode, wallet_key: &str, position_address: &str) -> anyhow::Result<WhirlpoolPosition> {
+        let address = Pubkey::from_str(position_address)?;
+        let account = Rpc::get_account(rpc_mode, &address, Some(10000)).await?;
+
+        WhirlpoolPosition::from_account_data(address, Pubkey::from_str(wallet_key)?, &account.data)
+    }
+
+    pub async fn get_amounts_owed(rpc_mode: RpcMode, pool: &WhirlpoolState, position: &WhirlpoolPosition) -> anyhow::Result<WhirlpoolAmountsOwed> {
+        let lower_start = pool.tick_array_start_index(position.tick_lower_index);
+        let upper_start = pool.tick_array_start_index(position.tick_upper_index);
+        let tick_array_addresses = vec![
+            Orca::get_tick_array_address(&pool.address, lower_start)?,
+            Orca::get_tick_array_address(&pool.address, upper_start)?,
+        ];
+
+        let tick_arrays = Rpc::get_multiple_accounts(rpc_mode, &tick_array_addresses, Some(10000)).await?;
+
+        let tick_lower = WhirlpoolTick::from_tick_array_data(
+            tick_arrays[0].as_ref().map(|account| account.data.as_slice()),
+            lower_start,
+            position.tick_lower_index,
+            pool.tick_spacing,
+        )?;
+        let tick_upper = WhirlpoolTick::from_tick_array_data(
+            tick_arrays[1].as_ref().map(|account| account.data.as_slice()),
+            upper_start,
+            position.tick_upper_index,
+            pool.tick_spacing,
+        )?;
+
+        let growth_inside = |global: u128, outside_lower: u128, outside_upper: u128| {
+            clmm_fee_growth_inside(
+                pool.tick_current_index,
+                position.tick_lower_index,
+                position.tick_upper_index,
+                global,
+                outside_lower,
+                outside_upper,
+            )
+        };
+
+        let fee_growth_inside_a = growth_inside(pool.fee_growth_global_a, tick_lower.fee_growth_outside_a, tick_upper.fee_growth_outside_a);
+        let fee_growth_inside_b = growth_inside(pool.fee_growth_global_b, tick_lower.fee_growth_outside_b, tick_upper.fee_growth_outside_b);
+
+        // Reward growth is as of the pool's last update; emissions since then are not included
+        let rewards_owed = pool
+            .reward_infos
+            .iter()
+            .zip(position.reward_infos.iter())
+            .enumerate()
+            .map(|(index, (reward, position_reward))| {
+                if reward.mint == Pubkey::default() {
+                    return 0;
+                }
+                let reward_growth_inside = growth_inside(
+                    reward.growth_global_x64,
+                    tick_lower.reward_growths_outside[index],
+                    tick_upper.reward_growths_outside[index],
+                );
+                clmm_fees_owed(position.liquidity, reward_growth_inside, position_reward.growth_inside_checkpoint, position_reward.amount_owed)
+            })
+            .collect();
+
+        Ok(WhirlpoolAmountsOwed {
+            fee_owed_a: clmm_fees_owed(position.liquidity, fee_growth_inside_a, position.fee_growth_checkpoint_a, position.fee_owed_a),
+            fee_owed_b: clmm_fees_owed(position.liquidity, fee_growth_inside_b, position.fee_growth_checkpoint_b, position.fee_owed_b),
+            rewards_owed,
+        })
+    }
+
     pub async fn get_open_position_instructions(new_position: NewPosition) -> anyhow::Result<OrcaOpenPositionInstruction> {
         let token_amount = if new_position.amount_a > new_position.amount_b {
-            TokenAmount::TokenA(new_position.amount_a as u64)
+            TokenAmount::TokenA(new_position.amount_a)
         } else {
-            TokenAmount::TokenB(new_position.amount_b as u64)
+            TokenAmount::TokenB(new_position.amount_b)
         };
 
         let new_position_clone = new_position.clone();
@@ -61,6 +193,26 @@ impl Orca {
         let token_amount = TokenAmount::TokenB(token_amount_b);
         println!("token_amount: {:?}", token_amount);
         let wallet_key = Wallet::get_programmatic_pubkey()?.to_string();
+
+        Orca::get_wallet_open_position_instructions(
+            &wallet_key,
+            pool_address,
+            token_amount,
+            slippage,
+            range_lower,
+            range_upper,
+        ).await
+    }
+
+    pub async fn get_wallet_open_position_instructions(
+        wallet_key: &str,
+        pool_address: &str,
+        token_amount: TokenAmount,
+        slippage: u16,
+        range_lower: f64,
+        range_upper: f64,
+    ) -> anyhow::Result<OrcaOpenPositionInstruction> {
+        let wallet_key = wallet_key.to_string();
         let pool_address = pool_address.to_string();
         let response = Rpc::call_orca(
             RpcMode::fast(),
@@ -118,23 +270,30 @@ impl Orca {
         }
     }
 
-    pub async fn get_positions_for_wallet(wallet_key_str: String) -> anyhow::Result<Vec<OrcaPositionInfo>> {
-        let response = Rpc::call_orca(
-            RpcMode::conservative(),
-            move |url| {
-                Request::GetPositionsForWallet {
-                    rpc_url: url,
-                    wallet_key: wallet_key_str.clone(),
-                }
-            },
-            Some(20000)
-        ).await?;
+    /// Finds Whirlpool positions by looking up the position account behind every NFT the wallet holds
+    pub async fn get_positions_for_wallet(wallet_key: String) -> anyhow::Result<Vec<WhirlpoolPosition>> {
+        let owner = Pubkey::from_str(&wallet_key)?;
+        let nft_mints = Token::get_nft_mints_for_owner(RpcMode::conservative(), &owner).await?;
 
-        match response {
-            Response::Positions(position_infos) => Ok(position_infos),
-            _ => Err(anyhow::anyhow!("Unexpected response")),
+        let position_addresses = nft_mints
+            .iter()
+            .map(Orca::get_position_address)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let accounts = Rpc::get_multiple_accounts(RpcMode::conservative(), &position_addresses, Some(20000)).await?;
+        let program_id = Orca::program_id()?;
+
+        let mut positions = vec![];
+        for (address, account) in position_addresses.into_iter().zip(accounts) {
+            match account {
+                Some(account) if account.owner == program_id && account.data.len() == POSITION_LEN => {
+                    positions.push(WhirlpoolPosition::from_account_data(address, owner, &account.data)?);
+                }
+                _ => {}
+            }
         }
-        
+
+        Ok(positions)
     }
 
     pub async fn get_swap_instructions(token_swap: TokenSwap) -> anyhow::Result<OrcaSwapInstructions> {
@@ -166,7 +325,7 @@ impl Orca {
 
             match response {
                 Response::SwapInstructions(instructions) => Ok(instructions),
-                _ => return Err(anyhow::anyhow!("Unexpected response: {:?}", response)),
+                _ => Err(anyhow::anyhow!("Unexpected response: {:?}", response)),
             }
     }
 
@@ -184,7 +343,7 @@ impl Orca {
     
         match response {
             Response::PoolInfo(response) => Ok(response),
-            _ => return Err(anyhow::anyhow!("Unexpected response"))
+            _ => Err(anyhow::anyhow!("Unexpected response"))
         }
     }
 
@@ -201,7 +360,7 @@ impl Orca {
     
         match response {
             Response::PoolTokensAndTick(response) => Ok(response),
-            _ => return Err(anyhow::anyhow!("Unexpected response"))
+            _ => Err(anyhow::anyhow!("Unexpected response"))
         }
     }
 
@@ -243,44 +402,23 @@ impl Orca {
     
     
     pub async fn get_pool_price(rpc_mode: RpcMode, pool_address: &str) -> anyhow::Result<f64> {
-        let tokens_and_tick = Orca::get_tokens_and_tick(rpc_mode.clone(), &pool_address).await?;
-            let pool = Orca::get_clp_pool(
-                rpc_mode,
-                &tokens_and_tick.token_a,
-                &tokens_and_tick.token_b,
-                tokens_and_tick.tick_spacing,
-            )
-            .await?;
+        let pool = Orca::get_whirlpool(rpc_mode, pool_address).await?;
+        let token_a = Token::from_mint_address(&pool.token_mint_a.to_string()).await?;
+        let token_b = Token::from_mint_address(&pool.token_mint_b.to_string()).await?;
 
-        Ok(pool.price)
+        Ok(pool.price(token_a.decimals, token_b.decimals))
     }
 
     pub async fn perform_orca_transaction(
         instructions: Vec<SolanaInstruction>,
         additional_signer_strings: Vec<String>,
         priority_level: Option<PriorityLevel>,
+        kind: TransactionKind,
         // pool_address: &str,
     ) -> anyhow::Result<Signature> {
         let instructions = Orca::solana_instructions_to_instructions(&instructions)?;
-        let signers = ProgrammaticTransaction::get_all_signers(additional_signer_strings.clone())?;
-        let transaction = ProgrammaticTransaction::new(instructions.clone(), signers).await?;
-        let new_instructions = transaction.simulate_and_update_instructions(Some(20000), priority_level).await?;
-        
-        println!("simulated and adjusted instructions");
-        let signers = ProgrammaticTransaction::get_all_signers(additional_signer_strings.clone())?;
-
-        let start = Instant::now();
-        blue!("sending transaction");
-        let signature = Rpc::send_and_confirm_transaction(
-            RpcMode::fast(),
-            new_instructions,
-            signers,
-            Some(60000),
-        ).await?;
-
-        green!("sent transaction in {}ms", start.elapsed().as_millis());
 
-        Ok(signature)
+        ProgrammaticTransaction::perform(instructions, additional_signer_strings, priority_level, kind).await
     }
 
     // pub async fn handle_open_position_instructions(open_position_instruction: OrcaOpenPositionInstruction) -> anyhow::Result<()> {
@@ -1,13 +1,12 @@
+use std::str::FromStr;
+
 use chrono::Utc;
 use figlet_rs::FIGfont;
-use helius::types::PriorityLevel;
 use serde::{Deserialize, Serialize};
 use solana_sdk::signature::Signature;
 use kebtech_utils::*;
 
-use crate::{pool_manager::{new_position::NewPositionData, POOL_MANAGER}, rpc::{ComputeUnitLimit, PriorityFee, Rpc, RpcMode}, wallet::Wallet};
-
-use super::Orca;
+use crate::pool_manager::{position_manager::managed_position::PoolType, swap_router::SwapRouter};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TokenSwap {
@@ -31,29 +30,18 @@ impl TokenSwap {
         }
     }
 
+    /// Swaps with the programmatic wallet over the better of Jupiter and the Orca pool
     pub async fn swap(self) -> anyhow::Result<Signature> {
         let start = Utc::now();
-        blue!("Getting swap instructions");
-        let swap_instructions = Orca::get_swap_instructions(self.clone()).await?;
-        green!("Got swap instructions in {}ms", start.signed_duration_since(Utc::now()).num_milliseconds());
-
-        println!("swap instructions: {:?}", swap_instructions);
-
-        let start = Utc::now();
-        blue!("Performing swap transaction");
-        let signature = Orca::perform_orca_transaction(
-            swap_instructions.instructions,
-            swap_instructions.additional_signers,
-            Some(PriorityLevel::High),
-        ).await?;
-
-        green!("Performed swap transaction in {}ms", start.signed_duration_since(Utc::now()).num_milliseconds());
+        blue!("Routing swap");
+        let route_record = SwapRouter::execute(self, &PoolType::Orca).await?;
+        green!("Performed swap via {:?} in {}ms", route_record.chosen.route, Utc::now().signed_duration_since(start).num_milliseconds());
 
         let font = FIGfont::standard().unwrap();
         let banner = font.convert("Swapped Tokens").unwrap();
         green!("\n\n{}\n\n", banner);
 
-        Ok(signature)
+        Ok(Signature::from_str(&route_record.signature)?)
     }
     
 }
\ No newline at end of file
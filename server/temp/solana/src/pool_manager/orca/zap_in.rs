@@ -0,0 +1,152 @@
+use chrono::Utc;
+use kebtech_utils::*;
+use orca_pools_ipc_types::{request::TokenAmount, solana::SolanaInstruction};
+use serde::{Deserialize, Serialize};
+
+use crate::{rpc::RpcMode, utils::clmm_token_a_value_ratio};
+
+use super::{token_swap::TokenSwap, Orca};
+
+// Swaps below this share of the input are skipped and the position is opened from the input side only
+const MIN_SWAP_RATIO: f64 = 0.001;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ZapIn {
+    pub wallet_key: String,
+    pub pool_address: String,
+    pub input_mint: String,
+    pub amount: u64,
+    pub range_lower: f64,
+    pub range_upper: f64,
+    pub slippage_tolerance: Option<u16>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ZapInInstructions {
+    pub instructions: Vec<SolanaInstruction>,
+    pub additional_signers: Vec<String>,
+    pub input_mint: String,
+    pub output_mint: Option<String>,
+    pub swap_amount_in: u64,
+    pub estimated_amount_out: u64,
+    pub minimum_amount_out: u64,
+}
+
+impl ZapIn {
+    pub fn new(
+        wallet_key: String,
+        pool_address: String,
+        input_mint: String,
+        amount: u64,
+        range_lower: f64,
+        range_upper: f64,
+        slippage_tolerance: Option<u16>,
+    ) -> Self {
+        ZapIn {
+            wallet_key,
+            pool_address,
+            input_mint,
+            amount,
+            range_lower,
+            range_upper,
+            slippage_tolerance,
+        }
+    }
+
+    /// Builds a single instruction set that swaps part of the input into the other side of
+    /// the pair and then opens the position with liquidity, so the wallet only signs once.
+    pub async fn get_instructions(&self) -> anyhow::Result<ZapInInstructions> {
+        if self.range_lower >= self.range_upper {
+            return Err(anyhow::anyhow!("Invalid range: lower {} must be below upper {}", self.range_lower, self.range_upper));
+        }
+
+        let start = Utc::now();
+        let slippage = self.slippage_tolerance.unwrap_or(100);
+        let tokens_and_tick = Orca::get_tokens_and_tick(RpcMode::fast(), &self.pool_address).await?;
+
+        let input_is_a = if self.input_mint == tokens_and_tick.token_a {
+            true
+        } else if self.input_mint == tokens_and_tick.token_b {
+            false
+        } else {
+            return Err(anyhow::anyhow!("Input mint {} is not part of pool {}", self.input_mint, self.pool_address));
+        };
+
+        let pool = Orca::get_clp_pool(
+            RpcMode::fast(),
+            &tokens_and_tick.token_a,
+            &tokens_and_tick.token_b,
+            tokens_and_tick.tick_spacing,
+        ).await?;
+
+        let ratio_a = clmm_token_a_value_ratio(pool.price, self.range_lower, self.range_upper);
+        let swap_ratio = if input_is_a { 1.0 - ratio_a } else { ratio_a };
+
+        println!(
+            "Zap-in ratios: Token A: {:.2}%, Token B: {:.2}%, swapping {:.2}% of input",
+            ratio_a * 100.0,
+            (1.0 - ratio_a) * 100.0,
+            swap_ratio * 100.0
+        );
+
+        let mut instructions = vec![];
+        let mut additional_signers = vec![];
+        let mut output_mint = None;
+        let mut swap_amount_in = 0;
+        let mut estimated_amount_out = 0;
+        let mut minimum_amount_out = 0;
+
+        let token_amount = if swap_ratio < MIN_SWAP_RATIO {
+            if input_is_a { TokenAmount::TokenA(self.amount) } else { TokenAmount::TokenB(self.amount) }
+        } else {
+            swap_amount_in = (self.amount as f64 * swap_ratio) as u64;
+            let mint_out = if input_is_a { tokens_and_tick.token_b.clone() } else { tokens_and_tick.token_a.clone() };
+
+            let swap_instructions = Orca::get_swap_instructions(TokenSwap::new(
+                self.wallet_key.clone(),
+                self.pool_address.clone(),
+                swap_amount_in,
+                true,
+                self.input_mint.clone(),
+                Some(slippage),
+            )).await?;
+
+            // The sidecar's quote walks the pool's liquidity, so its minimum out is net of swap fees,
+            // price impact and slippage, where the spot price would overstate the output
+            let quote = swap_instructions.quote.exact_in()?;
+            estimated_amount_out = quote.token_est_out;
+            minimum_amount_out = quote.token_min_out;
+
+            instructions.extend(swap_instructions.instructions);
+            additional_signers.extend(swap_instructions.additional_signers);
+            output_mint = Some(mint_out);
+
+            // The swapped side is the binding amount since it is only guaranteed down to the minimum out
+            if input_is_a { TokenAmount::TokenB(minimum_amount_out) } else { TokenAmount::TokenA(minimum_amount_out) }
+        };
+
+        let open_position_instructions = Orca::get_wallet_open_position_instructions(
+            &self.wallet_key,
+            &self.pool_address,
+            token_amount,
+            slippage,
+            self.range_lower,
+            self.range_upper,
+        ).await?;
+
+        instructions.extend(open_position_instructions.instructions);
+        additional_signers.extend(open_position_instructions.additional_signers);
+
+        green!("Built zap-in instructions in {}ms", Utc::now().signed_duration_since(start).num_milliseconds());
+
+        Ok(ZapInInstructions {
+            instructions,
+            additional_signers,
+            input_mint: self.input_mint.clone(),
+            output_mint,
+            swap_amount_in,
+            estimated_amount_out,
+            minimum_amount_out,
+        })
+    }
+}
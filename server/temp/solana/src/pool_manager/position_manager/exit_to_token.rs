@@ -0,0 +1,268 @@
+use chrono::Utc;
+use kebtech_utils::*;
+use orca_pools_ipc_types::{response::close_position_instruction::OrcaClosePositionInstruction, solana::SolanaInstruction};
+use serde::{Deserialize, Serialize};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+use crate::{pool_manager::{dex_adapter::DexRegistry, orca::{token_swap::TokenSwap, Orca}, swap_router::SwapRouter}, rpc::RpcMode, token::Token, wallet::Wallet};
+
+use super::managed_position::{ManagedPosition, PoolType};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PositionExit {
+    pub position: ManagedPosition,
+    pub target_mint: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExitToTokenInstructions {
+    pub instructions: Vec<SolanaInstruction>,
+    pub additional_signers: Vec<String>,
+    pub target_mint: String,
+    pub swap_mint: String,
+    pub swap_amount_in: u64,
+    /// Swaps of the rewards collected by the close into the target mint, to send after the close
+    pub reward_swaps: Vec<RewardSwap>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RewardSwap {
+    pub mint: String,
+    pub amount_in: u64,
+    /// Base64 encoded Jupiter swap transaction for the wallet to sign
+    pub transaction: String,
+}
+
+impl PositionExit {
+    pub fn new(position: ManagedPosition, target_mint: String) -> anyhow::Result<Self> {
+        let position_exit = PositionExit { position, target_mint };
+        position_exit.get_swap_mint()?;
+
+        Ok(position_exit)
+    }
+
+    /// The token of the pair that has to be swapped into the target mint
+    pub fn get_swap_mint(&self) -> anyhow::Result<String> {
+        let token_a = self.position.token_a.clone().ok_or_else(|| anyhow::anyhow!("Token A not found in managed position"))?;
+        let token_b = self.position.token_b.clone().ok_or_else(|| anyhow::anyhow!("Token B not found in managed position"))?;
+
+        if self.target_mint == token_a.address {
+            Ok(token_b.address)
+        } else if self.target_mint == token_b.address {
+            Ok(token_a.address)
+        } else {
+            Err(anyhow::anyhow!("Target mint {} is not part of pool {}", self.target_mint, self.position.pool_address))
+        }
+    }
+
+    fn swap_is_a(&self, swap_mint: &str) -> bool {
+        self.position.token_a.as_ref().map(|token| token.address == swap_mint).unwrap_or(false)
+    }
+
+    /// What the close returns of the swap mint at worst: the minimum out of the liquidity plus
+    /// the fees owed
+    fn get_swap_amount_from_quote(swap_is_a: bool, close_position_instruction: &OrcaClosePositionInstruction) -> u64 {
+        if swap_is_a {
+            close_position_instruction.quote.token_min_a + close_position_instruction.fees_quote.fee_owed_a
+        } else {
+            close_position_instruction.quote.token_min_b + close_position_instruction.fees_quote.fee_owed_b
+        }
+    }
+
+    /// Pairs the amounts owed per reward index with the mint of that reward, leaving out slots
+    /// that are not initialized, nothing owed, and rewards already paid in the target mint
+    fn get_rewards(&self, reward_mints: &[Option<String>], rewards_owed: &[u64]) -> Vec<(String, u64)> {
+        reward_mints
+            .iter()
+            .zip(rewards_owed)
+            .filter_map(|(mint, rewards_owed)| match mint {
+                Some(mint) if *rewards_owed > 0 && *mint != self.target_mint => Some((mint.clone(), *rewards_owed)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Adds the rewards paid in the swap mint to the amount swapped with the pair, and returns the rest
+    fn fold_rewards_into_swap(swap_mint: &str, swap_amount: &mut u64, rewards: Vec<(String, u64)>) -> Vec<(String, u64)> {
+        rewards
+            .into_iter()
+            .filter(|(mint, rewards_owed)| {
+                if mint == swap_mint {
+                    *swap_amount += rewards_owed;
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect()
+    }
+
+    async fn get_balance(&self, mint: &str, after_close: bool) -> anyhow::Result<u64> {
+        let (balance, _) = Wallet::get_token_balance(&self.position.wallet_key, mint, RpcMode::fast())
+            .await
+            .map_err(|e| if after_close {
+                anyhow::anyhow!("Closed position {} but failed to read its {} proceeds to swap: {}", self.position.address, mint, e)
+            } else {
+                anyhow::anyhow!("Failed to read {} balance before exiting position {}: {}", mint, self.position.address, e)
+            })?;
+
+        Ok(balance)
+    }
+
+    /// Closes a programmatic position, collecting fees and rewards, then swaps the proceeds of
+    /// the other token and every reward token into the target mint
+    pub async fn execute(&self) -> anyhow::Result<()> {
+        magenta!("exiting position {} to {}", self.position.address, self.target_mint);
+        let swap_mint = self.get_swap_mint()?;
+        let wallet_key = self.position.wallet_key.clone();
+        let reward_mints = DexRegistry::get(&self.position.pool_type)?.reward_mints(&self.position.pool_address).await?;
+
+        let (swap_amount, rewards) = if self.position.pool_type == PoolType::Orca {
+            let close_position_instruction = Orca::get_close_position_instructions(
+                RpcMode::fast(),
+                self.position.position_mint.clone(),
+                wallet_key.clone(),
+                None,
+            ).await?;
+
+            let mut swap_amount = PositionExit::get_swap_amount_from_quote(self.swap_is_a(&swap_mint), &close_position_instruction);
+            let rewards = self.get_rewards(&reward_mints, &close_position_instruction.rewards_quote);
+            let rewards = PositionExit::fold_rewards_into_swap(&swap_mint, &mut swap_amount, rewards);
+
+            self.position.force_close_with(
+                Orca::solana_instructions_to_instructions(&close_position_instruction.instructions)?,
+                close_position_instruction.additional_signers,
+            ).await?;
+
+            (swap_amount, rewards)
+        } else {
+            // Without a close quote the proceeds are the balance changes, which are only safe to
+            // swap when both balances were actually read. Rewards in the swap mint show up in its
+            // balance change already.
+            let reward_mints: Vec<String> = self
+                .get_rewards(&reward_mints, &self.position.rewards_owed)
+                .into_iter()
+                .map(|(mint, _)| mint)
+                .filter(|mint| *mint != swap_mint)
+                .collect();
+
+            let balance_before = self.get_balance(&swap_mint, false).await?;
+            let mut reward_balances_before = Vec::with_capacity(reward_mints.len());
+            for mint in &reward_mints {
+                reward_balances_before.push(self.get_balance(mint, false).await?);
+            }
+
+            self.position.force_close().await?;
+
+            let mut swap_amount = self.get_balance(&swap_mint, true).await?.saturating_sub(balance_before);
+
+            // Leave the reclaimed rent in SOL so there is still enough for fees
+            if swap_mint == Token::solana().address {
+                swap_amount = swap_amount.saturating_sub(LAMPORTS_PER_SOL / 100);
+            }
+
+            let mut rewards = Vec::with_capacity(reward_mints.len());
+            for (mint, balance_before) in reward_mints.into_iter().zip(reward_balances_before) {
+                let amount = self.get_balance(&mint, true).await?.saturating_sub(balance_before);
+                if amount > 0 {
+                    rewards.push((mint, amount));
+                }
+            }
+
+            (swap_amount, rewards)
+        };
+
+        let start = Utc::now();
+        if swap_amount > 0 {
+            blue!("Swapping {} of {} into {}", swap_amount, swap_mint, self.target_mint);
+            let token_swap = TokenSwap::new(
+                wallet_key,
+                self.position.pool_address.clone(),
+                swap_amount,
+                true,
+                swap_mint,
+                Some(50),
+            );
+            SwapRouter::execute(token_swap, &self.position.pool_type).await?;
+        } else {
+            yellow!("Nothing of {} to swap after closing position {}", swap_mint, self.position.address);
+        }
+
+        // Reward mints are outside the pool, so they go through Jupiter
+        let mut failed_rewards = vec![];
+        for (mint, amount) in rewards {
+            blue!("Swapping {} of reward {} into {}", amount, mint, self.target_mint);
+            if let Err(e) = SwapRouter::swap_into(&mint, &self.target_mint, amount, Some(50)).await {
+                red!("Failed to swap reward {} into {}: {:?}", mint, self.target_mint, e);
+                failed_rewards.push(mint);
+            }
+        }
+
+        if !failed_rewards.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Closed position {} but could not swap rewards {} into {}",
+                self.position.address,
+                failed_rewards.join(", "),
+                self.target_mint,
+            ));
+        }
+
+        green!("Exited position to target token in {}ms", Utc::now().signed_duration_since(start).num_milliseconds());
+
+        Ok(())
+    }
+
+    /// Builds unsigned close and swap instructions for a position owned by a local wallet
+    pub async fn get_instructions(&self) -> anyhow::Result<ExitToTokenInstructions> {
+        if self.position.pool_type != PoolType::Orca {
+            return Err(anyhow::anyhow!("Exit instructions for local wallets are only available for Orca positions"));
+        }
+
+        let swap_mint = self.get_swap_mint()?;
+
+        let close_position_instruction = Orca::get_close_position_instructions(
+            RpcMode::fast(),
+            self.position.position_mint.clone(),
+            self.position.wallet_key.clone(),
+            None,
+        ).await?;
+
+        let reward_mints = DexRegistry::get(&self.position.pool_type)?.reward_mints(&self.position.pool_address).await?;
+        let mut swap_amount_in = PositionExit::get_swap_amount_from_quote(self.swap_is_a(&swap_mint), &close_position_instruction);
+        let rewards = self.get_rewards(&reward_mints, &close_position_instruction.rewards_quote);
+        let rewards = PositionExit::fold_rewards_into_swap(&swap_mint, &mut swap_amount_in, rewards);
+
+        let mut instructions = close_position_instruction.instructions;
+        let mut additional_signers = close_position_instruction.additional_signers;
+
+        if swap_amount_in > 0 {
+            let swap_instructions = Orca::get_swap_instructions(TokenSwap::new(
+                self.position.wallet_key.clone(),
+                self.position.pool_address.clone(),
+                swap_amount_in,
+                true,
+                swap_mint.clone(),
+                Some(50),
+            )).await?;
+
+            instructions.extend(swap_instructions.instructions);
+            additional_signers.extend(swap_instructions.additional_signers);
+        }
+
+        // Jupiter transactions carry their own lookup tables, so each reward swap is kept separate
+        let mut reward_swaps = Vec::with_capacity(rewards.len());
+        for (mint, amount_in) in rewards {
+            let transaction = SwapRouter::get_unsigned_jupiter_swap(&self.position.wallet_key, &mint, &self.target_mint, amount_in, Some(50)).await?;
+            reward_swaps.push(RewardSwap { mint, amount_in, transaction });
+        }
+
+        Ok(ExitToTokenInstructions {
+            instructions,
+            additional_signers,
+            target_mint: self.target_mint.clone(),
+            swap_mint,
+            swap_amount_in,
+            reward_swaps,
+        })
+    }
+}
@@ -1,11 +1,14 @@
 use chrono::{DateTime, Utc};
 use figlet_rs::FIGfont;
 use helius::types::PriorityLevel;
-use orca_pools_ipc_types::response::{orca_pool_info::OrcaPoolInfo, orca_position_info::{OrcaPositionInfo, OrcaPositionRewardInfo}};
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, signature::Signature};
 use kebtech_utils::*;
 
-use crate::{pool_manager::{orca::Orca, PoolManager, POOL_MANAGER}, price_info::coinbase::ticker::TickerState, rpc::RpcMode, token::Token, utils::*};
+use crate::{pool_manager::{dex_adapter::DexRegistry, meteora::{accounts::{MeteoraLbPair, MeteoraPosition}, MeteoraPositionAmounts}, orca::{accounts::{WhirlpoolPosition, WhirlpoolState}, WhirlpoolAmountsOwed}, raydium::accounts::{RaydiumPersonalPosition, RaydiumPoolState}, PoolManager, POOL_MANAGER}, price_info::coinbase::ticker::TickerState, token::Token, utils::*, wallet::{prepared_transaction::{PreparedSendError, PreparedTransaction}, programmatic_transaction::{ProgrammaticTransaction, TransactionKind}}};
+
+/// Range score, 0 in the middle and 1 at the edge, past which a close is prepared ahead of time
+const PREPARE_CLOSE_RANGE_SCORE: f64 = 0.8;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -33,6 +36,7 @@ impl RangeState {
 pub enum PoolType {
     Orca,
     Raydium,
+    Meteora,
     Saber,
     Mango,
     Serum,
@@ -78,6 +82,55 @@ pub struct ManagedPosition {
     // pub range_state_history: Vec<RangeState>,
     pub out_of_range_start: Option<DateTime<Utc>>,
     pub auto_rebalance: bool,
+    /// Set for bin based pools, where `tick_spacing` and `sqrt_price` do not apply
+    #[serde(default)]
+    pub bin_range: Option<BinRange>,
+    /// Raw liquidity for tick based pools, used to notice liquidity added or removed outside the bot
+    #[serde(
+        default,
+        serialize_with = "u128_to_string",
+        deserialize_with = "string_to_u128"
+    )]
+    pub liquidity: u128,
+    /// USD put into the position, reduced pro rata as liquidity is withdrawn
+    #[serde(default)]
+    pub cost_basis_usd: f64,
+    #[serde(default)]
+    pub realized_pnl_usd: f64,
+    #[serde(default)]
+    pub pnl_usd: f64,
+    #[serde(default)]
+    pub liquidity_changes: Vec<LiquidityChange>,
+}
+
+/// Range of a Meteora DLMM position in bins. `upper_bin_id` is inclusive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BinRange {
+    pub lower_bin_id: i32,
+    pub upper_bin_id: i32,
+    pub active_bin_id: i32,
+    pub bin_step: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum LiquidityChangeKind {
+    Increase,
+    Decrease,
+}
+
+/// Liquidity added to or removed from an open position. Changes made outside the bot are picked
+/// up on refresh and have no signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityChange {
+    pub kind: LiquidityChangeKind,
+    #[serde(
+        serialize_with = "u128_to_string",
+        deserialize_with = "string_to_u128"
+    )]
+    pub liquidity_delta: u128,
+    pub value_usd: f64,
+    pub signature: Option<String>,
+    pub at: DateTime<Utc>,
 }
 
 
@@ -88,29 +141,40 @@ pub struct PositionRewardInfo {
 }
 
 impl PositionRewardInfo {
-    pub fn from_orca_position_reward_info(orca_position_reward_infos: Vec<OrcaPositionRewardInfo>) -> Vec<Self> {
-        let mut reward_infos: Vec<Self> = Vec::new();
-        for reward_info in orca_position_reward_infos {
-            reward_infos.push(Self {
+    pub fn from_whirlpool_position(whirlpool_position: &WhirlpoolPosition) -> Vec<Self> {
+        whirlpool_position
+            .reward_infos
+            .iter()
+            .map(|reward_info| Self {
                 growth_inside_checkpoint: reward_info.growth_inside_checkpoint,
                 amount_owed: reward_info.amount_owed,
-            });
-        }
-        reward_infos
+            })
+            .collect()
+    }
+
+    pub fn from_raydium_position(raydium_position: &RaydiumPersonalPosition) -> Vec<Self> {
+        raydium_position
+            .reward_infos
+            .iter()
+            .map(|reward_info| Self {
+                growth_inside_checkpoint: reward_info.growth_inside_last_x64,
+                amount_owed: reward_info.reward_amount_owed,
+            })
+            .collect()
     }
 }
 
 impl ManagedPosition {
-    pub fn from_orca_position_info(orca_position_info: OrcaPositionInfo, created_at: DateTime<Utc>) -> Self {
+    pub fn from_whirlpool_position(whirlpool_position: WhirlpoolPosition, created_at: DateTime<Utc>) -> Self {
         Self {
             pool_type: PoolType::Orca,
             created_at,
             updated_at: Utc::now(),
             closed_at: None,
-            address: orca_position_info.address,
-            wallet_key: orca_position_info.wallet_pubkey,
-            position_mint: orca_position_info.position_mint,
-            pool_address: orca_position_info.whirlpool_address,
+            address: whirlpool_position.address.to_string(),
+            wallet_key: whirlpool_position.wallet_key.to_string(),
+            position_mint: whirlpool_position.position_mint.to_string(),
+            pool_address: whirlpool_position.whirlpool.to_string(),
             tick_spacing: 0,
             sqrt_price: 0,
             token_a: None,
@@ -129,13 +193,111 @@ impl ManagedPosition {
             yield_total_usd: 0.0,
             range_lower: 0.0,
             range_upper: 0.0,
-            reward_infos: PositionRewardInfo::from_orca_position_reward_info(orca_position_info.reward_infos),
+            reward_infos: PositionRewardInfo::from_whirlpool_position(&whirlpool_position),
             rewards_owed: Vec::new(),
             current_price: 0.0,
             current_ticker_price: 0.0,
             // range_state_history: Vec::new(),
             out_of_range_start: None,
             auto_rebalance: true,
+            bin_range: None,
+            liquidity: whirlpool_position.liquidity,
+            cost_basis_usd: 0.0,
+            realized_pnl_usd: 0.0,
+            pnl_usd: 0.0,
+            liquidity_changes: Vec::new(),
+        }
+    }
+
+    pub fn from_raydium_position(raydium_position: RaydiumPersonalPosition, created_at: DateTime<Utc>) -> Self {
+        Self {
+            pool_type: PoolType::Raydium,
+            created_at,
+            updated_at: Utc::now(),
+            closed_at: None,
+            address: raydium_position.address.to_string(),
+            wallet_key: raydium_position.wallet_key.to_string(),
+            position_mint: raydium_position.nft_mint.to_string(),
+            pool_address: raydium_position.pool_id.to_string(),
+            tick_spacing: 0,
+            sqrt_price: 0,
+            token_a: None,
+            token_b: None,
+            balance_token_a: 0.0,
+            balance_token_a_usd: 0.0,
+            balance_token_a_percentage: 0.0,
+            balance_token_b: 0.0,
+            balance_token_b_usd: 0.0,
+            balance_token_b_percentage: 0.0,
+            balance_total_usd: 0.0,
+            yield_token_a: 0.0,
+            yield_token_a_usd: 0.0,
+            yield_token_b: 0.0,
+            yield_token_b_usd: 0.0,
+            yield_total_usd: 0.0,
+            range_lower: 0.0,
+            range_upper: 0.0,
+            reward_infos: PositionRewardInfo::from_raydium_position(&raydium_position),
+            rewards_owed: Vec::new(),
+            current_price: 0.0,
+            current_ticker_price: 0.0,
+            out_of_range_start: None,
+            auto_rebalance: true,
+            bin_range: None,
+            liquidity: raydium_position.liquidity,
+            cost_basis_usd: 0.0,
+            realized_pnl_usd: 0.0,
+            pnl_usd: 0.0,
+            liquidity_changes: Vec::new(),
+        }
+    }
+
+    pub fn from_meteora_position(meteora_position: MeteoraPosition, created_at: DateTime<Utc>) -> Self {
+        Self {
+            pool_type: PoolType::Meteora,
+            created_at,
+            updated_at: Utc::now(),
+            closed_at: None,
+            address: meteora_position.address.to_string(),
+            wallet_key: meteora_position.owner.to_string(),
+            // DLMM positions are plain accounts without an NFT, so the address stands in for the mint
+            position_mint: meteora_position.address.to_string(),
+            pool_address: meteora_position.lb_pair.to_string(),
+            tick_spacing: 0,
+            sqrt_price: 0,
+            token_a: None,
+            token_b: None,
+            balance_token_a: 0.0,
+            balance_token_a_usd: 0.0,
+            balance_token_a_percentage: 0.0,
+            balance_token_b: 0.0,
+            balance_token_b_usd: 0.0,
+            balance_token_b_percentage: 0.0,
+            balance_total_usd: 0.0,
+            yield_token_a: 0.0,
+            yield_token_a_usd: 0.0,
+            yield_token_b: 0.0,
+            yield_token_b_usd: 0.0,
+            yield_total_usd: 0.0,
+            range_lower: 0.0,
+            range_upper: 0.0,
+            reward_infos: Vec::new(),
+            rewards_owed: Vec::new(),
+            current_price: 0.0,
+            current_ticker_price: 0.0,
+            out_of_range_start: None,
+            auto_rebalance: true,
+            bin_range: Some(BinRange {
+                lower_bin_id: meteora_position.lower_bin_id,
+                upper_bin_id: meteora_position.upper_bin_id,
+                active_bin_id: 0,
+                bin_step: 0,
+            }),
+            liquidity: 0,
+            cost_basis_usd: 0.0,
+            realized_pnl_usd: 0.0,
+            pnl_usd: 0.0,
+            liquidity_changes: Vec::new(),
         }
     }
 
@@ -217,84 +379,313 @@ impl ManagedPosition {
         1.0
     }
 
-    pub fn calculate_range(&self, tick_index: i32) -> f64 {
-        if let (Some(token_a), Some(token_b)) = (&self.token_a, &self.token_b) {
-            let base_multiplier = if token_a.is_stablecoin && token_b.is_stablecoin {
-                1.0 // Skip multiplier for stablecoin-stablecoin pools
-            } else {
-                1000.0 // Apply multiplier for other pools
-            };
-            1.0001f64.powi(tick_index) * base_multiplier
-        } else {
-            // Default to no multiplier if token metadata is missing
-            1.0001f64.powi(tick_index)
-        }
-    }
-
+    /// Refreshes prices, balances and fees from decoded Whirlpool state. `amounts_owed` comes from
+    /// `Orca::get_amounts_owed`.
     pub async fn update_prices(
         &mut self,
-        pool: OrcaPoolInfo,
-        position: OrcaPositionInfo,
+        pool: &WhirlpoolState,
+        position: WhirlpoolPosition,
+        amounts_owed: WhirlpoolAmountsOwed,
     ) -> anyhow::Result<Self> {
-        self.current_price = pool.price;
         self.tick_spacing = pool.tick_spacing;
         self.sqrt_price = pool.sqrt_price;
-    
-        let token_a = Token::from_mint_address(&pool.token_mint_a).await?;
-        let token_b = Token::from_mint_address(&pool.token_mint_b).await?;
-    
-        self.token_a = Some(token_a.clone());
-        self.token_b = Some(token_b.clone());
 
-        self.range_lower = self.calculate_range(position.tick_lower_index);
-        self.range_upper = self.calculate_range(position.tick_upper_index);
+        let token_a = Token::from_mint_address(&pool.token_mint_a.to_string()).await?;
+        let token_b = Token::from_mint_address(&pool.token_mint_b.to_string()).await?;
 
-        // let price_tick_info = PriceTickInfo {
-        //     sqrt_price: pool.sqrt_price,
-        //     tick_lower_index: position.tick_lower_index,
-        //     tick_upper_index: position.tick_upper_index,
-        // };
+        self.token_a = Some(token_a.clone());
+        self.token_b = Some(token_b.clone());
 
-        let close_position_instruction = Orca::get_close_position_instructions(
-            RpcMode::conservative(),
-            self.position_mint.clone(),
-            self.wallet_key.clone(),
-            None,
+        self.current_price = pool.price(token_a.decimals, token_b.decimals);
+        self.range_lower = tick_index_to_price(position.tick_lower_index, token_a.decimals, token_b.decimals);
+        self.range_upper = tick_index_to_price(position.tick_upper_index, token_a.decimals, token_b.decimals);
+
+        let (amount_a, amount_b) = clmm_amounts_from_liquidity(
+            position.liquidity,
+            pool.sqrt_price,
+            position.tick_lower_index,
+            position.tick_upper_index,
+        );
+
+        // What actually reaches the wallet on withdrawal after Token-2022 transfer fees
+        let (amount_a, amount_b) = Token::amounts_after_transfer_fees(&token_a, amount_a, &token_b, amount_b).await?;
+        let (fee_owed_a, fee_owed_b) = Token::amounts_after_transfer_fees(
+            &token_a,
+            amounts_owed.fee_owed_a as f64,
+            &token_b,
+            amounts_owed.fee_owed_b as f64,
         ).await?;
-    
+
         // Scale raw balances using decimals
-        self.balance_token_a = close_position_instruction.quote.token_est_a as f64 
-            / 10u64.pow(token_a.decimals as u32) as f64;
-    
-        self.balance_token_b = close_position_instruction.quote.token_est_b as f64 
-            / 10u64.pow(token_b.decimals as u32) as f64;
-    
+        self.balance_token_a = amount_a / 10u64.pow(token_a.decimals as u32) as f64;
+        self.balance_token_b = amount_b / 10u64.pow(token_b.decimals as u32) as f64;
+
         // Scale raw yields using decimals
-        self.yield_token_a = close_position_instruction.fees_quote.fee_owed_a as f64 
-            / 10u64.pow(token_a.decimals as u32) as f64;
-    
-        self.yield_token_b = close_position_instruction.fees_quote.fee_owed_b as f64 
-            / 10u64.pow(token_b.decimals as u32) as f64;
-    
+        self.yield_token_a = fee_owed_a / 10u64.pow(token_a.decimals as u32) as f64;
+        self.yield_token_b = fee_owed_b / 10u64.pow(token_b.decimals as u32) as f64;
+
         // Call helper methods for derived values
         self.balance_token_a_usd = self.balance_token_a_usd();
         self.balance_token_b_usd = self.balance_token_b_usd();
         self.balance_total_usd = self.balance_total_usd();
-    
+
         self.balance_token_a_percentage = self.balance_token_a_percentage();
         self.balance_token_b_percentage = self.balance_token_b_percentage();
-    
+
         self.yield_token_a_usd = self.yield_token_a_usd();
         self.yield_token_b_usd = self.yield_token_b_usd();
         self.yield_total_usd = self.yield_total_usd();
-    
-        self.rewards_owed = close_position_instruction.rewards_quote;
-        // self.in_range = close_position_instruction.in_range.unwrap_or(false);
+
+        self.reward_infos = PositionRewardInfo::from_whirlpool_position(&position);
+        self.rewards_owed = amounts_owed.rewards_owed;
         self.updated_at = Utc::now();
-    
+
+        Ok(self.clone())
+    }
+
+    /// Raydium counterpart of `update_prices`, working from decoded account state instead of a
+    /// sidecar close quote. `fees_owed` is the raw (token 0, token 1) fee amount.
+    pub async fn update_raydium_prices(
+        &mut self,
+        pool: &RaydiumPoolState,
+        position: RaydiumPersonalPosition,
+        fees_owed: (u64, u64),
+    ) -> anyhow::Result<Self> {
+        self.current_price = pool.price();
+        self.tick_spacing = pool.tick_spacing;
+        self.sqrt_price = pool.sqrt_price_x64;
+
+        let token_a = Token::from_mint_address(&pool.token_mint_0.to_string()).await?;
+        let token_b = Token::from_mint_address(&pool.token_mint_1.to_string()).await?;
+
+        self.token_a = Some(token_a.clone());
+        self.token_b = Some(token_b.clone());
+
+        self.range_lower = tick_index_to_price(position.tick_lower_index, pool.mint_decimals_0, pool.mint_decimals_1);
+        self.range_upper = tick_index_to_price(position.tick_upper_index, pool.mint_decimals_0, pool.mint_decimals_1);
+
+        let (amount_a, amount_b) = clmm_amounts_from_liquidity(
+            position.liquidity,
+            pool.sqrt_price_x64,
+            position.tick_lower_index,
+            position.tick_upper_index,
+        );
+
+        let (amount_a, amount_b) = Token::amounts_after_transfer_fees(&token_a, amount_a, &token_b, amount_b).await?;
+        let (fee_owed_a, fee_owed_b) = Token::amounts_after_transfer_fees(&token_a, fees_owed.0 as f64, &token_b, fees_owed.1 as f64).await?;
+
+        // Scale raw balances using decimals
+        self.balance_token_a = amount_a / 10u64.pow(token_a.decimals as u32) as f64;
+        self.balance_token_b = amount_b / 10u64.pow(token_b.decimals as u32) as f64;
+
+        // Scale raw yields using decimals
+        self.yield_token_a = fee_owed_a / 10u64.pow(token_a.decimals as u32) as f64;
+        self.yield_token_b = fee_owed_b / 10u64.pow(token_b.decimals as u32) as f64;
+
+        self.balance_token_a_usd = self.balance_token_a_usd();
+        self.balance_token_b_usd = self.balance_token_b_usd();
+        self.balance_total_usd = self.balance_total_usd();
+
+        self.balance_token_a_percentage = self.balance_token_a_percentage();
+        self.balance_token_b_percentage = self.balance_token_b_percentage();
+
+        self.yield_token_a_usd = self.yield_token_a_usd();
+        self.yield_token_b_usd = self.yield_token_b_usd();
+        self.yield_total_usd = self.yield_total_usd();
+
+        self.reward_infos = PositionRewardInfo::from_raydium_position(&position);
+        self.rewards_owed = position.reward_infos.iter().map(|reward_info| reward_info.reward_amount_owed).collect();
+        self.updated_at = Utc::now();
+
         Ok(self.clone())
     }
 
+    /// Meteora counterpart of `update_prices`. Token X and Y map onto token A and B, and the
+    /// price range spans from the bottom of the lower bin to the top of the upper bin.
+    pub async fn update_meteora_prices(
+        &mut self,
+        pair: &MeteoraLbPair,
+        position: MeteoraPosition,
+        amounts: MeteoraPositionAmounts,
+    ) -> anyhow::Result<Self> {
+        let token_a = Token::from_mint_address(&pair.token_x_mint.to_string()).await?;
+        let token_b = Token::from_mint_address(&pair.token_y_mint.to_string()).await?;
+
+        self.current_price = pair.price(token_a.decimals, token_b.decimals);
+        self.bin_range = Some(BinRange {
+            lower_bin_id: position.lower_bin_id,
+            upper_bin_id: position.upper_bin_id,
+            active_bin_id: pair.active_id,
+            bin_step: pair.bin_step,
+        });
+
+        self.range_lower = bin_id_to_price(position.lower_bin_id, pair.bin_step, token_a.decimals, token_b.decimals);
+        self.range_upper = bin_id_to_price(position.upper_bin_id + 1, pair.bin_step, token_a.decimals, token_b.decimals);
+
+        let (amount_x, amount_y) = Token::amounts_after_transfer_fees(&token_a, amounts.amount_x, &token_b, amounts.amount_y).await?;
+        let (fee_x, fee_y) = Token::amounts_after_transfer_fees(&token_a, amounts.fee_x, &token_b, amounts.fee_y).await?;
+
+        // Scale raw balances using decimals
+        self.balance_token_a = amount_x / 10u64.pow(token_a.decimals as u32) as f64;
+        self.balance_token_b = amount_y / 10u64.pow(token_b.decimals as u32) as f64;
+
+        // Scale raw yields using decimals
+        self.yield_token_a = fee_x / 10u64.pow(token_a.decimals as u32) as f64;
+        self.yield_token_b = fee_y / 10u64.pow(token_b.decimals as u32) as f64;
+
+        self.token_a = Some(token_a);
+        self.token_b = Some(token_b);
+
+        self.balance_token_a_usd = self.balance_token_a_usd();
+        self.balance_token_b_usd = self.balance_token_b_usd();
+        self.balance_total_usd = self.balance_total_usd();
+
+        self.balance_token_a_percentage = self.balance_token_a_percentage();
+        self.balance_token_b_percentage = self.balance_token_b_percentage();
+
+        self.yield_token_a_usd = self.yield_token_a_usd();
+        self.yield_token_b_usd = self.yield_token_b_usd();
+        self.yield_total_usd = self.yield_total_usd();
+
+        self.updated_at = Utc::now();
+
+        Ok(self.clone())
+    }
+
+    /// Takes on freshly priced state from a venue while keeping what the pool manager tracks
+    /// itself: creation and close times, out-of-range timing, the auto rebalance flag and the
+    /// position's accounting.
+    pub fn refresh_from(&mut self, fresh: ManagedPosition) {
+        let liquidity_before = self.liquidity;
+        let balance_before_usd = self.balance_total_usd;
+
+        *self = ManagedPosition {
+            created_at: self.created_at,
+            closed_at: self.closed_at,
+            current_ticker_price: self.current_ticker_price,
+            out_of_range_start: self.out_of_range_start,
+            auto_rebalance: self.auto_rebalance,
+            cost_basis_usd: self.cost_basis_usd,
+            realized_pnl_usd: self.realized_pnl_usd,
+            liquidity_changes: self.liquidity_changes.clone(),
+            ..fresh
+        };
+
+        // Liquidity changed by something other than the pool manager, valued at today's prices
+        if liquidity_before != 0 && self.liquidity != liquidity_before {
+            let (kind, liquidity_delta) = if self.liquidity > liquidity_before {
+                (LiquidityChangeKind::Increase, self.liquidity - liquidity_before)
+            } else {
+                (LiquidityChangeKind::Decrease, liquidity_before - self.liquidity)
+            };
+            // Pro rata to the fresh balance, or everything that was there if it is now empty
+            let value_usd = if self.liquidity != 0 {
+                self.balance_total_usd * liquidity_delta as f64 / self.liquidity as f64
+            } else {
+                balance_before_usd
+            };
+            self.record_liquidity_change(kind, liquidity_delta, liquidity_before, value_usd, None);
+        }
+
+        self.update_accounting();
+    }
+
+    /// Cost basis is taken from the first valuation, the same as position groups
+    pub fn update_accounting(&mut self) {
+        if self.cost_basis_usd == 0.0 && self.liquidity_changes.is_empty() && self.realized_pnl_usd == 0.0 {
+            self.cost_basis_usd = self.balance_total_usd;
+        }
+        self.pnl_usd = self.realized_pnl_usd + self.balance_total_usd + self.yield_total_usd - self.cost_basis_usd;
+    }
+
+    /// Deposits add to the cost basis. Withdrawals realize the difference between what came out
+    /// and the share of cost basis they carried.
+    pub fn record_liquidity_change(
+        &mut self,
+        kind: LiquidityChangeKind,
+        liquidity_delta: u128,
+        liquidity_before: u128,
+        value_usd: f64,
+        signature: Option<String>,
+    ) {
+        match kind {
+            LiquidityChangeKind::Increase => {
+                self.cost_basis_usd += value_usd;
+                self.liquidity = liquidity_before.saturating_add(liquidity_delta);
+            }
+            LiquidityChangeKind::Decrease => {
+                let fraction = if liquidity_before == 0 {
+                    1.0
+                } else {
+                    (liquidity_delta as f64 / liquidity_before as f64).min(1.0)
+                };
+                let cost_withdrawn = self.cost_basis_usd * fraction;
+                self.realized_pnl_usd += value_usd - cost_withdrawn;
+                self.cost_basis_usd -= cost_withdrawn;
+                self.liquidity = liquidity_before.saturating_sub(liquidity_delta);
+            }
+        }
+
+        self.liquidity_changes.push(LiquidityChange {
+            kind,
+            liquidity_delta,
+            value_usd,
+            signature,
+            at: Utc::now(),
+        });
+        self.update_accounting();
+    }
+
+    /// Books a change the pool manager sent itself. A refresh that ran while it was in flight has
+    /// already booked it without a signature, so that entry takes the signature and value instead
+    /// of the change being counted twice.
+    pub fn record_sent_liquidity_change(
+        &mut self,
+        kind: LiquidityChangeKind,
+        liquidity_delta: u128,
+        liquidity_before: u128,
+        value_usd: f64,
+        signature: String,
+    ) {
+        let refreshed_index = if self.liquidity == liquidity_before {
+            None
+        } else {
+            self.liquidity_changes.iter().rposition(|change| {
+                change.signature.is_none() && change.kind == kind && change.liquidity_delta == liquidity_delta
+            })
+        };
+
+        let Some(index) = refreshed_index else {
+            self.record_liquidity_change(kind, liquidity_delta, liquidity_before, value_usd, Some(signature));
+            return;
+        };
+
+        // The refresh booked the same delta, so a decrease withdrew the same share of cost basis
+        // and only the value differs
+        let value_difference = value_usd - self.liquidity_changes[index].value_usd;
+        match kind {
+            LiquidityChangeKind::Increase => self.cost_basis_usd += value_difference,
+            LiquidityChangeKind::Decrease => self.realized_pnl_usd += value_difference,
+        }
+
+        let change = &mut self.liquidity_changes[index];
+        change.value_usd = value_usd;
+        change.signature = Some(signature);
+        self.update_accounting();
+    }
+
+    /// USD value of decimal adjusted token amounts at the position's current prices
+    pub fn amounts_value_usd(&self, amount_a: f64, amount_b: f64) -> f64 {
+        let mut valued = self.clone();
+        valued.balance_token_a = amount_a;
+        valued.balance_token_b = amount_b;
+        valued.balance_total_usd()
+    }
+
+    pub async fn get_pool_price(&self) -> anyhow::Result<f64> {
+        DexRegistry::get(&self.pool_type)?.pool_price(&self.pool_address).await
+    }
+
     pub async fn toggle_auto_rebalance(&mut self) -> anyhow::Result<()> {
         self.auto_rebalance = !self.auto_rebalance;
         let mut managed_positions = POOL_MANAGER.get().lock().await.clone().managed_positions;
@@ -579,7 +970,7 @@ impl ManagedPosition {
             let range_score = (middle_of_range - current_ticker_price) / (middle_of_range - range_lower);
             self.out_of_range_start = None;
             RangeState::InLower(range_score)
-        } else if (current_ticker_price > middle_of_range) {
+        } else if current_ticker_price > middle_of_range {
             let range_score = (current_ticker_price - middle_of_range) / (range_upper - middle_of_range);
             self.out_of_range_start = None;
             RangeState::InHigher(range_score)
@@ -604,7 +995,7 @@ impl ManagedPosition {
 
         if should_rebalance {
             // make sure pool price is also outside of range
-            self.current_price = Orca::get_pool_price(RpcMode::fast(), &self.pool_address.clone()).await?;
+            self.current_price = self.get_pool_price().await?;
             println!("Ticker price out of range. Current Pool Price: {}", self.current_price);
             if self.current_price < range_lower || self.current_price > range_upper {
 
@@ -623,11 +1014,7 @@ impl ManagedPosition {
     pub async fn close(&self) -> anyhow::Result<()> {
         let start = Utc::now();
         blue!("Getting close position instructions");
-        let close_position_instruction = Orca::get_close_position_instructions(
-            RpcMode::fast(),
-            self.position_mint.clone(),
-            self.wallet_key.clone(),
-            None).await?;
+        let (instructions, additional_signers) = self.get_close_instructions().await?;
         green!("Got close position instructions in {}", start.signed_duration_since(Utc::now()).num_milliseconds());
 
         // let token_min_a = close_position_instruction.quote.token_min_a;
@@ -645,13 +1032,76 @@ impl ManagedPosition {
             return Err(anyhow::anyhow!("Position is not out of range, no need to close"));
         }
 
+        if let Some(prepared_close) = PoolManager::take_prepared_close(&self.address).await {
+            match prepared_close.send().await {
+                Ok(signature) => {
+                    green!("Closed position {} with prepared transaction {}", self.address, signature);
+                    return Ok(());
+                }
+                Err(PreparedSendError::Dropped(e)) => yellow!("Prepared close for {} was not used, sending a new one: {:?}", self.address, e),
+                Err(PreparedSendError::Unresolved(prepared_close, e)) => {
+                    // A second close could land next to it, so the same one is retried instead
+                    PoolManager::restore_prepared_close(&self.address, *prepared_close).await;
+                    return Err(anyhow::anyhow!("Prepared close for {} is unresolved: {:?}", self.address, e));
+                }
+            }
+        }
+
+        self.perform_close(instructions, additional_signers).await?;
+
+        Ok(())
+    }
+
+    /// Closes the position without re-checking the range, used when the close was explicitly requested
+    pub async fn force_close(&self) -> anyhow::Result<Signature> {
+        let start = Utc::now();
+        blue!("Getting close position instructions");
+        let (instructions, additional_signers) = self.get_close_instructions().await?;
+        green!("Got close position instructions in {}", start.signed_duration_since(Utc::now()).num_milliseconds());
+
+        self.perform_close(instructions, additional_signers).await
+    }
+
+    /// Closes the position with close instructions the caller already built from its own quote
+    pub async fn force_close_with(&self, instructions: Vec<Instruction>, additional_signers: Vec<String>) -> anyhow::Result<Signature> {
+        self.perform_close(instructions, additional_signers).await
+    }
+
+    /// Whether the ticker price `should_rebalance` last read is far enough from the middle of
+    /// the range that a rebalance is likely soon
+    pub fn near_range_edge(&self) -> bool {
+        let half_width = (self.range_upper - self.range_lower) / 2.0;
+        if half_width <= 0.0 {
+            return false;
+        }
+
+        let middle_of_range = (self.range_lower + self.range_upper) / 2.0;
+        (self.current_ticker_price - middle_of_range).abs() / half_width >= PREPARE_CLOSE_RANGE_SCORE
+    }
+
+    /// Builds and signs the close against a durable nonce, to send once the rebalance triggers
+    pub async fn prepare_close(&self) -> anyhow::Result<PreparedTransaction> {
+        let (instructions, additional_signers) = self.get_close_instructions().await?;
+
+        PreparedTransaction::prepare(instructions, additional_signers, Some(PriorityLevel::High), TransactionKind::Close, Some(self.address.clone())).await
+    }
+
+    async fn get_close_instructions(&self) -> anyhow::Result<(Vec<Instruction>, Vec<String>)> {
+        let close_position_instructions = DexRegistry::get(&self.pool_type)?.close_position(self).await?;
+
+        Ok((close_position_instructions.instructions, close_position_instructions.additional_signers))
+    }
+
+    async fn perform_close(&self, instructions: Vec<Instruction>, additional_signers: Vec<String>) -> anyhow::Result<Signature> {
         let start = Utc::now();
         blue!("Closing position");
         
-        let _signature = Orca::perform_orca_transaction(
-            close_position_instruction.instructions,
-            close_position_instruction.additional_signers,
+        let signature = ProgrammaticTransaction::perform_for_position(
+            instructions,
+            additional_signers,
             Some(PriorityLevel::High),
+            TransactionKind::Close,
+            Some(self.address.clone()),
         ).await?;
 
         green!("Closed position in {}", start.signed_duration_since(Utc::now()).num_milliseconds());
@@ -659,12 +1109,13 @@ impl ManagedPosition {
         let banner = font.convert("Closed Position").unwrap();
         green!("\n\n{}\n\n", banner);
 
-        Ok(())
+        Ok(signature)
     }
     
 }
 
 
+#[allow(dead_code)]
 fn calculate_average_price(history: &[TickerState]) -> f64 {
     if history.is_empty() {
         return 0.0;
@@ -673,6 +1124,7 @@ fn calculate_average_price(history: &[TickerState]) -> f64 {
     total / history.len() as f64
 }
 
+#[allow(dead_code)]
 fn calculate_volatility(history: &[TickerState]) -> f64 {
     if history.is_empty() {
         return 0.0;
@@ -688,6 +1140,7 @@ fn calculate_volatility(history: &[TickerState]) -> f64 {
     variance.sqrt() // Standard deviation
 }
 
+#[allow(dead_code)]
 fn calculate_new_range(
     current_price: f64,
     volatility: f64,
@@ -1 +1,3 @@
-pub mod managed_position;
\ No newline at end of file
+pub mod managed_position;
+pub mod exit_to_token;
+pub mod position_group;
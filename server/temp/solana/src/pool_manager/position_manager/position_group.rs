@@ -0,0 +1,314 @@
+use chrono::{DateTime, Utc};
+use figlet_rs::FIGfont;
+use helius::types::PriorityLevel;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{pool_manager::{new_position::{NewPositionData, NewProgrammaticPosition}, orca::Orca}, wallet::{programmatic_transaction::TransactionKind, Wallet}};
+
+use super::managed_position::{ManagedPosition, PoolType};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LadderLeg {
+    pub range_factor: f64,
+    pub weight: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LadderStrategy {
+    pub legs: Vec<LadderLeg>,
+    pub auto_rebalance: bool,
+}
+
+impl Default for LadderStrategy {
+    /// Narrow core range with wider wings on either side
+    fn default() -> Self {
+        Self {
+            legs: vec![
+                LadderLeg { range_factor: 0.01, weight: 0.6 },
+                LadderLeg { range_factor: 0.03, weight: 0.4 },
+            ],
+            auto_rebalance: true,
+        }
+    }
+}
+
+impl LadderStrategy {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.legs.is_empty() {
+            return Err(anyhow::anyhow!("Ladder strategy needs at least one leg"));
+        }
+        if self.legs.iter().any(|leg| leg.range_factor <= 0.0 || leg.range_factor >= 1.0) {
+            return Err(anyhow::anyhow!("Ladder range factors must be between 0 and 1"));
+        }
+        if self.legs.iter().any(|leg| leg.weight <= 0.0) {
+            return Err(anyhow::anyhow!("Ladder weights must be positive"));
+        }
+
+        Ok(())
+    }
+
+    /// (range_lower, range_upper, normalized weight) for each leg centered on the given price
+    pub fn get_ranges(&self, price: f64) -> Vec<(f64, f64, f64)> {
+        let total_weight: f64 = self.legs.iter().map(|leg| leg.weight).sum();
+
+        self.legs
+            .iter()
+            .map(|leg| {
+                (
+                    price - (price * leg.range_factor),
+                    price + (price * leg.range_factor),
+                    leg.weight / total_weight,
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PositionGroup {
+    pub id: String,
+    pub name: String,
+    pub pool_type: PoolType,
+    pub pool_address: String,
+    pub token_mint_a: String,
+    pub token_mint_b: String,
+    pub wallet_key: String,
+    pub strategy: LadderStrategy,
+    /// Leg positions in strategy order, so an open that failed part way resumes at the first
+    /// leg without one
+    pub position_addresses: Vec<String>,
+    /// Leg sent when the last open attempt failed, which may still have landed
+    #[serde(default)]
+    pub pending_position_address: Option<String>,
+    /// Price the legs of an open in progress are centered on
+    #[serde(default)]
+    pub ladder_price: Option<f64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub range_lower: f64,
+    pub range_upper: f64,
+    pub balance_token_a: f64,
+    pub balance_token_b: f64,
+    pub balance_total_usd: f64,
+    pub yield_total_usd: f64,
+    pub cost_basis_usd: f64,
+    pub realized_pnl_usd: f64,
+    pub pnl_usd: f64,
+    pub rebalance_count: u32,
+}
+
+impl PositionGroup {
+    pub fn new(
+        name: String,
+        pool_type: PoolType,
+        pool_address: String,
+        token_mint_a: String,
+        token_mint_b: String,
+        strategy: LadderStrategy,
+    ) -> anyhow::Result<Self> {
+        strategy.validate()?;
+        if pool_type != PoolType::Orca {
+            return Err(anyhow::anyhow!("Ladder groups only support Orca pools"));
+        }
+        let wallet_key = Wallet::get_programmatic_pubkey()?.to_string();
+
+        Ok(Self {
+            id: format!("{}-{}", pool_address, Utc::now().timestamp_millis()),
+            name,
+            pool_type,
+            pool_address,
+            token_mint_a,
+            token_mint_b,
+            wallet_key,
+            strategy,
+            position_addresses: Vec::new(),
+            pending_position_address: None,
+            ladder_price: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            range_lower: 0.0,
+            range_upper: 0.0,
+            balance_token_a: 0.0,
+            balance_token_b: 0.0,
+            balance_total_usd: 0.0,
+            yield_total_usd: 0.0,
+            cost_basis_usd: 0.0,
+            realized_pnl_usd: 0.0,
+            pnl_usd: 0.0,
+            rebalance_count: 0,
+        })
+    }
+
+    pub fn contains(&self, position_address: &str) -> bool {
+        self.position_addresses.iter().any(|address| address == position_address)
+    }
+
+    pub fn get_legs(&self, managed_positions: &[ManagedPosition]) -> Vec<ManagedPosition> {
+        managed_positions
+            .iter()
+            .filter(|position| self.contains(&position.address))
+            .cloned()
+            .collect()
+    }
+
+    /// A leg whose send failed may still have landed. It counts as opened if the wallet holds it.
+    async fn confirm_pending_leg(&mut self) -> anyhow::Result<()> {
+        let pending_position_address = match self.pending_position_address.clone() {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+
+        let landed = Orca::get_positions_for_wallet(self.wallet_key.clone())
+            .await?
+            .iter()
+            .any(|position| position.address.to_string() == pending_position_address);
+
+        if landed {
+            yellow!("ladder leg {} of group {} landed after all", pending_position_address, self.name);
+            self.position_addresses.push(pending_position_address);
+        }
+        self.pending_position_address = None;
+
+        Ok(())
+    }
+
+    pub fn update_aggregates(&mut self, managed_positions: &[ManagedPosition]) {
+        let legs = self.get_legs(managed_positions);
+
+        self.balance_token_a = legs.iter().map(|leg| leg.balance_token_a).sum();
+        self.balance_token_b = legs.iter().map(|leg| leg.balance_token_b).sum();
+        self.balance_total_usd = legs.iter().map(|leg| leg.balance_total_usd).sum();
+        self.yield_total_usd = legs.iter().map(|leg| leg.yield_total_usd).sum();
+        self.range_lower = legs.iter().map(|leg| leg.range_lower).fold(f64::INFINITY, f64::min);
+        self.range_upper = legs.iter().map(|leg| leg.range_upper).fold(0.0, f64::max);
+
+        // Cost basis is taken from the first valuation after the ladder is opened
+        if self.cost_basis_usd == 0.0 && !legs.is_empty() {
+            self.cost_basis_usd = self.balance_total_usd;
+        }
+        self.pnl_usd = self.realized_pnl_usd + self.balance_total_usd + self.yield_total_usd - self.cost_basis_usd;
+        self.updated_at = Utc::now();
+    }
+
+    /// The whole ladder moves when its narrowest leg needs to rebalance
+    pub async fn should_rebalance(&self, managed_positions: &[ManagedPosition]) -> anyhow::Result<bool> {
+        let legs = self.get_legs(managed_positions);
+        let core_leg = legs
+            .into_iter()
+            .min_by(|a, b| (a.range_upper - a.range_lower).total_cmp(&(b.range_upper - b.range_lower)));
+
+        match core_leg {
+            Some(mut core_leg) => core_leg.should_rebalance().await,
+            None => Ok(false),
+        }
+    }
+
+    /// Closes every leg, realizing each one's PnL as it closes. When a leg fails the group keeps
+    /// the legs still open, along with their share of the cost basis, so the close can be retried.
+    pub async fn close(&mut self, managed_positions: &[ManagedPosition]) -> anyhow::Result<()> {
+        magenta!("closing position group: {}", self.name);
+        self.update_aggregates(managed_positions);
+        let mut open_value_usd = self.balance_total_usd + self.yield_total_usd;
+
+        for leg in self.get_legs(managed_positions) {
+            leg.force_close().await?;
+
+            // The cost basis is shared out in proportion to what each leg is worth
+            let leg_value_usd = leg.balance_total_usd + leg.yield_total_usd;
+            let leg_cost_basis_usd = if open_value_usd > 0.0 {
+                self.cost_basis_usd * (leg_value_usd / open_value_usd).min(1.0)
+            } else {
+                self.cost_basis_usd
+            };
+            self.realized_pnl_usd += leg_value_usd - leg_cost_basis_usd;
+            self.cost_basis_usd -= leg_cost_basis_usd;
+            open_value_usd -= leg_value_usd;
+            self.position_addresses.retain(|address| address != &leg.address);
+            self.updated_at = Utc::now();
+        }
+
+        // Whatever is left belonged to legs that were no longer tracked
+        self.realized_pnl_usd -= self.cost_basis_usd;
+        self.cost_basis_usd = 0.0;
+        self.position_addresses.clear();
+
+        Ok(())
+    }
+
+    /// Opens the legs that don't have a position yet. Each leg's address is recorded as soon as
+    /// it lands, so when a leg fails the group can be retried without duplicating earlier legs.
+    pub async fn open(&mut self) -> anyhow::Result<()> {
+        magenta!("opening position group: {}", self.name);
+        self.confirm_pending_leg().await?;
+
+        let opened_legs = self.position_addresses.len();
+        if opened_legs > 0 && opened_legs < self.strategy.legs.len() {
+            yellow!("resuming position group {} at leg {}/{}", self.name, opened_legs + 1, self.strategy.legs.len());
+        }
+
+        let new_position = NewProgrammaticPosition {
+            pool_type: self.pool_type.clone(),
+            pool_address: self.pool_address.clone(),
+            token_mint_a: self.token_mint_a.clone(),
+            token_mint_b: self.token_mint_b.clone(),
+        };
+
+        let price = match self.ladder_price {
+            Some(price) => price,
+            None => {
+                NewPositionData::set_pool_price(&new_position).await?;
+                let price = NewPositionData::get_pool_price(&new_position).await?;
+                self.ladder_price = Some(price);
+                price
+            }
+        };
+
+        let remaining_legs: Vec<(f64, f64, f64)> = self.strategy.get_ranges(price).into_iter().skip(opened_legs).collect();
+        let remaining_weight: f64 = remaining_legs.iter().map(|(_, _, weight)| weight).sum();
+
+        if !remaining_legs.is_empty() {
+            NewPositionData::set_token_amounts(&new_position).await?;
+            NewPositionData::set_pool_price(&new_position).await?;
+            let (_token_amount_a, token_amount_b, _range_lower, _range_upper) = new_position.balance_tokens().await?;
+
+            let buffer_percent = 0.075;
+            let token_amount_b_with_buffer = token_amount_b.saturating_sub((token_amount_b as f64 * buffer_percent) as u64);
+
+            for (range_lower, range_upper, weight) in remaining_legs {
+                let leg_amount_b = (token_amount_b_with_buffer as f64 * weight / remaining_weight) as u64;
+                blue!("opening ladder leg {:.4} - {:.4} with {} token b", range_lower, range_upper, leg_amount_b);
+
+                let open_position_instructions = Orca::get_prog_open_position_instructions(
+                    &self.pool_address,
+                    leg_amount_b,
+                    500,
+                    range_lower,
+                    range_upper,
+                ).await?;
+                let position_address = Orca::get_opened_position_address(&open_position_instructions)?.to_string();
+
+                self.pending_position_address = Some(position_address.clone());
+                Orca::perform_orca_transaction(
+                    open_position_instructions.instructions,
+                    open_position_instructions.additional_signers,
+                    Some(PriorityLevel::High),
+                    TransactionKind::Open,
+                ).await?;
+
+                self.pending_position_address = None;
+                self.position_addresses.push(position_address);
+                self.updated_at = Utc::now();
+            }
+        }
+
+        self.ladder_price = None;
+        self.updated_at = Utc::now();
+
+        let font = FIGfont::standard().unwrap();
+        let banner = font.convert("Opened Ladder").unwrap();
+        green!("\n\n{}\n\n", banner);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use chrono::{DateTime, Utc};
+use helius::types::PriorityLevel;
+use kebtech_utils::*;
+use orca_pools_ipc_types::request::TokenAmount;
+use serde::{Deserialize, Serialize};
+
+use crate::{price_info::coinbase::ticker::TickerState, rpc::RpcMode, wallet::{programmatic_transaction::TransactionKind, Wallet}};
+
+use super::{orca::Orca, position_manager::managed_position::ManagedPosition};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum RangeOrderSide {
+    SellTokenA, // Range above the current price, funded with token A
+    BuyTokenA,  // Range below the current price, funded with token B
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum RangeOrderStatus {
+    Pending,
+    PartiallyFilled,
+    Filled,
+    Closed,
+    Cancelled,
+    Failed(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RangeOrder {
+    pub id: String,
+    pub pool_address: String,
+    pub token_mint_a: String,
+    pub token_mint_b: String,
+    pub side: RangeOrderSide,
+    pub range_lower: f64,
+    pub range_upper: f64,
+    pub amount: u64,
+    pub wallet_key: String,
+    pub position_address: Option<String>,
+    pub status: RangeOrderStatus,
+    pub fill_ratio: f64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+impl RangeOrder {
+    pub async fn new(
+        pool_address: String,
+        token_mint_a: String,
+        token_mint_b: String,
+        side: RangeOrderSide,
+        range_lower: f64,
+        range_upper: f64,
+        amount: u64,
+    ) -> anyhow::Result<Self> {
+        if range_lower >= range_upper {
+            return Err(anyhow::anyhow!("Invalid range: lower {} must be below upper {}", range_lower, range_upper));
+        }
+
+        let price = Orca::get_pool_price(RpcMode::fast(), &pool_address).await?;
+        match side {
+            RangeOrderSide::SellTokenA if range_lower <= price => {
+                return Err(anyhow::anyhow!("Sell range must be entirely above the current price {}", price));
+            }
+            RangeOrderSide::BuyTokenA if range_upper >= price => {
+                return Err(anyhow::anyhow!("Buy range must be entirely below the current price {}", price));
+            }
+            _ => {}
+        }
+
+        Ok(Self {
+            id: format!("{}-{}", pool_address, Utc::now().timestamp_millis()),
+            pool_address,
+            token_mint_a,
+            token_mint_b,
+            side,
+            range_lower,
+            range_upper,
+            amount,
+            wallet_key: Wallet::get_programmatic_pubkey()?.to_string(),
+            position_address: None,
+            status: RangeOrderStatus::Pending,
+            fill_ratio: 0.0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            closed_at: None,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(self.status, RangeOrderStatus::Pending | RangeOrderStatus::PartiallyFilled | RangeOrderStatus::Filled)
+    }
+
+    /// Opens the single-sided position backing the order
+    pub async fn place(&mut self) -> anyhow::Result<()> {
+        magenta!("placing range order: {:?}", self);
+        let token_amount = match self.side {
+            RangeOrderSide::SellTokenA => TokenAmount::TokenA(self.amount),
+            RangeOrderSide::BuyTokenA => TokenAmount::TokenB(self.amount),
+        };
+
+        let open_position_instructions = Orca::get_wallet_open_position_instructions(
+            &self.wallet_key,
+            &self.pool_address,
+            token_amount,
+            100,
+            self.range_lower,
+            self.range_upper,
+        ).await?;
+        let position_address = Orca::get_opened_position_address(&open_position_instructions)?;
+
+        Orca::perform_orca_transaction(
+            open_position_instructions.instructions,
+            open_position_instructions.additional_signers,
+            Some(PriorityLevel::High),
+            TransactionKind::Open,
+        ).await?;
+
+        self.position_address = Some(position_address.to_string());
+        self.updated_at = Utc::now();
+
+        green!("placed range order {} at position {:?}", self.id, self.position_address);
+
+        Ok(())
+    }
+
+    pub fn get_position(&self, managed_positions: &[ManagedPosition]) -> Option<ManagedPosition> {
+        let position_address = self.position_address.as_ref()?;
+
+        managed_positions.iter().find(|position| &position.address == position_address).cloned()
+    }
+
+    fn is_crossed(&self, price: f64) -> bool {
+        match self.side {
+            RangeOrderSide::SellTokenA => price >= self.range_upper,
+            RangeOrderSide::BuyTokenA => price <= self.range_lower,
+        }
+    }
+
+    /// Updates the fill ratio from the position balances and marks the order filled once the price
+    /// is fully through the range. Returns true when anything changed.
+    pub async fn update_status(&mut self, managed_positions: &[ManagedPosition]) -> anyhow::Result<bool> {
+        if !self.is_active() || self.status == RangeOrderStatus::Filled {
+            return Ok(false);
+        }
+
+        let position = match self.get_position(managed_positions) {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+
+        let previous_status = self.status.clone();
+        let previous_fill_ratio = self.fill_ratio;
+
+        if position.balance_total_usd > 0.0 {
+            self.fill_ratio = match self.side {
+                RangeOrderSide::SellTokenA => position.balance_token_b_usd / position.balance_total_usd,
+                RangeOrderSide::BuyTokenA => position.balance_token_a_usd / position.balance_total_usd,
+            };
+        }
+
+        // Confirm a ticker cross with the pool price before treating the order as filled
+        let ticker_price = TickerState::get_current_price().unwrap_or(position.current_price);
+        let crossed = if self.is_crossed(ticker_price) || self.is_crossed(position.current_price) {
+            let pool_price = Orca::get_pool_price(RpcMode::fast(), &self.pool_address).await?;
+            self.is_crossed(pool_price)
+        } else {
+            false
+        };
+
+        self.status = if crossed {
+            self.fill_ratio = 1.0;
+            RangeOrderStatus::Filled
+        } else if self.fill_ratio > 0.0 {
+            RangeOrderStatus::PartiallyFilled
+        } else {
+            RangeOrderStatus::Pending
+        };
+
+        let changed = self.status != previous_status || (self.fill_ratio - previous_fill_ratio).abs() > f64::EPSILON;
+        if changed {
+            self.updated_at = Utc::now();
+        }
+
+        Ok(changed)
+    }
+
+    /// Closes the backing position so a filled order does not convert back. A position the pool
+    /// manager hasn't picked up yet is an error, so the close is retried once it has.
+    pub async fn close(&mut self, managed_positions: &[ManagedPosition], status: RangeOrderStatus) -> anyhow::Result<()> {
+        match (self.get_position(managed_positions), &self.position_address) {
+            (Some(position), _) => {
+                position.force_close().await?;
+            }
+            (None, Some(position_address)) => {
+                let still_open = Orca::get_positions_for_wallet(self.wallet_key.clone())
+                    .await?
+                    .iter()
+                    .any(|position| &position.address.to_string() == position_address);
+
+                if still_open {
+                    return Err(anyhow::anyhow!("Position {} of range order {} is not tracked yet", position_address, self.id));
+                }
+                yellow!("position {} of range order {} is already closed", position_address, self.id);
+            }
+            (None, None) => (),
+        }
+
+        self.status = status;
+        self.closed_at = Some(Utc::now());
+        self.updated_at = Utc::now();
+
+        green!("closed range order {} as {:?}", self.id, self.status);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::utils::*;
+
+pub const POOL_STATE_LEN: usize = 1544;
+pub const PERSONAL_POSITION_LEN: usize = 281;
+pub const TICK_ARRAY_SIZE: i32 = 60;
+const TICK_STATE_LEN: usize = 168;
+const REWARD_INFO_LEN: usize = 169;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaydiumRewardInfo {
+    pub initialized: bool,
+    pub token_mint: Pubkey,
+    pub token_vault: Pubkey,
+}
+
+/// Decoded CLMM `PoolState`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaydiumPoolState {
+    pub address: Pubkey,
+    pub amm_config: Pubkey,
+    pub token_mint_0: Pubkey,
+    pub token_mint_1: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    pub observation_key: Pubkey,
+    pub mint_decimals_0: u8,
+    pub mint_decimals_1: u8,
+    pub tick_spacing: u16,
+    pub liquidity: u128,
+    pub sqrt_price_x64: u128,
+    pub tick_current: i32,
+    pub fee_growth_global_0_x64: u128,
+    pub fee_growth_global_1_x64: u128,
+    pub reward_infos: Vec<RaydiumRewardInfo>,
+}
+
+impl RaydiumPoolState {
+    pub fn from_account_data(address: Pubkey, data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < POOL_STATE_LEN {
+            return Err(anyhow::anyhow!("Account {} is not a Raydium CLMM pool", address));
+        }
+
+        let mut reward_infos = Vec::with_capacity(3);
+        for index in 0..3 {
+            let offset = 397 + index * REWARD_INFO_LEN;
+            reward_infos.push(RaydiumRewardInfo {
+                initialized: read_u8(data, offset)? != 0,
+                token_mint: read_pubkey(data, offset + 57)?,
+                token_vault: read_pubkey(data, offset + 89)?,
+            });
+        }
+
+        Ok(Self {
+            address,
+            amm_config: read_pubkey(data, 9)?,
+            token_mint_0: read_pubkey(data, 73)?,
+            token_mint_1: read_pubkey(data, 105)?,
+            token_vault_0: read_pubkey(data, 137)?,
+            token_vault_1: read_pubkey(data, 169)?,
+            observation_key: read_pubkey(data, 201)?,
+            mint_decimals_0: read_u8(data, 233)?,
+            mint_decimals_1: read_u8(data, 234)?,
+            tick_spacing: read_u16(data, 235)?,
+            liquidity: read_u128(data, 237)?,
+            sqrt_price_x64: read_u128(data, 253)?,
+            tick_current: read_i32(data, 269)?,
+            fee_growth_global_0_x64: read_u128(data, 277)?,
+            fee_growth_global_1_x64: read_u128(data, 293)?,
+            reward_infos,
+        })
+    }
+
+    pub fn price(&self) -> f64 {
+        sqrt_price_x64_to_price(self.sqrt_price_x64, self.mint_decimals_0, self.mint_decimals_1)
+    }
+
+    /// Start index of the tick array holding the given tick
+    pub fn tick_array_start_index(&self, tick_index: i32) -> i32 {
+        let ticks_in_array = self.tick_spacing as i32 * TICK_ARRAY_SIZE;
+        tick_index.div_euclid(ticks_in_array) * ticks_in_array
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaydiumPositionRewardInfo {
+    pub growth_inside_last_x64: u128,
+    pub reward_amount_owed: u64,
+}
+
+/// Decoded CLMM `PersonalPositionState`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaydiumPersonalPosition {
+    pub address: Pubkey,
+    pub wallet_key: Pubkey,
+    pub nft_mint: Pubkey,
+    pub pool_id: Pubkey,
+    pub tick_lower_index: i32,
+    pub tick_upper_index: i32,
+    pub liquidity: u128,
+    pub fee_growth_inside_0_last_x64: u128,
+    pub fee_growth_inside_1_last_x64: u128,
+    pub token_fees_owed_0: u64,
+    pub token_fees_owed_1: u64,
+    pub reward_infos: Vec<RaydiumPositionRewardInfo>,
+}
+
+impl RaydiumPersonalPosition {
+    pub fn from_account_data(address: Pubkey, wallet_key: Pubkey, data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < PERSONAL_POSITION_LEN {
+            return Err(anyhow::anyhow!("Account {} is not a Raydium CLMM position", address));
+        }
+
+        let mut reward_infos = Vec::with_capacity(3);
+        for index in 0..3 {
+            let offset = 145 + index * 24;
+            reward_infos.push(RaydiumPositionRewardInfo {
+                growth_inside_last_x64: read_u128(data, offset)?,
+                reward_amount_owed: read_u64(data, offset + 16)?,
+            });
+        }
+
+        Ok(Self {
+            address,
+            wallet_key,
+            nft_mint: read_pubkey(data, 9)?,
+            pool_id: read_pubkey(data, 41)?,
+            tick_lower_index: read_i32(data, 73)?,
+            tick_upper_index: read_i32(data, 77)?,
+            liquidity: read_u128(data, 81)?,
+            fee_growth_inside_0_last_x64: read_u128(data, 97)?,
+            fee_growth_inside_1_last_x64: read_u128(data, 113)?,
+            token_fees_owed_0: read_u64(data, 129)?,
+            token_fees_owed_1: read_u64(data, 137)?,
+            reward_infos,
+        })
+    }
+}
+
+/// The fields of a `TickState` needed to work out fees earned inside a range
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RaydiumTickState {
+    pub tick: i32,
+    pub fee_growth_outside_0_x64: u128,
+    pub fee_growth_outside_1_x64: u128,
+}
+
+impl RaydiumTickState {
+    /// Reads a tick out of `TickArrayState` data, returning the default (uninitialized) tick when
+    /// the array has not been created yet
+    pub fn from_tick_array_data(data: Option<&[u8]>, start_index: i32, tick_index: i32, tick_spacing: u16) -> anyhow::Result<Self> {
+        let data = match data {
+            Some(data) => data,
+            None => return Ok(Self::default()),
+        };
+
+        if read_i32(data, 40)? != start_index {
+            return Err(anyhow::anyhow!("Tick array start index mismatch for tick {}", tick_index));
+        }
+
+        let offset = 44 + ((tick_index - start_index) / tick_spacing as i32) as usize * TICK_STATE_LEN;
+
+        Ok(Self {
+            tick: read_i32(data, offset)?,
+            fee_growth_outside_0_x64: read_u128(data, offset + 36)?,
+            fee_growth_outside_1_x64: read_u128(data, offset + 52)?,
+        })
+    }
+}
+
+/// Reads `trade_fee_rate` (hundredths of a bip) from an `AmmConfig` account
+pub fn amm_config_trade_fee_rate(data: &[u8]) -> anyhow::Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes::<4>(data, 47)?))
+}
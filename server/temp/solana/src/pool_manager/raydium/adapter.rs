@@ -0,0 +1,118 @@
+use chrono::Utc;
+use orca_pools_ipc_types::request::TokenAmount;
+
+use crate::{pool_manager::{dex_adapter::{AdapterFuture, AdapterInstructions, DexAdapter, DexPoolInfo, OpenPositionRequest, SwapQuote}, orca::token_swap::TokenSwap, position_manager::managed_position::{ManagedPosition, PoolType}}, rpc::{Rpc, RpcMode}};
+
+use super::{accounts::{amm_config_trade_fee_rate, RaydiumPoolState}, Raydium, RaydiumInstructions};
+
+impl From<RaydiumInstructions> for AdapterInstructions {
+    fn from(raydium_instructions: RaydiumInstructions) -> Self {
+        Self {
+            instructions: raydium_instructions.instructions,
+            additional_signers: raydium_instructions.additional_signers,
+        }
+    }
+}
+
+impl DexAdapter for Raydium {
+    fn pool_type(&self) -> PoolType {
+        PoolType::Raydium
+    }
+
+    fn list_positions<'a>(&'a self, wallet_key: &'a str) -> AdapterFuture<'a, Vec<ManagedPosition>> {
+        Box::pin(async move {
+            let positions = Raydium::get_positions_for_wallet(wallet_key.to_string()).await?;
+            let mut pools: Vec<RaydiumPoolState> = vec![];
+            let mut managed_positions = vec![];
+
+            for position in positions {
+                let pool = match pools.iter().find(|pool| pool.address == position.pool_id) {
+                    Some(pool) => pool.clone(),
+                    None => {
+                        let pool = Raydium::get_pool_state(RpcMode::conservative(), &position.pool_id.to_string()).await?;
+                        pools.push(pool.clone());
+                        pool
+                    }
+                };
+                let fees_owed = Raydium::get_fees_owed(RpcMode::conservative(), &pool, &position).await?;
+
+                let mut managed_position = ManagedPosition::from_raydium_position(position.clone(), Utc::now());
+                managed_position.update_raydium_prices(&pool, position, fees_owed).await?;
+                managed_positions.push(managed_position);
+            }
+
+            Ok(managed_positions)
+        })
+    }
+
+    fn pool_info<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, DexPoolInfo> {
+        Box::pin(async move {
+            let pool = Raydium::get_pool_state(RpcMode::fast(), pool_address).await?;
+            let amm_config = Rpc::get_account(RpcMode::fast(), &pool.amm_config, Some(10000)).await?;
+
+            Ok(DexPoolInfo {
+                pool_type: PoolType::Raydium,
+                address: pool_address.to_string(),
+                token_mint_a: pool.token_mint_0.to_string(),
+                token_mint_b: pool.token_mint_1.to_string(),
+                price: pool.price(),
+                tick_spacing: Some(pool.tick_spacing),
+                bin_step: None,
+                fee_rate: Some(amm_config_trade_fee_rate(&amm_config.data)? as f64 / 1_000_000.0),
+            })
+        })
+    }
+
+    fn pool_price<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, f64> {
+        Box::pin(Raydium::get_pool_price(RpcMode::fast(), pool_address))
+    }
+
+    fn open_position<'a>(&'a self, request: &'a OpenPositionRequest) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            let open_position_instructions = Raydium::get_open_position_instructions(
+                &request.wallet_key,
+                &request.pool_address,
+                TokenAmount::TokenB(request.token_amount_b),
+                request.slippage,
+                request.range_lower,
+                request.range_upper,
+            ).await?;
+
+            Ok(open_position_instructions.into())
+        })
+    }
+
+    fn close_position<'a>(&'a self, position: &'a ManagedPosition) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            Ok(Raydium::get_close_position_instructions(&position.wallet_key, &position.address).await?.into())
+        })
+    }
+
+    fn collect_fees<'a>(&'a self, position: &'a ManagedPosition) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            Ok(Raydium::get_collect_fees_instructions(&position.wallet_key, &position.address).await?.into())
+        })
+    }
+
+    fn swap<'a>(&'a self, token_swap: &'a TokenSwap) -> AdapterFuture<'a, AdapterInstructions> {
+        Box::pin(async move {
+            Ok(Raydium::get_swap_instructions(token_swap.clone()).await?.into())
+        })
+    }
+
+    fn quote<'a>(&'a self, token_swap: &'a TokenSwap) -> AdapterFuture<'a, SwapQuote> {
+        Box::pin(Raydium::get_swap_quote(token_swap))
+    }
+
+    fn reward_mints<'a>(&'a self, pool_address: &'a str) -> AdapterFuture<'a, Vec<Option<String>>> {
+        Box::pin(async move {
+            let pool = Raydium::get_pool_state(RpcMode::fast(), pool_address).await?;
+
+            Ok(pool
+                .reward_infos
+                .iter()
+                .map(|reward| reward.initialized.then(|| reward.token_mint.to_string()))
+                .collect())
+        })
+    }
+}
@@ -0,0 +1,199 @@
+use std::str::FromStr;
+
+use solana_sdk::{instruction::{AccountMeta, Instruction}, pubkey::Pubkey, sysvar};
+use solana_system_interface::program as system_program;
+
+use crate::{token::{ASSOCIATED_TOKEN_PROGRAM_ID, MEMO_PROGRAM_ID, TOKEN_2022_PROGRAM_ID}, utils::anchor_discriminator};
+
+use super::{accounts::RaydiumPoolState, Raydium};
+
+/// Accounts shared by the open, decrease and close builders for one position
+pub struct RaydiumPositionAccounts {
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub nft_token_program: Pubkey,
+    pub nft_account: Pubkey,
+    pub personal_position: Pubkey,
+    pub protocol_position: Pubkey,
+    pub tick_array_lower: Pubkey,
+    pub tick_array_upper: Pubkey,
+    pub token_account_0: Pubkey,
+    pub token_account_1: Pubkey,
+}
+
+pub struct OpenPositionArgs {
+    pub tick_lower_index: i32,
+    pub tick_upper_index: i32,
+    pub tick_array_lower_start_index: i32,
+    pub tick_array_upper_start_index: i32,
+    pub amount_0_max: u64,
+    pub amount_1_max: u64,
+    pub base_flag: Option<bool>,
+}
+
+pub fn open_position_v2(
+    pool: &RaydiumPoolState,
+    accounts: &RaydiumPositionAccounts,
+    args: &OpenPositionArgs,
+) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("open_position_v2").to_vec();
+    data.extend_from_slice(&args.tick_lower_index.to_le_bytes());
+    data.extend_from_slice(&args.tick_upper_index.to_le_bytes());
+    data.extend_from_slice(&args.tick_array_lower_start_index.to_le_bytes());
+    data.extend_from_slice(&args.tick_array_upper_start_index.to_le_bytes());
+    // Liquidity is derived on chain from the base amount
+    data.extend_from_slice(&0u128.to_le_bytes());
+    data.extend_from_slice(&args.amount_0_max.to_le_bytes());
+    data.extend_from_slice(&args.amount_1_max.to_le_bytes());
+    data.push(0); // with_metadata
+    match args.base_flag {
+        Some(base_flag) => data.extend_from_slice(&[1, base_flag as u8]),
+        None => data.push(0),
+    }
+
+    let (metadata_account, _) = mpl_token_metadata::accounts::Metadata::find_pda(&accounts.nft_mint);
+
+    Ok(Instruction {
+        program_id: Raydium::program_id()?,
+        accounts: vec![
+            AccountMeta::new(accounts.owner, true),
+            AccountMeta::new_readonly(accounts.owner, false),
+            AccountMeta::new(accounts.nft_mint, true),
+            AccountMeta::new(accounts.nft_account, false),
+            AccountMeta::new(metadata_account, false),
+            AccountMeta::new(pool.address, false),
+            AccountMeta::new(accounts.protocol_position, false),
+            AccountMeta::new(accounts.tick_array_lower, false),
+            AccountMeta::new(accounts.tick_array_upper, false),
+            AccountMeta::new(accounts.personal_position, false),
+            AccountMeta::new(accounts.token_account_0, false),
+            AccountMeta::new(accounts.token_account_1, false),
+            AccountMeta::new(pool.token_vault_0, false),
+            AccountMeta::new(pool.token_vault_1, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID)?, false),
+            AccountMeta::new_readonly(mpl_token_metadata::ID, false),
+            AccountMeta::new_readonly(Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?, false),
+            AccountMeta::new_readonly(pool.token_mint_0, false),
+            AccountMeta::new_readonly(pool.token_mint_1, false),
+        ],
+        data,
+    })
+}
+
+/// Removes liquidity and collects fees. `reward_accounts` holds (reward vault, recipient, reward mint)
+/// for every initialized pool reward.
+pub fn decrease_liquidity_v2(
+    pool: &RaydiumPoolState,
+    accounts: &RaydiumPositionAccounts,
+    liquidity: u128,
+    amount_0_min: u64,
+    amount_1_min: u64,
+    reward_accounts: &[(Pubkey, Pubkey, Pubkey)],
+) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("decrease_liquidity_v2").to_vec();
+    data.extend_from_slice(&liquidity.to_le_bytes());
+    data.extend_from_slice(&amount_0_min.to_le_bytes());
+    data.extend_from_slice(&amount_1_min.to_le_bytes());
+
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(accounts.owner, true),
+        AccountMeta::new_readonly(accounts.nft_account, false),
+        AccountMeta::new(accounts.personal_position, false),
+        AccountMeta::new(pool.address, false),
+        AccountMeta::new(accounts.protocol_position, false),
+        AccountMeta::new(pool.token_vault_0, false),
+        AccountMeta::new(pool.token_vault_1, false),
+        AccountMeta::new(accounts.tick_array_lower, false),
+        AccountMeta::new(accounts.tick_array_upper, false),
+        AccountMeta::new(accounts.token_account_0, false),
+        AccountMeta::new(accounts.token_account_1, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(Pubkey::from_str(MEMO_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(pool.token_mint_0, false),
+        AccountMeta::new_readonly(pool.token_mint_1, false),
+    ];
+
+    for (reward_vault, recipient, reward_mint) in reward_accounts {
+        account_metas.push(AccountMeta::new(*reward_vault, false));
+        account_metas.push(AccountMeta::new(*recipient, false));
+        account_metas.push(AccountMeta::new_readonly(*reward_mint, false));
+    }
+
+    Ok(Instruction {
+        program_id: Raydium::program_id()?,
+        accounts: account_metas,
+        data,
+    })
+}
+
+pub fn close_position(accounts: &RaydiumPositionAccounts) -> anyhow::Result<Instruction> {
+    Ok(Instruction {
+        program_id: Raydium::program_id()?,
+        accounts: vec![
+            AccountMeta::new(accounts.owner, true),
+            AccountMeta::new(accounts.nft_mint, false),
+            AccountMeta::new(accounts.nft_account, false),
+            AccountMeta::new(accounts.personal_position, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(accounts.nft_token_program, false),
+        ],
+        data: anchor_discriminator("close_position").to_vec(),
+    })
+}
+
+pub struct SwapArgs {
+    pub amount: u64,
+    pub other_amount_threshold: u64,
+    pub is_base_input: bool,
+    pub zero_for_one: bool,
+}
+
+/// `remaining_accounts` is the bitmap extension followed by the tick arrays the swap walks through
+pub fn swap_v2(
+    pool: &RaydiumPoolState,
+    payer: &Pubkey,
+    input_token_account: &Pubkey,
+    output_token_account: &Pubkey,
+    args: &SwapArgs,
+    remaining_accounts: &[Pubkey],
+) -> anyhow::Result<Instruction> {
+    let mut data = anchor_discriminator("swap_v2").to_vec();
+    data.extend_from_slice(&args.amount.to_le_bytes());
+    data.extend_from_slice(&args.other_amount_threshold.to_le_bytes());
+    // A zero limit lets the program use the min/max sqrt price for the direction
+    data.extend_from_slice(&0u128.to_le_bytes());
+    data.push(args.is_base_input as u8);
+
+    let (input_vault, output_vault, input_mint, output_mint) = if args.zero_for_one {
+        (pool.token_vault_0, pool.token_vault_1, pool.token_mint_0, pool.token_mint_1)
+    } else {
+        (pool.token_vault_1, pool.token_vault_0, pool.token_mint_1, pool.token_mint_0)
+    };
+
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*payer, true),
+        AccountMeta::new_readonly(pool.amm_config, false),
+        AccountMeta::new(pool.address, false),
+        AccountMeta::new(*input_token_account, false),
+        AccountMeta::new(*output_token_account, false),
+        AccountMeta::new(input_vault, false),
+        AccountMeta::new(output_vault, false),
+        AccountMeta::new(pool.observation_key, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(Pubkey::from_str(MEMO_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(input_mint, false),
+        AccountMeta::new_readonly(output_mint, false),
+    ];
+    account_metas.extend(remaining_accounts.iter().map(|account| AccountMeta::new(*account, false)));
+
+    Ok(Instruction {
+        program_id: Raydium::program_id()?,
+        accounts: account_metas,
+        data,
+    })
+}
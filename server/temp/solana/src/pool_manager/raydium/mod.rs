@@ -1,8 +1,474 @@
+pub mod accounts;
+pub mod adapter;
+pub mod instructions;
+
+use std::str::FromStr;
+
+use accounts::{amm_config_trade_fee_rate, RaydiumPersonalPosition, RaydiumPoolState, RaydiumTickState, TICK_ARRAY_SIZE};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use instructions::{OpenPositionArgs, RaydiumPositionAccounts, SwapArgs};
+use orca_pools_ipc_types::request::TokenAmount;
 use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
+
+use crate::{rpc::{Rpc, RpcMode}, token::Token, utils::*};
+
+use super::{dex_adapter::SwapQuote, orca::token_swap::TokenSwap};
+
+pub const CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+
+// Raydium positions are closed with a wide floor, the same as Orca closes
+const CLOSE_SLIPPAGE_BPS: u64 = 1000;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Raydium;
 
+/// Instructions built locally for the Raydium CLMM program. Additional signers are base64 encoded
+/// keypairs, the same as the Orca sidecar returns.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaydiumInstructions {
+    pub instructions: Vec<Instruction>,
+    pub additional_signers: Vec<String>,
+}
+
 impl Raydium {
+    pub fn program_id() -> anyhow::Result<Pubkey> {
+        Ok(Pubkey::from_str(CLMM_PROGRAM_ID)?)
+    }
+
+    pub fn get_personal_position_address(nft_mint: &Pubkey) -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(&[b"position", nft_mint.as_ref()], &Raydium::program_id()?);
+        Ok(address)
+    }
+
+    pub fn get_protocol_position_address(pool: &Pubkey, tick_lower_index: i32, tick_upper_index: i32) -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(
+            &[b"position", pool.as_ref(), &tick_lower_index.to_be_bytes(), &tick_upper_index.to_be_bytes()],
+            &Raydium::program_id()?,
+        );
+        Ok(address)
+    }
+
+    pub fn get_tick_array_address(pool: &Pubkey, start_index: i32) -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(
+            &[b"tick_array", pool.as_ref(), &start_index.to_be_bytes()],
+            &Raydium::program_id()?,
+        );
+        Ok(address)
+    }
+
+    pub fn get_bitmap_extension_address(pool: &Pubkey) -> anyhow::Result<Pubkey> {
+        let (address, _) = Pubkey::find_program_address(
+            &[b"pool_tick_array_bitmap_extension", pool.as_ref()],
+            &Raydium::program_id()?,
+        );
+        Ok(address)
+    }
+
+    pub async fn get_pool_state(rpc_mode: RpcMode, pool_address: &str) -> anyhow::Result<RaydiumPoolState> {
+        let pool_pubkey = Pubkey::from_str(pool_address)?;
+        let account = Rpc::get_account(rpc_mode, &pool_pubkey, Some(10000)).await?;
+
+        if account.owner != Raydium::program_id()? {
+            return Err(anyhow::anyhow!("Pool {} is not owned by the Raydium CLMM program", pool_address));
+        }
+
+        RaydiumPoolState::from_account_data(pool_pubkey, &account.data)
+    }
+
+    pub async fn get_pool_price(rpc_mode: RpcMode, pool_address: &str) -> anyhow::Result<f64> {
+        let pool = Raydium::get_pool_state(rpc_mode, pool_address).await?;
+
+        Ok(pool.price())
+    }
+
+    /// Finds CLMM positions by looking up the position account behind every NFT the wallet holds
+    pub async fn get_positions_for_wallet(wallet_key: String) -> anyhow::Result<Vec<RaydiumPersonalPosition>> {
+        let owner = Pubkey::from_str(&wallet_key)?;
+        let nft_mints = Token::get_nft_mints_for_owner(RpcMode::conservative(), &owner).await?;
+
+        let position_addresses = nft_mints
+            .iter()
+            .map(Raydium::get_personal_position_address)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let accounts = Rpc::get_multiple_accounts(RpcMode::conservative(), &position_addresses, Some(20000)).await?;
+        let program_id = Raydium::program_id()?;
+
+        let mut positions = vec![];
+        for (address, account) in position_addresses.into_iter().zip(accounts) {
+            match account {
+                Some(account) if account.owner == program_id => {
+                    positions.push(RaydiumPersonalPosition::from_account_data(address, owner, &account.data)?);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(positions)
+    }
+
+    pub async fn get_position(rpc_mode: RpcMode, wallet_key: &str, position_address: &str) -> anyhow::Result<RaydiumPersonalPosition> {
+        let address = Pubkey::from_str(position_address)?;
+        let account = Rpc::get_account(rpc_mode, &address, Some(10000)).await?;
+
+        RaydiumPersonalPosition::from_account_data(address, Pubkey::from_str(wallet_key)?, &account.data)
+    }
+
+    /// Fees owed to the position including growth since its last on-chain checkpoint, in raw units
+    pub async fn get_fees_owed(rpc_mode: RpcMode, pool: &RaydiumPoolState, position: &RaydiumPersonalPosition) -> anyhow::Result<(u64, u64)> {
+        let lower_start = pool.tick_array_start_index(position.tick_lower_index);
+        let upper_start = pool.tick_array_start_index(position.tick_upper_index);
+        let tick_array_addresses = vec![
+            Raydium::get_tick_array_address(&pool.address, lower_start)?,
+            Raydium::get_tick_array_address(&pool.address, upper_start)?,
+        ];
+
+        let tick_arrays = Rpc::get_multiple_accounts(rpc_mode, &tick_array_addresses, Some(10000)).await?;
+
+        let tick_lower = RaydiumTickState::from_tick_array_data(
+            tick_arrays[0].as_ref().map(|account| account.data.as_slice()),
+            lower_start,
+            position.tick_lower_index,
+            pool.tick_spacing,
+        )?;
+        let tick_upper = RaydiumTickState::from_tick_array_data(
+            tick_arrays[1].as_ref().map(|account| account.data.as_slice()),
+            upper_start,
+            position.tick_upper_index,
+            pool.tick_spacing,
+        )?;
+
+        let fee_growth_inside_0 = clmm_fee_growth_inside(
+            pool.tick_current,
+            position.tick_lower_index,
+            position.tick_upper_index,
+            pool.fee_growth_global_0_x64,
+            tick_lower.fee_growth_outside_0_x64,
+            tick_upper.fee_growth_outside_0_x64,
+        );
+        let fee_growth_inside_1 = clmm_fee_growth_inside(
+            pool.tick_current,
+            position.tick_lower_index,
+            position.tick_upper_index,
+            pool.fee_growth_global_1_x64,
+            tick_lower.fee_growth_outside_1_x64,
+            tick_upper.fee_growth_outside_1_x64,
+        );
+
+        Ok((
+            clmm_fees_owed(position.liquidity, fee_growth_inside_0, position.fee_growth_inside_0_last_x64, position.token_fees_owed_0),
+            clmm_fees_owed(position.liquidity, fee_growth_inside_1, position.fee_growth_inside_1_last_x64, position.token_fees_owed_1),
+        ))
+    }
+
+    pub async fn get_open_position_instructions(
+        wallet_key: &str,
+        pool_address: &str,
+        token_amount: TokenAmount,
+        slippage: u16,
+        range_lower: f64,
+        range_upper: f64,
+    ) -> anyhow::Result<RaydiumInstructions> {
+        let owner = Pubkey::from_str(wallet_key)?;
+        let pool = Raydium::get_pool_state(RpcMode::fast(), pool_address).await?;
+        let token_programs = Token::get_token_programs(RpcMode::fast(), &[pool.token_mint_0, pool.token_mint_1]).await?;
+
+        let tick_lower_index = align_tick_index(
+            price_to_tick_index(range_lower, pool.mint_decimals_0, pool.mint_decimals_1),
+            pool.tick_spacing,
+        );
+        let mut tick_upper_index = align_tick_index(
+            price_to_tick_index(range_upper, pool.mint_decimals_0, pool.mint_decimals_1),
+            pool.tick_spacing,
+        );
+        if tick_upper_index <= tick_lower_index {
+            tick_upper_index = tick_lower_index + pool.tick_spacing as i32;
+        }
+
+        // Work out the liquidity the base amount buys, then the other side it needs
+        let sqrt_price = pool.sqrt_price_x64 as f64 / Q64;
+        let sqrt_lower = 1.0001f64.powi(tick_lower_index).sqrt();
+        let sqrt_upper = 1.0001f64.powi(tick_upper_index).sqrt();
+
+        let (liquidity, base_flag) = match token_amount {
+            TokenAmount::TokenA(amount) => {
+                let sqrt_from = sqrt_price.max(sqrt_lower);
+                if sqrt_from >= sqrt_upper {
+                    return Err(anyhow::anyhow!("Range is below the current price and cannot be funded with token A"));
+                }
+                (amount as f64 * sqrt_from * sqrt_upper / (sqrt_upper - sqrt_from), true)
+            }
+            TokenAmount::TokenB(amount) => {
+                let sqrt_to = sqrt_price.min(sqrt_upper);
+                if sqrt_to <= sqrt_lower {
+                    return Err(anyhow::anyhow!("Range is above the current price and cannot be funded with token B"));
+                }
+                (amount as f64 / (sqrt_to - sqrt_lower), false)
+            }
+        };
+
+        let (amount_0, amount_1) = clmm_amounts_from_liquidity(liquidity as u128, pool.sqrt_price_x64, tick_lower_index, tick_upper_index);
+        let slippage_multiplier = (10_000 + slippage as u64) as f64 / 10_000.0;
+        let (amount_0_max, amount_1_max) = match token_amount {
+            TokenAmount::TokenA(amount) => (amount, (amount_1 * slippage_multiplier).ceil() as u64),
+            TokenAmount::TokenB(amount) => ((amount_0 * slippage_multiplier).ceil() as u64, amount),
+        };
+
+        let nft_mint = Keypair::new();
+        let tick_array_lower_start_index = pool.tick_array_start_index(tick_lower_index);
+        let tick_array_upper_start_index = pool.tick_array_start_index(tick_upper_index);
+
+        let position_accounts = RaydiumPositionAccounts {
+            owner,
+            nft_mint: nft_mint.pubkey(),
+            nft_token_program: spl_token::id(),
+            nft_account: Token::get_associated_token_address(&owner, &nft_mint.pubkey(), &spl_token::id())?,
+            personal_position: Raydium::get_personal_position_address(&nft_mint.pubkey())?,
+            protocol_position: Raydium::get_protocol_position_address(&pool.address, tick_lower_index, tick_upper_index)?,
+            tick_array_lower: Raydium::get_tick_array_address(&pool.address, tick_array_lower_start_index)?,
+            tick_array_upper: Raydium::get_tick_array_address(&pool.address, tick_array_upper_start_index)?,
+            token_account_0: Token::get_associated_token_address(&owner, &pool.token_mint_0, &token_programs[0])?,
+            token_account_1: Token::get_associated_token_address(&owner, &pool.token_mint_1, &token_programs[1])?,
+        };
+
+        let mut instructions = vec![];
+        let mut closing_instructions = vec![];
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+
+        for (mint, token_program, token_account, amount_max) in [
+            (pool.token_mint_0, token_programs[0], position_accounts.token_account_0, amount_0_max),
+            (pool.token_mint_1, token_programs[1], position_accounts.token_account_1, amount_1_max),
+        ] {
+            instructions.push(Token::create_associated_token_account_idempotent(&owner, &owner, &mint, &token_program)?);
+            if mint == wsol_mint {
+                instructions.extend(Token::wrap_sol_instructions(&owner, &token_account, amount_max)?);
+                closing_instructions.push(Token::unwrap_sol_instruction(&owner, &token_account)?);
+            }
+        }
+
+        instructions.push(instructions::open_position_v2(
+            &pool,
+            &position_accounts,
+            &OpenPositionArgs {
+                tick_lower_index,
+                tick_upper_index,
+                tick_array_lower_start_index,
+                tick_array_upper_start_index,
+                amount_0_max,
+                amount_1_max,
+                base_flag: Some(base_flag),
+            },
+        )?);
+        instructions.extend(closing_instructions);
+
+        Ok(RaydiumInstructions {
+            instructions,
+            additional_signers: vec![BASE64_STANDARD.encode(nft_mint.to_bytes())],
+        })
+    }
+
+    /// Removes all liquidity, collects fees and rewards, and burns the position NFT
+    pub async fn get_close_position_instructions(wallet_key: &str, position_address: &str) -> anyhow::Result<RaydiumInstructions> {
+        Raydium::get_withdraw_instructions(wallet_key, position_address, true).await
+    }
+
+    /// Collects fees and rewards by decreasing zero liquidity, leaving the position open
+    pub async fn get_collect_fees_instructions(wallet_key: &str, position_address: &str) -> anyhow::Result<RaydiumInstructions> {
+        Raydium::get_withdraw_instructions(wallet_key, position_address, false).await
+    }
+
+    async fn get_withdraw_instructions(wallet_key: &str, position_address: &str, close: bool) -> anyhow::Result<RaydiumInstructions> {
+        let owner = Pubkey::from_str(wallet_key)?;
+        let position = Raydium::get_position(RpcMode::fast(), wallet_key, position_address).await?;
+        let pool = Raydium::get_pool_state(RpcMode::fast(), &position.pool_id.to_string()).await?;
+
+        let reward_infos: Vec<_> = pool.reward_infos.iter().filter(|reward| reward.initialized).cloned().collect();
+        let mut mints = vec![position.nft_mint, pool.token_mint_0, pool.token_mint_1];
+        mints.extend(reward_infos.iter().map(|reward| reward.token_mint));
+        let token_programs = Token::get_token_programs(RpcMode::fast(), &mints).await?;
+
+        let position_accounts = RaydiumPositionAccounts {
+            owner,
+            nft_mint: position.nft_mint,
+            nft_token_program: token_programs[0],
+            nft_account: Token::get_associated_token_address(&owner, &position.nft_mint, &token_programs[0])?,
+            personal_position: position.address,
+            protocol_position: Raydium::get_protocol_position_address(&pool.address, position.tick_lower_index, position.tick_upper_index)?,
+            tick_array_lower: Raydium::get_tick_array_address(&pool.address, pool.tick_array_start_index(position.tick_lower_index))?,
+            tick_array_upper: Raydium::get_tick_array_address(&pool.address, pool.tick_array_start_index(position.tick_upper_index))?,
+            token_account_0: Token::get_associated_token_address(&owner, &pool.token_mint_0, &token_programs[1])?,
+            token_account_1: Token::get_associated_token_address(&owner, &pool.token_mint_1, &token_programs[2])?,
+        };
+
+        let mut instructions = vec![
+            Token::create_associated_token_account_idempotent(&owner, &owner, &pool.token_mint_0, &token_programs[1])?,
+            Token::create_associated_token_account_idempotent(&owner, &owner, &pool.token_mint_1, &token_programs[2])?,
+        ];
+
+        let mut reward_accounts = vec![];
+        for (reward, token_program) in reward_infos.iter().zip(token_programs.iter().skip(3)) {
+            instructions.push(Token::create_associated_token_account_idempotent(&owner, &owner, &reward.token_mint, token_program)?);
+            reward_accounts.push((
+                reward.token_vault,
+                Token::get_associated_token_address(&owner, &reward.token_mint, token_program)?,
+                reward.token_mint,
+            ));
+        }
+
+        let liquidity = if close { position.liquidity } else { 0 };
+        let (amount_0, amount_1) = clmm_amounts_from_liquidity(
+            liquidity,
+            pool.sqrt_price_x64,
+            position.tick_lower_index,
+            position.tick_upper_index,
+        );
+        let amount_0_min = (amount_0 * (10_000 - CLOSE_SLIPPAGE_BPS) as f64 / 10_000.0) as u64;
+        let amount_1_min = (amount_1 * (10_000 - CLOSE_SLIPPAGE_BPS) as f64 / 10_000.0) as u64;
+
+        instructions.push(instructions::decrease_liquidity_v2(
+            &pool,
+            &position_accounts,
+            liquidity,
+            amount_0_min,
+            amount_1_min,
+            &reward_accounts,
+        )?);
+        if close {
+            instructions.push(instructions::close_position(&position_accounts)?);
+        }
+
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+        if pool.token_mint_0 == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &position_accounts.token_account_0)?);
+        } else if pool.token_mint_1 == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &position_accounts.token_account_1)?);
+        }
+
+        Ok(RaydiumInstructions {
+            instructions,
+            additional_signers: vec![],
+        })
+    }
+
+    /// Spot estimate of a swap at the current pool price and trade fee, without price impact
+    pub async fn get_swap_quote(token_swap: &TokenSwap) -> anyhow::Result<SwapQuote> {
+        let pool = Raydium::get_pool_state(RpcMode::fast(), &token_swap.pool_address).await?;
+        let sell_mint = Pubkey::from_str(&token_swap.mint_out_address)?;
+
+        let zero_for_one = if sell_mint == pool.token_mint_0 {
+            true
+        } else if sell_mint == pool.token_mint_1 {
+            false
+        } else {
+            return Err(anyhow::anyhow!("Mint {} is not part of pool {}", sell_mint, token_swap.pool_address));
+        };
+
+        Raydium::quote_with_pool(&pool, token_swap, zero_for_one).await
+    }
+
+    async fn quote_with_pool(pool: &RaydiumPoolState, token_swap: &TokenSwap, zero_for_one: bool) -> anyhow::Result<SwapQuote> {
+        let amm_config = Rpc::get_account(RpcMode::fast(), &pool.amm_config, Some(10000)).await?;
+        let fee_rate = amm_config_trade_fee_rate(&amm_config.data)? as f64 / 1_000_000.0;
+
+        let buy_mint = if zero_for_one { pool.token_mint_1 } else { pool.token_mint_0 };
+
+        SwapQuote::from_clmm_liquidity(token_swap, zero_for_one, pool.liquidity, pool.sqrt_price_x64, fee_rate)?
+            .net_of_transfer_fees(token_swap, &buy_mint.to_string())
+            .await
+    }
+
+    /// Builds a swap on a Raydium CLMM pool. `mint_out_address` is the mint being sold, matching
+    /// how swaps are requested from the Orca sidecar.
+    pub async fn get_swap_instructions(token_swap: TokenSwap) -> anyhow::Result<RaydiumInstructions> {
+        let owner = Pubkey::from_str(&token_swap.wallet_key)?;
+        let pool = Raydium::get_pool_state(RpcMode::fast(), &token_swap.pool_address).await?;
+        let sell_mint = Pubkey::from_str(&token_swap.mint_out_address)?;
+
+        let zero_for_one = if sell_mint == pool.token_mint_0 {
+            true
+        } else if sell_mint == pool.token_mint_1 {
+            false
+        } else {
+            return Err(anyhow::anyhow!("Mint {} is not part of pool {}", sell_mint, token_swap.pool_address));
+        };
+
+        let quote = Raydium::quote_with_pool(&pool, &token_swap, zero_for_one).await?;
+        let other_amount_threshold = quote.other_amount_threshold;
+        let max_amount_in = if token_swap.amount_is_in { token_swap.amount } else { other_amount_threshold };
+
+        let (input_mint, output_mint) = if zero_for_one {
+            (pool.token_mint_0, pool.token_mint_1)
+        } else {
+            (pool.token_mint_1, pool.token_mint_0)
+        };
+        let token_programs = Token::get_token_programs(RpcMode::fast(), &[input_mint, output_mint]).await?;
+        let input_token_account = Token::get_associated_token_address(&owner, &input_mint, &token_programs[0])?;
+        let output_token_account = Token::get_associated_token_address(&owner, &output_mint, &token_programs[1])?;
+
+        // Bitmap extension and the next initialized tick arrays in the swap direction
+        let ticks_in_array = pool.tick_spacing as i32 * TICK_ARRAY_SIZE;
+        let current_start = pool.tick_array_start_index(pool.tick_current);
+        let mut candidates = vec![Raydium::get_bitmap_extension_address(&pool.address)?];
+        for step in 0..6 {
+            let start_index = if zero_for_one {
+                current_start - step * ticks_in_array
+            } else {
+                current_start + step * ticks_in_array
+            };
+            candidates.push(Raydium::get_tick_array_address(&pool.address, start_index)?);
+        }
+
+        let candidate_accounts = Rpc::get_multiple_accounts(RpcMode::fast(), &candidates, Some(10000)).await?;
+        let mut remaining_accounts = vec![];
+        let mut tick_array_count = 0;
+        for (index, (address, account)) in candidates.into_iter().zip(candidate_accounts).enumerate() {
+            if account.is_none() || (index > 0 && tick_array_count == 3) {
+                continue;
+            }
+            if index > 0 {
+                tick_array_count += 1;
+            }
+            remaining_accounts.push(address);
+        }
+
+        if tick_array_count == 0 {
+            return Err(anyhow::anyhow!("No initialized tick arrays found for pool {}", token_swap.pool_address));
+        }
+
+        let mut instructions = vec![
+            Token::create_associated_token_account_idempotent(&owner, &owner, &input_mint, &token_programs[0])?,
+            Token::create_associated_token_account_idempotent(&owner, &owner, &output_mint, &token_programs[1])?,
+        ];
+
+        let wsol_mint = Pubkey::from_str(&Token::solana().address)?;
+        if input_mint == wsol_mint {
+            instructions.extend(Token::wrap_sol_instructions(&owner, &input_token_account, max_amount_in)?);
+        }
+
+        instructions.push(instructions::swap_v2(
+            &pool,
+            &owner,
+            &input_token_account,
+            &output_token_account,
+            &SwapArgs {
+                amount: token_swap.amount,
+                other_amount_threshold,
+                is_base_input: token_swap.amount_is_in,
+                zero_for_one,
+            },
+            &remaining_accounts,
+        )?);
+
+        if input_mint == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &input_token_account)?);
+        } else if output_mint == wsol_mint {
+            instructions.push(Token::unwrap_sol_instruction(&owner, &output_token_account)?);
+        }
 
-}
\ No newline at end of file
+        Ok(RaydiumInstructions {
+            instructions,
+            additional_signers: vec![],
+        })
+    }
+}
@@ -0,0 +1,151 @@
+use std::str::FromStr;
+
+use kebtech_utils::*;
+use solana_sdk::{compute_budget::ComputeBudgetInstruction, instruction::Instruction, signature::Signature, transaction::VersionedTransaction};
+
+use crate::{rpc::{jito::{BundleResult, BundleStatus, Jito}, ComputeUnitLimit, Rpc, RpcMode}, token::Token, wallet::{lookup_table::LookupTable, programmatic_transaction::{ProgrammaticTransaction, TransactionKind}, transaction_ledger::{LedgerRecord, LedgerStatus, TransactionLedger}, Wallet}};
+
+use super::{dex_adapter::{AdapterInstructions, DexRegistry, OpenPositionRequest}, new_position::{NewPositionData, NewProgrammaticPosition}, orca::token_swap::TokenSwap, position_manager::managed_position::ManagedPosition};
+
+/// Same reserve and buffer `NewProgrammaticPosition::open` leaves when sizing a position
+const SOL_FEE_RESERVE: f64 = 0.1;
+const OPEN_BUFFER_PERCENT: f64 = 0.075;
+const SWAP_SLIPPAGE_BPS: u16 = 50;
+const OPEN_SLIPPAGE_BPS: u16 = 500;
+
+/// Close, swap and reopen of a programmatic position sent as one Jito bundle, so nothing can be
+/// slipped in between them. Everything is built up front from the position's current amounts,
+/// as the bundle lands all at once or not at all.
+pub struct RebalanceBundle;
+
+impl RebalanceBundle {
+    pub async fn execute(position: &ManagedPosition) -> anyhow::Result<BundleResult> {
+        let payer = Wallet::get_programmatic_pubkey()?;
+        let adapter = DexRegistry::get(&position.pool_type)?;
+        let new_position = NewProgrammaticPosition::from_managed_position(position)?;
+        let token_a = position.token_a.clone().ok_or_else(|| anyhow::anyhow!("Token A not found in managed position"))?;
+        let token_b = position.token_b.clone().ok_or_else(|| anyhow::anyhow!("Token B not found in managed position"))?;
+        let scale_a = 10f64.powi(token_a.decimals as i32);
+        let scale_b = 10f64.powi(token_b.decimals as i32);
+
+        let close = adapter.close_position(position).await?;
+
+        // Wallet balances once the position's liquidity and fees are withdrawn
+        let mut amount_a = NewPositionData::fetch_balance_a_amount(&new_position).await?
+            + ((position.balance_token_a + position.yield_token_a) * scale_a) as u64;
+        let mut amount_b = NewPositionData::fetch_balance_b_amount(&new_position).await?
+            + ((position.balance_token_b + position.yield_token_b) * scale_b) as u64;
+        if token_a.address == Token::solana().address {
+            amount_a = amount_a.saturating_sub((SOL_FEE_RESERVE * scale_a) as u64);
+        }
+
+        let price = adapter.pool_price(&position.pool_address).await?;
+        let value_a = amount_a as f64 / scale_a * price;
+        let value_b = amount_b as f64 / scale_b;
+        let total_value = value_a + value_b;
+        if total_value <= 0.0 {
+            return Err(anyhow::anyhow!("Nothing to reopen position {} with", position.address));
+        }
+
+        let mut steps: Vec<(TransactionKind, AdapterInstructions)> = vec![(TransactionKind::Close, close)];
+
+        // Same 50/50 balancing as `NewProgrammaticPosition::balance_tokens_core`, sized from the
+        // quote since the swap can't be checked before the close lands
+        let ratio_a = value_a / total_value;
+        if !(0.45..=0.55).contains(&ratio_a) {
+            let (mint_sold, swap_amount) = if value_a > value_b {
+                (token_a.address.clone(), ((value_a - total_value / 2.0) / price * scale_a) as u64)
+            } else {
+                (token_b.address.clone(), ((value_b - total_value / 2.0) * scale_b) as u64)
+            };
+            let token_swap = TokenSwap::new(
+                payer.to_string(),
+                position.pool_address.clone(),
+                swap_amount,
+                true,
+                mint_sold.clone(),
+                Some(SWAP_SLIPPAGE_BPS),
+            );
+
+            let quote = adapter.quote(&token_swap).await?;
+            if mint_sold == token_a.address {
+                amount_a = amount_a.saturating_sub(quote.amount_in);
+                amount_b += quote.other_amount_threshold;
+            } else {
+                amount_b = amount_b.saturating_sub(quote.amount_in);
+                amount_a += quote.other_amount_threshold;
+            }
+
+            steps.push((TransactionKind::Swap, adapter.swap(&token_swap).await?));
+        }
+
+        let (range_lower, range_upper) = NewPositionData::get_ranges(price);
+        let open_position_request = OpenPositionRequest {
+            wallet_key: payer.to_string(),
+            pool_address: position.pool_address.clone(),
+            token_amount_a: amount_a.saturating_sub((amount_a as f64 * OPEN_BUFFER_PERCENT) as u64),
+            token_amount_b: amount_b.saturating_sub((amount_b as f64 * OPEN_BUFFER_PERCENT) as u64),
+            slippage: OPEN_SLIPPAGE_BPS,
+            range_lower,
+            range_upper,
+        };
+        let mut open = adapter.open_position(&open_position_request).await?;
+
+        // Tipping in the last transaction means the tip is only paid with the whole rebalance
+        let jito = Jito::from_env();
+        let tip_lamports = Jito::tip_lamports();
+        open.instructions.push(jito.tip_instruction(&payer, tip_lamports).await?);
+        steps.push((TransactionKind::Open, open));
+
+        let (recent_blockhash, last_valid_block_height) = Rpc::get_latest_blockhash_with_height(RpcMode::fast(), None).await?;
+        // Priority comes from the tip, so only the compute limit is set
+        let compute_unit_limit = ComputeUnitLimit::HighUsage.to_limit().unwrap_or(200_000);
+        let kinds: Vec<TransactionKind> = steps.iter().map(|(kind, _)| *kind).collect();
+        let transactions = steps
+            .into_iter()
+            .map(|(_, step)| {
+                let mut instructions: Vec<Instruction> = vec![ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit)];
+                instructions.extend(step.instructions);
+
+                let signers = ProgrammaticTransaction::get_all_signers(step.additional_signers)?;
+                LookupTable::record_usage(&instructions);
+                Rpc::build_transaction(&payer, &instructions, &signers, recent_blockhash)
+            })
+            .collect::<anyhow::Result<Vec<VersionedTransaction>>>()?;
+
+        magenta!("Sending rebalance of {} as a bundle of {} transactions", position.address, transactions.len());
+        let bundle_result = jito.send_and_track(transactions, tip_lamports, last_valid_block_height).await?;
+
+        RebalanceBundle::record(&bundle_result, &kinds, &position.address, compute_unit_limit);
+
+        Ok(bundle_result)
+    }
+
+    /// Writes each transaction of the bundle to the ledger. A bundle that didn't land can't
+    /// anymore: a failed one isn't forwarded again, and `Jito::send_and_track` waits out the
+    /// blockhash of any other. One whose outcome is unknown is left unresolved.
+    fn record(bundle_result: &BundleResult, kinds: &[TransactionKind], position_address: &str, compute_unit_limit: u32) {
+        for (signature, kind) in bundle_result.signatures.iter().zip(kinds) {
+            let status = match bundle_result.status {
+                BundleStatus::Landed { .. } => LedgerStatus::Confirmed,
+                BundleStatus::Unknown => LedgerStatus::Unresolved,
+                _ => LedgerStatus::Expired,
+            };
+            let mut record = LedgerRecord::new(signature.clone(), *kind, Some(position_address.to_string()), status);
+            record.signature = Some(signature.clone());
+            record.slot = match bundle_result.status {
+                BundleStatus::Landed { slot } => Some(slot),
+                _ => None,
+            };
+            record.compute_unit_limit = Some(compute_unit_limit);
+            record.rpc_provider = Some("jito".to_string());
+            TransactionLedger::record(record);
+
+            if bundle_result.landed() {
+                if let Ok(signature) = Signature::from_str(signature) {
+                    TransactionLedger::spawn_enrich(signature);
+                }
+            }
+        }
+    }
+}
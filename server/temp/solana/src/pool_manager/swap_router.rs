@@ -0,0 +1,759 @@
+use std::str::FromStr;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use jupiter_swap_api_client::{quote::{QuoteRequest, QuoteResponse, SwapMode}, swap::{SwapRequest, SwapResponse}, transaction_config::TransactionConfig, JupiterSwapApiClient};
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_response::RpcSimulateTransactionResult;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer, transaction::VersionedTransaction};
+
+use crate::{price_info::coinbase::ticker::TickerState, rpc::{Rpc, RpcMode}, token::Token, wallet::{programmatic_transaction::{ProgrammaticTransaction, TransactionKind}, transaction_ledger::TransactionLedger, Wallet}};
+
+use super::{dex_adapter::DexRegistry, orca::token_swap::TokenSwap, position_manager::managed_position::PoolType, POOL_MANAGER};
+
+const DEFAULT_JUPITER_API_URL: &str = "https://quote-api.jup.ag/v6";
+const MAX_SWAP_ROUTES: usize = 200;
+const DEFAULT_MAX_PRICE_IMPACT_BPS: f64 = 300.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum SwapRoute {
+    Jupiter,
+    Direct(PoolType),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SwapFee {
+    pub label: String,
+    pub mint: String,
+    pub amount: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteQuote {
+    pub route: SwapRoute,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub other_amount_threshold: u64,
+    /// Venue labels along the route, a single entry for direct swaps
+    pub hops: Vec<String>,
+    /// LP fees charged along the route, in raw units of the fee mint
+    #[serde(default)]
+    pub fees: Vec<SwapFee>,
+    /// Jupiter's own estimate of the route's price impact, as a fraction
+    #[serde(default)]
+    pub price_impact_pct: Option<f64>,
+}
+
+/// A swap to quote for the programmatic wallet. As with `TokenSwap`, `mint_out_address` is the
+/// mint leaving the wallet and `mint_in_address` the one coming in. The direct route is only
+/// compared when a pool is given.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SwapQuoteRequest {
+    pub mint_in_address: String,
+    pub mint_out_address: String,
+    pub amount: u64,
+    pub amount_is_in: bool,
+    pub slippage_tolerance: Option<u16>,
+    pub pool_address: Option<String>,
+    pub pool_type: Option<PoolType>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SwapSimulation {
+    pub success: bool,
+    pub error: Option<String>,
+    pub units_consumed: Option<u64>,
+    pub logs: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SwapQuoteResult {
+    pub chosen: RouteQuote,
+    pub alternative: Option<RouteQuote>,
+    pub expected_amount_in: u64,
+    pub expected_amount_out: u64,
+    /// Set for exact in swaps, the least the wallet accepts at `slippage_tolerance`
+    pub minimum_amount_out: Option<u64>,
+    /// Set for exact out swaps, the most the wallet pays at `slippage_tolerance`
+    pub maximum_amount_in: Option<u64>,
+    /// Mint bought per mint sold, in UI units
+    pub execution_price: f64,
+    pub reference_price: Option<f64>,
+    /// All-in shortfall of the execution price against the reference, fees included
+    pub price_impact_bps: Option<f64>,
+    pub max_price_impact_bps: f64,
+    pub simulation: SwapSimulation,
+}
+
+/// What the router did for one swap, kept on the pool manager so the choice can be audited
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SwapRouteRecord {
+    pub wallet_key: String,
+    pub pool_address: String,
+    pub mint_sold: String,
+    pub mint_bought: String,
+    pub amount_is_in: bool,
+    pub chosen: RouteQuote,
+    pub alternative: Option<RouteQuote>,
+    /// How much better the chosen route was than the alternative, in basis points of the
+    /// alternative's output (exact in) or input (exact out)
+    pub improvement_bps: Option<f64>,
+    pub signature: String,
+    pub executed_at: DateTime<Utc>,
+}
+
+/// Most price impact a swap may have, `MAX_SWAP_PRICE_IMPACT_BPS`. A swap with no impact
+/// estimate can't be held to it, so it is rejected unless `ALLOW_UNCHECKED_SWAP_PRICE_IMPACT`
+/// is set to `true`.
+#[derive(Debug, Clone, Copy)]
+pub struct PriceImpactCeiling {
+    pub max_price_impact_bps: f64,
+    pub allow_unchecked: bool,
+}
+
+impl PriceImpactCeiling {
+    pub fn from_env() -> Self {
+        PriceImpactCeiling::from_settings(|setting| std::env::var(setting).ok())
+    }
+
+    fn from_settings(env: impl Fn(&str) -> Option<String>) -> Self {
+        PriceImpactCeiling {
+            max_price_impact_bps: env("MAX_SWAP_PRICE_IMPACT_BPS")
+                .and_then(|value| value.parse::<f64>().ok())
+                .unwrap_or(DEFAULT_MAX_PRICE_IMPACT_BPS),
+            allow_unchecked: env("ALLOW_UNCHECKED_SWAP_PRICE_IMPACT").is_some_and(|value| value == "true"),
+        }
+    }
+
+    pub fn check(&self, price_impact_bps: Option<f64>) -> anyhow::Result<()> {
+        match price_impact_bps {
+            Some(price_impact_bps) if price_impact_bps > self.max_price_impact_bps => Err(anyhow::anyhow!(
+                "Swap rejected: price impact of {:.1} bps exceeds the ceiling of {:.1} bps",
+                price_impact_bps,
+                self.max_price_impact_bps,
+            )),
+            Some(_) => Ok(()),
+            None if self.allow_unchecked => {
+                yellow!("Swap has no price impact estimate, letting it through unchecked");
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Swap rejected: no price impact estimate to hold against the ceiling of {:.1} bps", self.max_price_impact_bps)),
+        }
+    }
+}
+
+pub struct SwapRouter;
+
+impl SwapRouter {
+    fn jupiter_client() -> JupiterSwapApiClient {
+        let base_path = std::env::var("JUPITER_API_URL").unwrap_or_else(|_| DEFAULT_JUPITER_API_URL.to_string());
+        JupiterSwapApiClient::new(base_path)
+    }
+
+    /// The mint the swap buys, taken from the other side of the swap's pool
+    async fn get_mint_bought(token_swap: &TokenSwap, pool_type: &PoolType) -> anyhow::Result<String> {
+        let pool_info = DexRegistry::get(pool_type)?.pool_info(&token_swap.pool_address).await?;
+
+        if pool_info.token_mint_a == token_swap.mint_out_address {
+            Ok(pool_info.token_mint_b)
+        } else if pool_info.token_mint_b == token_swap.mint_out_address {
+            Ok(pool_info.token_mint_a)
+        } else {
+            Err(anyhow::anyhow!("Mint {} is not part of pool {}", token_swap.mint_out_address, token_swap.pool_address))
+        }
+    }
+
+    pub async fn get_jupiter_quote(jupiter: &JupiterSwapApiClient, token_swap: &TokenSwap, mint_bought: &str) -> anyhow::Result<QuoteResponse> {
+        let quote_request = QuoteRequest {
+            input_mint: Pubkey::from_str(&token_swap.mint_out_address)?,
+            output_mint: Pubkey::from_str(mint_bought)?,
+            amount: token_swap.amount,
+            swap_mode: Some(if token_swap.amount_is_in { SwapMode::ExactIn } else { SwapMode::ExactOut }),
+            slippage_bps: token_swap.slippage_tolerance.unwrap_or(50),
+            ..QuoteRequest::default()
+        };
+
+        let quote_response = jupiter.quote(&quote_request).await?;
+
+        Ok(quote_response)
+    }
+
+    /// Quotes the swap through Jupiter and directly through its own pool, best route first.
+    /// A route that fails to quote is left out, so the result is only empty if both fail.
+    pub async fn get_route_quotes(token_swap: &TokenSwap, pool_type: &PoolType) -> anyhow::Result<Vec<(RouteQuote, Option<QuoteResponse>)>> {
+        let mint_bought = SwapRouter::get_mint_bought(token_swap, pool_type).await?;
+
+        SwapRouter::quote_routes(&SwapRouter::jupiter_client(), token_swap, Some(pool_type), &mint_bought).await
+    }
+
+    async fn quote_routes(jupiter: &JupiterSwapApiClient, token_swap: &TokenSwap, pool_type: Option<&PoolType>, mint_bought: &str) -> anyhow::Result<Vec<(RouteQuote, Option<QuoteResponse>)>> {
+        let direct_quote = async {
+            match pool_type {
+                Some(pool_type) => DexRegistry::get(pool_type)?.quote(token_swap).await.map(Some),
+                None => Ok(None),
+            }
+        };
+
+        let (direct_quote, jupiter_quote) = tokio::join!(
+            direct_quote,
+            SwapRouter::get_jupiter_quote(jupiter, token_swap, mint_bought),
+        );
+
+        let direct_quote = match (pool_type, direct_quote) {
+            (Some(pool_type), Ok(Some(quote))) => Some((
+                RouteQuote {
+                    route: SwapRoute::Direct(pool_type.clone()),
+                    amount_in: quote.amount_in,
+                    amount_out: quote.amount_out,
+                    other_amount_threshold: quote.other_amount_threshold,
+                    hops: vec![format!("{:?}", pool_type)],
+                    fees: vec![SwapFee {
+                        label: format!("{:?}", pool_type),
+                        mint: token_swap.mint_out_address.clone(),
+                        amount: (quote.amount_in as f64 * quote.fee_rate) as u64,
+                    }],
+                    price_impact_pct: None,
+                },
+                quote.price_impact_included,
+            )),
+            (Some(pool_type), Err(e)) => {
+                yellow!("Direct {:?} quote failed: {:?}", pool_type, e);
+                None
+            }
+            _ => None,
+        };
+
+        let jupiter_quote = match jupiter_quote {
+            Ok(quote_response) => Some((SwapRouter::jupiter_route_quote(&quote_response), quote_response)),
+            Err(e) => {
+                yellow!("Jupiter quote failed: {:?}", e);
+                None
+            }
+        };
+
+        let quotes = SwapRouter::rank_route_quotes(direct_quote, jupiter_quote, token_swap.amount_is_in);
+
+        if quotes.is_empty() {
+            return Err(anyhow::anyhow!("No route could quote a swap of {} {}", token_swap.amount, token_swap.mint_out_address));
+        }
+
+        Ok(quotes)
+    }
+
+    fn jupiter_route_quote(quote_response: &QuoteResponse) -> RouteQuote {
+        RouteQuote {
+            route: SwapRoute::Jupiter,
+            amount_in: quote_response.in_amount,
+            amount_out: quote_response.out_amount,
+            other_amount_threshold: quote_response.other_amount_threshold,
+            hops: quote_response.route_plan.iter().map(|step| step.swap_info.label.clone()).collect(),
+            fees: quote_response.route_plan.iter().map(|step| SwapFee {
+                label: step.swap_info.label.clone(),
+                mint: step.swap_info.fee_mint.to_string(),
+                amount: step.swap_info.fee_amount,
+            }).collect(),
+            price_impact_pct: quote_response.price_impact_pct.to_string().parse().ok(),
+        }
+    }
+
+    /// Orders the quotes best first. A direct estimate understates price impact and would beat
+    /// Jupiter's real quote on any sizeable swap, so it only stands in when Jupiter has no quote.
+    fn rank_route_quotes(
+        direct_quote: Option<(RouteQuote, bool)>,
+        jupiter_quote: Option<(RouteQuote, QuoteResponse)>,
+        amount_is_in: bool,
+    ) -> Vec<(RouteQuote, Option<QuoteResponse>)> {
+        let mut quotes = vec![];
+
+        match direct_quote {
+            Some((_, false)) if jupiter_quote.is_some() => yellow!("Direct quote is an estimate, comparing Jupiter only"),
+            Some((route_quote, _)) => quotes.push((route_quote, None)),
+            None => (),
+        }
+
+        if let Some((route_quote, quote_response)) = jupiter_quote {
+            quotes.push((route_quote, Some(quote_response)));
+        }
+
+        // More out for exact in swaps, less in for exact out swaps
+        if amount_is_in {
+            quotes.sort_by_key(|a| std::cmp::Reverse(a.0.amount_out));
+        } else {
+            quotes.sort_by_key(|a| a.0.amount_in);
+        }
+
+        quotes
+    }
+
+    /// Mint bought per mint sold to judge a quote against. The Coinbase ticker is used for SOL
+    /// against a stablecoin, otherwise the spot price of the direct pool when there is one.
+    pub async fn get_reference_price(token_sold: &Token, token_bought: &Token, pool: Option<(&PoolType, &str)>) -> Option<f64> {
+        let sol_address = Token::solana().address;
+        let ticker_price = TickerState::get_current_price().ok().filter(|price| *price > 0.0);
+
+        if let Some(ticker_price) = ticker_price {
+            if token_sold.address == sol_address && token_bought.is_stablecoin {
+                return Some(ticker_price);
+            }
+            if token_sold.is_stablecoin && token_bought.address == sol_address {
+                return Some(1.0 / ticker_price);
+            }
+        }
+
+        let (pool_type, pool_address) = pool?;
+        let pool_info = DexRegistry::get(pool_type).ok()?.pool_info(pool_address).await.ok()?;
+
+        if pool_info.price <= 0.0 {
+            None
+        } else if pool_info.token_mint_a == token_sold.address {
+            Some(pool_info.price)
+        } else {
+            Some(1.0 / pool_info.price)
+        }
+    }
+
+    /// Mint bought per mint sold for a quote, in UI units
+    fn execution_price(quote: &RouteQuote, token_sold: &Token, token_bought: &Token) -> f64 {
+        let amount_in = quote.amount_in as f64 / 10f64.powi(token_sold.decimals as i32);
+        let amount_out = quote.amount_out as f64 / 10f64.powi(token_bought.decimals as i32);
+
+        if amount_in > 0.0 { amount_out / amount_in } else { 0.0 }
+    }
+
+    /// Shortfall of the route's execution price against the reference price, or Jupiter's own
+    /// estimate for the route when there is no reference price
+    fn price_impact_bps(quote: &RouteQuote, execution_price: f64, reference_price: Option<f64>) -> Option<f64> {
+        reference_price
+            .map(|reference_price| (reference_price - execution_price) / reference_price * 10_000.0)
+            .or(quote.price_impact_pct.map(|price_impact_pct| price_impact_pct * 10_000.0))
+    }
+
+    /// Rejects a swap against the configured price impact ceiling
+    pub fn check_price_impact(price_impact_bps: Option<f64>) -> anyhow::Result<()> {
+        PriceImpactCeiling::from_env().check(price_impact_bps)
+    }
+
+    /// Quotes both routes for the programmatic wallet and simulates the better one
+    pub async fn quote(request: SwapQuoteRequest) -> anyhow::Result<SwapQuoteResult> {
+        let wallet_key = Wallet::get_programmatic_pubkey()?.to_string();
+        let token_sold = Token::from_mint_address(&request.mint_out_address).await?;
+        let token_bought = Token::from_mint_address(&request.mint_in_address).await?;
+
+        let pool = match (&request.pool_type, &request.pool_address) {
+            (Some(pool_type), Some(pool_address)) => Some((pool_type, pool_address.as_str())),
+            (None, None) => None,
+            _ => return Err(anyhow::anyhow!("Pool type and pool address must be given together")),
+        };
+
+        let token_swap = TokenSwap::new(
+            wallet_key,
+            request.pool_address.clone().unwrap_or_default(),
+            request.amount,
+            request.amount_is_in,
+            request.mint_out_address.clone(),
+            request.slippage_tolerance,
+        );
+
+        let jupiter = SwapRouter::jupiter_client();
+        let mut quotes = SwapRouter::quote_routes(&jupiter, &token_swap, pool.map(|(pool_type, _)| pool_type), &request.mint_in_address).await?.into_iter();
+        let (chosen, jupiter_quote) = quotes.next().ok_or_else(|| anyhow::anyhow!("No swap route available"))?;
+        let alternative = quotes.next().map(|(quote, _)| quote);
+
+        let execution_price = SwapRouter::execution_price(&chosen, &token_sold, &token_bought);
+        let reference_price = SwapRouter::get_reference_price(&token_sold, &token_bought, pool).await;
+
+        let simulation = match &chosen.route {
+            SwapRoute::Jupiter => match jupiter_quote {
+                Some(quote_response) => SwapRouter::simulate_jupiter(&jupiter, quote_response).await,
+                None => Err(anyhow::anyhow!("Jupiter route chosen without a Jupiter quote")),
+            },
+            SwapRoute::Direct(pool_type) => SwapRouter::simulate_direct(&token_swap, pool_type).await,
+        }
+        .unwrap_or_else(|e| SwapSimulation { success: false, error: Some(e.to_string()), units_consumed: None, logs: vec![] });
+
+        Ok(SwapQuoteResult {
+            expected_amount_in: chosen.amount_in,
+            expected_amount_out: chosen.amount_out,
+            minimum_amount_out: request.amount_is_in.then_some(chosen.other_amount_threshold),
+            maximum_amount_in: (!request.amount_is_in).then_some(chosen.other_amount_threshold),
+            execution_price,
+            reference_price,
+            price_impact_bps: SwapRouter::price_impact_bps(&chosen, execution_price, reference_price),
+            max_price_impact_bps: PriceImpactCeiling::from_env().max_price_impact_bps,
+            chosen,
+            alternative,
+            simulation,
+        })
+    }
+
+    async fn simulate_direct(token_swap: &TokenSwap, pool_type: &PoolType) -> anyhow::Result<SwapSimulation> {
+        let swap_instructions = DexRegistry::get(pool_type)?.swap(token_swap).await?;
+        let transaction = ProgrammaticTransaction::new(swap_instructions.instructions, swap_instructions.additional_signers).await?;
+
+        let (_, result) = Rpc::simulate_versioned_transaction(RpcMode::fast(), transaction.transaction, Some(20000)).await?;
+
+        Ok(SwapSimulation::from(result))
+    }
+
+    async fn simulate_jupiter(jupiter: &JupiterSwapApiClient, quote_response: QuoteResponse) -> anyhow::Result<SwapSimulation> {
+        let wallet = Wallet::get_programmatic_keypair()?;
+        let (transaction, _) = SwapRouter::get_jupiter_transaction(jupiter, &wallet, quote_response).await?;
+        let (_, result) = Rpc::simulate_versioned_transaction(RpcMode::fast(), transaction, Some(20000)).await?;
+
+        Ok(SwapSimulation::from(result))
+    }
+
+    /// Swaps with the programmatic wallet over whichever route returns more, and records the choice
+    pub async fn execute(token_swap: TokenSwap, pool_type: &PoolType) -> anyhow::Result<SwapRouteRecord> {
+        let mint_bought = SwapRouter::get_mint_bought(&token_swap, pool_type).await?;
+
+        SwapRouter::execute_route(token_swap, Some(pool_type), mint_bought).await
+    }
+
+    /// Executes a quote request with the programmatic wallet. The impact ceiling is checked on
+    /// the route that is about to execute, not on a separate quote.
+    pub async fn execute_request(request: SwapQuoteRequest) -> anyhow::Result<SwapRouteRecord> {
+        if request.pool_type.is_some() != request.pool_address.is_some() {
+            return Err(anyhow::anyhow!("Pool type and pool address must be given together"));
+        }
+
+        let token_swap = TokenSwap::new(
+            Wallet::get_programmatic_pubkey()?.to_string(),
+            request.pool_address.unwrap_or_default(),
+            request.amount,
+            request.amount_is_in,
+            request.mint_out_address,
+            request.slippage_tolerance,
+        );
+
+        SwapRouter::execute_route(token_swap, request.pool_type.as_ref(), request.mint_in_address).await
+    }
+
+    /// Sells `amount` of `mint_sold` for `mint_bought` with the programmatic wallet through
+    /// Jupiter, for swaps that no single pool of ours covers. The record's `other_amount_threshold`
+    /// is the least the wallet is guaranteed to receive.
+    pub async fn swap_into(mint_sold: &str, mint_bought: &str, amount: u64, slippage_tolerance: Option<u16>) -> anyhow::Result<SwapRouteRecord> {
+        let token_swap = TokenSwap::new(
+            Wallet::get_programmatic_pubkey()?.to_string(),
+            String::new(),
+            amount,
+            true,
+            mint_sold.to_string(),
+            slippage_tolerance,
+        );
+
+        SwapRouter::execute_route(token_swap, None, mint_bought.to_string()).await
+    }
+
+    /// Jupiter's unsigned transaction selling `amount` of `mint_sold` for `mint_bought` from a
+    /// local wallet, base64 encoded for the wallet to sign
+    pub async fn get_unsigned_jupiter_swap(wallet_key: &str, mint_sold: &str, mint_bought: &str, amount: u64, slippage_tolerance: Option<u16>) -> anyhow::Result<String> {
+        let token_swap = TokenSwap::new(
+            wallet_key.to_string(),
+            String::new(),
+            amount,
+            true,
+            mint_sold.to_string(),
+            slippage_tolerance,
+        );
+
+        let jupiter = SwapRouter::jupiter_client();
+        let quote_response = SwapRouter::get_jupiter_quote(&jupiter, &token_swap, mint_bought).await?;
+        let route_quote = SwapRouter::jupiter_route_quote(&quote_response);
+        SwapRouter::check_price_impact(route_quote.price_impact_pct.map(|price_impact_pct| price_impact_pct * 10_000.0))?;
+
+        let swap_response = SwapRouter::get_jupiter_swap(&jupiter, Pubkey::from_str(wallet_key)?, quote_response).await?;
+
+        Ok(BASE64_STANDARD.encode(swap_response.swap_transaction))
+    }
+
+    async fn execute_route(token_swap: TokenSwap, pool_type: Option<&PoolType>, mint_bought: String) -> anyhow::Result<SwapRouteRecord> {
+        let jupiter = SwapRouter::jupiter_client();
+        let mut quotes = SwapRouter::quote_routes(&jupiter, &token_swap, pool_type, &mint_bought).await?.into_iter();
+        let (chosen, jupiter_quote) = quotes.next().ok_or_else(|| anyhow::anyhow!("No swap route available"))?;
+        let alternative = quotes.next().map(|(quote, _)| quote);
+
+        let improvement_bps = alternative.as_ref().map(|alternative| {
+            if token_swap.amount_is_in {
+                (chosen.amount_out as f64 - alternative.amount_out as f64) / alternative.amount_out.max(1) as f64 * 10_000.0
+            } else {
+                (alternative.amount_in as f64 - chosen.amount_in as f64) / alternative.amount_in.max(1) as f64 * 10_000.0
+            }
+        });
+
+        let token_sold = Token::from_mint_address(&token_swap.mint_out_address).await?;
+        let token_bought = Token::from_mint_address(&mint_bought).await?;
+        let pool = pool_type.map(|pool_type| (pool_type, token_swap.pool_address.as_str()));
+        let reference_price = SwapRouter::get_reference_price(&token_sold, &token_bought, pool).await;
+        let execution_price = SwapRouter::execution_price(&chosen, &token_sold, &token_bought);
+
+        SwapRouter::check_price_impact(SwapRouter::price_impact_bps(&chosen, execution_price, reference_price))?;
+
+        blue!(
+            "Swapping {} of {} via {:?} ({}), {:.1} bps better than the alternative",
+            token_swap.amount,
+            token_swap.mint_out_address,
+            chosen.route,
+            chosen.hops.join(" > "),
+            improvement_bps.unwrap_or(0.0),
+        );
+
+        let signature = match (&chosen.route, jupiter_quote) {
+            (SwapRoute::Jupiter, Some(quote_response)) => SwapRouter::execute_jupiter(&jupiter, quote_response).await?,
+            (SwapRoute::Jupiter, None) => return Err(anyhow::anyhow!("Jupiter route chosen without a Jupiter quote")),
+            (SwapRoute::Direct(pool_type), _) => DexRegistry::get(pool_type)?.execute_swap(token_swap.clone()).await?,
+        };
+        green!("Swap routed via {:?}: {}", chosen.route, signature);
+
+        let record = SwapRouteRecord {
+            wallet_key: token_swap.wallet_key,
+            pool_address: token_swap.pool_address,
+            mint_sold: token_swap.mint_out_address,
+            mint_bought,
+            amount_is_in: token_swap.amount_is_in,
+            chosen,
+            alternative,
+            improvement_bps,
+            signature: signature.to_string(),
+            executed_at: Utc::now(),
+        };
+
+        SwapRouter::record(record.clone()).await;
+
+        Ok(record)
+    }
+
+    async fn get_jupiter_swap(jupiter: &JupiterSwapApiClient, user_public_key: Pubkey, quote_response: QuoteResponse) -> anyhow::Result<SwapResponse> {
+        let swap_response = jupiter.swap(&SwapRequest {
+            user_public_key,
+            quote_response,
+            config: TransactionConfig::default(),
+        }, None).await?;
+
+        Ok(swap_response)
+    }
+
+    /// Jupiter's swap transaction for the quote, signed by the wallet, with the last block height
+    /// its blockhash is valid for
+    async fn get_jupiter_transaction(jupiter: &JupiterSwapApiClient, wallet: &Keypair, quote_response: QuoteResponse) -> anyhow::Result<(VersionedTransaction, u64)> {
+        let swap_response = SwapRouter::get_jupiter_swap(jupiter, wallet.pubkey(), quote_response).await?;
+
+        let unsigned_transaction: VersionedTransaction = bincode::deserialize(&swap_response.swap_transaction)?;
+
+        let transaction = VersionedTransaction::try_new(unsigned_transaction.message, &[wallet])?;
+
+        Ok((transaction, swap_response.last_valid_block_height))
+    }
+
+    async fn execute_jupiter(jupiter: &JupiterSwapApiClient, quote_response: QuoteResponse) -> anyhow::Result<solana_sdk::signature::Signature> {
+        let wallet = Wallet::get_programmatic_keypair()?;
+        let (transaction, last_valid_block_height) = SwapRouter::get_jupiter_transaction(jupiter, &wallet, quote_response).await?;
+
+        yellow!("Sending Jupiter swap {}...", transaction.signatures[0]);
+        let sent = Rpc::broadcast_and_confirm(RpcMode::fast(), transaction, last_valid_block_height).await?;
+        TransactionLedger::record_sent(&sent, TransactionKind::Swap, None, None);
+
+        sent.confirmed_signature()
+    }
+
+    async fn record(record: SwapRouteRecord) {
+        if let Some(pool_manager) = POOL_MANAGER.try_get() {
+            let mut pool_manager = pool_manager.lock().await;
+            pool_manager.swap_routes.push(record);
+
+            let overflow = pool_manager.swap_routes.len().saturating_sub(MAX_SWAP_ROUTES);
+            pool_manager.swap_routes.drain(..overflow);
+        }
+    }
+}
+
+impl From<RpcSimulateTransactionResult> for SwapSimulation {
+    fn from(result: RpcSimulateTransactionResult) -> Self {
+        Self {
+            success: result.err.is_none(),
+            error: result.err.map(|err| err.to_string()),
+            units_consumed: result.units_consumed,
+            logs: result.logs.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use solana_sdk::{message::{Message, VersionedMessage}, signature::{Keypair, Signature}};
+
+    use crate::utils::mock_http::MockHttp;
+
+    use super::*;
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn quote_json(in_amount: u64, out_amount: u64) -> serde_json::Value {
+        json!({
+            "inputMint": SOL,
+            "inAmount": in_amount.to_string(),
+            "outputMint": USDC,
+            "outAmount": out_amount.to_string(),
+            "otherAmountThreshold": (out_amount * 995 / 1000).to_string(),
+            "swapMode": "ExactIn",
+            "slippageBps": 50,
+            "platformFee": null,
+            "priceImpactPct": "0.0012",
+            "routePlan": [{
+                "swapInfo": {
+                    "ammKey": Pubkey::new_unique().to_string(),
+                    "label": "Whirlpool",
+                    "inputMint": SOL,
+                    "outputMint": USDC,
+                    "inAmount": in_amount.to_string(),
+                    "outAmount": out_amount.to_string(),
+                    "feeAmount": "3000",
+                    "feeMint": SOL,
+                },
+                "percent": 100,
+            }],
+        })
+    }
+
+    fn route_quote(route: SwapRoute, amount_out: u64) -> RouteQuote {
+        RouteQuote { route, amount_in: 1_000_000_000, amount_out, other_amount_threshold: amount_out, hops: vec![], fees: vec![], price_impact_pct: None }
+    }
+
+    fn token_swap(amount: u64) -> TokenSwap {
+        TokenSwap::new(Pubkey::new_unique().to_string(), String::new(), amount, true, SOL.to_string(), Some(50))
+    }
+
+    #[tokio::test]
+    async fn quotes_jupiter_from_the_configured_api() {
+        let mock = MockHttp::start(|_| (200, quote_json(1_000_000_000, 150_000_000).to_string())).await;
+        let jupiter = JupiterSwapApiClient::new(mock.url.clone());
+
+        let quotes = SwapRouter::quote_routes(&jupiter, &token_swap(1_000_000_000), None, USDC).await.unwrap();
+
+        assert_eq!(quotes.len(), 1);
+        let (chosen, quote_response) = &quotes[0];
+        assert_eq!(chosen.route, SwapRoute::Jupiter);
+        assert_eq!(chosen.amount_out, 150_000_000);
+        assert_eq!(chosen.other_amount_threshold, 149_250_000);
+        assert_eq!(chosen.hops, vec!["Whirlpool".to_string()]);
+        assert_eq!(chosen.fees[0].amount, 3000);
+        assert_eq!(chosen.price_impact_pct, Some(0.0012));
+        assert!(quote_response.is_some());
+
+        let request = &mock.requests()[0];
+        assert_eq!(request.method, "GET");
+        assert!(request.path.starts_with("/quote?"));
+        assert!(request.path.contains(&format!("inputMint={}", SOL)));
+        assert!(request.path.contains(&format!("outputMint={}", USDC)));
+        assert!(request.path.contains("amount=1000000000"));
+        assert!(request.path.contains("swapMode=ExactIn"));
+        assert!(request.path.contains("slippageBps=50"));
+    }
+
+    #[tokio::test]
+    async fn fails_when_no_route_quotes() {
+        let mock = MockHttp::start(|_| (500, json!({ "error": "no route" }).to_string())).await;
+        let jupiter = JupiterSwapApiClient::new(mock.url.clone());
+
+        let error = SwapRouter::quote_routes(&jupiter, &token_swap(1_000), None, USDC).await.unwrap_err();
+
+        assert!(error.to_string().contains("No route could quote"));
+    }
+
+    #[tokio::test]
+    async fn signs_jupiter_swap_transaction_with_the_wallet() {
+        let wallet = Keypair::new();
+        let message = Message::new(
+            &[solana_system_interface::instruction::transfer(&wallet.pubkey(), &Pubkey::new_unique(), 1)],
+            Some(&wallet.pubkey()),
+        );
+        let unsigned_transaction = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message: VersionedMessage::Legacy(message),
+        };
+        let swap_transaction = BASE64_STANDARD.encode(bincode::serialize(&unsigned_transaction).unwrap());
+
+        let mock = MockHttp::start(move |_| (200, json!({
+            "swapTransaction": swap_transaction,
+            "lastValidBlockHeight": 321,
+            "prioritizationFeeLamports": 0,
+            "computeUnitLimit": 200000,
+            "prioritizationType": null,
+            "dynamicSlippageReport": null,
+            "simulationError": null,
+        }).to_string())).await;
+        let jupiter = JupiterSwapApiClient::new(mock.url.clone());
+
+        let quote_response: QuoteResponse = serde_json::from_value(quote_json(1_000, 150)).unwrap();
+        let (transaction, last_valid_block_height) = SwapRouter::get_jupiter_transaction(&jupiter, &wallet, quote_response).await.unwrap();
+
+        assert_eq!(last_valid_block_height, 321);
+        assert!(transaction.signatures[0].verify(wallet.pubkey().as_ref(), &transaction.message.serialize()));
+
+        let request = &mock.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert!(request.path.starts_with("/swap"));
+        assert_eq!(request.json()["userPublicKey"], wallet.pubkey().to_string());
+        assert_eq!(request.json()["quoteResponse"]["outAmount"], "150");
+    }
+
+    #[test]
+    fn spot_estimate_gives_way_to_jupiter() {
+        let quote_response: QuoteResponse = serde_json::from_value(quote_json(1_000_000_000, 150_000_000)).unwrap();
+        let direct = route_quote(SwapRoute::Direct(PoolType::Orca), 151_000_000);
+        let jupiter = route_quote(SwapRoute::Jupiter, 150_000_000);
+
+        let quotes = SwapRouter::rank_route_quotes(Some((direct.clone(), false)), Some((jupiter.clone(), quote_response.clone())), true);
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].0.route, SwapRoute::Jupiter);
+
+        let quotes = SwapRouter::rank_route_quotes(Some((direct.clone(), false)), None, true);
+        assert_eq!(quotes[0].0.route, SwapRoute::Direct(PoolType::Orca));
+
+        let quotes = SwapRouter::rank_route_quotes(Some((direct, true)), Some((jupiter, quote_response)), true);
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[0].0.route, SwapRoute::Direct(PoolType::Orca));
+    }
+
+    #[test]
+    fn exact_out_ranks_by_least_in() {
+        let quote_response: QuoteResponse = serde_json::from_value(quote_json(1_000, 150)).unwrap();
+        let direct = RouteQuote { amount_in: 1_010, ..route_quote(SwapRoute::Direct(PoolType::Raydium), 150) };
+        let jupiter = RouteQuote { amount_in: 1_000, ..route_quote(SwapRoute::Jupiter, 150) };
+
+        let quotes = SwapRouter::rank_route_quotes(Some((direct, true)), Some((jupiter, quote_response)), false);
+
+        assert_eq!(quotes[0].0.route, SwapRoute::Jupiter);
+    }
+
+    #[test]
+    fn price_impact_falls_back_to_jupiter_without_a_reference_price() {
+        let jupiter = RouteQuote { price_impact_pct: Some(0.05), ..route_quote(SwapRoute::Jupiter, 150_000_000) };
+        let direct = route_quote(SwapRoute::Direct(PoolType::Orca), 150_000_000);
+
+        let against_reference = SwapRouter::price_impact_bps(&jupiter, 0.15, Some(0.16)).unwrap();
+        assert!((against_reference - 625.0).abs() < 0.001);
+
+        assert_eq!(SwapRouter::price_impact_bps(&jupiter, 0.15, None), Some(500.0));
+        assert_eq!(SwapRouter::price_impact_bps(&direct, 0.15, None), None);
+    }
+
+    #[test]
+    fn swaps_without_an_impact_estimate_need_opting_in() {
+        let ceiling = PriceImpactCeiling::from_settings(|_| None);
+        assert!(ceiling.check(Some(500.0)).is_err());
+        assert!(ceiling.check(Some(100.0)).is_ok());
+        assert!(ceiling.check(None).is_err());
+
+        let ceiling = PriceImpactCeiling::from_settings(|setting| match setting {
+            "MAX_SWAP_PRICE_IMPACT_BPS" => Some("600".to_string()),
+            "ALLOW_UNCHECKED_SWAP_PRICE_IMPACT" => Some("true".to_string()),
+            _ => None,
+        });
+        assert!(ceiling.check(Some(500.0)).is_ok());
+        assert!(ceiling.check(None).is_ok());
+    }
+}
@@ -71,7 +71,7 @@ pub struct TickerMessage {
 impl TickerMessage {
     pub fn to_ticker_state(&self) -> anyhow::Result<TickerState> {
         let price = self.price.parse::<f64>().map_err(|_| anyhow::anyhow!("Failed to parse price"))?;
-        Ok(TickerState::from_iso8601(price, &self.time)?)
+        TickerState::from_iso8601(price, &self.time)
     }
 }
 
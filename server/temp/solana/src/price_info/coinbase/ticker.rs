@@ -96,7 +96,7 @@ impl TickerState {
             TimePeriod::TwentyFourHours => now - 86400,
         };
 
-        let filtered_history: Vec<Self> = history.iter().cloned().filter(|state| state.time >= threshold).collect();
+        let filtered_history: Vec<Self> = history.iter().filter(|&state| state.time >= threshold).cloned().collect();
         Ok(filtered_history)
     }
 
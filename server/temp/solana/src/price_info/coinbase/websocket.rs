@@ -1,7 +1,3 @@
-use std::collections::HashMap;
-use std::sync::Arc;
-use state::InitCell;
-use tokio::sync::RwLock;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 use serde::{Deserialize, Serialize};
 use futures_util::{SinkExt, StreamExt};
@@ -12,7 +8,7 @@ use anyhow::{Result, anyhow};
 use chrono::Utc;
 use base64::{engine::general_purpose::STANDARD as Base64Engine, Engine};
 
-use crate::price_info::coinbase::channel_messages::{ChannelMessage, FullChannelMessage, TickerMessage};
+use crate::price_info::coinbase::channel_messages::{ChannelMessage, TickerMessage};
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -137,19 +133,16 @@ impl CoinbaseWebsocket {
                 // println!("Raw WebSocket Message: {}", text);
                 match serde_json::from_str::<ChannelMessage>(&text) {
                     Ok(message) => {
-                        match &*message.r#type {
-                            "ticker" => {
-                                let ticker_message = serde_json::from_str::<TickerMessage>(&text)?;
-                                // println!("Ticker Message: {:?}", ticker_message);
-                                let ticker_state = ticker_message.to_ticker_state()?;
-                                match ticker_state.update() {
-                                    Ok(_) => {}
-                                    Err(e) => {
-                                        eprintln!("Failed to update ticker state: {}", e);
-                                    }
+                        if &*message.r#type == "ticker" {
+                            let ticker_message = serde_json::from_str::<TickerMessage>(&text)?;
+                            // println!("Ticker Message: {:?}", ticker_message);
+                            let ticker_state = ticker_message.to_ticker_state()?;
+                            match ticker_state.update() {
+                                Ok(_) => {}
+                                Err(e) => {
+                                    eprintln!("Failed to update ticker state: {}", e);
                                 }
                             }
-                            _ => {}
                         }
                     }
                     Err(e) => {
@@ -8,6 +8,12 @@ use super::coinbase::ticker::{TickerState, TimePeriod};
 
 pub struct PriceChecker;
 
+impl Default for PriceChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PriceChecker {
     pub fn new() -> Self {
         PriceChecker
@@ -0,0 +1,201 @@
+use std::{fmt, time::{Duration, Instant}};
+
+use futures_util::future::join_all;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use solana_sdk::{commitment_config::{CommitmentConfig, CommitmentLevel}, signature::Signature, transaction::{TransactionError, VersionedTransaction}};
+use tokio::time::{sleep, timeout};
+
+use super::{provider_registry::{CallOutcome, ProviderRegistry}, rate_limiter::{RateLimiter, RpcMethod}, rpc_url::RpcUrl, DomainExtractor, Rpc, RpcMode};
+
+const REBROADCAST_INTERVAL: Duration = Duration::from_secs(2);
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(800);
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+const STATUS_TIMEOUT_MS: u64 = 5000;
+/// Backstop for when block height can't be read, or never passes `last_valid_block_height`. A
+/// blockhash lasts about 60 to 90 seconds.
+const MAX_CONFIRMATION_TIME: Duration = Duration::from_secs(120);
+
+/// How a sent transaction ended up. `Expired` means it never landed before its blockhash
+/// stopped being valid, so it can no longer land and is safe to rebuild.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum TransactionOutcome {
+    Confirmed { slot: u64 },
+    Failed(TransactionError),
+    Expired,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SentTransaction {
+    pub signature: Signature,
+    pub outcome: TransactionOutcome,
+    pub broadcasts: u32,
+    /// First provider that accepted the transaction
+    pub provider: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// Error for a transaction that was still unconfirmed at `MAX_CONFIRMATION_TIME` without block
+/// height showing its blockhash expired. It may still land, so it must not be sent again.
+#[derive(Debug, Clone)]
+pub struct ConfirmationTimeout {
+    pub signature: Signature,
+    pub broadcasts: u32,
+    pub provider: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+impl fmt::Display for ConfirmationTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Transaction {} is unconfirmed after {}ms and may still land", self.signature, self.elapsed_ms)
+    }
+}
+
+impl std::error::Error for ConfirmationTimeout {}
+
+impl ConfirmationTimeout {
+    /// For a caller that has since made sure the transaction can't land
+    pub fn expired(self) -> SentTransaction {
+        SentTransaction {
+            signature: self.signature,
+            outcome: TransactionOutcome::Expired,
+            broadcasts: self.broadcasts,
+            provider: self.provider,
+            elapsed_ms: self.elapsed_ms,
+        }
+    }
+}
+
+impl SentTransaction {
+    /// The signature of a confirmed transaction, otherwise the outcome as an error
+    pub fn confirmed_signature(&self) -> anyhow::Result<Signature> {
+        match &self.outcome {
+            TransactionOutcome::Confirmed { .. } => Ok(self.signature),
+            TransactionOutcome::Failed(err) => Err(anyhow::anyhow!("Transaction {} failed: {:?}", self.signature, err)),
+            TransactionOutcome::Expired => Err(anyhow::anyhow!("Transaction {} expired before confirming", self.signature)),
+        }
+    }
+}
+
+impl Rpc {
+    /// Broadcasts a signed transaction to every provider in `rpc_mode` and keeps rebroadcasting
+    /// it while polling its status, until it confirms, fails on chain, or the chain moves past
+    /// `last_valid_block_height`. Gives up with a `ConfirmationTimeout` error if none of those
+    /// can be seen by `MAX_CONFIRMATION_TIME`.
+    pub async fn broadcast_and_confirm(
+        rpc_mode: RpcMode,
+        transaction: VersionedTransaction,
+        last_valid_block_height: u64,
+    ) -> anyhow::Result<SentTransaction> {
+        let signature = *transaction
+            .signatures
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Transaction is not signed"))?;
+        let urls = rpc_mode.urls();
+
+        let started = Instant::now();
+        let mut broadcasts = 0;
+        let mut last_broadcast: Option<Instant> = None;
+        let mut blockhash_expired = false;
+        let mut provider: Option<String> = None;
+
+        let finish = |outcome: TransactionOutcome, broadcasts: u32, provider: Option<String>| SentTransaction {
+            signature,
+            outcome,
+            broadcasts,
+            provider,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        };
+
+        loop {
+            if last_broadcast.is_none_or(|at| at.elapsed() >= REBROADCAST_INTERVAL) {
+                let accepted = Rpc::broadcast(&urls, &transaction).await;
+                if let Some(url) = accepted.first() {
+                    broadcasts += 1;
+                    provider.get_or_insert_with(|| RpcUrl::provider(url).to_string());
+                }
+                last_broadcast = Some(Instant::now());
+            }
+
+            sleep(STATUS_POLL_INTERVAL).await;
+
+            match Rpc::get_statuses(rpc_mode.clone(), signature, Some(STATUS_TIMEOUT_MS)).await {
+                Ok(statuses) => {
+                    if let Some(Some(status)) = statuses.first() {
+                        if let Some(err) = &status.err {
+                            red!("Transaction {} failed: {:?}", signature, err);
+                            return Ok(finish(TransactionOutcome::Failed(err.clone()), broadcasts, provider));
+                        }
+                        if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                            green!("Transaction {} confirmed in slot {} after {} broadcasts", signature, status.slot, broadcasts);
+                            return Ok(finish(TransactionOutcome::Confirmed { slot: status.slot }, broadcasts, provider));
+                        }
+                    }
+                }
+                Err(e) => yellow!("Failed to poll status of {}: {}", signature, e),
+            }
+
+            // Expiry is only declared after one more status poll, in case it landed at the edge
+            if blockhash_expired {
+                red!("Transaction {} expired after {} broadcasts", signature, broadcasts);
+                return Ok(finish(TransactionOutcome::Expired, broadcasts, provider));
+            }
+
+            if started.elapsed() > MAX_CONFIRMATION_TIME {
+                red!("Transaction {} unconfirmed after {} broadcasts, and its expiry could not be seen", signature, broadcasts);
+                return Err(ConfirmationTimeout {
+                    signature,
+                    broadcasts,
+                    provider,
+                    elapsed_ms: started.elapsed().as_millis() as u64,
+                }.into());
+            }
+
+            if last_broadcast.is_some_and(|at| at.elapsed() >= REBROADCAST_INTERVAL) {
+                match Rpc::get_block_height(rpc_mode.clone(), Some(STATUS_TIMEOUT_MS)).await {
+                    Ok(block_height) => blockhash_expired = block_height > last_valid_block_height,
+                    Err(e) => yellow!("Failed to fetch block height: {}", e),
+                }
+            }
+        }
+    }
+
+    /// Sends to every url that has rate limit room, without preflight or provider-side retries
+    /// since rebroadcasting is done here. Returns the urls that accepted it, in `urls` order.
+    async fn broadcast(urls: &[String], transaction: &VersionedTransaction) -> Vec<String> {
+        let config = RpcSendTransactionConfig {
+            skip_preflight: true,
+            preflight_commitment: Some(CommitmentLevel::Confirmed),
+            max_retries: Some(0),
+            ..Default::default()
+        };
+
+        let sends = urls
+            .iter()
+            .filter(|url| RateLimiter::try_acquire(url, RpcMethod::SendTransaction))
+            .map(|url| async move {
+                let client = RpcClient::new(url.clone());
+                let start = Instant::now();
+
+                match timeout(SEND_TIMEOUT, client.send_transaction_with_config(transaction, config)).await {
+                    Ok(Ok(_)) => {
+                        ProviderRegistry::record(url, CallOutcome::Success, start.elapsed().as_millis() as u64, None);
+                        Some(url.clone())
+                    }
+                    Ok(Err(e)) => {
+                        let err: anyhow::Error = e.into();
+                        red!("Failed to broadcast with {}: {}", url.domain(), err);
+                        ProviderRegistry::record(url, CallOutcome::from_error(&err), start.elapsed().as_millis() as u64, Some(err.to_string()));
+                        None
+                    }
+                    Err(_) => {
+                        ProviderRegistry::record(url, CallOutcome::Timeout, start.elapsed().as_millis() as u64, None);
+                        None
+                    }
+                }
+            });
+
+        join_all(sends).await.into_iter().flatten().collect()
+    }
+}
@@ -0,0 +1,279 @@
+use std::{collections::HashSet, sync::{Arc, Mutex, MutexGuard}};
+
+use chrono::{DateTime, Utc};
+use helius::types::{GetPriorityFeeEstimateOptions, GetPriorityFeeEstimateRequest, PriorityLevel};
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Signature, transaction::VersionedTransaction};
+use state::InitCell;
+
+use crate::{utils::serialize_transaction_to_base58, wallet::programmatic_transaction::TransactionKind};
+
+use super::{Rpc, RpcMode, PriorityFee};
+
+const DEFAULT_STRATEGIES: &str = "helius,percentile:75,static:medium";
+/// Headroom on top of the Helius estimate so transactions don't sit right at the market rate
+const HELIUS_FEE_MULTIPLIER: f64 = 1.5;
+/// `getRecentPrioritizationFees` takes at most this many accounts
+const MAX_FEE_ACCOUNTS: usize = 128;
+const MAX_FEE_RECORDS: usize = 200;
+
+pub static FEE_ORACLE: InitCell<Arc<Mutex<FeeOracle>>> = InitCell::new();
+
+/// A way of pricing compute units, in micro-lamports per CU
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum FeeStrategy {
+    /// Helius `getPriorityFeeEstimate` for the serialized transaction
+    Helius,
+    /// Percentile of `getRecentPrioritizationFees` over the accounts the transaction writes to
+    Percentile(u8),
+    Static(PriorityFee),
+}
+
+impl FeeStrategy {
+    /// Parses `helius`, `percentile:<0-100>` or `static:<none|low|medium|high|highest|micro-lamports>`
+    fn parse(value: &str) -> Option<Self> {
+        let (name, argument) = match value.trim().split_once(':') {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (value.trim(), None),
+        };
+
+        match (name.to_lowercase().as_str(), argument) {
+            ("helius", None) => Some(FeeStrategy::Helius),
+            ("percentile", Some(percentile)) => percentile.parse::<u8>().ok().filter(|p| *p <= 100).map(FeeStrategy::Percentile),
+            ("static", Some(level)) => {
+                let priority_fee = match level.to_lowercase().as_str() {
+                    "none" => PriorityFee::None,
+                    "low" => PriorityFee::Low,
+                    "medium" => PriorityFee::Medium,
+                    "high" => PriorityFee::High,
+                    "highest" => PriorityFee::Highest,
+                    custom => PriorityFee::Custom(custom.parse::<u64>().ok()?),
+                };
+                Some(FeeStrategy::Static(priority_fee))
+            }
+            _ => None,
+        }
+    }
+
+    /// Strategies tried in order until one answers, from `PRIORITY_FEE_STRATEGIES`, e.g.
+    /// `PRIORITY_FEE_STRATEGIES=percentile:90,static:high`
+    pub fn chain() -> Vec<FeeStrategy> {
+        let configured = std::env::var("PRIORITY_FEE_STRATEGIES").unwrap_or_else(|_| DEFAULT_STRATEGIES.to_string());
+
+        let chain: Vec<FeeStrategy> = configured
+            .split(',')
+            .filter(|value| !value.trim().is_empty())
+            .filter_map(|value| {
+                let strategy = FeeStrategy::parse(value);
+                if strategy.is_none() {
+                    yellow!("Ignoring unknown priority fee strategy {}", value);
+                }
+                strategy
+            })
+            .collect();
+
+        if chain.is_empty() {
+            DEFAULT_STRATEGIES.split(',').filter_map(FeeStrategy::parse).collect()
+        } else {
+            chain
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct FeeEstimate {
+    pub micro_lamports: u64,
+    pub strategy: FeeStrategy,
+    /// Whether the estimate was lowered to the operation's cap
+    pub capped: bool,
+}
+
+/// Priority fee a sent transaction paid
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeeRecord {
+    pub signature: String,
+    pub kind: TransactionKind,
+    pub strategy: Option<FeeStrategy>,
+    pub micro_lamports: u64,
+    pub compute_unit_limit: u32,
+    /// Priority fee in lamports, charged on the requested compute limit
+    pub priority_fee_lamports: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct FeeOracle {
+    records: Vec<FeeRecord>,
+}
+
+impl FeeOracle {
+    fn lock() -> MutexGuard<'static, FeeOracle> {
+        if FEE_ORACLE.try_get().is_none() {
+            FEE_ORACLE.set(Arc::new(Mutex::new(FeeOracle::default())));
+        }
+
+        FEE_ORACLE.get().lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Highest price an operation will pay, overridable with `<KIND>_MAX_PRIORITY_FEE` in
+    /// micro-lamports per CU, e.g. `CLOSE_MAX_PRIORITY_FEE=8000000`. Closes get the most room
+    /// since a stuck close keeps the position out of range.
+    pub fn max_micro_lamports(kind: TransactionKind) -> u64 {
+        let default = match kind {
+            TransactionKind::Close => PriorityFee::Highest.to_micro_lamports(),
+            TransactionKind::Open | TransactionKind::Swap => PriorityFee::High.to_micro_lamports(),
+            TransactionKind::Liquidity | TransactionKind::CollectFees | TransactionKind::Maintenance => 1_000_000,
+        };
+
+        std::env::var(format!("{}_MAX_PRIORITY_FEE", kind.env_prefix()))
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(default)
+    }
+
+    /// Lowers a fee to the operation's cap
+    pub fn cap(kind: TransactionKind, strategy: FeeStrategy, micro_lamports: u64) -> FeeEstimate {
+        let max_micro_lamports = FeeOracle::max_micro_lamports(kind);
+        let capped = micro_lamports > max_micro_lamports;
+        if capped {
+            yellow!("{:?} fee of {} capped to {} for {:?}", strategy, micro_lamports, max_micro_lamports, kind);
+        }
+
+        FeeEstimate {
+            micro_lamports: micro_lamports.min(max_micro_lamports),
+            strategy,
+            capped,
+        }
+    }
+
+    /// Runs the strategy chain until one answers and caps the result for the operation. `None`
+    /// when every strategy failed, in which case no priority fee is set.
+    pub async fn estimate(
+        transaction: &VersionedTransaction,
+        instructions: &[Instruction],
+        priority_level: Option<&PriorityLevel>,
+        kind: TransactionKind,
+    ) -> Option<FeeEstimate> {
+        for strategy in FeeStrategy::chain() {
+            let estimate = match strategy {
+                FeeStrategy::Helius => FeeOracle::helius(transaction, priority_level).await,
+                FeeStrategy::Percentile(percentile) => FeeOracle::percentile(instructions, percentile).await,
+                FeeStrategy::Static(priority_fee) => Ok(priority_fee.to_micro_lamports()),
+            };
+
+            match estimate {
+                Ok(micro_lamports) => return Some(FeeOracle::cap(kind, strategy, micro_lamports)),
+                Err(e) => yellow!("{:?} priority fee estimate failed, trying next: {}", strategy, e),
+            }
+        }
+
+        red!("No priority fee strategy answered, sending without a priority fee");
+        None
+    }
+
+    async fn helius(transaction: &VersionedTransaction, priority_level: Option<&PriorityLevel>) -> anyhow::Result<u64> {
+        let api_key = std::env::var("HELIUS_API_KEY")?;
+        let config = helius::config::Config::new(&api_key, helius::types::Cluster::MainnetBeta)?;
+        let request_client = reqwest::Client::new();
+        let client = helius::rpc_client::RpcClient::new(Arc::new(request_client), Arc::new(config))?;
+
+        let recommended = if priority_level.is_none() {
+            Some(true)
+        } else {
+            None
+        };
+        let request = GetPriorityFeeEstimateRequest {
+            transaction: Some(serialize_transaction_to_base58(transaction)?),
+            account_keys: None,
+            options: Some(GetPriorityFeeEstimateOptions {
+                priority_level: priority_level.map(copy_priority_level),
+                include_all_priority_fee_levels: None,
+                transaction_encoding: None,
+                lookback_slots: None,
+                recommended,
+                include_vote: None,
+            }),
+        };
+        let response = client.get_priority_fee_estimate(request).await.map_err(|e| {
+            anyhow::anyhow!("Helius API call failed: {:?}", e)
+        })?;
+
+        let fee_estimate = response
+            .priority_fee_estimate
+            .ok_or_else(|| anyhow::anyhow!("No priority fee estimate available from Helius API"))?;
+
+        Ok((fee_estimate * HELIUS_FEE_MULTIPLIER) as u64)
+    }
+
+    async fn percentile(instructions: &[Instruction], percentile: u8) -> anyhow::Result<u64> {
+        let mut seen = HashSet::new();
+        let writable_accounts: Vec<Pubkey> = instructions
+            .iter()
+            .flat_map(|instruction| instruction.accounts.iter())
+            .filter(|meta| meta.is_writable && seen.insert(meta.pubkey))
+            .map(|meta| meta.pubkey)
+            .take(MAX_FEE_ACCOUNTS)
+            .collect();
+
+        let prioritization_fees = Rpc::call(
+            move |client| {
+                let writable_accounts = writable_accounts.clone();
+                Box::pin(async move {
+                    client
+                        .get_recent_prioritization_fees(&writable_accounts)
+                        .await
+                        .map_err(|e| e.into())
+                })
+            },
+            Some(5000),
+            RpcMode::fast(),
+        )
+        .await?;
+
+        let mut fees: Vec<u64> = prioritization_fees.iter().map(|fee| fee.prioritization_fee).collect();
+        if fees.is_empty() {
+            return Err(anyhow::anyhow!("No recent prioritization fees returned"));
+        }
+        fees.sort_unstable();
+
+        let index = ((fees.len() - 1) as f64 * percentile as f64 / 100.0).round() as usize;
+
+        Ok(fees[index])
+    }
+
+    pub fn record(signature: &Signature, kind: TransactionKind, estimate: Option<FeeEstimate>, compute_unit_limit: u32) {
+        let micro_lamports = estimate.map_or(0, |estimate| estimate.micro_lamports);
+        let record = FeeRecord {
+            signature: signature.to_string(),
+            kind,
+            strategy: estimate.map(|estimate| estimate.strategy),
+            micro_lamports,
+            compute_unit_limit,
+            priority_fee_lamports: (micro_lamports as u128 * compute_unit_limit as u128).div_ceil(1_000_000) as u64,
+            created_at: Utc::now(),
+        };
+
+        let mut fee_oracle = FeeOracle::lock();
+        fee_oracle.records.push(record);
+        let overflow = fee_oracle.records.len().saturating_sub(MAX_FEE_RECORDS);
+        fee_oracle.records.drain(..overflow);
+    }
+
+    pub fn get_records() -> Vec<FeeRecord> {
+        FeeOracle::lock().records.clone()
+    }
+}
+
+/// helius' `PriorityLevel` doesn't implement `Clone`
+fn copy_priority_level(priority_level: &PriorityLevel) -> PriorityLevel {
+    match priority_level {
+        PriorityLevel::Min => PriorityLevel::Min,
+        PriorityLevel::Low => PriorityLevel::Low,
+        PriorityLevel::Medium => PriorityLevel::Medium,
+        PriorityLevel::High => PriorityLevel::High,
+        PriorityLevel::VeryHigh => PriorityLevel::VeryHigh,
+        PriorityLevel::UnsafeMax => PriorityLevel::UnsafeMax,
+        PriorityLevel::Default => PriorityLevel::Default,
+    }
+}
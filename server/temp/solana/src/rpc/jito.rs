@@ -0,0 +1,362 @@
+use std::{str::FromStr, time::{Duration, Instant}};
+
+use base64::Engine;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_client::client_error::reqwest;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, transaction::VersionedTransaction};
+use tokio::time::sleep;
+
+use super::{Rpc, RpcMode};
+
+const DEFAULT_BLOCK_ENGINE_URL: &str = "https://mainnet.block-engine.jito.wtf";
+const DEFAULT_TIP_LAMPORTS: u64 = 10_000;
+/// The block engine rejects bundles with more transactions than this
+pub const MAX_BUNDLE_TRANSACTIONS: usize = 5;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the block engine is polled before the bundle is treated as not landed
+const BUNDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const EXPIRY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How long the blockhash expiry is watched for, well past the ~60-90s a blockhash is valid, before
+/// the outcome is given up on as unknown
+const EXPIRY_TIMEOUT: Duration = Duration::from_secs(180);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum BundleStatus {
+    Pending,
+    Landed { slot: u64 },
+    Failed,
+    /// Not known to the block engine, e.g. dropped before reaching a leader
+    Invalid,
+    /// Submitted, but neither a landing nor the blockhash expiring could be confirmed. It may
+    /// still land, so nothing can be resent in its place.
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleResult {
+    pub bundle_id: String,
+    pub status: BundleStatus,
+    pub signatures: Vec<String>,
+    pub tip_lamports: u64,
+    pub elapsed_ms: u64,
+}
+
+impl BundleResult {
+    pub fn landed(&self) -> bool {
+        matches!(self.status, BundleStatus::Landed { .. })
+    }
+
+    /// Whether the bundle is known not to land, so its transactions can be sent another way
+    pub fn can_resend(&self) -> bool {
+        !matches!(self.status, BundleStatus::Landed { .. } | BundleStatus::Unknown)
+    }
+}
+
+/// How watching a submitted bundle's blockhash ended
+#[derive(Debug, Clone, PartialEq)]
+enum BundleExpiry {
+    Landed { slot: u64 },
+    Expired,
+    Unknown,
+}
+
+/// Client for a Jito block engine. The endpoint is set with `JITO_BLOCK_ENGINE_URL`, which can
+/// point at a local mock engine, and bundles are only used when `JITO_BUNDLES=true`.
+pub struct Jito {
+    block_engine_url: String,
+}
+
+impl Jito {
+    pub fn new(block_engine_url: String) -> Self {
+        Jito { block_engine_url }
+    }
+
+    pub fn from_env() -> Self {
+        Jito::new(std::env::var("JITO_BLOCK_ENGINE_URL").unwrap_or_else(|_| DEFAULT_BLOCK_ENGINE_URL.to_string()))
+    }
+
+    pub fn enabled() -> bool {
+        std::env::var("JITO_BUNDLES").is_ok_and(|value| value == "true")
+    }
+
+    pub fn tip_lamports() -> u64 {
+        std::env::var("JITO_TIP_LAMPORTS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TIP_LAMPORTS)
+    }
+
+    async fn request(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/api/v1/bundles", self.block_engine_url))
+            .timeout(REQUEST_TIMEOUT)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }));
+        if let Ok(uuid) = std::env::var("JITO_AUTH_UUID") {
+            request = request.header("x-jito-auth", uuid);
+        }
+
+        let response: Value = request.send().await?.error_for_status()?.json().await?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow::anyhow!("Block engine {} failed: {}", method, error));
+        }
+
+        Ok(response["result"].clone())
+    }
+
+    pub async fn get_tip_accounts(&self) -> anyhow::Result<Vec<Pubkey>> {
+        let result = self.request("getTipAccounts", json!([])).await?;
+
+        result
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected getTipAccounts response: {}", result))?
+            .iter()
+            .filter_map(|account| account.as_str())
+            .map(|account| Pubkey::from_str(account).map_err(|e| anyhow::anyhow!(e.to_string())))
+            .collect()
+    }
+
+    /// Transfer to one of the tip accounts, picked at random to spread write locks
+    pub async fn tip_instruction(&self, payer: &Pubkey, tip_lamports: u64) -> anyhow::Result<Instruction> {
+        let tip_accounts = self.get_tip_accounts().await?;
+        if tip_accounts.is_empty() {
+            return Err(anyhow::anyhow!("Block engine returned no tip accounts"));
+        }
+
+        let index = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.subsec_nanos() as usize % tip_accounts.len();
+
+        Ok(solana_system_interface::instruction::transfer(payer, &tip_accounts[index], tip_lamports))
+    }
+
+    pub async fn send_bundle(&self, transactions: &[VersionedTransaction]) -> anyhow::Result<String> {
+        if transactions.is_empty() || transactions.len() > MAX_BUNDLE_TRANSACTIONS {
+            return Err(anyhow::anyhow!("Bundles take 1 to {} transactions, got {}", MAX_BUNDLE_TRANSACTIONS, transactions.len()));
+        }
+
+        let encoded = transactions
+            .iter()
+            .map(|transaction| Ok(base64::engine::general_purpose::STANDARD.encode(bincode::serialize(transaction)?)))
+            .collect::<anyhow::Result<Vec<String>>>()?;
+
+        let result = self.request("sendBundle", json!([encoded, { "encoding": "base64" }])).await?;
+
+        result
+            .as_str()
+            .map(|bundle_id| bundle_id.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Unexpected sendBundle response: {}", result))
+    }
+
+    pub async fn get_bundle_status(&self, bundle_id: &str) -> anyhow::Result<BundleStatus> {
+        let result = self.request("getInflightBundleStatuses", json!([[bundle_id]])).await?;
+        let status = &result["value"][0];
+
+        Ok(match status["status"].as_str() {
+            Some("Landed") => BundleStatus::Landed { slot: status["landed_slot"].as_u64().unwrap_or_default() },
+            Some("Failed") => BundleStatus::Failed,
+            Some("Pending") => BundleStatus::Pending,
+            _ => BundleStatus::Invalid,
+        })
+    }
+
+    /// Submits the bundle and polls the block engine until it lands or fails. A bundle still
+    /// pending or unknown to the block engine could land until its blockhash expires, so in that
+    /// case this waits out `last_valid_block_height` before reporting it, leaving the caller free
+    /// to resend normally. A failed bundle is reported right away, it won't be forwarded again.
+    /// Only errors from before the bundle is accepted are returned, after that the outcome is in
+    /// the result, `BundleStatus::Unknown` if it couldn't be determined.
+    pub async fn send_and_track(
+        &self,
+        transactions: Vec<VersionedTransaction>,
+        tip_lamports: u64,
+        last_valid_block_height: u64,
+    ) -> anyhow::Result<BundleResult> {
+        let started = Instant::now();
+        let signatures: Vec<String> = transactions.iter().map(|transaction| transaction.signatures[0].to_string()).collect();
+
+        let bundle_id = self.send_bundle(&transactions).await?;
+        blue!("Sent bundle {} with {} transactions", bundle_id, transactions.len());
+
+        let mut status = BundleStatus::Pending;
+        while started.elapsed() < BUNDLE_TIMEOUT {
+            sleep(STATUS_POLL_INTERVAL).await;
+
+            match self.get_bundle_status(&bundle_id).await {
+                Ok(current) => status = current,
+                Err(e) => yellow!("Failed to fetch status of bundle {}: {}", bundle_id, e),
+            }
+
+            if matches!(status, BundleStatus::Landed { .. } | BundleStatus::Failed) {
+                break;
+            }
+        }
+
+        if matches!(status, BundleStatus::Pending | BundleStatus::Invalid) {
+            match Jito::wait_for_expiry(&transactions[0], last_valid_block_height, EXPIRY_TIMEOUT).await {
+                BundleExpiry::Landed { slot } => status = BundleStatus::Landed { slot },
+                BundleExpiry::Expired => (),
+                BundleExpiry::Unknown => status = BundleStatus::Unknown,
+            }
+        }
+
+        match &status {
+            BundleStatus::Landed { slot } => green!("Bundle {} landed in slot {}", bundle_id, slot),
+            status => red!("Bundle {} did not land: {:?}", bundle_id, status),
+        }
+
+        Ok(BundleResult {
+            bundle_id,
+            status,
+            signatures,
+            tip_lamports,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Watches the bundle's first transaction until its blockhash expires, and reports the slot
+    /// if it landed after all, since bundles land all or nothing. Failed reads are retried, and
+    /// if neither is confirmed within `timeout` the outcome is unknown.
+    async fn wait_for_expiry(transaction: &VersionedTransaction, last_valid_block_height: u64, timeout: Duration) -> BundleExpiry {
+        let signature = transaction.signatures[0];
+        let started = Instant::now();
+
+        loop {
+            // Read before the status so a landing in the final valid block is still seen. A
+            // failed read is not taken as expiry, the bundle could still land.
+            let expired = match Rpc::get_block_height(RpcMode::fast(), Some(5000)).await {
+                Ok(block_height) => block_height > last_valid_block_height,
+                Err(e) => {
+                    yellow!("Failed to fetch block height while waiting on bundle {}: {}", signature, e);
+                    false
+                }
+            };
+
+            match Rpc::get_statuses(RpcMode::fast(), signature, Some(5000)).await {
+                Ok(statuses) => {
+                    if let Some(Some(status)) = statuses.first() {
+                        if status.err.is_none() {
+                            return BundleExpiry::Landed { slot: status.slot };
+                        }
+                    }
+
+                    if expired {
+                        return BundleExpiry::Expired;
+                    }
+                }
+                Err(e) => yellow!("Failed to fetch status of bundle transaction {}: {}", signature, e),
+            }
+
+            if started.elapsed() >= timeout {
+                red!("Gave up waiting on bundle transaction {} after {}s, its outcome is unknown", signature, timeout.as_secs());
+                return BundleExpiry::Unknown;
+            }
+
+            sleep(EXPIRY_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{hash::Hash, message::{Message, VersionedMessage}, signature::Keypair, signer::Signer};
+
+    use crate::utils::mock_http::{MockHttp, MockRequest};
+
+    use super::*;
+
+    fn block_engine(tip_accounts: Vec<Pubkey>, bundle_status: &'static str) -> impl Fn(&MockRequest) -> (u16, String) {
+        move |request| {
+            let result = match request.json()["method"].as_str() {
+                Some("getTipAccounts") => json!(tip_accounts.iter().map(|account| account.to_string()).collect::<Vec<_>>()),
+                Some("sendBundle") => json!("bundle-1"),
+                Some("getInflightBundleStatuses") => json!({
+                    "context": { "slot": 100 },
+                    "value": [{ "bundle_id": "bundle-1", "status": bundle_status, "landed_slot": 42 }],
+                }),
+                _ => return (200, json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32601, "message": "unknown method" } }).to_string()),
+            };
+
+            (200, json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string())
+        }
+    }
+
+    fn transaction() -> VersionedTransaction {
+        let payer = Keypair::new();
+        let message = Message::new_with_blockhash(
+            &[solana_system_interface::instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1)],
+            Some(&payer.pubkey()),
+            &Hash::default(),
+        );
+
+        VersionedTransaction::try_new(VersionedMessage::Legacy(message), &[&payer]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn tips_one_of_the_block_engine_tip_accounts() {
+        let tip_accounts = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let mock = MockHttp::start(block_engine(tip_accounts.clone(), "Landed")).await;
+        let jito = Jito::new(mock.url.clone());
+
+        let payer = Pubkey::new_unique();
+        let instruction = jito.tip_instruction(&payer, 10_000).await.unwrap();
+
+        assert_eq!(instruction.accounts[0].pubkey, payer);
+        assert!(tip_accounts.contains(&instruction.accounts[1].pubkey));
+        assert_eq!(instruction, solana_system_interface::instruction::transfer(&payer, &instruction.accounts[1].pubkey, 10_000));
+        assert_eq!(mock.requests()[0].path, "/api/v1/bundles");
+    }
+
+    #[tokio::test]
+    async fn reports_a_landed_bundle_with_its_slot() {
+        let mock = MockHttp::start(block_engine(vec![], "Landed")).await;
+        let jito = Jito::new(mock.url.clone());
+
+        let transactions = vec![transaction(), transaction()];
+        let signatures: Vec<String> = transactions.iter().map(|transaction| transaction.signatures[0].to_string()).collect();
+
+        let bundle_result = jito.send_and_track(transactions.clone(), 10_000, 0).await.unwrap();
+
+        assert!(bundle_result.landed());
+        assert_eq!(bundle_result.status, BundleStatus::Landed { slot: 42 });
+        assert_eq!(bundle_result.bundle_id, "bundle-1");
+        assert_eq!(bundle_result.signatures, signatures);
+
+        let send_bundle = mock.requests().into_iter().find(|request| request.json()["method"] == "sendBundle").unwrap().json();
+        let encoded = send_bundle["params"][0].as_array().unwrap();
+        assert_eq!(encoded.len(), 2);
+        assert_eq!(send_bundle["params"][1]["encoding"], "base64");
+
+        let decoded: VersionedTransaction = bincode::deserialize(&base64::engine::general_purpose::STANDARD.decode(encoded[0].as_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(decoded.signatures[0], transactions[0].signatures[0]);
+    }
+
+    #[tokio::test]
+    async fn reports_a_failed_bundle_without_waiting_out_the_blockhash() {
+        let mock = MockHttp::start(block_engine(vec![], "Failed")).await;
+        let jito = Jito::new(mock.url.clone());
+
+        let bundle_result = jito.send_and_track(vec![transaction()], 10_000, u64::MAX).await.unwrap();
+
+        // The caller falls back to sending normally
+        assert!(!bundle_result.landed());
+        assert_eq!(bundle_result.status, BundleStatus::Failed);
+        assert!(bundle_result.elapsed_ms < BUNDLE_TIMEOUT.as_millis() as u64);
+    }
+
+    #[tokio::test]
+    async fn surfaces_block_engine_errors() {
+        let mock = MockHttp::start(|_| (200, json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32602, "message": "bundle rejected" } }).to_string())).await;
+        let jito = Jito::new(mock.url.clone());
+
+        let error = jito.send_bundle(&[transaction()]).await.unwrap_err();
+
+        assert!(error.to_string().contains("bundle rejected"));
+        assert!(jito.send_bundle(&[]).await.is_err());
+    }
+}
@@ -5,22 +5,33 @@ use anyhow::anyhow;
 use kebtech_utils::*;
 use futures_util::future::join_all;
 use orca_pools_ipc_types::{request::Request, response::Response};
+use confirmation::SentTransaction;
+use provider_registry::{CallOutcome, ProviderRegistry, RankBy};
+use rate_limiter::{RateLimiter, RpcMethod};
 use rpc_url::RpcUrl;
+use sidecar::{SidecarClient, SidecarError};
 use serde::{Deserialize, Serialize};
-use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig, rpc_response::{RpcResponseContext, RpcSimulateTransactionResult}};
-use solana_sdk::{commitment_config::CommitmentLevel, hash::Hash, instruction::Instruction, pubkey::Pubkey, signature::Signature, signer::Signer, transaction::{self, Transaction}};
-use solana_transaction_status::TransactionStatus;
-use tokio::time::{sleep, timeout};
-
-use crate::{pool_manager::new_position::NewPositionData, wallet::programmatic_transaction::ProgrammaticTransaction};
-
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::{RpcProgramAccountsConfig, RpcTransactionConfig}, rpc_filter::RpcFilterType, rpc_response::{RpcResponseContext, RpcSimulateTransactionResult}};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, hash::Hash, instruction::Instruction, message::{v0, Message, VersionedMessage}, packet::PACKET_DATA_SIZE, pubkey::Pubkey, signature::Signature, signer::Signer, transaction::{Transaction, VersionedTransaction}};
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, TransactionStatus, UiTransactionEncoding};
+use tokio::time::timeout;
+
+use crate::wallet::lookup_table::LookupTable;
+
+pub mod confirmation;
+pub mod fee_oracle;
+pub mod jito;
+pub mod provider_registry;
+pub mod rate_limiter;
 pub mod rpc_url;
+pub mod sidecar;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum ComputeUnitLimit {
     Default,         // Default compute unit limit (200,000 CUs)
     HighUsage,       // Higher compute unit limit for more complex actions
     Max,             // Maximum compute unit limit (1.4 million CUs)
+    Custom(u32),     // Custom compute unit limit
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -39,6 +50,7 @@ impl ComputeUnitLimit {
             ComputeUnitLimit::Default => None,          // Default is 200,000
             ComputeUnitLimit::HighUsage => Some(500_000), // High usage: 500,000 CU
             ComputeUnitLimit::Max => Some(1_400_000),    // Max limit: 1.4M CU
+            ComputeUnitLimit::Custom(limit) => Some(*limit), // Custom limit
         }
     }
 }
@@ -80,12 +92,20 @@ impl RpcMode {
         RpcMode::Failover(vec![rpc_url])
     }
 
+    /// Races the quickest healthy providers by live latency
     pub fn fast() -> Self {
-        RpcMode::Concurrent(RpcUrl::speed_priority())
+        RpcMode::Concurrent(ProviderRegistry::fastest(RpcUrl::speed_priority()))
     }
 
+    /// Works through every healthy provider, most reliable first
     pub fn conservative() -> Self {
-        RpcMode::Failover(RpcUrl::volume_priority())
+        RpcMode::Failover(ProviderRegistry::rank(RpcUrl::volume_priority(), RankBy::Reliability))
+    }
+
+    pub fn urls(&self) -> Vec<String> {
+        match self {
+            RpcMode::Failover(urls) | RpcMode::Concurrent(urls) => urls.clone(),
+        }
     }
 }
 
@@ -97,6 +117,27 @@ impl Rpc {
         timeout_ms: Option<u64>,
         mode: RpcMode,
     ) -> anyhow::Result<T>
+    where
+        F: Fn(RpcClient) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<T>> + Send>>
+            + Sync
+            + Clone
+            + Send
+            + 'static,
+        T: Send 
+            + 'static
+            + std::fmt::Debug,
+    {
+        Rpc::call_weighted(RpcMethod::Other, client_call, timeout_ms, mode).await
+    }
+
+    /// `Rpc::call` charged at `method`'s weight against each provider's rate limit. Throttled
+    /// providers are skipped in favour of the next one rather than failing the call.
+    pub async fn call_weighted<T, F>(
+        method: RpcMethod,
+        client_call: F,
+        timeout_ms: Option<u64>,
+        mode: RpcMode,
+    ) -> anyhow::Result<T>
     where
         F: Fn(RpcClient) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<T>> + Send>>
             + Sync
@@ -108,9 +149,11 @@ impl Rpc {
             + std::fmt::Debug,
     {
         match mode {
-            RpcMode::Failover(rpc_urls) => {
+            RpcMode::Failover(mut rpc_urls) => {
+                let mut attempted = false;
 
-                for (i, url) in rpc_urls.iter().enumerate() {
+                while let Some(url) = RateLimiter::acquire_next(&mut rpc_urls, method).await {
+                    attempted = true;
                     let client = RpcClient::new(url.clone());
                     let start = Utc::now();
     
@@ -129,11 +172,16 @@ impl Rpc {
                         Ok(Ok(data)) => {
                             let elapsed = Utc::now().signed_duration_since(start).num_milliseconds();
                             println!("Success from RPC Domain: {:?}, Time: {}ms", url.domain(), elapsed);
+                            ProviderRegistry::record(&url, CallOutcome::Success, elapsed as u64, None);
                             return Ok(data);
                         }
                         Ok(Err(err)) => {
                             let elapsed =
                                 Utc::now().signed_duration_since(start).num_milliseconds();
+                            if RateLimiter::is_rate_limited(&err.to_string()) {
+                                RateLimiter::record_rate_limited(&url);
+                            }
+                            ProviderRegistry::record(&url, CallOutcome::from_error(&err), elapsed as u64, Some(err.to_string()));
                             red!(
                                 "Failed call from RPC Domain: {}, Time: {}ms, Error: {:?}",
                                 url.domain(),
@@ -144,6 +192,7 @@ impl Rpc {
                         Err(_) => {
                             let elapsed =
                                 Utc::now().signed_duration_since(start).num_milliseconds();
+                            ProviderRegistry::record(&url, CallOutcome::Timeout, elapsed as u64, None);
                             red!(
                                 "Timeout! RPC Domain: {}, Time: {}ms exceeded timeout of {:?}",
                                 url.domain(),
@@ -153,11 +202,20 @@ impl Rpc {
                         }
                     }
                 }
+
+                if !attempted {
+                    return Err(anyhow::anyhow!("All RPC providers are throttled"));
+                }
     
                 Err(anyhow::anyhow!("All RPC calls failed"))
             }
     
             RpcMode::Concurrent(rpc_urls) => {
+                let rpc_urls = RateLimiter::acquire_all(rpc_urls, method).await;
+                if rpc_urls.is_empty() {
+                    return Err(anyhow::anyhow!("All RPC providers are throttled"));
+                }
+
                 let futures = rpc_urls.into_iter().map(|url| {
                     let client = RpcClient::new(url.clone());
                     let client_call = client_call.clone();
@@ -174,12 +232,17 @@ impl Rpc {
                             Ok(Ok(data)) => {
                                 let elapsed =
                                     Utc::now().signed_duration_since(start).num_milliseconds();
+                                ProviderRegistry::record(&url, CallOutcome::Success, elapsed as u64, None);
                                 // println!("Success from RPC Domain: {:?}, Time: {}ms", url.domain(), elapsed);
                                 Ok(data)
                             }
                             Ok(Err(err)) => {
                                 let elapsed =
                                     Utc::now().signed_duration_since(start).num_milliseconds();
+                                if RateLimiter::is_rate_limited(&err.to_string()) {
+                                    RateLimiter::record_rate_limited(&url);
+                                }
+                                ProviderRegistry::record(&url, CallOutcome::from_error(&err), elapsed as u64, Some(err.to_string()));
                                 red!(
                                     "Failed call from RPC Domain: {}, Time: {}ms, Error: {:?}",
                                     url.domain(),
@@ -191,6 +254,7 @@ impl Rpc {
                             Err(_) => {
                                 let elapsed =
                                     Utc::now().signed_duration_since(start).num_milliseconds();
+                                ProviderRegistry::record(&url, CallOutcome::Timeout, elapsed as u64, None);
                                 red!(
                                     "Timeout! RPC Domain: {}, Time: {}ms exceeded timeout of {:?}",
                                     url.domain(),
@@ -205,12 +269,10 @@ impl Rpc {
     
                 let results = join_all(futures).await;
     
-                for result in results {
-                    if let Ok(data) = result {
-                        println!("RPC result data: {:?}", data);
-                        
-                        return Ok(data);
-                    }
+                if let Some(data) = results.into_iter().flatten().next() {
+                    println!("RPC result data: {:?}", data);
+                    
+                    return Ok(data);
                 }
     
                 Err(anyhow::anyhow!("All RPC calls failed"))
@@ -218,6 +280,9 @@ impl Rpc {
         }
     }
      
+    /// Sends a sidecar request built for each RPC url. RPC failures reported by the sidecar fall
+    /// through to the next url, sidecar failures are returned straight away as a `SidecarError`.
+    /// Requests are rate limited like `Rpc::call` at the `RpcMethod::Sidecar` weight.
     pub async fn call_orca<F>(
         mode: RpcMode,
         request_builder: F,
@@ -226,94 +291,77 @@ impl Rpc {
     where
         F: Fn(String) -> Request + Clone + Sync + Send + 'static,
     {
-        match mode {
-            RpcMode::Failover(rpc_urls) => {
-                for (i, url) in rpc_urls.iter().enumerate() {
-                    let start = Utc::now();
-                    let timeout_duration = if timeout_ms.is_some() {
-                        timeout_ms.map(Duration::from_millis)
-                    } else {
-                        Some(Duration::from_secs(20))
-                    };
-
-                    let request = request_builder(url.clone());
-
-                    let result = match timeout_duration {
-                        Some(duration) => timeout(duration, request.send()).await,
-                        None => Ok(request.send().await),
-                    };
+        let sidecar = SidecarClient::get();
 
-                    match result {
-                        Ok(Ok(response)) => {
-                            // println!(
-                            //     "Success from RPC Domain: {}, Time: {}ms",
-                            //     url.domain(),
-                            //     Utc::now().signed_duration_since(start).num_milliseconds(),
-                            // );
+        match mode {
+            RpcMode::Failover(mut rpc_urls) => {
+                let timeout_ms = timeout_ms.unwrap_or(20_000);
+                let no_urls_message = if rpc_urls.is_empty() { "No RPC urls configured" } else { "All RPC providers are throttled" };
+                let mut last_error = SidecarError::Rpc { url: String::new(), message: no_urls_message.to_string() };
+
+                while let Some(url) = RateLimiter::acquire_next(&mut rpc_urls, RpcMethod::Sidecar).await {
+                    let start = Instant::now();
+                    match sidecar.send(request_builder(url.clone()), &url, timeout_ms).await {
+                        Ok(response) => {
+                            ProviderRegistry::record(&url, CallOutcome::Success, start.elapsed().as_millis() as u64, None);
                             return Ok(response);
                         }
-                        Ok(Err(err)) => {
-                            red!(
-                                "Failed call from RPC Domain: {}, Error: {:?}",
-                                url.domain(),
-                                err
-                            );
-                        }
-                        Err(_) => {
-                            red!(
-                                "Timeout! RPC Domain: {}, Time exceeded timeout of 10s",
-                                url.domain()
-                            );
+                        Err(err) if err.is_rpc() => {
+                            red!("Failed call from RPC Domain: {}, Error: {}", url.domain(), err);
+                            if RateLimiter::is_rate_limited(&err.to_string()) {
+                                RateLimiter::record_rate_limited(&url);
+                            }
+                            ProviderRegistry::record(&url, CallOutcome::Error, start.elapsed().as_millis() as u64, Some(err.to_string()));
+                            last_error = err;
                         }
+                        Err(err) => return Err(err.into()),
                     }
                 }
 
-                Err(anyhow::anyhow!("All RPC calls failed"))
+                Err(last_error.into())
             }
 
             RpcMode::Concurrent(rpc_urls) => {
+                let timeout_ms = timeout_ms.unwrap_or(120_000);
+                let no_urls_message = if rpc_urls.is_empty() { "No RPC urls configured" } else { "All RPC providers are throttled" };
+                let rpc_urls = RateLimiter::acquire_all(rpc_urls, RpcMethod::Sidecar).await;
+
                 let futures = rpc_urls.into_iter().map(|url| {
                     let request = request_builder(url.clone());
+                    let sidecar = sidecar.clone();
                     async move {
-                        let start = Utc::now();
-                        let result = timeout(Duration::from_secs(120), request.send()).await;
-                        match result {
-                            Ok(Ok(response)) => {
-                                // println!(
-                                //     "Success from RPC Domain: {}, Time: {}ms",
-                                //     url.domain(),
-                                //     Utc::now().signed_duration_since(start).num_milliseconds(),
-                                // );
-                                Ok(response)
-                            }
-                            Ok(Err(err)) => {
-                                red!(
-                                    "Failed call from RPC Domain: {}, Error: {:?}",
-                                    url.domain(),
-                                    err
-                                );
-                                Err(anyhow::anyhow!("RPC call failed"))
-                            }
-                            Err(_) => {
-                                red!(
-                                    "Timeout! RPC Domain: {}, Time exceeded timeout of 10s",
-                                    url.domain()
-                                );
-                                Err(anyhow::anyhow!("Timeout"))
+                        let start = Instant::now();
+                        let result = sidecar.send(request, &url, timeout_ms).await;
+                        let elapsed = start.elapsed().as_millis() as u64;
+                        match &result {
+                            Ok(_) => ProviderRegistry::record(&url, CallOutcome::Success, elapsed, None),
+                            Err(err) => {
+                                red!("Failed call from RPC Domain: {}, Error: {}", url.domain(), err);
+                                // Only RPC failures say anything about the provider
+                                if err.is_rpc() {
+                                    if RateLimiter::is_rate_limited(&err.to_string()) {
+                                        RateLimiter::record_rate_limited(&url);
+                                    }
+                                    ProviderRegistry::record(&url, CallOutcome::Error, elapsed, Some(err.to_string()));
+                                }
                             }
                         }
+                        result
                     }
                 });
 
-                let results = join_all(futures).await;
+                let mut last_error = SidecarError::Rpc { url: String::new(), message: no_urls_message.to_string() };
 
-                for result in results {
-                    if let Ok(data) = result {
-                        return Ok(data);
+                for result in join_all(futures).await {
+                    match result {
+                        Ok(response) => return Ok(response),
+                        // Prefer reporting a sidecar failure over an RPC one
+                        Err(err) if err.is_rpc() && !last_error.is_rpc() => (),
+                        Err(err) => last_error = err,
                     }
                 }
 
-                Err(anyhow::anyhow!("All RPC calls failed"))
+                Err(last_error.into())
             }
         }
     }
@@ -333,117 +381,146 @@ impl Rpc {
         Ok(response)
     }
 
+    /// Latest blockhash with the last block height a transaction using it can land in
+    pub async fn get_latest_blockhash_with_height(rpc_mode: RpcMode, timeout_ms: Option<u64>) -> anyhow::Result<(Hash, u64)> {
+        let response = Rpc::call(
+            move |client| {
+                Box::pin(async move {
+                    client
+                        .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+                        .await
+                        .map_err(|e| e.into())
+                })
+            },
+            timeout_ms,
+            rpc_mode,
+        ).await?;
 
+        Ok(response)
+    }
 
+    pub async fn get_slot(rpc_mode: RpcMode, timeout_ms: Option<u64>) -> anyhow::Result<u64> {
+        let slot = Rpc::call(
+            move |client| {
+                Box::pin(async move {
+                    client.get_slot().await.map_err(|e| e.into())
+                })
+            },
+            timeout_ms,
+            rpc_mode,
+        ).await?;
+
+        Ok(slot)
+    }
+
+    pub async fn get_block_height(rpc_mode: RpcMode, timeout_ms: Option<u64>) -> anyhow::Result<u64> {
+        let block_height = Rpc::call(
+            move |client| {
+                Box::pin(async move {
+                    client
+                        .get_block_height_with_commitment(CommitmentConfig::confirmed())
+                        .await
+                        .map_err(|e| e.into())
+                })
+            },
+            timeout_ms,
+            rpc_mode,
+        ).await?;
+
+        Ok(block_height)
+    }
+
+    /// Signs and sends the instructions, rebroadcasting until the transaction confirms, fails or
+    /// its blockhash expires. The first signer pays the fees.
     pub async fn send_and_confirm_transaction_with_config(
         rpc_mode: RpcMode,
         instructions: Vec<Instruction>,
-        signers: Vec<Box<dyn Signer>>,
+        signers: Vec<Box<dyn Signer + Send + Sync>>,
         timeout_ms: Option<u64>,
-    ) -> anyhow::Result<Signature> {
+    ) -> anyhow::Result<SentTransaction> {
         // Resolve the latest blockhash
-        let start = Utc::now();
+        let start = Instant::now();
         blue!("Fetching latest blockhash");
-        let recent_blockhash = Rpc::get_latest_blockhash(rpc_mode.clone(), timeout_ms).await?;
-    
-        green!(
-            "Fetched latest blockhash in {}ms",
-            start.signed_duration_since(Utc::now()).num_milliseconds()
-        );
-    
-        // Build the transaction
-        let message = solana_sdk::message::Message::new(&instructions, Some(&signers[0].pubkey()));
-        let transaction = Transaction::new(&signers, message, recent_blockhash);
-    
-        // Define transaction config
-        let transaction_config = RpcSendTransactionConfig {
-            skip_preflight: true,
-            preflight_commitment: Some(CommitmentLevel::Confirmed),
-            max_retries: Some(0),
-            ..Default::default()
-        };
+        let (recent_blockhash, last_valid_block_height) = Rpc::get_latest_blockhash_with_height(rpc_mode.clone(), timeout_ms).await?;
+        green!("Fetched latest blockhash in {}ms", start.elapsed().as_millis());
     
-        // Send the transaction
-        let send_transaction_result = Rpc::call(
-            move |client| {
-                let transaction = transaction.clone();
-                yellow!("Sending transaction with {}...", client.url().domain());
-                let config = transaction_config.clone();
-                Box::pin(async move {
-                    let signature = client
-                        .send_transaction_with_config(&transaction, config)
-                        .await?;
-                    sleep(Duration::from_secs(2)).await;
-                    let statuses = client.get_signature_statuses(&[signature]).await?;
-                    Ok((signature, statuses.value))
-                })
-            },
-            timeout_ms,
-            rpc_mode.clone(),
-        )
-        .await;
-    
-        // Handle the transaction result
-        let (signature, statuses) = send_transaction_result?;
-        if let Some(Some(status)) = statuses.get(0) {
-            let status = status.clone();
-            println!("Transaction status: {:?}", status);
-            if status.confirmation_status.is_some() && status.err.is_none() {
-                green!("Transaction confirmed with signature: {}", signature);
-                return Ok(signature);
-            } else if let Some(err) = status.err {
-                return Err(anyhow::anyhow!(format!("Transaction error: {:?}", err)));
-            }
+        let transaction = Rpc::build_transaction(&signers[0].pubkey(), &instructions, &signers, recent_blockhash)?;
+        LookupTable::record_usage(&instructions);
+
+        yellow!("Sending transaction {}...", transaction.signatures[0]);
+        Rpc::broadcast_and_confirm(rpc_mode, transaction, last_valid_block_height).await
+    }
+
+    /// Signs the instructions as a legacy transaction, or as a v0 transaction against the
+    /// programmatic wallet's lookup table when the legacy one would be too large to send
+    pub fn build_transaction(
+        payer: &Pubkey,
+        instructions: &[Instruction],
+        signers: &Vec<Box<dyn Signer + Send + Sync>>,
+        recent_blockhash: Hash,
+    ) -> anyhow::Result<VersionedTransaction> {
+        let message = Message::new_with_blockhash(instructions, Some(payer), &recent_blockhash);
+        let mut transaction = Transaction::new_unsigned(message);
+        transaction.try_sign(signers, recent_blockhash)?;
+
+        let legacy_size = bincode::serialized_size(&transaction)? as usize;
+        if legacy_size <= PACKET_DATA_SIZE {
+            return Ok(transaction.into());
         }
-    
-        Err(anyhow::anyhow!("Transaction failed to confirm"))
+
+        let lookup_tables = LookupTable::get_accounts();
+        if lookup_tables.is_empty() {
+            return Err(anyhow!("Transaction is {} bytes, over the {} byte limit, and no lookup table is loaded", legacy_size, PACKET_DATA_SIZE));
+        }
+
+        let message = v0::Message::try_compile(payer, instructions, &lookup_tables, recent_blockhash)?;
+        let transaction = VersionedTransaction::try_new(VersionedMessage::V0(message), signers)?;
+
+        let size = bincode::serialized_size(&transaction)? as usize;
+        if size > PACKET_DATA_SIZE {
+            return Err(anyhow!("Transaction is {} bytes with the lookup table, over the {} byte limit", size, PACKET_DATA_SIZE));
+        }
+        blue!("Built v0 transaction of {} bytes, {} as legacy", size, legacy_size);
+
+        Ok(transaction)
     }
     
-    
     pub async fn send_and_confirm_transaction(
         rpc_mode: RpcMode,
         instructions: Vec<Instruction>,
-        signers: Vec<Box<dyn Signer>>,
+        signers: Vec<Box<dyn Signer + Send + Sync>>,
         timeout_ms: Option<u64>,
     ) -> anyhow::Result<Signature> {
-        // Resolve the latest blockhash
-        
-        sleep(Duration::from_secs(1)).await;
-        let start = Instant::now();
-        // let recent_blockhash = NewPositionData::get_latest_blockhash().await?;
-        let recent_blockhash = Rpc::get_latest_blockhash(rpc_mode.clone(), timeout_ms).await?;
-        blue!("Fetched latest blockhash in {}ms", start.elapsed().as_millis());
-    
-        // Build the transaction
-        let message = solana_sdk::message::Message::new(&instructions, Some(&signers[0].pubkey()));
-        let transaction = Transaction::new(&signers, message, recent_blockhash);
-        
-        let transaction_clone = transaction.clone();
         let start = Instant::now();
-        let signature = Rpc::call(
-            move |client| {
-                yellow!("Sending transaction with {}...", client.url().domain());
-                let transaction = transaction_clone.clone();
-                Box::pin(async move {
-                    client.send_and_confirm_transaction(&transaction).await.map_err(|e| e.into())
-                })
-            },
-            timeout_ms,
-            rpc_mode.clone(),
-        ).await?;
+        let sent = Rpc::send_and_confirm_transaction_with_config(rpc_mode, instructions, signers, timeout_ms).await?;
         green!("Sent transaction in {}ms", start.elapsed().as_millis());
 
-        Ok(signature)
-    
+        sent.confirmed_signature()
     }
     
+    /// Sends an already signed versioned transaction, such as one built by an aggregator.
+    /// `last_valid_block_height` belongs to the blockhash the builder used.
+    pub async fn send_and_confirm_versioned_transaction(
+        rpc_mode: RpcMode,
+        transaction: VersionedTransaction,
+        last_valid_block_height: u64,
+    ) -> anyhow::Result<Signature> {
+        let start = Instant::now();
+        yellow!("Sending versioned transaction {}...", transaction.signatures[0]);
+        let sent = Rpc::broadcast_and_confirm(rpc_mode, transaction, last_valid_block_height).await?;
+        green!("Sent versioned transaction in {}ms", start.elapsed().as_millis());
+
+        sent.confirmed_signature()
+    }
+
     pub async fn get_account_creation_date(
         rpc_mode: RpcMode,
         address: &str,
         timeout_ms: Option<u64>,
     ) -> anyhow::Result<DateTime<Utc>> {
         let address = Pubkey::from_str(address).map_err(|e| anyhow!(e.to_string()))?;
-        let signatures = Rpc::call(
+        let signatures = Rpc::call_weighted(
+            RpcMethod::GetSignatures,
             move |client| {
                 Box::pin(async move {
                     client.get_signatures_for_address(&address).await.map_err(|e| e.into())
@@ -468,7 +545,8 @@ impl Rpc {
         let transaction_clone = transaction.clone();
     
         // Call your `Rpc::call` implementation
-        let response = Rpc::call(
+        let response = Rpc::call_weighted(
+            RpcMethod::SimulateTransaction,
             move |client| {
                 let transaction = transaction_clone.clone();
                 Box::pin(async move {
@@ -484,10 +562,110 @@ impl Rpc {
         Ok((response.context, response.value))
     }
 
+    pub async fn simulate_versioned_transaction(
+        rpc_mode: RpcMode,
+        transaction: VersionedTransaction,
+        timeout_ms: Option<u64>,
+    ) -> anyhow::Result<(RpcResponseContext, RpcSimulateTransactionResult)> {
+        let response = Rpc::call_weighted(
+            RpcMethod::SimulateTransaction,
+            move |client| {
+                let transaction = transaction.clone();
+                Box::pin(async move {
+                    client.simulate_transaction(&transaction).await.map_err(|e| e.into())
+                })
+            },
+            timeout_ms,
+            rpc_mode,
+        )
+        .await?;
+
+        Ok((response.context, response.value))
+    }
+
+    pub async fn get_epoch(rpc_mode: RpcMode) -> anyhow::Result<u64> {
+        let epoch_info = Rpc::call(
+            move |client| {
+                Box::pin(async move {
+                    client.get_epoch_info().await.map_err(|e| e.into())
+                })
+            },
+            Some(5000),
+            rpc_mode,
+        ).await?;
+
+        Ok(epoch_info.epoch)
+    }
+
+    pub async fn get_account(rpc_mode: RpcMode, address: &Pubkey, timeout_ms: Option<u64>) -> anyhow::Result<Account> {
+        let address = *address;
+        let account = Rpc::call_weighted(
+            RpcMethod::GetAccount,
+            move |client| {
+                Box::pin(async move {
+                    client.get_account(&address).await.map_err(|e| e.into())
+                })
+            },
+            timeout_ms,
+            rpc_mode,
+        ).await?;
+
+        Ok(account)
+    }
+
+    pub async fn get_multiple_accounts(rpc_mode: RpcMode, addresses: &[Pubkey], timeout_ms: Option<u64>) -> anyhow::Result<Vec<Option<Account>>> {
+        let mut accounts = Vec::with_capacity(addresses.len());
+
+        // getMultipleAccounts is capped at 100 keys per request
+        for chunk in addresses.chunks(100) {
+            let chunk = chunk.to_vec();
+            let chunk_accounts = Rpc::call_weighted(
+                RpcMethod::GetMultipleAccounts,
+                move |client| {
+                    let chunk = chunk.clone();
+                    Box::pin(async move {
+                        client.get_multiple_accounts(&chunk).await.map_err(|e| e.into())
+                    })
+                },
+                timeout_ms,
+                rpc_mode.clone(),
+            ).await?;
+            accounts.extend(chunk_accounts);
+        }
+
+        Ok(accounts)
+    }
+
+    pub async fn get_program_accounts(
+        rpc_mode: RpcMode,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+        timeout_ms: Option<u64>,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+        let program_id = *program_id;
+        let accounts = Rpc::call_weighted(
+            RpcMethod::GetProgramAccounts,
+            move |client| {
+                let filters = filters.clone();
+                Box::pin(async move {
+                    let config = RpcProgramAccountsConfig {
+                        filters: Some(filters),
+                        ..Default::default()
+                    };
+                    client.get_program_accounts_with_config(&program_id, config).await.map_err(|e| e.into())
+                })
+            },
+            timeout_ms,
+            rpc_mode,
+        ).await?;
+
+        Ok(accounts)
+    }
+
     pub async fn get_statuses(rpc_mode: RpcMode, signature: Signature, timeout_ms: Option<u64>) -> anyhow::Result<Vec<Option<TransactionStatus>>> {
-        let statuses_response = Rpc::call(
+        let statuses_response = Rpc::call_weighted(
+            RpcMethod::GetSignatureStatuses,
             move |client| {
-                let signature = signature.clone();
                 Box::pin(async move {
                     client.get_signature_statuses(&[signature]).await.map_err(|e| e.into())
                 })
@@ -501,6 +679,26 @@ impl Rpc {
         Ok(statuses)
         
     }
+
+    /// A confirmed transaction with its status meta, including v0 transactions
+    pub async fn get_transaction(rpc_mode: RpcMode, signature: Signature, timeout_ms: Option<u64>) -> anyhow::Result<EncodedConfirmedTransactionWithStatusMeta> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+
+        Rpc::call_weighted(
+            RpcMethod::GetTransaction,
+            move |client| {
+                Box::pin(async move {
+                    client.get_transaction_with_config(&signature, config).await.map_err(|e| e.into())
+                })
+            },
+            timeout_ms,
+            rpc_mode,
+        ).await
+    }
     
 
     // pub async fn estimate_transaction_fees(
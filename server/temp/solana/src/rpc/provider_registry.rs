@@ -0,0 +1,399 @@
+use std::{collections::{HashMap, VecDeque}, sync::{Arc, Mutex, MutexGuard}, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use solana_client::{client_error::{ClientError, ClientErrorKind}, nonblocking::rpc_client::RpcClient, rpc_request::RpcError};
+use state::InitCell;
+use tokio::time::{interval, timeout};
+
+use super::{rate_limiter::{RateLimiter, RpcMethod}, rpc_url::RpcUrl, DomainExtractor};
+
+/// Outcomes kept per provider for percentiles and rates
+const WINDOW: usize = 200;
+/// Score given to providers that have not answered yet, so they still get tried
+const UNMEASURED_LATENCY_MS: f64 = 400.0;
+const COOLDOWN_AFTER_FAILURES: u32 = 3;
+const BASE_COOLDOWN: Duration = Duration::from_secs(30);
+const MAX_COOLDOWN: Duration = Duration::from_secs(300);
+/// Providers further behind the highest slot seen are taken out of rotation
+const MAX_SLOT_LAG: u64 = 50;
+const SLOT_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+const SLOT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many providers `RpcMode::fast()` races at once
+const FAST_PROVIDER_COUNT: usize = 3;
+
+pub static PROVIDER_REGISTRY: InitCell<Arc<Mutex<ProviderRegistry>>> = InitCell::new();
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum CallOutcome {
+    Success,
+    Error,
+    Timeout,
+}
+
+/// What a provider list is being ordered for. `Latency` favours the quickest providers,
+/// `Reliability` weighs errors and timeouts more heavily.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum RankBy {
+    Latency,
+    Reliability,
+}
+
+impl CallOutcome {
+    /// Classifies a failed call. Errors about the request itself, such as a missing account or a
+    /// failed preflight, mean the provider answered fine and are not held against it.
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        let client_error = match err.downcast_ref::<ClientError>() {
+            Some(client_error) => client_error,
+            None => return CallOutcome::Success,
+        };
+
+        match client_error.kind() {
+            ClientErrorKind::RpcError(RpcError::ForUser(_)) => CallOutcome::Success,
+            // Invalid request, params or method, and transaction preflight failures
+            ClientErrorKind::RpcError(RpcError::RpcResponseError { code: -32600 | -32601 | -32602 | -32002, .. }) => CallOutcome::Success,
+            ClientErrorKind::TransactionError(_) | ClientErrorKind::SigningError(_) => CallOutcome::Success,
+            _ => CallOutcome::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ProviderStats {
+    domain: String,
+    latencies_ms: VecDeque<u64>,
+    outcomes: VecDeque<CallOutcome>,
+    total_calls: u64,
+    consecutive_failures: u32,
+    cooldown_until: Option<DateTime<Utc>>,
+    last_slot: Option<u64>,
+    last_slot_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+/// Live view of a provider for diagnostics. Only the domain is exposed, URLs carry API keys.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProviderScore {
+    pub domain: String,
+    pub score: f64,
+    pub available: bool,
+    pub latency_p50_ms: Option<u64>,
+    pub latency_p90_ms: Option<u64>,
+    pub latency_p99_ms: Option<u64>,
+    pub error_rate: f64,
+    pub timeout_rate: f64,
+    pub total_calls: u64,
+    pub consecutive_failures: u32,
+    pub cooldown_until: Option<DateTime<Utc>>,
+    pub last_slot: Option<u64>,
+    pub last_slot_at: Option<DateTime<Utc>>,
+    pub slot_lag: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProviderRegistry {
+    providers: HashMap<String, ProviderStats>,
+    highest_slot: u64,
+}
+
+impl ProviderStats {
+    fn new(url: &str) -> Self {
+        Self {
+            domain: url.domain(),
+            ..Default::default()
+        }
+    }
+
+    fn percentile(&self, percentile: f64) -> Option<u64> {
+        if self.latencies_ms.is_empty() {
+            return None;
+        }
+
+        let mut sorted: Vec<u64> = self.latencies_ms.iter().copied().collect();
+        sorted.sort_unstable();
+        let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+
+        Some(sorted[index])
+    }
+
+    fn rate(&self, outcome: CallOutcome) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+
+        self.outcomes.iter().filter(|recorded| **recorded == outcome).count() as f64 / self.outcomes.len() as f64
+    }
+
+    fn slot_lag(&self, highest_slot: u64) -> Option<u64> {
+        self.last_slot.map(|slot| highest_slot.saturating_sub(slot))
+    }
+
+    fn in_cooldown(&self) -> bool {
+        self.cooldown_until.is_some_and(|until| until > Utc::now())
+    }
+
+    fn available(&self, highest_slot: u64) -> bool {
+        !self.in_cooldown() && self.slot_lag(highest_slot).is_none_or(|lag| lag <= MAX_SLOT_LAG)
+    }
+
+    /// Lower is better. Roughly the latency to expect once failures and staleness are priced in.
+    fn score(&self, highest_slot: u64, rank_by: RankBy) -> f64 {
+        let latency = match rank_by {
+            RankBy::Latency => self.percentile(0.5),
+            RankBy::Reliability => self.percentile(0.9),
+        }
+        .map(|latency| latency as f64)
+        .unwrap_or(UNMEASURED_LATENCY_MS);
+
+        let failure_weight = match rank_by {
+            RankBy::Latency => 2.0,
+            RankBy::Reliability => 6.0,
+        };
+        let failure_rate = self.rate(CallOutcome::Error) + self.rate(CallOutcome::Timeout) * 1.5;
+        let slot_penalty = self.slot_lag(highest_slot).unwrap_or(0) as f64 * 20.0;
+
+        latency * (1.0 + failure_weight * failure_rate) + slot_penalty
+    }
+}
+
+impl ProviderRegistry {
+    /// Locks the shared registry, creating it on first use. A poisoned lock still holds usable
+    /// statistics, so it is recovered rather than propagated.
+    fn lock() -> MutexGuard<'static, ProviderRegistry> {
+        if PROVIDER_REGISTRY.try_get().is_none() {
+            PROVIDER_REGISTRY.set(Arc::new(Mutex::new(ProviderRegistry::default())));
+        }
+
+        PROVIDER_REGISTRY.get().lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Starts probing every configured provider's slot so lagging nodes drop out of rotation
+    pub fn start_slot_monitor() {
+        tokio::spawn(async {
+            let mut interval = interval(SLOT_PROBE_INTERVAL);
+            loop {
+                interval.tick().await;
+                ProviderRegistry::probe_slots().await;
+            }
+        });
+    }
+
+    /// Records a call against a provider. Only successful calls feed the latency percentiles.
+    pub fn record(url: &str, outcome: CallOutcome, elapsed_ms: u64, error: Option<String>) {
+        let mut registry = ProviderRegistry::lock();
+
+        let stats = registry.providers.entry(url.to_string()).or_insert_with(|| ProviderStats::new(url));
+        stats.total_calls += 1;
+        stats.outcomes.push_back(outcome);
+        if stats.outcomes.len() > WINDOW {
+            stats.outcomes.pop_front();
+        }
+
+        match outcome {
+            CallOutcome::Success => {
+                stats.latencies_ms.push_back(elapsed_ms);
+                if stats.latencies_ms.len() > WINDOW {
+                    stats.latencies_ms.pop_front();
+                }
+                stats.consecutive_failures = 0;
+                stats.cooldown_until = None;
+            }
+            CallOutcome::Error | CallOutcome::Timeout => {
+                stats.consecutive_failures += 1;
+                stats.last_error = error.or_else(|| Some(format!("{:?}", outcome)));
+
+                if stats.consecutive_failures >= COOLDOWN_AFTER_FAILURES {
+                    // Doubles with every failure past the threshold, up to the cap
+                    let exponent = (stats.consecutive_failures - COOLDOWN_AFTER_FAILURES).min(8);
+                    let cooldown = (BASE_COOLDOWN * 2u32.pow(exponent)).min(MAX_COOLDOWN);
+                    let until = Utc::now() + chrono::Duration::from_std(cooldown).unwrap_or_default();
+
+                    if stats.cooldown_until.is_none_or(|current| current < until) {
+                        yellow!("RPC provider {} in cooldown for {}s", stats.domain, cooldown.as_secs());
+                        stats.cooldown_until = Some(until);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn record_slot(url: &str, slot: u64) {
+        let mut registry = ProviderRegistry::lock();
+
+        registry.highest_slot = registry.highest_slot.max(slot);
+        let stats = registry.providers.entry(url.to_string()).or_insert_with(|| ProviderStats::new(url));
+        stats.last_slot = Some(slot);
+        stats.last_slot_at = Some(Utc::now());
+    }
+
+    /// Orders `urls` best first. Providers in cooldown or lagging are dropped unless that would
+    /// leave nothing, in which case everything is returned in score order.
+    pub fn rank(urls: Vec<String>, rank_by: RankBy) -> Vec<String> {
+        let registry = ProviderRegistry::lock();
+
+        let mut scored: Vec<(String, f64, bool)> = urls
+            .into_iter()
+            .map(|url| match registry.providers.get(&url) {
+                Some(stats) => {
+                    let score = stats.score(registry.highest_slot, rank_by);
+                    let available = stats.available(registry.highest_slot);
+                    (url, score, available)
+                }
+                None => (url, UNMEASURED_LATENCY_MS, true),
+            })
+            .collect();
+        scored.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        if scored.iter().any(|(_, _, available)| *available) {
+            scored.retain(|(_, _, available)| *available);
+        }
+
+        scored.into_iter().map(|(url, _, _)| url).collect()
+    }
+
+    /// The best few providers for racing concurrent calls
+    pub fn fastest(urls: Vec<String>) -> Vec<String> {
+        ProviderRegistry::rank(urls, RankBy::Latency).into_iter().take(FAST_PROVIDER_COUNT).collect()
+    }
+
+    pub fn get_scores() -> Vec<ProviderScore> {
+        let registry = ProviderRegistry::lock();
+
+        let mut scores: Vec<ProviderScore> = registry
+            .providers
+            .values()
+            .map(|stats| ProviderScore {
+                domain: stats.domain.clone(),
+                score: stats.score(registry.highest_slot, RankBy::Latency),
+                available: stats.available(registry.highest_slot),
+                latency_p50_ms: stats.percentile(0.5),
+                latency_p90_ms: stats.percentile(0.9),
+                latency_p99_ms: stats.percentile(0.99),
+                error_rate: stats.rate(CallOutcome::Error),
+                timeout_rate: stats.rate(CallOutcome::Timeout),
+                total_calls: stats.total_calls,
+                consecutive_failures: stats.consecutive_failures,
+                cooldown_until: stats.cooldown_until,
+                last_slot: stats.last_slot,
+                last_slot_at: stats.last_slot_at,
+                slot_lag: stats.slot_lag(registry.highest_slot),
+                last_error: stats.last_error.clone(),
+            })
+            .collect();
+        scores.sort_by(|a, b| a.score.total_cmp(&b.score));
+
+        scores
+    }
+
+    /// Asks every configured provider for its slot directly, bypassing `Rpc::call` so probes do
+    /// not depend on the ranking they feed
+    async fn probe_slots() {
+        let mut urls = RpcUrl::volume_priority();
+        for url in RpcUrl::speed_priority() {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+
+        // Probes share the providers' rate limits, a throttled provider just skips this round
+        urls.retain(|url| RateLimiter::try_acquire(url, RpcMethod::Other));
+
+        let probes = urls.into_iter().map(|url| async move {
+            let client = RpcClient::new(url.clone());
+            let start = std::time::Instant::now();
+
+            match timeout(SLOT_PROBE_TIMEOUT, client.get_slot()).await {
+                Ok(Ok(slot)) => {
+                    ProviderRegistry::record(&url, CallOutcome::Success, start.elapsed().as_millis() as u64, None);
+                    ProviderRegistry::record_slot(&url, slot);
+                }
+                Ok(Err(e)) => ProviderRegistry::record(&url, CallOutcome::Error, start.elapsed().as_millis() as u64, Some(e.to_string())),
+                Err(_) => ProviderRegistry::record(&url, CallOutcome::Timeout, start.elapsed().as_millis() as u64, None),
+            }
+        });
+
+        join_all(probes).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::rpc_request::RpcResponseErrorData;
+
+    fn stats(latencies_ms: &[u64], outcomes: &[CallOutcome]) -> ProviderStats {
+        ProviderStats {
+            domain: "provider".to_string(),
+            latencies_ms: latencies_ms.iter().copied().collect(),
+            outcomes: outcomes.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn failures_and_slot_lag_raise_the_score() {
+        let healthy = stats(&[100; 10], &[CallOutcome::Success; 10]);
+        let mut failing = stats(&[100; 8], &[CallOutcome::Success; 8]);
+        failing.outcomes.extend([CallOutcome::Error, CallOutcome::Timeout]);
+
+        assert_eq!(healthy.score(0, RankBy::Latency), 100.0);
+        // 10% errors and 10% timeouts weighted 1.5x
+        assert!((failing.score(0, RankBy::Latency) - 100.0 * (1.0 + 2.0 * 0.25)).abs() < 1e-9);
+        assert!((failing.score(0, RankBy::Reliability) - 100.0 * (1.0 + 6.0 * 0.25)).abs() < 1e-9);
+
+        let mut lagging = stats(&[100; 10], &[CallOutcome::Success; 10]);
+        lagging.last_slot = Some(1_000);
+        assert_eq!(lagging.score(1_010, RankBy::Latency), 100.0 + 10.0 * 20.0);
+        assert!(lagging.available(1_000 + MAX_SLOT_LAG));
+        assert!(!lagging.available(1_001 + MAX_SLOT_LAG));
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let stats = stats(&(1..=100).collect::<Vec<_>>(), &[]);
+        assert_eq!(stats.percentile(0.5), Some(51));
+        assert_eq!(stats.percentile(0.9), Some(90));
+        assert_eq!(stats.percentile(0.99), Some(99));
+        assert_eq!(ProviderStats::default().percentile(0.5), None);
+    }
+
+    #[test]
+    fn rank_drops_providers_in_cooldown_unless_none_are_left() {
+        let fast = "https://fast.rank.test".to_string();
+        let slow = "https://slow.rank.test".to_string();
+        let failing = "https://failing.rank.test".to_string();
+
+        ProviderRegistry::record(&fast, CallOutcome::Success, 50, None);
+        ProviderRegistry::record(&slow, CallOutcome::Success, 300, None);
+        for _ in 0..COOLDOWN_AFTER_FAILURES {
+            ProviderRegistry::record(&failing, CallOutcome::Error, 10, Some("connection refused".to_string()));
+        }
+
+        assert_eq!(ProviderRegistry::rank(vec![slow.clone(), failing.clone(), fast.clone()], RankBy::Latency), vec![fast, slow]);
+        assert_eq!(ProviderRegistry::rank(vec![failing.clone()], RankBy::Latency), vec![failing.clone()]);
+
+        // A success ends the cooldown
+        ProviderRegistry::record(&failing, CallOutcome::Success, 10, None);
+        assert_eq!(ProviderRegistry::rank(vec![failing.clone()], RankBy::Latency), vec![failing]);
+    }
+
+    #[test]
+    fn request_errors_are_not_held_against_the_provider() {
+        let response_error = |code: i64| -> anyhow::Error {
+            ClientError::from(ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                code,
+                message: "error".to_string(),
+                data: RpcResponseErrorData::Empty,
+            }))
+            .into()
+        };
+
+        assert_eq!(CallOutcome::from_error(&response_error(-32602)), CallOutcome::Success);
+        assert_eq!(CallOutcome::from_error(&response_error(-32005)), CallOutcome::Error);
+        assert_eq!(CallOutcome::from_error(&ClientError::from(ClientErrorKind::Custom("connection reset".to_string())).into()), CallOutcome::Error);
+        assert_eq!(CallOutcome::from_error(&anyhow::anyhow!("account not found")), CallOutcome::Success);
+    }
+}
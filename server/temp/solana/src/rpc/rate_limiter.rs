@@ -0,0 +1,388 @@
+use std::{collections::HashMap, sync::{Arc, Mutex, MutexGuard}, time::{Duration, Instant}};
+
+use chrono::{NaiveDate, Utc};
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use state::InitCell;
+use tokio::time::sleep;
+
+use super::rpc_url::RpcUrl;
+
+/// Longest a call waits for a bucket to refill when every provider is throttled
+const MAX_THROTTLE_WAIT: Duration = Duration::from_secs(2);
+/// How long a provider is left alone after answering with a 429
+const RATE_LIMITED_PAUSE: Duration = Duration::from_secs(1);
+
+pub static RATE_LIMITER: InitCell<Arc<Mutex<RateLimiter>>> = InitCell::new();
+
+/// What a call costs against a provider's bucket and daily quota. Weights loosely follow the
+/// credit tables providers bill by, where scans and sends cost more than single reads.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum RpcMethod {
+    GetAccount,
+    GetMultipleAccounts,
+    GetProgramAccounts,
+    GetTokenAccounts,
+    GetSignatures,
+    GetSignatureStatuses,
+    GetTransaction,
+    SimulateTransaction,
+    SendTransaction,
+    /// A sidecar request, which makes several RPC calls of its own
+    Sidecar,
+    Other,
+}
+
+impl RpcMethod {
+    pub fn weight(&self) -> u64 {
+        match self {
+            RpcMethod::GetAccount => 1,
+            RpcMethod::GetMultipleAccounts => 2,
+            RpcMethod::GetProgramAccounts => 10,
+            RpcMethod::GetTokenAccounts => 5,
+            RpcMethod::GetSignatures => 5,
+            RpcMethod::GetSignatureStatuses => 1,
+            RpcMethod::GetTransaction => 2,
+            RpcMethod::SimulateTransaction => 2,
+            RpcMethod::SendTransaction => 2,
+            RpcMethod::Sidecar => 5,
+            RpcMethod::Other => 1,
+        }
+    }
+}
+
+/// Token bucket limits for one provider. `daily_quota` is in weighted credits and resets at
+/// midnight UTC.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    pub burst: f64,
+    pub daily_quota: Option<u64>,
+}
+
+impl RateLimit {
+    /// Defaults sized for each provider's entry plan
+    fn default_for(provider: &str) -> Self {
+        let requests_per_second = match provider {
+            "helius" => 10.0,
+            "quicknode" => 15.0,
+            "alchemy" => 25.0,
+            "syndica" => 25.0,
+            "chainstack" => 25.0,
+            "solana" => 8.0,
+            _ => 10.0,
+        };
+
+        RateLimit {
+            requests_per_second,
+            burst: requests_per_second * 2.0,
+            daily_quota: None,
+        }
+    }
+
+    /// Limits for a provider, overridable with `<PROVIDER>_RPC_RPS`, `<PROVIDER>_RPC_BURST` and
+    /// `<PROVIDER>_RPC_DAILY_QUOTA`, e.g. `HELIUS_RPC_RPS=50`
+    pub fn for_provider(provider: &str) -> Self {
+        let default = RateLimit::default_for(provider);
+        let env = |setting: &str| std::env::var(format!("{}_RPC_{}", provider.to_uppercase(), setting)).ok();
+
+        let requests_per_second = env("RPS")
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| *value > 0.0)
+            .unwrap_or(default.requests_per_second);
+
+        RateLimit {
+            requests_per_second,
+            burst: env("BURST")
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|value| *value >= 1.0)
+                .unwrap_or(requests_per_second * 2.0),
+            daily_quota: env("DAILY_QUOTA")
+                .and_then(|value| value.parse::<u64>().ok())
+                .or(default.daily_quota),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+    paused_until: Option<Instant>,
+    quota_day: NaiveDate,
+    quota_used: u64,
+    throttled_calls: u64,
+    rate_limited_responses: u64,
+}
+
+/// Current budget of a provider for diagnostics
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProviderUsage {
+    pub provider: String,
+    pub limit: RateLimit,
+    pub available_tokens: f64,
+    pub quota_used: u64,
+    pub quota_remaining: Option<u64>,
+    pub throttled_calls: u64,
+    pub rate_limited_responses: u64,
+    pub paused: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, TokenBucket>,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            last_refill: Instant::now(),
+            paused_until: None,
+            quota_day: Utc::now().date_naive(),
+            quota_used: 0,
+            throttled_calls: 0,
+            rate_limited_responses: 0,
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.requests_per_second).min(self.limit.burst);
+        self.last_refill = now;
+
+        let today = Utc::now().date_naive();
+        if today != self.quota_day {
+            self.quota_day = today;
+            self.quota_used = 0;
+        }
+    }
+
+    /// A weight above the burst could never be paid for, so it is charged the full bucket instead
+    fn cost(&self, method: RpcMethod) -> f64 {
+        (method.weight() as f64).min(self.limit.burst)
+    }
+
+    fn quota_exhausted(&self, method: RpcMethod) -> bool {
+        self.limit.daily_quota.is_some_and(|quota| self.quota_used + method.weight() > quota)
+    }
+
+    fn paused(&self) -> bool {
+        self.paused_until.is_some_and(|until| until > Instant::now())
+    }
+
+    fn try_take(&mut self, method: RpcMethod) -> bool {
+        self.refill();
+
+        let cost = self.cost(method);
+        if self.paused() || self.quota_exhausted(method) || self.tokens < cost {
+            self.throttled_calls += 1;
+            return false;
+        }
+
+        self.tokens -= cost;
+        self.quota_used += method.weight();
+        true
+    }
+
+    fn wait_time(&mut self, method: RpcMethod) -> Duration {
+        self.refill();
+
+        let refill_wait = (self.cost(method) - self.tokens).max(0.0) / self.limit.requests_per_second;
+        let pause_wait = self.paused_until
+            .map(|until| until.saturating_duration_since(Instant::now()).as_secs_f64())
+            .unwrap_or(0.0);
+
+        Duration::from_secs_f64(refill_wait.max(pause_wait))
+    }
+}
+
+impl RateLimiter {
+    fn lock() -> MutexGuard<'static, RateLimiter> {
+        if RATE_LIMITER.try_get().is_none() {
+            RATE_LIMITER.set(Arc::new(Mutex::new(RateLimiter::default())));
+        }
+
+        RATE_LIMITER.get().lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Buckets are shared by every url of the same provider, since limits apply per account
+    fn bucket(&mut self, url: &str) -> &mut TokenBucket {
+        let provider = RpcUrl::provider(url);
+        self.buckets
+            .entry(provider)
+            .or_insert_with(|| TokenBucket::new(RateLimit::for_provider(provider)))
+    }
+
+    /// Takes a call's weight from the provider's bucket, or returns false if it is throttled
+    pub fn try_acquire(url: &str, method: RpcMethod) -> bool {
+        RateLimiter::lock().bucket(url).try_take(method)
+    }
+
+    /// Removes and returns the first url in `urls` with room for the call, so throttled
+    /// providers overflow to the next one in order. Providers out of daily quota are dropped.
+    /// When all of them are throttled it waits briefly for a refill, and gives up with `None`.
+    pub async fn acquire_next(urls: &mut Vec<String>, method: RpcMethod) -> Option<String> {
+        let started = Instant::now();
+
+        loop {
+            let wait = {
+                let mut limiter = RateLimiter::lock();
+
+                urls.retain(|url| {
+                    let bucket = limiter.bucket(url);
+                    bucket.refill();
+                    let exhausted = bucket.quota_exhausted(method);
+                    if exhausted {
+                        yellow!("RPC provider {} is out of daily quota", RpcUrl::provider(url));
+                    }
+                    !exhausted
+                });
+
+                if let Some(index) = urls.iter().position(|url| limiter.bucket(url).try_take(method)) {
+                    return Some(urls.remove(index));
+                }
+
+                urls.iter().map(|url| limiter.bucket(url).wait_time(method)).min()?
+            };
+
+            if started.elapsed() + wait > MAX_THROTTLE_WAIT {
+                return None;
+            }
+            sleep(wait).await;
+        }
+    }
+
+    /// Keeps the urls with room for the call, for racing concurrently. If none have room, waits
+    /// for the first to free up as `acquire_next` does.
+    pub async fn acquire_all(mut urls: Vec<String>, method: RpcMethod) -> Vec<String> {
+        let allowed: Vec<String> = {
+            let mut limiter = RateLimiter::lock();
+            urls.iter().filter(|url| limiter.bucket(url).try_take(method)).cloned().collect()
+        };
+
+        if !allowed.is_empty() {
+            return allowed;
+        }
+
+        RateLimiter::acquire_next(&mut urls, method).await.into_iter().collect()
+    }
+
+    /// Whether an error is the provider telling us to slow down. A bare "429" is not matched as
+    /// it turns up inside base58 addresses and signatures.
+    pub fn is_rate_limited(message: &str) -> bool {
+        let message = message.to_lowercase();
+        message.contains("too many requests") || message.contains("(429") || message.contains("status: 429")
+    }
+
+    /// Empties the provider's bucket and pauses it after a 429
+    pub fn record_rate_limited(url: &str) {
+        let mut limiter = RateLimiter::lock();
+        let bucket = limiter.bucket(url);
+
+        bucket.tokens = 0.0;
+        bucket.paused_until = Some(Instant::now() + RATE_LIMITED_PAUSE);
+        bucket.rate_limited_responses += 1;
+        yellow!("RPC provider {} returned 429, pausing for {}s", RpcUrl::provider(url), RATE_LIMITED_PAUSE.as_secs());
+    }
+
+    pub fn get_usage() -> Vec<ProviderUsage> {
+        let mut limiter = RateLimiter::lock();
+
+        let mut usage: Vec<ProviderUsage> = limiter
+            .buckets
+            .iter_mut()
+            .map(|(provider, bucket)| {
+                bucket.refill();
+                ProviderUsage {
+                    provider: provider.to_string(),
+                    limit: bucket.limit,
+                    available_tokens: bucket.tokens,
+                    quota_used: bucket.quota_used,
+                    quota_remaining: bucket.limit.daily_quota.map(|quota| quota.saturating_sub(bucket.quota_used)),
+                    throttled_calls: bucket.throttled_calls,
+                    rate_limited_responses: bucket.rate_limited_responses,
+                    paused: bucket.paused(),
+                }
+            })
+            .collect();
+        usage.sort_by(|a, b| a.provider.cmp(&b.provider));
+
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(requests_per_second: f64, burst: f64, daily_quota: Option<u64>) -> TokenBucket {
+        TokenBucket::new(RateLimit { requests_per_second, burst, daily_quota })
+    }
+
+    #[test]
+    fn bucket_throttles_past_the_burst_and_refills_over_time() {
+        let mut bucket = bucket(10.0, 4.0, None);
+
+        assert!(bucket.try_take(RpcMethod::GetMultipleAccounts));
+        assert!(bucket.try_take(RpcMethod::GetTransaction));
+        assert!(!bucket.try_take(RpcMethod::GetAccount));
+        assert_eq!(bucket.throttled_calls, 1);
+
+        // 0.2s at 10 per second buys back two credits
+        bucket.last_refill -= Duration::from_millis(200);
+        assert!(bucket.try_take(RpcMethod::SendTransaction));
+
+        bucket.last_refill -= Duration::from_secs(60);
+        bucket.refill();
+        assert_eq!(bucket.tokens, 4.0);
+    }
+
+    #[test]
+    fn heavy_calls_cost_at_most_the_burst() {
+        let mut bucket = bucket(2.0, 4.0, None);
+
+        assert_eq!(bucket.cost(RpcMethod::GetProgramAccounts), 4.0);
+        assert!(bucket.try_take(RpcMethod::GetProgramAccounts));
+        assert_eq!(bucket.quota_used, RpcMethod::GetProgramAccounts.weight());
+
+        let wait = bucket.wait_time(RpcMethod::GetAccount);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn daily_quota_runs_out_and_resets_the_next_day() {
+        let mut bucket = bucket(1_000.0, 1_000.0, Some(12));
+
+        assert!(bucket.try_take(RpcMethod::GetProgramAccounts));
+        assert!(bucket.try_take(RpcMethod::GetMultipleAccounts));
+        assert!(!bucket.try_take(RpcMethod::GetAccount));
+        assert!(bucket.quota_exhausted(RpcMethod::GetAccount));
+
+        bucket.quota_day = bucket.quota_day.pred_opt().unwrap();
+        assert!(bucket.try_take(RpcMethod::GetAccount));
+        assert_eq!(bucket.quota_used, 1);
+    }
+
+    #[test]
+    fn paused_bucket_waits_out_the_pause() {
+        let mut bucket = bucket(10.0, 20.0, None);
+        bucket.paused_until = Some(Instant::now() + RATE_LIMITED_PAUSE);
+
+        assert!(!bucket.try_take(RpcMethod::GetAccount));
+        assert!(bucket.wait_time(RpcMethod::GetAccount) > Duration::from_millis(900));
+
+        bucket.paused_until = Some(Instant::now() - Duration::from_millis(1));
+        assert!(bucket.try_take(RpcMethod::GetAccount));
+    }
+
+    #[test]
+    fn recognises_rate_limit_errors() {
+        assert!(RateLimiter::is_rate_limited("HTTP status client error (429 Too Many Requests) for url"));
+        assert!(RateLimiter::is_rate_limited("status: 429, body: rate limited"));
+        assert!(!RateLimiter::is_rate_limited("Account 4299xQk1pZ not found"));
+    }
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::DomainExtractor;
+
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RpcUrl;
@@ -47,6 +49,25 @@ impl RpcUrl {
         "https://solana.drpc.org".to_string()
     }
 
+    /// Short provider name for a url, used to key per-provider configuration such as rate limits
+    pub fn provider(url: &str) -> &'static str {
+        let domain = url.domain();
+        [
+            ("helius.xyz", "helius"),
+            ("quiknode.pro", "quicknode"),
+            ("alchemy.com", "alchemy"),
+            ("syndica.io", "syndica"),
+            ("chainstack.com", "chainstack"),
+            ("publicnode.com", "publicnode"),
+            ("drpc.org", "drpc"),
+            ("mainnet-beta.solana.com", "solana"),
+        ]
+        .into_iter()
+        .find(|(suffix, _)| domain.ends_with(suffix))
+        .map(|(_, provider)| provider)
+        .unwrap_or("unknown")
+    }
+
     pub fn volume_priority() -> Vec<String> {
         let mut urls = vec![];
         // match Self::alchemy() {
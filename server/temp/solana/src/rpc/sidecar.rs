@@ -0,0 +1,421 @@
+use std::{collections::HashMap, env, fmt, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
+
+use chrono::{DateTime, Utc};
+use kebtech_utils::*;
+use orca_pools_ipc_types::{envelope::{SidecarEnvelope, SidecarReply, SidecarRequest, PROTOCOL_VERSION}, request::Request, response::Response, DEFAULT_SOCKET_PATH};
+use serde::{Deserialize, Serialize};
+use state::InitCell;
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::{unix::OwnedWriteHalf, UnixStream}, sync::{oneshot, Mutex, RwLock}, time::{sleep, timeout}};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+const CONNECT_ATTEMPTS: u32 = 5;
+
+pub static SIDECAR: InitCell<Arc<SidecarClient>> = InitCell::new();
+
+/// Failures of an Orca sidecar call. `Rpc` means the sidecar is healthy but the Solana RPC it
+/// used failed, so the call can be retried against another URL. Everything else is a problem
+/// with the sidecar itself.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SidecarError {
+    Unavailable(String),
+    VersionMismatch { expected: u32, found: u32 },
+    Timeout { request_id: u64, timeout_ms: u64 },
+    Protocol(String),
+    Remote(String),
+    Rpc { url: String, message: String },
+}
+
+impl fmt::Display for SidecarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SidecarError::Unavailable(reason) => write!(f, "Orca sidecar unavailable: {}", reason),
+            SidecarError::VersionMismatch { expected, found } => write!(f, "Orca sidecar speaks protocol v{}, expected v{}", found, expected),
+            SidecarError::Timeout { request_id, timeout_ms } => write!(f, "Orca sidecar request {} timed out after {}ms", request_id, timeout_ms),
+            SidecarError::Protocol(reason) => write!(f, "Orca sidecar protocol error: {}", reason),
+            SidecarError::Remote(reason) => write!(f, "Orca sidecar error: {}", reason),
+            SidecarError::Rpc { url, message } => write!(f, "RPC error via Orca sidecar ({}): {}", url, message),
+        }
+    }
+}
+
+impl std::error::Error for SidecarError {}
+
+impl SidecarError {
+    pub fn is_rpc(&self) -> bool {
+        matches!(self, SidecarError::Rpc { .. })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SidecarStatus {
+    pub socket_path: String,
+    /// Whether calls use the versioned envelope protocol rather than a connection per request
+    pub envelope_protocol: bool,
+    pub connected: bool,
+    pub protocol_version: Option<u32>,
+    pub connected_at: Option<DateTime<Utc>>,
+    pub last_ping_at: Option<DateTime<Utc>>,
+    pub last_ping_latency_ms: Option<i64>,
+    pub consecutive_failures: u32,
+    pub reconnects: u32,
+    pub pending_requests: usize,
+    pub last_error: Option<String>,
+}
+
+type PendingReplies = Arc<std::sync::Mutex<HashMap<u64, oneshot::Sender<SidecarReply>>>>;
+
+struct SidecarConnection {
+    generation: u64,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+}
+
+/// Client for the Orca sidecar. Requests and replies are newline delimited JSON envelopes over a
+/// unix socket, matched up by request id so concurrent calls can share one connection. With
+/// `ORCA_SIDECAR_PROTOCOL=legacy`, each call is a bare request on its own connection instead,
+/// which is all sidecars without envelope support understand.
+pub struct SidecarClient {
+    socket_path: String,
+    envelope_protocol: bool,
+    next_id: AtomicU64,
+    generation: AtomicU64,
+    connection: Mutex<Option<SidecarConnection>>,
+    pending: PendingReplies,
+    status: Arc<RwLock<SidecarStatus>>,
+}
+
+impl SidecarClient {
+    fn new(socket_path: String, envelope_protocol: bool) -> Self {
+        Self {
+            socket_path: socket_path.clone(),
+            envelope_protocol,
+            next_id: AtomicU64::new(1),
+            generation: AtomicU64::new(0),
+            connection: Mutex::new(None),
+            pending: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            status: Arc::new(RwLock::new(SidecarStatus { socket_path, envelope_protocol, ..Default::default() })),
+        }
+    }
+
+    /// Creates the shared client and, for the envelope protocol, starts the health ping
+    pub fn init() {
+        let socket_path = env::var("ORCA_SIDECAR_SOCKET").unwrap_or_else(|_| DEFAULT_SOCKET_PATH.to_string());
+        let envelope_protocol = !env::var("ORCA_SIDECAR_PROTOCOL").is_ok_and(|protocol| protocol == "legacy");
+
+        if SIDECAR.set(Arc::new(SidecarClient::new(socket_path, envelope_protocol))) && envelope_protocol {
+            tokio::spawn(async {
+                SIDECAR.get().clone().run_health_check().await;
+            });
+        }
+    }
+
+    pub fn get() -> Arc<SidecarClient> {
+        if SIDECAR.try_get().is_none() {
+            SidecarClient::init();
+        }
+
+        SIDECAR.get().clone()
+    }
+
+    pub async fn get_status() -> SidecarStatus {
+        let client = SidecarClient::get();
+        let mut status = client.status.read().await.clone();
+        status.pending_requests = client.pending.lock().map(|pending| pending.len()).unwrap_or(0);
+
+        status
+    }
+
+    /// Sends a request for a single RPC url and waits for the matching reply
+    pub async fn send(&self, request: Request, rpc_url: &str, timeout_ms: u64) -> Result<Response, SidecarError> {
+        if !self.envelope_protocol {
+            return self.send_legacy(request, rpc_url, timeout_ms).await;
+        }
+
+        match self.request(SidecarRequest::Call(request), Duration::from_millis(timeout_ms)).await? {
+            SidecarReply::Response(response) => Ok(response),
+            SidecarReply::RpcError(message) => Err(SidecarError::Rpc { url: rpc_url.to_string(), message }),
+            SidecarReply::Error(message) => Err(SidecarError::Remote(message)),
+            reply => Err(SidecarError::Protocol(format!("Unexpected reply to call: {:?}", reply))),
+        }
+    }
+
+    /// Failing to reach the socket or read a reply is the sidecar's problem. Past that the legacy
+    /// protocol can't tell RPC failures from sidecar ones, so errors the sidecar replies with and
+    /// timeouts are treated as the RPC's and the call moves on to the next url, as it always has.
+    async fn send_legacy(&self, request: Request, rpc_url: &str, timeout_ms: u64) -> Result<Response, SidecarError> {
+        let err = match timeout(Duration::from_millis(timeout_ms), request.send_to(&self.socket_path)).await {
+            Ok(Ok(response)) => return Ok(response),
+            Ok(Err(e)) if e.is::<std::io::Error>() => SidecarError::Unavailable(format!("{}: {}", self.socket_path, e)),
+            Ok(Err(e)) if e.is::<serde_json::Error>() => SidecarError::Protocol(e.to_string()),
+            Ok(Err(e)) => SidecarError::Rpc { url: rpc_url.to_string(), message: e.to_string() },
+            Err(_) => SidecarError::Rpc { url: rpc_url.to_string(), message: format!("Timed out after {}ms", timeout_ms) },
+        };
+
+        self.status.write().await.last_error = Some(err.to_string());
+        Err(err)
+    }
+
+    pub async fn ping(&self) -> Result<i64, SidecarError> {
+        let start = Utc::now();
+
+        match self.request(SidecarRequest::Ping, PING_TIMEOUT).await? {
+            SidecarReply::Pong => Ok(Utc::now().signed_duration_since(start).num_milliseconds()),
+            reply => Err(SidecarError::Protocol(format!("Unexpected reply to ping: {:?}", reply))),
+        }
+    }
+
+    async fn request(&self, body: SidecarRequest, request_timeout: Duration) -> Result<SidecarReply, SidecarError> {
+        let (generation, writer) = self.ensure_connected().await?;
+
+        match self.request_on(&writer, body, request_timeout).await {
+            Err(err @ SidecarError::Unavailable(_)) => {
+                self.disconnect(generation, &err.to_string()).await;
+                Err(err)
+            }
+            result => result,
+        }
+    }
+
+    async fn request_on(&self, writer: &Arc<Mutex<OwnedWriteHalf>>, body: SidecarRequest, request_timeout: Duration) -> Result<SidecarReply, SidecarError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let envelope = SidecarEnvelope { id, version: PROTOCOL_VERSION, body };
+        let mut line = serde_json::to_vec(&envelope).map_err(|e| SidecarError::Protocol(e.to_string()))?;
+        line.push(b'\n');
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.pending.lock().map_err(|e| SidecarError::Protocol(e.to_string()))?.insert(id, reply_tx);
+
+        if let Err(e) = writer.lock().await.write_all(&line).await {
+            self.forget(id);
+            return Err(SidecarError::Unavailable(e.to_string()));
+        }
+
+        match timeout(request_timeout, reply_rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(SidecarError::Unavailable("Connection closed before reply".to_string())),
+            Err(_) => {
+                self.forget(id);
+                Err(SidecarError::Timeout { request_id: id, timeout_ms: request_timeout.as_millis() as u64 })
+            }
+        }
+    }
+
+    fn forget(&self, id: u64) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(&id);
+        }
+    }
+
+    async fn ensure_connected(&self) -> Result<(u64, Arc<Mutex<OwnedWriteHalf>>), SidecarError> {
+        let mut connection = self.connection.lock().await;
+
+        if let Some(connection) = connection.as_ref() {
+            return Ok((connection.generation, connection.writer.clone()));
+        }
+
+        let mut backoff = INITIAL_BACKOFF;
+        let mut last_error = SidecarError::Unavailable("No connection attempts made".to_string());
+
+        for attempt in 1..=CONNECT_ATTEMPTS {
+            match self.connect().await {
+                Ok(new_connection) => {
+                    let handle = (new_connection.generation, new_connection.writer.clone());
+                    *connection = Some(new_connection);
+                    return Ok(handle);
+                }
+                // A schema change will not fix itself, no point in retrying
+                Err(err @ SidecarError::VersionMismatch { .. }) => {
+                    self.record_failure(&err).await;
+                    return Err(err);
+                }
+                Err(err) => {
+                    yellow!("Orca sidecar connect attempt {}/{} failed: {}", attempt, CONNECT_ATTEMPTS, err);
+                    self.record_failure(&err).await;
+                    last_error = err;
+                }
+            }
+
+            if attempt < CONNECT_ATTEMPTS {
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+
+        Err(last_error)
+    }
+
+    async fn connect(&self) -> Result<SidecarConnection, SidecarError> {
+        let stream = UnixStream::connect(&self.socket_path)
+            .await
+            .map_err(|e| SidecarError::Unavailable(format!("{}: {}", self.socket_path, e)))?;
+        let (reader, writer) = stream.into_split();
+        let writer = Arc::new(Mutex::new(writer));
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let pending = self.pending.clone();
+        let status = self.status.clone();
+        let client = SIDECAR.try_get().cloned();
+
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(reader).lines();
+
+            let reason = loop {
+                match lines.next_line().await {
+                    Ok(Some(line)) => match serde_json::from_str::<SidecarEnvelope<SidecarReply>>(&line) {
+                        Ok(envelope) => {
+                            let reply_tx = pending.lock().ok().and_then(|mut pending| pending.remove(&envelope.id));
+                            match reply_tx {
+                                Some(reply_tx) => { let _ = reply_tx.send(envelope.body); }
+                                None => yellow!("Orca sidecar reply for unknown request {}", envelope.id),
+                            }
+                        }
+                        Err(e) => red!("Unreadable Orca sidecar reply: {}", e),
+                    },
+                    Ok(None) => break "Sidecar closed the connection".to_string(),
+                    Err(e) => break e.to_string(),
+                }
+            };
+
+            red!("Orca sidecar connection {} lost: {}", generation, reason);
+            match client {
+                Some(client) => client.disconnect(generation, &reason).await,
+                None => status.write().await.connected = false,
+            }
+        });
+
+        let reply = self.request_on(&writer, SidecarRequest::Hello, HANDSHAKE_TIMEOUT).await?;
+        let version = match reply {
+            SidecarReply::Hello { version } => version,
+            reply => return Err(SidecarError::Protocol(format!("Unexpected reply to handshake: {:?}", reply))),
+        };
+
+        if version != PROTOCOL_VERSION {
+            let _ = writer.lock().await.shutdown().await;
+            return Err(SidecarError::VersionMismatch { expected: PROTOCOL_VERSION, found: version });
+        }
+
+        let mut status = self.status.write().await;
+        if status.connected_at.is_some() {
+            status.reconnects += 1;
+        }
+        status.connected = true;
+        status.protocol_version = Some(version);
+        status.connected_at = Some(Utc::now());
+        status.consecutive_failures = 0;
+        status.last_error = None;
+        drop(status);
+
+        green!("Connected to Orca sidecar at {} (protocol v{})", self.socket_path, version);
+
+        Ok(SidecarConnection { generation, writer })
+    }
+
+    /// Drops the connection if it is still the one that failed. In-flight requests are
+    /// failed by dropping their reply senders.
+    async fn disconnect(&self, generation: u64, reason: &str) {
+        let mut connection = self.connection.lock().await;
+
+        if connection.as_ref().map(|connection| connection.generation) == Some(generation) {
+            *connection = None;
+            if let Ok(mut pending) = self.pending.lock() {
+                pending.clear();
+            }
+
+            let mut status = self.status.write().await;
+            status.connected = false;
+            status.last_error = Some(reason.to_string());
+        }
+    }
+
+    async fn record_failure(&self, err: &SidecarError) {
+        let mut status = self.status.write().await;
+        status.consecutive_failures += 1;
+        status.last_error = Some(err.to_string());
+    }
+
+    async fn run_health_check(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(PING_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match self.ping().await {
+                Ok(latency_ms) => {
+                    let mut status = self.status.write().await;
+                    status.last_ping_at = Some(Utc::now());
+                    status.last_ping_latency_ms = Some(latency_ms);
+                    status.consecutive_failures = 0;
+                }
+                Err(err) => {
+                    red!("Orca sidecar health check failed: {}", err);
+                    self.record_failure(&err).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use orca_pools_ipc_types::envelope::serve;
+
+    use super::*;
+
+    fn socket_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("sidecar-{}-{}.sock", name, std::process::id())).to_string_lossy().to_string()
+    }
+
+    fn request(rpc_url: &str) -> Request {
+        Request::GetPoolTokensAndTick { rpc_url: rpc_url.to_string(), whirlpool_address: "pool".to_string() }
+    }
+
+    /// Sidecar whose RPC fails for the `throttled` url, and which fails itself for any other
+    async fn start_sidecar(socket_path: &str) {
+        let served_path = socket_path.to_string();
+        tokio::spawn(async move {
+            serve(&served_path, |request| async move {
+                match request {
+                    Request::GetPoolTokensAndTick { rpc_url, .. } if rpc_url == "throttled" => SidecarReply::RpcError("429 Too Many Requests".to_string()),
+                    _ => SidecarReply::Error("whirlpool program not loaded".to_string()),
+                }
+            }).await
+        });
+
+        while UnixStream::connect(socket_path).await.is_err() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn envelope_calls_tell_rpc_failures_from_sidecar_ones() {
+        let socket_path = socket_path("envelope");
+        start_sidecar(&socket_path).await;
+        let client = SidecarClient::new(socket_path, true);
+
+        let err = client.send(request("throttled"), "throttled", 1000).await.unwrap_err();
+        assert!(matches!(err, SidecarError::Rpc { .. }), "{:?}", err);
+
+        let err = client.send(request("other"), "other", 1000).await.unwrap_err();
+        assert!(matches!(err, SidecarError::Remote(_)), "{:?}", err);
+
+        assert_eq!(client.ping().await.map(|_| ()).ok(), Some(()));
+        assert_eq!(client.status.read().await.protocol_version, Some(PROTOCOL_VERSION));
+    }
+
+    #[tokio::test]
+    async fn legacy_calls_only_blame_the_rpc_for_errors_the_sidecar_replies_with() {
+        let socket_path = socket_path("legacy");
+        let client = SidecarClient::new(socket_path.clone(), false);
+
+        // Nothing is listening yet
+        let err = client.send(request("throttled"), "throttled", 1000).await.unwrap_err();
+        assert!(matches!(err, SidecarError::Unavailable(_)), "{:?}", err);
+
+        start_sidecar(&socket_path).await;
+        let err = client.send(request("throttled"), "throttled", 1000).await.unwrap_err();
+        assert!(matches!(err, SidecarError::Rpc { .. }), "{:?}", err);
+    }
+}
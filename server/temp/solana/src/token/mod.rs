@@ -1,14 +1,24 @@
 // pub mod solana_token;
+pub mod token_2022;
 
 use std::{collections::HashMap, str::FromStr, sync::{Arc, Mutex}};
 
 use mpl_token_metadata::accounts::Metadata;
 use serde::{Deserialize, Serialize};
-use solana_sdk::{program_pack::Pack, pubkey::Pubkey};
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::{instruction::{AccountMeta, Instruction}, program_pack::Pack, pubkey::Pubkey};
+use solana_system_interface::{instruction as system_instruction, program as system_program};
 use spl_token::state::Mint;
 use state::InitCell;
+use token_2022::{MintExtensions, TransferFeeConfig};
 
-use crate::{rpc::{Rpc, RpcMode}, utils::trim_null_bytes};
+use crate::{rpc::{rate_limiter::RpcMethod, Rpc, RpcMode}, utils::trim_null_bytes};
+
+pub const TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLxBhN5Va7kMJ3DMr9x2vY";
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+pub const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+pub const METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
 
 pub static TOKEN_STORE: InitCell<Arc<Mutex<HashMap<String, Token>>>> = InitCell::new();
 
@@ -19,6 +29,10 @@ pub struct Token {
     pub address: String,
     pub decimals: u8,
     pub is_stablecoin: bool,
+    #[serde(default = "Token::default_token_program")]
+    pub token_program: String,
+    #[serde(default)]
+    pub transfer_fee_config: Option<TransferFeeConfig>,
 }
 
 impl Token {
@@ -32,6 +46,8 @@ impl Token {
             address,
             decimals,
             is_stablecoin,
+            token_program: TOKEN_PROGRAM_ID.to_string(),
+            transfer_fee_config: None,
         }
     }
 
@@ -51,9 +67,9 @@ impl Token {
 
         let mint_pubkey = Pubkey::from_str(address)?;
 
-        let account_data = Rpc::call(
+        let account_data = Rpc::call_weighted(
+            RpcMethod::GetAccount,
             move |client| {
-                let mint_pubkey = mint_pubkey.clone();
                 Box::pin(async move {
                     client.get_account(&mint_pubkey).await.map_err(|e| e.into())
                 })
@@ -61,42 +77,22 @@ impl Token {
             Some(5000),
             RpcMode::fast(),
         ).await?;
-        
-        let mint = Mint::unpack(&account_data.data)?;
-        
-        let metadata_program_id = Pubkey::from_str("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s")?;
-        
-        let seeds = &[
-            b"metadata",
-            metadata_program_id.as_ref(),
-            mint_pubkey.as_ref(),
-        ];
-
-        let (metadata_pda, _) = Pubkey::find_program_address(seeds, &metadata_program_id);
 
-        // Fetch the metadata account
-        let metadata_account = Rpc::call(
-            move |client| {
-                let metadata_pda = metadata_pda.clone();
-                Box::pin(async move {
-                    client.get_account(&metadata_pda).await.map_err(|e| e.into())
-                })
-            },
-            Some(5000),
-            RpcMode::fast(),
-        ).await?;
-    
-        let metadata_data = metadata_account.data;
-
-        // Deserialize the metadata account
-        let metadata = Metadata::safe_deserialize(&mut metadata_data.as_slice())?;
-    
-        let token = Token::new(
-            metadata.name,
-            metadata.symbol,
+        // Token-2022 mints carry extensions after the base mint, which is laid out the same way
+        let mint_data = account_data.data.get(..Mint::LEN).ok_or_else(|| anyhow::anyhow!("Account {} is not a mint", address))?;
+        let mint = Mint::unpack(mint_data)?;
+        let extensions = MintExtensions::from_mint_data(&account_data.data)?;
+
+        let (name, symbol) = Token::get_name_and_symbol(&mint_pubkey, &extensions).await?;
+
+        let mut token = Token::new(
+            name,
+            symbol,
             address.to_string(),
             mint.decimals,
         );
+        token.token_program = account_data.owner.to_string();
+        token.transfer_fee_config = extensions.transfer_fee_config;
 
         println!("Adding token to store: {}", token.symbol);
         {
@@ -108,6 +104,97 @@ impl Token {
 
     }
 
+    /// Name and symbol from the Token-2022 metadata extension, either in the mint itself or in
+    /// the account its metadata pointer names, falling back to Metaplex metadata
+    async fn get_name_and_symbol(mint_pubkey: &Pubkey, extensions: &MintExtensions) -> anyhow::Result<(String, String)> {
+        if let Some(token_metadata) = &extensions.token_metadata {
+            return Ok((token_metadata.name.clone(), token_metadata.symbol.clone()));
+        }
+
+        let metadata_program_id = Pubkey::from_str(METADATA_PROGRAM_ID)?;
+
+        let metadata_address = match extensions.metadata_pointer {
+            Some(metadata_address) if metadata_address != *mint_pubkey => metadata_address,
+            _ => {
+                let seeds = &[
+                    b"metadata",
+                    metadata_program_id.as_ref(),
+                    mint_pubkey.as_ref(),
+                ];
+                let (metadata_pda, _) = Pubkey::find_program_address(seeds, &metadata_program_id);
+                metadata_pda
+            }
+        };
+
+        // Fetch the metadata account
+        let metadata_account = Rpc::call_weighted(
+            RpcMethod::GetAccount,
+            move |client| {
+                Box::pin(async move {
+                    client.get_account(&metadata_address).await.map_err(|e| e.into())
+                })
+            },
+            Some(5000),
+            RpcMode::fast(),
+        ).await?;
+
+        if metadata_account.owner == metadata_program_id {
+            let metadata = Metadata::safe_deserialize(metadata_account.data.as_slice())?;
+            return Ok((metadata.name, metadata.symbol));
+        }
+
+        // A pointer to another Token-2022 mint holding the metadata extension
+        let pointed_extensions = MintExtensions::from_mint_data(&metadata_account.data)?;
+        let token_metadata = pointed_extensions
+            .token_metadata
+            .ok_or_else(|| anyhow::anyhow!("No token metadata found for {}", mint_pubkey))?;
+
+        Ok((token_metadata.name, token_metadata.symbol))
+    }
+
+    fn default_token_program() -> String {
+        TOKEN_PROGRAM_ID.to_string()
+    }
+
+    pub fn is_token_2022(&self) -> bool {
+        self.token_program == TOKEN_2022_PROGRAM_ID
+    }
+
+    /// Fee withheld by a Token-2022 transfer fee when `amount` is transferred in `epoch`
+    pub fn get_transfer_fee(&self, amount: u64, epoch: u64) -> u64 {
+        self.transfer_fee_config
+            .as_ref()
+            .map(|config| config.get_epoch_fee(epoch).calculate_fee(amount))
+            .unwrap_or(0)
+    }
+
+    pub fn amount_after_transfer_fee(&self, amount: u64, epoch: u64) -> u64 {
+        amount.saturating_sub(self.get_transfer_fee(amount, epoch))
+    }
+
+    /// Amount to send so that `amount` arrives after the transfer fee
+    pub fn amount_before_transfer_fee(&self, amount: u64, epoch: u64) -> u64 {
+        self.transfer_fee_config
+            .as_ref()
+            .map(|config| config.get_epoch_fee(epoch).calculate_pre_fee_amount(amount))
+            .unwrap_or(amount)
+    }
+
+    /// Raw amounts of a pair as they would arrive in a wallet. Only looks up the epoch when one
+    /// of the tokens has a transfer fee.
+    pub async fn amounts_after_transfer_fees(token_a: &Token, amount_a: f64, token_b: &Token, amount_b: f64) -> anyhow::Result<(f64, f64)> {
+        if token_a.transfer_fee_config.is_none() && token_b.transfer_fee_config.is_none() {
+            return Ok((amount_a, amount_b));
+        }
+
+        let epoch = Rpc::get_epoch(RpcMode::fast()).await?;
+
+        Ok((
+            token_a.amount_after_transfer_fee(amount_a as u64, epoch) as f64,
+            token_b.amount_after_transfer_fee(amount_b as u64, epoch) as f64,
+        ))
+    }
+
     pub fn solana() -> Self {
         Token {
             name: "Solana".to_string(),
@@ -115,6 +202,105 @@ impl Token {
             address: "So11111111111111111111111111111111111111112".to_string(),
             decimals: 9,
             is_stablecoin: false,
+            token_program: TOKEN_PROGRAM_ID.to_string(),
+            transfer_fee_config: None,
         }
     }
-}
\ No newline at end of file
+
+    pub fn get_associated_token_address(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> anyhow::Result<Pubkey> {
+        let associated_token_program = Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID)?;
+        let (address, _) = Pubkey::find_program_address(
+            &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
+            &associated_token_program,
+        );
+
+        Ok(address)
+    }
+
+    /// CreateIdempotent instruction of the associated token account program
+    pub fn create_associated_token_account_idempotent(
+        payer: &Pubkey,
+        owner: &Pubkey,
+        mint: &Pubkey,
+        token_program: &Pubkey,
+    ) -> anyhow::Result<Instruction> {
+        let associated_token_program = Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID)?;
+        let associated_token_address = Token::get_associated_token_address(owner, mint, token_program)?;
+
+        Ok(Instruction {
+            program_id: associated_token_program,
+            accounts: vec![
+                AccountMeta::new(*payer, true),
+                AccountMeta::new(associated_token_address, false),
+                AccountMeta::new_readonly(*owner, false),
+                AccountMeta::new_readonly(*mint, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(*token_program, false),
+            ],
+            data: vec![1],
+        })
+    }
+
+    /// Token program owning each mint, so user token accounts are derived under the right program
+    pub async fn get_token_programs(rpc_mode: RpcMode, mints: &[Pubkey]) -> anyhow::Result<Vec<Pubkey>> {
+        let accounts = Rpc::get_multiple_accounts(rpc_mode, mints, Some(10000)).await?;
+
+        mints
+            .iter()
+            .zip(accounts)
+            .map(|(mint, account)| {
+                account
+                    .map(|account| account.owner)
+                    .ok_or_else(|| anyhow::anyhow!("Mint account not found: {}", mint))
+            })
+            .collect()
+    }
+
+    /// Mints of every NFT-like token account (amount 1, no decimals) the owner holds, across both
+    /// token programs. Position NFTs from the concentrated liquidity venues are found this way.
+    pub async fn get_nft_mints_for_owner(rpc_mode: RpcMode, owner: &Pubkey) -> anyhow::Result<Vec<Pubkey>> {
+        let owner = *owner;
+        let mut nft_mints: Vec<Pubkey> = vec![];
+
+        for token_program in [spl_token::id(), Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?] {
+            let token_accounts = Rpc::call_weighted(
+                RpcMethod::GetTokenAccounts,
+                move |client| {
+                    Box::pin(async move {
+                        client
+                            .get_token_accounts_by_owner(&owner, TokenAccountsFilter::ProgramId(token_program))
+                            .await
+                            .map_err(|e| e.into())
+                    })
+                },
+                Some(20000),
+                rpc_mode.clone(),
+            ).await?;
+
+            for token_account in token_accounts {
+                let data = serde_json::to_value(&token_account.account.data)?;
+                let info = &data["parsed"]["info"];
+                let is_nft = info["tokenAmount"]["amount"] == "1" && info["tokenAmount"]["decimals"] == 0;
+
+                if let (true, Some(mint)) = (is_nft, info["mint"].as_str()) {
+                    nft_mints.push(Pubkey::from_str(mint)?);
+                }
+            }
+        }
+
+        Ok(nft_mints)
+    }
+
+    /// Funds a wrapped SOL token account from the owner's lamports
+    pub fn wrap_sol_instructions(owner: &Pubkey, token_account: &Pubkey, lamports: u64) -> anyhow::Result<Vec<Instruction>> {
+        Ok(vec![
+            system_instruction::transfer(owner, token_account, lamports),
+            spl_token::instruction::sync_native(&spl_token::id(), token_account)?,
+        ])
+    }
+
+    /// Closes a wrapped SOL token account, returning everything in it to the owner as SOL
+    pub fn unwrap_sol_instruction(owner: &Pubkey, token_account: &Pubkey) -> anyhow::Result<Instruction> {
+        Ok(spl_token::instruction::close_account(&spl_token::id(), token_account, owner, owner, &[])?)
+    }
+}
@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use solana_sdk::{program_pack::Pack, pubkey::Pubkey};
+use spl_token::state::{Account, Mint};
+
+use crate::utils::*;
+
+pub const TRANSFER_FEE_CONFIG_EXTENSION: u16 = 1;
+pub const METADATA_POINTER_EXTENSION: u16 = 18;
+pub const TOKEN_METADATA_EXTENSION: u16 = 19;
+
+/// Extensions start after the base mint, padded out to the size of a token account, and the
+/// account type byte
+const MINT_ACCOUNT_TYPE: u8 = 1;
+const EXTENSIONS_OFFSET: usize = Account::LEN + 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransferFee {
+    pub epoch: u64,
+    pub maximum_fee: u64,
+    pub transfer_fee_basis_points: u16,
+}
+
+impl TransferFee {
+    /// Fee withheld when `pre_fee_amount` is transferred, rounded up like the program does
+    pub fn calculate_fee(&self, pre_fee_amount: u64) -> u64 {
+        if self.transfer_fee_basis_points == 0 || pre_fee_amount == 0 {
+            return 0;
+        }
+
+        let fee = (pre_fee_amount as u128 * self.transfer_fee_basis_points as u128).div_ceil(10_000);
+        (fee as u64).min(self.maximum_fee)
+    }
+
+    /// Amount that has to be sent for `post_fee_amount` to arrive
+    pub fn calculate_pre_fee_amount(&self, post_fee_amount: u64) -> u64 {
+        match self.transfer_fee_basis_points {
+            0 => post_fee_amount,
+            10_000 => post_fee_amount.saturating_add(self.maximum_fee),
+            basis_points => {
+                let pre_fee_amount = (post_fee_amount as u128 * 10_000).div_ceil(10_000 - basis_points as u128) as u64;
+
+                if pre_fee_amount.saturating_sub(post_fee_amount) >= self.maximum_fee {
+                    post_fee_amount.saturating_add(self.maximum_fee)
+                } else {
+                    pre_fee_amount
+                }
+            }
+        }
+    }
+}
+
+/// `TransferFeeConfig` extension. The newer fee takes over once its epoch is reached.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransferFeeConfig {
+    pub older_transfer_fee: TransferFee,
+    pub newer_transfer_fee: TransferFee,
+}
+
+impl TransferFeeConfig {
+    pub fn get_epoch_fee(&self, epoch: u64) -> &TransferFee {
+        if epoch >= self.newer_transfer_fee.epoch {
+            &self.newer_transfer_fee
+        } else {
+            &self.older_transfer_fee
+        }
+    }
+
+    fn from_extension_data(data: &[u8]) -> anyhow::Result<Self> {
+        let read_fee = |offset: usize| -> anyhow::Result<TransferFee> {
+            Ok(TransferFee {
+                epoch: read_u64(data, offset)?,
+                maximum_fee: read_u64(data, offset + 8)?,
+                transfer_fee_basis_points: read_u16(data, offset + 16)?,
+            })
+        };
+
+        Ok(Self {
+            older_transfer_fee: read_fee(72)?,
+            newer_transfer_fee: read_fee(90)?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenMetadataFields {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+impl TokenMetadataFields {
+    /// Borsh encoded `TokenMetadata`: update authority, mint, then name, symbol and uri
+    pub fn from_extension_data(data: &[u8]) -> anyhow::Result<Self> {
+        let mut offset = 64;
+        let mut read_string = || -> anyhow::Result<String> {
+            let length = u32::from_le_bytes(read_bytes::<4>(data, offset)?) as usize;
+            let bytes = data
+                .get(offset + 4..offset + 4 + length)
+                .ok_or_else(|| anyhow::anyhow!("Token metadata string out of bounds"))?;
+            offset += 4 + length;
+            Ok(String::from_utf8_lossy(bytes).to_string())
+        };
+
+        Ok(Self {
+            name: read_string()?,
+            symbol: read_string()?,
+            uri: read_string()?,
+        })
+    }
+}
+
+/// The Token-2022 mint extensions the pool manager cares about
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MintExtensions {
+    pub extension_types: Vec<u16>,
+    pub transfer_fee_config: Option<TransferFeeConfig>,
+    pub metadata_pointer: Option<Pubkey>,
+    pub token_metadata: Option<TokenMetadataFields>,
+}
+
+impl MintExtensions {
+    /// Walks the TLV entries of a Token-2022 mint. Classic mints have none.
+    pub fn from_mint_data(data: &[u8]) -> anyhow::Result<Self> {
+        let mut extensions = Self::default();
+
+        if data.len() <= Mint::LEN {
+            return Ok(extensions);
+        }
+
+        if read_u8(data, Account::LEN)? != MINT_ACCOUNT_TYPE {
+            return Err(anyhow::anyhow!("Account is not a Token-2022 mint"));
+        }
+
+        let mut offset = EXTENSIONS_OFFSET;
+        while offset + 4 <= data.len() {
+            let extension_type = read_u16(data, offset)?;
+            let length = read_u16(data, offset + 2)? as usize;
+            let value = data
+                .get(offset + 4..offset + 4 + length)
+                .ok_or_else(|| anyhow::anyhow!("Mint extension {} out of bounds", extension_type))?;
+
+            // Zeroed space past the last entry
+            if extension_type == 0 && length == 0 {
+                break;
+            }
+
+            match extension_type {
+                TRANSFER_FEE_CONFIG_EXTENSION => {
+                    extensions.transfer_fee_config = Some(TransferFeeConfig::from_extension_data(value)?);
+                }
+                METADATA_POINTER_EXTENSION => {
+                    let metadata_address = read_pubkey(value, 32)?;
+                    if metadata_address != Pubkey::default() {
+                        extensions.metadata_pointer = Some(metadata_address);
+                    }
+                }
+                TOKEN_METADATA_EXTENSION => {
+                    extensions.token_metadata = Some(TokenMetadataFields::from_extension_data(value)?);
+                }
+                _ => (),
+            }
+
+            extensions.extension_types.push(extension_type);
+            offset += 4 + length;
+        }
+
+        Ok(extensions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(extension_type: u16, value: &[u8]) -> Vec<u8> {
+        let mut data = extension_type.to_le_bytes().to_vec();
+        data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        data.extend_from_slice(value);
+        data
+    }
+
+    fn mint_with(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0u8; Account::LEN];
+        data.push(MINT_ACCOUNT_TYPE);
+        for entry in entries {
+            data.extend_from_slice(entry);
+        }
+        data
+    }
+
+    fn fee_bytes(epoch: u64, maximum_fee: u64, transfer_fee_basis_points: u16) -> Vec<u8> {
+        [epoch.to_le_bytes().as_slice(), &maximum_fee.to_le_bytes(), &transfer_fee_basis_points.to_le_bytes()].concat()
+    }
+
+    fn borsh_string(value: &str) -> Vec<u8> {
+        [(value.len() as u32).to_le_bytes().as_slice(), value.as_bytes()].concat()
+    }
+
+    #[test]
+    fn classic_mint_has_no_extensions() {
+        let extensions = MintExtensions::from_mint_data(&[0u8; Mint::LEN]).unwrap();
+        assert!(extensions.extension_types.is_empty());
+        assert!(extensions.transfer_fee_config.is_none());
+    }
+
+    #[test]
+    fn parses_transfer_fee_metadata_pointer_and_metadata() {
+        let transfer_fee = [vec![0u8; 72], fee_bytes(10, 5_000, 50), fee_bytes(20, 9_000, 100)].concat();
+        let metadata_address = Pubkey::new_unique();
+        let metadata_pointer = [[0u8; 32].as_slice(), metadata_address.as_ref()].concat();
+        let metadata = [vec![0u8; 64], borsh_string("Token"), borsh_string("TKN"), borsh_string("https://token"), vec![0u8; 4]].concat();
+
+        let data = [
+            mint_with(&[entry(TRANSFER_FEE_CONFIG_EXTENSION, &transfer_fee), entry(METADATA_POINTER_EXTENSION, &metadata_pointer), entry(TOKEN_METADATA_EXTENSION, &metadata)]),
+            vec![0u8; 16],
+        ]
+        .concat();
+        let extensions = MintExtensions::from_mint_data(&data).unwrap();
+
+        assert_eq!(extensions.extension_types, vec![TRANSFER_FEE_CONFIG_EXTENSION, METADATA_POINTER_EXTENSION, TOKEN_METADATA_EXTENSION]);
+        let config = extensions.transfer_fee_config.unwrap();
+        assert_eq!(config.get_epoch_fee(15).transfer_fee_basis_points, 50);
+        assert_eq!(config.get_epoch_fee(20).maximum_fee, 9_000);
+        assert_eq!(extensions.metadata_pointer, Some(metadata_address));
+        let metadata = extensions.token_metadata.unwrap();
+        assert_eq!((metadata.name.as_str(), metadata.symbol.as_str(), metadata.uri.as_str()), ("Token", "TKN", "https://token"));
+    }
+
+    #[test]
+    fn rejects_truncated_entries_and_non_mints() {
+        let mut truncated = mint_with(&[entry(TRANSFER_FEE_CONFIG_EXTENSION, &[0u8; 108])]);
+        truncated.truncate(truncated.len() - 10);
+        assert!(MintExtensions::from_mint_data(&truncated).is_err());
+
+        let mut token_account = mint_with(&[]);
+        token_account[Account::LEN] = 2;
+        assert!(MintExtensions::from_mint_data(&token_account).is_err());
+    }
+
+    #[test]
+    fn transfer_fee_rounds_up_and_caps() {
+        let fee = TransferFee { epoch: 0, maximum_fee: 1_000, transfer_fee_basis_points: 100 };
+        assert_eq!(fee.calculate_fee(150), 2);
+        assert_eq!(fee.calculate_fee(1_000_000), 1_000);
+
+        let pre_fee_amount = fee.calculate_pre_fee_amount(9_900);
+        assert_eq!(pre_fee_amount, 10_000);
+        assert_eq!(pre_fee_amount - fee.calculate_fee(pre_fee_amount), 9_900);
+        assert_eq!(fee.calculate_pre_fee_amount(10_000_000), 10_001_000);
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener, task::JoinHandle};
+
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    /// Path with the query string
+    pub path: String,
+    pub body: String,
+}
+
+impl MockRequest {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or_default()
+    }
+}
+
+type Handler = dyn Fn(&MockRequest) -> (u16, String) + Send + Sync;
+
+/// Minimal HTTP server answering every request with the handler's status and JSON body
+pub struct MockHttp {
+    pub url: String,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+    task: JoinHandle<()>,
+}
+
+impl MockHttp {
+    pub async fn start(handler: impl Fn(&MockRequest) -> (u16, String) + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock server");
+        let url = format!("http://{}", listener.local_addr().expect("mock server address"));
+        let requests = Arc::new(Mutex::new(vec![]));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let task = tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let requests = requests.clone();
+                    let handler = handler.clone();
+
+                    tokio::spawn(async move {
+                        let Some(request) = MockHttp::read_request(&mut stream).await else { return };
+                        let (status, body) = handler(&request);
+                        requests.lock().unwrap().push(request);
+
+                        let response = format!(
+                            "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            status,
+                            body.len(),
+                            body,
+                        );
+                        let _ = stream.write_all(response.as_bytes()).await;
+                        let _ = stream.shutdown().await;
+                    });
+                }
+            }
+        });
+
+        Self { url, requests, task }
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<MockRequest> {
+        let mut data = vec![];
+        let mut buffer = [0u8; 4096];
+
+        let header_end = loop {
+            let read = stream.read(&mut buffer).await.ok()?;
+            if read == 0 {
+                return None;
+            }
+            data.extend_from_slice(&buffer[..read]);
+
+            if let Some(position) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+                break position + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+        let content_length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+
+        while data.len() < header_end + content_length {
+            let read = stream.read(&mut buffer).await.ok()?;
+            if read == 0 {
+                break;
+            }
+            data.extend_from_slice(&buffer[..read]);
+        }
+
+        let mut request_line = head.lines().next()?.split_whitespace();
+
+        Some(MockRequest {
+            method: request_line.next()?.to_string(),
+            path: request_line.next()?.to_string(),
+            body: String::from_utf8_lossy(&data[header_end..]).to_string(),
+        })
+    }
+}
+
+impl Drop for MockHttp {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -1,12 +1,16 @@
 use base64::Engine;
-use serde::{Deserialize, Deserializer, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use solana_sdk::{bs58, transaction::Transaction};
 
+#[cfg(test)]
+pub mod mock_http;
+
 pub fn trim_null_bytes(s: &str) -> String {
     s.trim_end_matches('\0').to_string()
 }
 
-pub fn serialize_transaction_to_base58(transaction: &Transaction) -> anyhow::Result<String> {
+/// Takes a legacy `Transaction` or a `VersionedTransaction`
+pub fn serialize_transaction_to_base58<T: Serialize>(transaction: &T) -> anyhow::Result<String> {
     let serialized_tx = bincode::serialize(transaction)
         .map_err(|e| anyhow::anyhow!("Failed to serialize transaction: {:?}", e))?;
     let base58_encoded_tx = bs58::encode(serialized_tx).into_string();
@@ -39,4 +43,306 @@ where
     let s: String = Deserialize::deserialize(deserializer)?;
     // Parse the string into a u128
     s.parse::<u128>().map_err(serde::de::Error::custom)
-}
\ No newline at end of file
+}
+
+/// Fraction (0.0 - 1.0) of a concentrated liquidity position's value held in token A
+/// for a given pool price and range. Prices are token A priced in token B.
+pub fn clmm_token_a_value_ratio(price: f64, range_lower: f64, range_upper: f64) -> f64 {
+    if price <= range_lower {
+        return 1.0;
+    }
+    if price >= range_upper {
+        return 0.0;
+    }
+
+    let sqrt_price = price.sqrt();
+    let amount_a = 1.0 / sqrt_price - 1.0 / range_upper.sqrt();
+    let amount_b = sqrt_price - range_lower.sqrt();
+    let value_a = amount_a * price;
+
+    value_a / (value_a + amount_b)
+}
+
+pub const Q64: f64 = 18_446_744_073_709_551_616.0;
+
+/// Price of token A in token B from a Q64.64 sqrt price, adjusted for mint decimals
+pub fn sqrt_price_x64_to_price(sqrt_price_x64: u128, decimals_a: u8, decimals_b: u8) -> f64 {
+    let sqrt_price = sqrt_price_x64 as f64 / Q64;
+    sqrt_price * sqrt_price * 10f64.powi(decimals_a as i32 - decimals_b as i32)
+}
+
+pub fn tick_index_to_price(tick_index: i32, decimals_a: u8, decimals_b: u8) -> f64 {
+    1.0001f64.powi(tick_index) * 10f64.powi(decimals_a as i32 - decimals_b as i32)
+}
+
+pub fn price_to_tick_index(price: f64, decimals_a: u8, decimals_b: u8) -> i32 {
+    let raw_price = price / 10f64.powi(decimals_a as i32 - decimals_b as i32);
+    (raw_price.ln() / 1.0001f64.ln()).floor() as i32
+}
+
+/// Rounds a tick index down to the nearest usable tick for the pool's spacing
+pub fn align_tick_index(tick_index: i32, tick_spacing: u16) -> i32 {
+    let tick_spacing = tick_spacing as i32;
+    tick_index.div_euclid(tick_spacing) * tick_spacing
+}
+
+/// Price of token X in token Y for a DLMM bin, adjusted for mint decimals
+pub fn bin_id_to_price(bin_id: i32, bin_step: u16, decimals_x: u8, decimals_y: u8) -> f64 {
+    (1.0 + bin_step as f64 / 10_000.0).powi(bin_id) * 10f64.powi(decimals_x as i32 - decimals_y as i32)
+}
+
+pub fn price_to_bin_id(price: f64, bin_step: u16, decimals_x: u8, decimals_y: u8) -> i32 {
+    let raw_price = price / 10f64.powi(decimals_x as i32 - decimals_y as i32);
+    (raw_price.ln() / (1.0 + bin_step as f64 / 10_000.0).ln()).round() as i32
+}
+
+/// Raw token amounts held by a concentrated liquidity position
+pub fn clmm_amounts_from_liquidity(liquidity: u128, sqrt_price_x64: u128, tick_lower: i32, tick_upper: i32) -> (f64, f64) {
+    let liquidity = liquidity as f64;
+    let sqrt_price = sqrt_price_x64 as f64 / Q64;
+    let sqrt_lower = 1.0001f64.powi(tick_lower).sqrt();
+    let sqrt_upper = 1.0001f64.powi(tick_upper).sqrt();
+
+    if sqrt_price <= sqrt_lower {
+        (liquidity * (sqrt_upper - sqrt_lower) / (sqrt_lower * sqrt_upper), 0.0)
+    } else if sqrt_price < sqrt_upper {
+        (
+            liquidity * (sqrt_upper - sqrt_price) / (sqrt_price * sqrt_upper),
+            liquidity * (sqrt_price - sqrt_lower),
+        )
+    } else {
+        (0.0, liquidity * (sqrt_upper - sqrt_lower))
+    }
+}
+
+/// Largest liquidity a concentrated liquidity range can take from the given raw amounts
+pub fn clmm_liquidity_from_amounts(amount_a: u64, amount_b: u64, sqrt_price_x64: u128, tick_lower: i32, tick_upper: i32) -> u128 {
+    let sqrt_price = sqrt_price_x64 as f64 / Q64;
+    let sqrt_lower = 1.0001f64.powi(tick_lower).sqrt();
+    let sqrt_upper = 1.0001f64.powi(tick_upper).sqrt();
+
+    let liquidity_a = |sqrt_from: f64| amount_a as f64 * sqrt_from * sqrt_upper / (sqrt_upper - sqrt_from);
+    let liquidity_b = |sqrt_to: f64| amount_b as f64 / (sqrt_to - sqrt_lower);
+
+    let liquidity = if sqrt_price <= sqrt_lower {
+        liquidity_a(sqrt_lower)
+    } else if sqrt_price < sqrt_upper {
+        liquidity_a(sqrt_price).min(liquidity_b(sqrt_price))
+    } else {
+        liquidity_b(sqrt_upper)
+    };
+
+    liquidity.max(0.0) as u128
+}
+
+/// Fee growth inside a tick range from the pool's global growth and the boundary ticks' outside growth
+pub fn clmm_fee_growth_inside(
+    tick_current: i32,
+    tick_lower: i32,
+    tick_upper: i32,
+    fee_growth_global_x64: u128,
+    fee_growth_outside_lower_x64: u128,
+    fee_growth_outside_upper_x64: u128,
+) -> u128 {
+    let fee_growth_below = if tick_current >= tick_lower {
+        fee_growth_outside_lower_x64
+    } else {
+        fee_growth_global_x64.wrapping_sub(fee_growth_outside_lower_x64)
+    };
+
+    let fee_growth_above = if tick_current < tick_upper {
+        fee_growth_outside_upper_x64
+    } else {
+        fee_growth_global_x64.wrapping_sub(fee_growth_outside_upper_x64)
+    };
+
+    fee_growth_global_x64
+        .wrapping_sub(fee_growth_below)
+        .wrapping_sub(fee_growth_above)
+}
+
+/// Fees earned since the last checkpoint, in raw token units
+pub fn clmm_fees_owed(liquidity: u128, fee_growth_inside_x64: u128, fee_growth_checkpoint_x64: u128, fee_owed: u64) -> u64 {
+    let fee_growth_delta = fee_growth_inside_x64.wrapping_sub(fee_growth_checkpoint_x64) as f64;
+    fee_owed + (fee_growth_delta * liquidity as f64 / Q64) as u64
+}
+
+/// Raw amount on the other side of a swap against the active liquidity, before fees. For exact in
+/// swaps this is the output for `amount`, for exact out swaps the input needed to receive it. Ticks
+/// are not crossed, so the quote assumes the active liquidity extends as far as the swap moves the
+/// price. None when there is no liquidity or an exact out amount the range can't provide.
+pub fn clmm_swap_amount(liquidity: u128, sqrt_price_x64: u128, amount: u64, amount_is_in: bool, a_to_b: bool) -> Option<f64> {
+    let liquidity = liquidity as f64;
+    let sqrt_price = sqrt_price_x64 as f64 / Q64;
+    let amount = amount as f64;
+
+    if liquidity <= 0.0 || sqrt_price <= 0.0 {
+        return None;
+    }
+
+    let other_amount = match (amount_is_in, a_to_b) {
+        (true, true) => {
+            let sqrt_price_after = liquidity * sqrt_price / (liquidity + amount * sqrt_price);
+            liquidity * (sqrt_price - sqrt_price_after)
+        }
+        (true, false) => {
+            let sqrt_price_after = sqrt_price + amount / liquidity;
+            liquidity * (1.0 / sqrt_price - 1.0 / sqrt_price_after)
+        }
+        (false, true) => {
+            let sqrt_price_after = sqrt_price - amount / liquidity;
+            if sqrt_price_after <= 0.0 {
+                return None;
+            }
+            liquidity * (1.0 / sqrt_price_after - 1.0 / sqrt_price)
+        }
+        (false, false) => {
+            let inverse_sqrt_price_after = 1.0 / sqrt_price - amount / liquidity;
+            if inverse_sqrt_price_after <= 0.0 {
+                return None;
+            }
+            liquidity * (1.0 / inverse_sqrt_price_after - sqrt_price)
+        }
+    };
+
+    Some(other_amount)
+}
+
+pub fn read_u8(data: &[u8], offset: usize) -> anyhow::Result<u8> {
+    data.get(offset).copied().ok_or_else(|| anyhow::anyhow!("Account data too short at offset {}", offset))
+}
+
+pub fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> anyhow::Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .ok_or_else(|| anyhow::anyhow!("Account data too short at offset {}", offset))?
+        .try_into()
+        .map_err(|e| anyhow::anyhow!("Failed to read {} bytes at offset {}: {:?}", N, offset, e))
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    Ok(u16::from_le_bytes(read_bytes::<2>(data, offset)?))
+}
+
+pub fn read_i32(data: &[u8], offset: usize) -> anyhow::Result<i32> {
+    Ok(i32::from_le_bytes(read_bytes::<4>(data, offset)?))
+}
+
+pub fn read_i64(data: &[u8], offset: usize) -> anyhow::Result<i64> {
+    Ok(i64::from_le_bytes(read_bytes::<8>(data, offset)?))
+}
+
+pub fn read_u64(data: &[u8], offset: usize) -> anyhow::Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes::<8>(data, offset)?))
+}
+
+pub fn read_u128(data: &[u8], offset: usize) -> anyhow::Result<u128> {
+    Ok(u128::from_le_bytes(read_bytes::<16>(data, offset)?))
+}
+
+pub fn read_pubkey(data: &[u8], offset: usize) -> anyhow::Result<solana_sdk::pubkey::Pubkey> {
+    Ok(solana_sdk::pubkey::Pubkey::new_from_array(read_bytes::<32>(data, offset)?))
+}
+
+/// First 8 bytes of sha256("global:<name>"), the Anchor instruction discriminator
+pub fn anchor_discriminator(instruction_name: &str) -> [u8; 8] {
+    use sha2::{Digest, Sha256};
+
+    let hash = Sha256::digest(format!("global:{}", instruction_name).as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+/// First 8 bytes of sha256("account:<Name>"), the Anchor account discriminator
+pub fn anchor_account_discriminator(account_name: &str) -> [u8; 8] {
+    use sha2::{Digest, Sha256};
+
+    let hash = Sha256::digest(format!("account:{}", account_name).as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqrt_price_x64(raw_price: f64) -> u128 {
+        (raw_price.sqrt() * Q64) as u128
+    }
+
+    #[test]
+    fn token_a_value_ratio_is_all_a_below_range_and_all_b_above() {
+        assert_eq!(clmm_token_a_value_ratio(90.0, 100.0, 200.0), 1.0);
+        assert_eq!(clmm_token_a_value_ratio(250.0, 100.0, 200.0), 0.0);
+
+        let ratio = clmm_token_a_value_ratio(150.0, 100.0, 200.0);
+        assert!(ratio > 0.0 && ratio < 1.0);
+    }
+
+    #[test]
+    fn tick_index_and_price_round_trip() {
+        let tick_index = price_to_tick_index(150.0, 9, 6);
+        let price = tick_index_to_price(tick_index, 9, 6);
+
+        assert!(price <= 150.0 && price > 150.0 / 1.0001);
+        assert_eq!(align_tick_index(-7, 4), -8);
+        assert_eq!(align_tick_index(7, 4), 4);
+    }
+
+    #[test]
+    fn liquidity_and_amounts_round_trip() {
+        let sqrt_price = sqrt_price_x64(1.0001f64.powi(100));
+        let liquidity = clmm_liquidity_from_amounts(1_000_000, 1_000_000, sqrt_price, -1000, 1000);
+        let (amount_a, amount_b) = clmm_amounts_from_liquidity(liquidity, sqrt_price, -1000, 1000);
+
+        // The tighter side is used in full, the other one at most
+        assert!(amount_a <= 1_000_000.0 && amount_b <= 1_000_000.0);
+        assert!((amount_a - 1_000_000.0).abs() < 10.0 || (amount_b - 1_000_000.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn fee_growth_inside_subtracts_growth_outside_the_range() {
+        assert_eq!(clmm_fee_growth_inside(0, -10, 10, 1000, 100, 200), 700);
+        // Below the range, the lower tick's outside growth counts from above it
+        assert_eq!(clmm_fee_growth_inside(-20, -10, 10, 1000, 300, 200), 100);
+        assert_eq!(clmm_fees_owed(1, Q64 as u128 * 5, 0, 2), 7);
+    }
+
+    #[test]
+    fn small_swaps_trade_near_spot_and_large_ones_move_the_price() {
+        let liquidity = 1_000_000_000_000u128;
+        let sqrt_price = sqrt_price_x64(0.15);
+
+        let small = clmm_swap_amount(liquidity, sqrt_price, 1_000, true, true).unwrap();
+        assert!((small / 1_000.0 - 0.15).abs() < 0.0001);
+
+        let large = clmm_swap_amount(liquidity, sqrt_price, 1_000_000_000_000, true, true).unwrap();
+        assert!(large / 1_000_000_000_000.0 < 0.15 * 0.9);
+
+        let b_to_a = clmm_swap_amount(liquidity, sqrt_price, 1_000, true, false).unwrap();
+        assert!((b_to_a / 1_000.0 - 1.0 / 0.15).abs() < 0.01);
+    }
+
+    #[test]
+    fn exact_out_needs_what_exact_in_returns() {
+        let liquidity = 5_000_000_000u128;
+        let sqrt_price = sqrt_price_x64(2.5);
+
+        for a_to_b in [true, false] {
+            let amount_out = clmm_swap_amount(liquidity, sqrt_price, 1_000_000_000, true, a_to_b).unwrap();
+            let amount_in = clmm_swap_amount(liquidity, sqrt_price, amount_out as u64, false, a_to_b).unwrap();
+
+            assert!((amount_in - 1_000_000_000.0).abs() / 1_000_000_000.0 < 0.0001);
+        }
+    }
+
+    #[test]
+    fn swaps_without_liquidity_do_not_quote() {
+        let sqrt_price = sqrt_price_x64(1.0);
+
+        assert!(clmm_swap_amount(0, sqrt_price, 1_000, true, true).is_none());
+        // More token B out than the active liquidity holds
+        assert!(clmm_swap_amount(1_000, sqrt_price, 2_000, false, true).is_none());
+    }
+}
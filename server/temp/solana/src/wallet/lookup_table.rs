@@ -0,0 +1,213 @@
+use std::{collections::{HashMap, HashSet}, str::FromStr, sync::{Arc, Mutex, MutexGuard}, time::Duration};
+
+use helius::types::PriorityLevel;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_address_lookup_table_interface::{instruction::{create_lookup_table, extend_lookup_table}, state::AddressLookupTable};
+use solana_sdk::{message::AddressLookupTableAccount, instruction::Instruction, pubkey::Pubkey, sysvar};
+use state::InitCell;
+
+use crate::{pool_manager::orca::Orca, rpc::{Rpc, RpcMode}, token::{Token, ASSOCIATED_TOKEN_PROGRAM_ID, MEMO_PROGRAM_ID, TOKEN_2022_PROGRAM_ID}};
+
+use super::{programmatic_transaction::{ProgrammaticTransaction, TransactionKind}, Wallet};
+
+/// A lookup table holds at most 256 addresses
+const MAX_ADDRESSES: usize = 256;
+/// Addresses per extend instruction, which keeps each extend transaction under the size limit
+const EXTEND_CHUNK_SIZE: usize = 20;
+/// Transactions an account has to appear in before it earns a slot in the table
+const MIN_USES: u32 = 3;
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60);
+/// Authority pubkey offset, after the discriminator, deactivation slot, last extended slot, its
+/// start index and the authority's option tag
+const AUTHORITY_OFFSET: usize = 22;
+
+pub static LOOKUP_TABLE: InitCell<Arc<Mutex<LookupTable>>> = InitCell::new();
+
+/// The programmatic wallet's address lookup table, used to fit transactions that are too large
+/// as legacy transactions. Accounts the bot keeps touching are added to it over time.
+#[derive(Debug, Default)]
+pub struct LookupTable {
+    account: Option<AddressLookupTableAccount>,
+    usage: HashMap<Pubkey, u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LookupTableStatus {
+    pub address: Option<String>,
+    pub address_count: usize,
+    pub tracked_accounts: usize,
+}
+
+impl LookupTable {
+    fn lock() -> MutexGuard<'static, LookupTable> {
+        if LOOKUP_TABLE.try_get().is_none() {
+            LOOKUP_TABLE.set(Arc::new(Mutex::new(LookupTable::default())));
+        }
+
+        LOOKUP_TABLE.get().lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Finds or creates the table, then keeps extending it with frequently used accounts
+    pub fn start_maintenance() {
+        if Wallet::get_programmatic_pubkey().is_err() {
+            return;
+        }
+
+        tokio::spawn(async {
+            let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = LookupTable::maintain().await {
+                    red!("Failed to maintain lookup table: {:?}", e);
+                }
+            }
+        });
+    }
+
+    /// Loaded tables to compile v0 messages against. Empty until the table has been loaded.
+    pub fn get_accounts() -> Vec<AddressLookupTableAccount> {
+        LookupTable::lock().account.clone().into_iter().collect()
+    }
+
+    /// Counts the accounts a transaction that is about to be sent touches, other than its signers
+    /// and the programs it calls, towards a slot in the table
+    pub fn record_usage(instructions: &[Instruction]) {
+        let accounts: HashSet<Pubkey> = instructions
+            .iter()
+            .flat_map(|instruction| instruction.accounts.iter().filter(|meta| !meta.is_signer).map(|meta| meta.pubkey))
+            .collect();
+
+        let mut lookup_table = LookupTable::lock();
+        for account in accounts {
+            *lookup_table.usage.entry(account).or_insert(0) += 1;
+        }
+    }
+
+    pub fn get_status() -> LookupTableStatus {
+        let lookup_table = LookupTable::lock();
+
+        LookupTableStatus {
+            address: lookup_table.account.as_ref().map(|account| account.key.to_string()),
+            address_count: lookup_table.account.as_ref().map_or(0, |account| account.addresses.len()),
+            tracked_accounts: lookup_table.usage.len(),
+        }
+    }
+
+    async fn maintain() -> anyhow::Result<()> {
+        let authority = Wallet::get_programmatic_pubkey()?;
+
+        let current = LookupTable::lock().account.as_ref().map(|account| account.key);
+        let address = match current {
+            Some(address) => address,
+            None => match LookupTable::find(&authority).await? {
+                Some(address) => address,
+                None => LookupTable::create(&authority).await?,
+            },
+        };
+
+        let mut account = LookupTable::load(&address).await?;
+        let candidates = LookupTable::get_candidates(&account, &authority)?;
+
+        if !candidates.is_empty() {
+            for chunk in candidates.chunks(EXTEND_CHUNK_SIZE) {
+                let instruction = extend_lookup_table(address, authority, Some(authority), chunk.to_vec());
+                ProgrammaticTransaction::perform(vec![instruction], vec![], Some(PriorityLevel::High), TransactionKind::Maintenance).await?;
+            }
+            green!("Extended lookup table {} with {} addresses", address, candidates.len());
+
+            account = LookupTable::load(&address).await?;
+        }
+
+        let mut lookup_table = LookupTable::lock();
+        // Accounts already in the table no longer need counting
+        lookup_table.usage.retain(|pubkey, _| !account.addresses.contains(pubkey));
+        lookup_table.account = Some(account);
+
+        Ok(())
+    }
+
+    /// `LOOKUP_TABLE_ADDRESS` if set, otherwise the fullest active table the wallet already owns
+    async fn find(authority: &Pubkey) -> anyhow::Result<Option<Pubkey>> {
+        if let Ok(address) = std::env::var("LOOKUP_TABLE_ADDRESS") {
+            return Ok(Some(Pubkey::from_str(&address)?));
+        }
+
+        let filters = vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(AUTHORITY_OFFSET, &authority.to_bytes()))];
+        let accounts = Rpc::get_program_accounts(RpcMode::conservative(), &solana_address_lookup_table_interface::program::id(), filters, Some(30000)).await?;
+
+        let address = accounts
+            .into_iter()
+            .filter_map(|(address, account)| {
+                let table = AddressLookupTable::deserialize(&account.data).ok()?;
+                let active = table.meta.deactivation_slot == u64::MAX;
+                (active && table.addresses.len() < MAX_ADDRESSES).then(|| (address, table.addresses.len()))
+            })
+            .max_by_key(|(_, address_count)| *address_count)
+            .map(|(address, _)| address);
+
+        Ok(address)
+    }
+
+    async fn create(authority: &Pubkey) -> anyhow::Result<Pubkey> {
+        let recent_slot = Rpc::get_slot(RpcMode::fast(), Some(5000)).await?;
+        let (instruction, address) = create_lookup_table(*authority, *authority, recent_slot);
+
+        ProgrammaticTransaction::perform(vec![instruction], vec![], Some(PriorityLevel::High), TransactionKind::Maintenance).await?;
+        green!("Created lookup table {}", address);
+
+        Ok(address)
+    }
+
+    async fn load(address: &Pubkey) -> anyhow::Result<AddressLookupTableAccount> {
+        let account = Rpc::get_account(RpcMode::fast(), address, Some(10000)).await?;
+        let table = AddressLookupTable::deserialize(&account.data)
+            .map_err(|e| anyhow::anyhow!("Failed to read lookup table {}: {:?}", address, e))?;
+
+        Ok(AddressLookupTableAccount {
+            key: *address,
+            addresses: table.addresses.to_vec(),
+        })
+    }
+
+    /// Programs the bot calls. A v0 message can't load the program it calls from a table, so
+    /// they would only take up space.
+    fn get_program_ids() -> anyhow::Result<HashSet<Pubkey>> {
+        Ok(HashSet::from([
+            solana_system_interface::program::id(),
+            spl_token::id(),
+            Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?,
+            Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM_ID)?,
+            Pubkey::from_str(MEMO_PROGRAM_ID)?,
+            Orca::program_id()?,
+        ]))
+    }
+
+    /// Accounts every rebalance touches, then the most used accounts, that are not in the table
+    /// yet and fit in its remaining space
+    fn get_candidates(account: &AddressLookupTableAccount, authority: &Pubkey) -> anyhow::Result<Vec<Pubkey>> {
+        let program_ids = LookupTable::get_program_ids()?;
+        let mut candidates = vec![
+            sysvar::rent::id(),
+            Pubkey::from_str(&Token::solana().address)?,
+        ];
+
+        let mut frequent: Vec<(Pubkey, u32)> = LookupTable::lock()
+            .usage
+            .iter()
+            .filter(|(_, uses)| **uses >= MIN_USES)
+            .map(|(pubkey, uses)| (*pubkey, *uses))
+            .collect();
+        frequent.sort_by_key(|(_, uses)| std::cmp::Reverse(*uses));
+        candidates.extend(frequent.into_iter().map(|(pubkey, _)| pubkey));
+
+        let mut seen = HashSet::new();
+        candidates.retain(|pubkey| {
+            pubkey != authority && !program_ids.contains(pubkey) && !account.addresses.contains(pubkey) && seen.insert(*pubkey)
+        });
+        candidates.truncate(MAX_ADDRESSES.saturating_sub(account.addresses.len()));
+
+        Ok(candidates)
+    }
+}
@@ -1,12 +1,18 @@
+pub mod lookup_table;
+pub mod nonce_account;
+pub mod prepared_transaction;
 pub mod programmatic_transaction;
+pub mod transaction_ledger;
+pub mod transaction_policy;
+pub mod unsigned_transaction;
 
 use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 use solana_client::rpc_request::TokenAccountsFilter;
-use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey, signer::{keypair::Keypair, Signer}};
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, signer::{keypair::Keypair, Signer}};
 
-use crate::{rpc::{Rpc, RpcMode}, token::Token};
+use crate::{rpc::{rate_limiter::RpcMethod, Rpc, RpcMode}, token::Token};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Wallet {
@@ -40,60 +46,53 @@ impl Wallet {
         let is_sol = token_mint == Token::solana().address;
         if is_sol {
             let lamports = Self::get_account_lamports(wallet_key, rpc_mode).await?;
-            let sol = lamports_to_sol(lamports);
+            let sol = lamports as f64 / LAMPORTS_PER_SOL as f64;
             return Ok((lamports, sol));
-        } else {
-            let token_accounts = Rpc::call(
-                {
-                    let wallet_key = wallet_key.to_string();
-                    let token_mint = token_mint.to_string();
-                    move |client| {
-                        let wallet_key = wallet_key.clone();
-                        let token_mint = token_mint.clone();
-                        Box::pin(async move {
-                            client.get_token_accounts_by_owner(
-                                &Pubkey::from_str(&wallet_key).unwrap(),
-                                TokenAccountsFilter::Mint(Pubkey::from_str(&token_mint).unwrap()),
-                            ).await.map_err(|e| e.into())
-                        })
-                    }
-                },
-                None,
-                rpc_mode.clone(),
-            ).await?;
-
-            let token_account = token_accounts.first().ok_or(anyhow::anyhow!("No token accounts found"))?;
-            let pubkey_string = token_account.pubkey.clone();
-            let pubkey = Pubkey::from_str(&pubkey_string).map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            // Fetch account balance
-            let balance_response = Rpc::call(
-                move |client| {
-                    
-                    Box::pin(async move {
-                        client
-                            .get_token_account_balance(&pubkey)
-                            .await
-                            .map_err(|e| e.into())
-                    })
-                },
-                Some(5000),
-                rpc_mode,
-            )
-            .await?;
-    
-            let ui_amount = balance_response.ui_amount.ok_or(anyhow::anyhow!("UI amount missing"))?;
-            let amount = balance_response.amount.parse::<u64>()?;
-    
-            return Ok((amount, ui_amount));
-            
         }
 
+        let wallet_pubkey = Pubkey::from_str(wallet_key)?;
+        let mint_pubkey = Pubkey::from_str(token_mint)?;
+        let token = Token::from_mint_address(token_mint).await?;
+
+        // The mint filter resolves the mint's own token program, so Token-2022 accounts are
+        // returned as well. Balances are summed in case the wallet holds more than one account.
+        let token_accounts = Rpc::call_weighted(
+            RpcMethod::GetTokenAccounts,
+            move |client| {
+                Box::pin(async move {
+                    client
+                        .get_token_accounts_by_owner(&wallet_pubkey, TokenAccountsFilter::Mint(mint_pubkey))
+                        .await
+                        .map_err(|e| e.into())
+                })
+            },
+            None,
+            rpc_mode,
+        ).await?;
+
+        if token_accounts.is_empty() {
+            return Err(anyhow::anyhow!("No token accounts found"));
+        }
+
+        let mut amount: u64 = 0;
+        for token_account in token_accounts {
+            let data = serde_json::to_value(&token_account.account.data)?;
+            let account_amount = data["parsed"]["info"]["tokenAmount"]["amount"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Token amount missing for {}", token_account.pubkey))?
+                .parse::<u64>()?;
+            amount = amount.saturating_add(account_amount);
+        }
+
+        let ui_amount = amount as f64 / 10u64.pow(token.decimals as u32) as f64;
 
+        Ok((amount, ui_amount))
     }
 
     pub async fn get_account_lamports(account_str: &str, rpc_mode: RpcMode) -> anyhow::Result<u64> {
         let account_pubkey = Pubkey::from_str(account_str).map_err(|e| anyhow::anyhow!(e.to_string()))?;
-        let account_data = Rpc::call(
+        let account_data = Rpc::call_weighted(
+            RpcMethod::GetAccount,
             move |client| {
                 Box::pin(async move {
                     client.get_account(&account_pubkey).await.map_err(|e| e.into())
@@ -111,7 +110,7 @@ impl Wallet {
 
     pub async fn get_sol_balance(wallet_key: &str, rpc_mode: RpcMode) -> anyhow::Result<f64> {
         let lamports = Self::get_account_lamports(wallet_key, rpc_mode).await?;
-        let sol = lamports_to_sol(lamports);
+        let sol = lamports as f64 / LAMPORTS_PER_SOL as f64;
 
         Ok(sol)
     }
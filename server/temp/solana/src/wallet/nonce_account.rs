@@ -0,0 +1,200 @@
+use std::{collections::HashSet, sync::{Arc, Mutex, MutexGuard}, time::Duration};
+
+use helius::types::PriorityLevel;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use solana_nonce::{state::State, versions::Versions};
+use solana_sdk::{hash::Hash, pubkey::Pubkey};
+use solana_system_interface::{instruction as system_instruction, program as system_program};
+use state::InitCell;
+
+use crate::rpc::{Rpc, RpcMode};
+
+use super::{programmatic_transaction::{ProgrammaticTransaction, TransactionKind}, Wallet};
+
+/// Nonce accounts are derived from the wallet with these seeds, so they can be found again
+/// without storing keypairs
+const NONCE_SEED_PREFIX: &str = "rebalance-nonce-";
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub static NONCE_ACCOUNTS: InitCell<Arc<Mutex<NonceAccounts>>> = InitCell::new();
+
+/// Durable nonce accounts owned by the programmatic wallet. A transaction built against a nonce
+/// instead of a recent blockhash stays valid until the nonce is advanced, so it can be prepared
+/// well before it is sent. Each account is leased to one prepared transaction at a time.
+#[derive(Debug, Default)]
+pub struct NonceAccounts {
+    addresses: Vec<Pubkey>,
+    leased: HashSet<Pubkey>,
+    /// Leased accounts whose broadcast transaction was given up on but may still land. They stay
+    /// leased until maintenance advances them.
+    stale: HashSet<Pubkey>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NonceAccountStatus {
+    pub address: String,
+    pub leased: bool,
+    #[serde(default)]
+    pub stale: bool,
+}
+
+impl NonceAccounts {
+    fn lock() -> MutexGuard<'static, NonceAccounts> {
+        if NONCE_ACCOUNTS.try_get().is_none() {
+            NONCE_ACCOUNTS.set(Arc::new(Mutex::new(NonceAccounts::default())));
+        }
+
+        NONCE_ACCOUNTS.get().lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Accounts to keep, from `DURABLE_NONCE_ACCOUNTS`. Each one holds rent, so none are
+    /// created unless it is set.
+    pub fn count() -> usize {
+        std::env::var("DURABLE_NONCE_ACCOUNTS")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(0)
+    }
+
+    pub fn enabled() -> bool {
+        NonceAccounts::count() > 0
+    }
+
+    fn address(authority: &Pubkey, index: usize) -> anyhow::Result<Pubkey> {
+        Ok(Pubkey::create_with_seed(authority, &format!("{}{}", NONCE_SEED_PREFIX, index), &system_program::id())?)
+    }
+
+    /// Creates any missing nonce accounts and advances stale ones, then keeps checking
+    pub fn start_maintenance() {
+        if !NonceAccounts::enabled() || Wallet::get_programmatic_pubkey().is_err() {
+            return;
+        }
+
+        tokio::spawn(async {
+            let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = NonceAccounts::maintain().await {
+                    red!("Failed to maintain nonce accounts: {:?}", e);
+                }
+            }
+        });
+    }
+
+    async fn maintain() -> anyhow::Result<()> {
+        let authority = Wallet::get_programmatic_pubkey()?;
+        let addresses = (0..NonceAccounts::count())
+            .map(|index| NonceAccounts::address(&authority, index))
+            .collect::<anyhow::Result<Vec<Pubkey>>>()?;
+
+        let accounts = Rpc::get_multiple_accounts(RpcMode::fast(), &addresses, Some(10000)).await?;
+        let missing: Vec<usize> = accounts
+            .iter()
+            .enumerate()
+            .filter(|(_, account)| account.is_none())
+            .map(|(index, _)| index)
+            .collect();
+
+        if !missing.is_empty() {
+            let rent = Rpc::call(
+                move |client| {
+                    Box::pin(async move {
+                        client
+                            .get_minimum_balance_for_rent_exemption(State::size())
+                            .await
+                            .map_err(|e| e.into())
+                    })
+                },
+                Some(5000),
+                RpcMode::fast(),
+            )
+            .await?;
+
+            for index in missing {
+                let instructions = system_instruction::create_nonce_account_with_seed(
+                    &authority,
+                    &addresses[index],
+                    &authority,
+                    &format!("{}{}", NONCE_SEED_PREFIX, index),
+                    &authority,
+                    rent,
+                );
+                ProgrammaticTransaction::perform(instructions, vec![], Some(PriorityLevel::High), TransactionKind::Maintenance).await?;
+                green!("Created nonce account {}", addresses[index]);
+            }
+        }
+
+        let stale: Vec<Pubkey> = NonceAccounts::lock().stale.iter().copied().collect();
+        for address in stale {
+            let instruction = system_instruction::advance_nonce_account(&address, &authority);
+            match ProgrammaticTransaction::perform(vec![instruction], vec![], Some(PriorityLevel::High), TransactionKind::Maintenance).await {
+                Ok(_) => {
+                    let mut nonce_accounts = NonceAccounts::lock();
+                    nonce_accounts.stale.remove(&address);
+                    nonce_accounts.leased.remove(&address);
+                    green!("Advanced stale nonce account {}", address);
+                }
+                Err(e) => red!("Failed to advance stale nonce account {}: {:?}", address, e),
+            }
+        }
+
+        let mut nonce_accounts = NonceAccounts::lock();
+        nonce_accounts.leased.retain(|address| addresses.contains(address));
+        nonce_accounts.stale.retain(|address| addresses.contains(address));
+        nonce_accounts.addresses = addresses;
+
+        Ok(())
+    }
+
+    /// Takes a nonce account no other prepared transaction is using
+    pub fn lease() -> Option<Pubkey> {
+        let mut nonce_accounts = NonceAccounts::lock();
+        let address = nonce_accounts
+            .addresses
+            .iter()
+            .find(|address| !nonce_accounts.leased.contains(address))
+            .copied()?;
+        nonce_accounts.leased.insert(address);
+
+        Some(address)
+    }
+
+    pub fn release(address: &Pubkey) {
+        NonceAccounts::lock().leased.remove(address);
+    }
+
+    /// Keeps the account leased until maintenance has advanced it, for a transaction that was
+    /// broadcast and may still land
+    pub fn mark_stale(address: &Pubkey) {
+        let mut nonce_accounts = NonceAccounts::lock();
+        nonce_accounts.leased.insert(*address);
+        nonce_accounts.stale.insert(*address);
+    }
+
+    /// The stored nonce, used in place of a recent blockhash
+    pub async fn get_nonce(address: &Pubkey) -> anyhow::Result<Hash> {
+        let account = Rpc::get_account(RpcMode::fast(), address, Some(5000)).await?;
+        let versions: Versions = bincode::deserialize(&account.data)
+            .map_err(|e| anyhow::anyhow!("Failed to read nonce account {}: {:?}", address, e))?;
+
+        match versions.state() {
+            State::Initialized(data) => Ok(data.blockhash()),
+            State::Uninitialized => Err(anyhow::anyhow!("Nonce account {} is not initialized", address)),
+        }
+    }
+
+    pub fn get_status() -> Vec<NonceAccountStatus> {
+        let nonce_accounts = NonceAccounts::lock();
+
+        nonce_accounts
+            .addresses
+            .iter()
+            .map(|address| NonceAccountStatus {
+                address: address.to_string(),
+                leased: nonce_accounts.leased.contains(address),
+                stale: nonce_accounts.stale.contains(address),
+            })
+            .collect()
+    }
+}
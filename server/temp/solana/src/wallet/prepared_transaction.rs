@@ -0,0 +1,215 @@
+use chrono::{DateTime, Utc};
+use helius::types::PriorityLevel;
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Signature, transaction::VersionedTransaction};
+
+use crate::rpc::{confirmation::{ConfirmationTimeout, SentTransaction, TransactionOutcome}, fee_oracle::FeeOracle, Rpc, RpcMode};
+
+use super::{lookup_table::LookupTable, nonce_account::NonceAccounts, programmatic_transaction::{ComputeBudget, ProgrammaticTransaction, TransactionKind}, transaction_ledger::{LedgerRecord, LedgerStatus, TransactionLedger}, transaction_policy::TransactionPolicy, Wallet};
+
+/// A signed transaction built against a durable nonce, kept until the moment it should be sent.
+/// It stays valid until its nonce account is advanced, which sending it does.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreparedTransaction {
+    pub kind: TransactionKind,
+    pub position_address: Option<String>,
+    pub nonce_address: Pubkey,
+    pub transaction: VersionedTransaction,
+    pub compute_budget: ComputeBudget,
+    pub prepared_at: DateTime<Utc>,
+    /// Set once the transaction has gone out, after which it may land until its nonce moves
+    #[serde(default)]
+    pub broadcast: bool,
+}
+
+/// Why a prepared transaction was not confirmed
+#[derive(Debug)]
+pub enum PreparedSendError {
+    /// It did not land and can't anymore, so another transaction can take its place
+    Dropped(anyhow::Error),
+    /// It was broadcast but is neither confirmed nor invalidated, so it may still land. Sending
+    /// it again is safe, sending a replacement is not.
+    Unresolved(Box<PreparedTransaction>, anyhow::Error),
+}
+
+impl std::fmt::Display for PreparedSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreparedSendError::Dropped(e) => write!(f, "{}", e),
+            PreparedSendError::Unresolved(prepared, e) => write!(f, "{} (transaction {} may still land)", e, prepared.transaction.signatures[0]),
+        }
+    }
+}
+
+impl std::error::Error for PreparedSendError {}
+
+impl PreparedTransaction {
+    /// Leases a nonce account, then simulates and signs the instructions against it with the
+    /// operation's compute and fee policy
+    pub async fn prepare(
+        instructions: Vec<Instruction>,
+        additional_signer_strings: Vec<String>,
+        priority_level: Option<PriorityLevel>,
+        kind: TransactionKind,
+        position_address: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let policy = TransactionPolicy::for_kind(kind);
+
+        PreparedTransaction::prepare_with_policy(instructions, additional_signer_strings, priority_level, kind, position_address, &policy).await
+    }
+
+    /// `prepare` with a given policy. A transaction that can't simulate yet, such as an open
+    /// funded by a close still to be sent, needs a fixed compute limit.
+    pub async fn prepare_with_policy(
+        instructions: Vec<Instruction>,
+        additional_signer_strings: Vec<String>,
+        priority_level: Option<PriorityLevel>,
+        kind: TransactionKind,
+        position_address: Option<String>,
+        policy: &TransactionPolicy,
+    ) -> anyhow::Result<Self> {
+        let nonce_address = NonceAccounts::lease().ok_or_else(|| anyhow::anyhow!("No free nonce account to prepare a transaction with"))?;
+
+        let prepared = PreparedTransaction::build(instructions, additional_signer_strings, priority_level, kind, position_address, &nonce_address, policy).await;
+        match &prepared {
+            Ok(prepared) => {
+                let signature = prepared.transaction.signatures[0].to_string();
+                let mut record = LedgerRecord::new(signature.clone(), kind, prepared.position_address.clone(), LedgerStatus::Prepared)
+                    .with_compute_budget(Some(&prepared.compute_budget));
+                record.signature = Some(signature);
+                TransactionLedger::record(record);
+            }
+            Err(_) => NonceAccounts::release(&nonce_address),
+        }
+
+        prepared
+    }
+
+    async fn build(
+        instructions: Vec<Instruction>,
+        additional_signer_strings: Vec<String>,
+        priority_level: Option<PriorityLevel>,
+        kind: TransactionKind,
+        position_address: Option<String>,
+        nonce_address: &Pubkey,
+        policy: &TransactionPolicy,
+    ) -> anyhow::Result<Self> {
+        let nonce = NonceAccounts::get_nonce(nonce_address).await?;
+        let signers = ProgrammaticTransaction::get_all_signers(additional_signer_strings.clone())?;
+        let transaction = ProgrammaticTransaction::new_with_nonce(instructions, signers, nonce_address, nonce)?;
+        let (new_instructions, compute_budget) = transaction
+            .simulate_and_update_instructions(Some(20000), priority_level.as_ref(), kind, policy, 0)
+            .await?;
+
+        let payer = Wallet::get_programmatic_pubkey()?;
+        let signers = ProgrammaticTransaction::get_all_signers(additional_signer_strings)?;
+        let transaction = Rpc::build_transaction(&payer, &new_instructions, &signers, nonce)?;
+        LookupTable::record_usage(&new_instructions);
+        blue!("Prepared {:?} transaction {} against nonce account {}", kind, transaction.signatures[0], nonce_address);
+
+        Ok(PreparedTransaction {
+            kind,
+            position_address,
+            nonce_address: *nonce_address,
+            transaction,
+            compute_budget,
+            prepared_at: Utc::now(),
+            broadcast: false,
+        })
+    }
+
+    /// Re-simulates the transaction, as conditions may have changed since it was prepared, then
+    /// sends it. Unless it ends up unresolved, the nonce account is released whatever the outcome.
+    pub async fn send(mut self) -> Result<Signature, PreparedSendError> {
+        let signature = self.transaction.signatures[0];
+
+        let (_context, simulated_transaction) = match Rpc::simulate_versioned_transaction(RpcMode::fast(), self.transaction.clone(), Some(10000)).await {
+            Ok(simulation) => simulation,
+            Err(e) => {
+                self.discard();
+                return Err(PreparedSendError::Dropped(e));
+            }
+        };
+        if let Some(err) = simulated_transaction.err {
+            self.discard();
+            return Err(PreparedSendError::Dropped(anyhow::anyhow!("Prepared transaction {} no longer simulates: {:?}", signature, err)));
+        }
+
+        // A nonce transaction doesn't expire with block height, so confirmation runs until its
+        // backstop and a transaction still unconfirmed then is invalidated below
+        self.broadcast = true;
+        let sent = match Rpc::broadcast_and_confirm(RpcMode::fast(), self.transaction.clone(), u64::MAX).await {
+            Err(e) => e.downcast::<ConfirmationTimeout>().map(ConfirmationTimeout::expired),
+            sent => sent,
+        };
+
+        let sent = match sent {
+            Ok(sent) if sent.outcome == TransactionOutcome::Expired => {
+                yellow!("Prepared transaction {} did not confirm, advancing nonce {} to invalidate it", signature, self.nonce_address);
+                if let Err(e) = self.invalidate().await {
+                    TransactionLedger::record_sent(&sent, self.kind, self.position_address.clone(), Some(&self.compute_budget));
+                    return Err(PreparedSendError::Unresolved(Box::new(self), e));
+                }
+
+                // It may have landed before the nonce moved
+                let statuses = match Rpc::get_statuses(RpcMode::fast(), signature, Some(5000)).await {
+                    Ok(statuses) => statuses,
+                    Err(e) => return Err(PreparedSendError::Unresolved(Box::new(self), e)),
+                };
+                match statuses.first() {
+                    Some(Some(status)) if status.err.is_none() => {
+                        let confirmed = SentTransaction {
+                            outcome: TransactionOutcome::Confirmed { slot: status.slot },
+                            ..sent
+                        };
+                        TransactionLedger::record_sent(&confirmed, self.kind, self.position_address.clone(), Some(&self.compute_budget));
+                        FeeOracle::record(&signature, self.kind, self.compute_budget.fee, self.compute_budget.compute_unit_limit);
+                        return Ok(signature);
+                    }
+                    _ => {
+                        TransactionLedger::record_sent(&sent, self.kind, self.position_address.clone(), Some(&self.compute_budget));
+                        return Err(PreparedSendError::Dropped(anyhow::anyhow!("Prepared transaction {} expired", signature)));
+                    }
+                }
+            }
+            Ok(sent) => sent,
+            Err(e) => {
+                if let Err(invalidate_error) = self.invalidate().await {
+                    red!("Failed to invalidate prepared transaction {}: {:?}", signature, invalidate_error);
+                    return Err(PreparedSendError::Unresolved(Box::new(self), e));
+                }
+                return Err(PreparedSendError::Dropped(e));
+            }
+        };
+
+        NonceAccounts::release(&self.nonce_address);
+        TransactionLedger::record_sent(&sent, self.kind, self.position_address.clone(), Some(&self.compute_budget));
+        FeeOracle::record(&signature, self.kind, self.compute_budget.fee, self.compute_budget.compute_unit_limit);
+
+        sent.confirmed_signature().map_err(PreparedSendError::Dropped)
+    }
+
+    /// Gives the transaction up. The nonce account is released right away if the transaction
+    /// was never broadcast, otherwise once maintenance has advanced it.
+    pub fn discard(&self) {
+        if self.broadcast {
+            NonceAccounts::mark_stale(&self.nonce_address);
+        } else {
+            NonceAccounts::release(&self.nonce_address);
+        }
+        TransactionLedger::update_status(&self.transaction.signatures[0].to_string(), LedgerStatus::Discarded);
+    }
+
+    /// Advances the nonce so a broadcast transaction can no longer land, then releases it. On
+    /// failure the nonce stays leased, as the transaction may still land.
+    async fn invalidate(&self) -> anyhow::Result<()> {
+        let authority = Wallet::get_programmatic_pubkey()?;
+        let instruction = solana_system_interface::instruction::advance_nonce_account(&self.nonce_address, &authority);
+        ProgrammaticTransaction::perform(vec![instruction], vec![], Some(PriorityLevel::High), TransactionKind::Maintenance).await?;
+
+        NonceAccounts::release(&self.nonce_address);
+
+        Ok(())
+    }
+}
@@ -1,200 +1,227 @@
-use std::{str::FromStr, sync::Arc};
-
-use helius::{types::{GetPriorityFeeEstimateOptions, GetPriorityFeeEstimateRequest, PriorityLevel, UiTransactionEncoding}, Helius};
+use helius::types::PriorityLevel;
 use kebtech_utils::*;
 use base64::{prelude::BASE64_STANDARD, Engine};
 use serde::{Deserialize, Serialize};
-use solana_client::{client_error::reqwest, nonblocking::rpc_client::RpcClient};
-use solana_sdk::{compute_budget::ComputeBudgetInstruction, instruction::Instruction, message::Message, pubkey::Pubkey, signature::Keypair, signer::Signer, transaction::Transaction};
+use solana_sdk::{compute_budget::ComputeBudgetInstruction, hash::Hash, instruction::Instruction, pubkey::Pubkey, signature::{Keypair, Signature}, signer::Signer, transaction::VersionedTransaction};
+
+use crate::rpc::{confirmation::TransactionOutcome, fee_oracle::{FeeEstimate, FeeOracle, FeeStrategy}, Rpc, RpcMode};
+
+use super::{transaction_ledger::TransactionLedger, transaction_policy::{FeePolicy, TransactionPolicy}, Wallet};
+
+/// Limit the runtime applies when a transaction doesn't set one
+const DEFAULT_COMPUTE_UNIT_LIMIT: u32 = 200_000;
 
-use crate::{rpc::{rpc_url::RpcUrl, Rpc, RpcMode}, utils::serialize_transaction_to_base58};
+/// What a transaction does, for per-operation fee caps and policies, and as its ledger purpose
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum TransactionKind {
+    Open,
+    Close,
+    Swap,
+    CollectFees,
+    Liquidity,
+    /// Upkeep of the bot's own accounts, such as the lookup table
+    Maintenance,
+}
 
-use super::Wallet;
+impl TransactionKind {
+    pub fn env_prefix(&self) -> &'static str {
+        match self {
+            TransactionKind::Open => "OPEN",
+            TransactionKind::Close => "CLOSE",
+            TransactionKind::Swap => "SWAP",
+            TransactionKind::CollectFees => "COLLECT_FEES",
+            TransactionKind::Liquidity => "LIQUIDITY",
+            TransactionKind::Maintenance => "MAINTENANCE",
+        }
+    }
+}
+
+/// Compute limit and priority fee settled on for a transaction
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct ComputeBudget {
+    pub compute_unit_limit: u32,
+    pub fee: Option<FeeEstimate>,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProgrammaticTransaction {
-    pub transaction: Transaction,
+    pub instructions: Vec<Instruction>,
+    /// Legacy, or v0 against the lookup table when the legacy transaction is too large
+    pub transaction: VersionedTransaction,
 }
 
 impl ProgrammaticTransaction {
-    pub async fn new(instructions: Vec<Instruction>, signers: Vec<Box<dyn Signer>>) -> anyhow::Result<Self> {
-        let wallet = Wallet::get_programmatic_keypair()?;
-        let mut all_instructions = vec![];
-
-        all_instructions.extend(instructions);
-    
+    /// Signs with the programmatic wallet plus the base64 encoded additional signers
+    pub async fn new(instructions: Vec<Instruction>, additional_signer_strings: Vec<String>) -> anyhow::Result<Self> {
         let recent_blockhash = Rpc::get_latest_blockhash(RpcMode::fast(), None).await?;
+        let wallet = Wallet::get_programmatic_keypair()?;
+        let signers = ProgrammaticTransaction::get_all_signers(additional_signer_strings)?;
 
-        let message = Message::new_with_blockhash(&all_instructions, Some(&wallet.pubkey()), &recent_blockhash);
-    
-        let transaction = Transaction::new(&signers, message, recent_blockhash);
+        let transaction = Rpc::build_transaction(&wallet.pubkey(), &instructions, &signers, recent_blockhash)?;
         
         Ok(ProgrammaticTransaction {
+            instructions,
             transaction,
         })
     }
 
+    /// Builds against a durable nonce instead of a recent blockhash. Advancing the nonce has to
+    /// be the first instruction, so it is put in front of `instructions`.
+    pub fn new_with_nonce(instructions: Vec<Instruction>, signers: Vec<Box<dyn Signer + Send + Sync>>, nonce_address: &Pubkey, nonce: Hash) -> anyhow::Result<Self> {
+        let wallet = Wallet::get_programmatic_keypair()?;
+
+        let mut nonce_instructions = vec![solana_system_interface::instruction::advance_nonce_account(nonce_address, &wallet.pubkey())];
+        nonce_instructions.extend(instructions);
+
+        let transaction = Rpc::build_transaction(&wallet.pubkey(), &nonce_instructions, &signers, nonce)?;
+
+        Ok(ProgrammaticTransaction {
+            instructions: nonce_instructions,
+            transaction,
+        })
+    }
+
+    /// Simulates the transaction and appends the compute limit and priority fee the policy
+    /// asks for. `attempt` counts earlier sends that expired, each one escalating the fee.
     pub async fn simulate_and_update_instructions(
         &self,
         timeout_ms: Option<u64>,
-        // pool_address: &str,
-        priority_level: Option<PriorityLevel>,
-    ) -> anyhow::Result<Vec<Instruction>> {
+        priority_level: Option<&PriorityLevel>,
+        kind: TransactionKind,
+        policy: &TransactionPolicy,
+        attempt: u32,
+    ) -> anyhow::Result<(Vec<Instruction>, ComputeBudget)> {
         // Simulate the current transaction
         let start = std::time::Instant::now();
         blue!("simulating transaction");
-        let (_context, simulated_transaction) = Rpc::simulate_transaction(
+        let (_context, simulated_transaction) = Rpc::simulate_versioned_transaction(
             RpcMode::fast(),
-            &self.transaction,
+            self.transaction.clone(),
             timeout_ms,
         )
         .await?;
         green!("simulated transaction in {}ms", start.elapsed().as_millis());
     
-        // Resolve the pool address to a Pubkey
-        // let address_pubkey = Pubkey::from_str(pool_address)?;
         let mut additional_instructions = vec![];
+        let mut compute_budget = ComputeBudget {
+            compute_unit_limit: DEFAULT_COMPUTE_UNIT_LIMIT,
+            fee: None,
+        };
     
-        // Adjust limits if units were consumed
-        if let Some(units_consumed) = simulated_transaction.units_consumed {
-            let units_consumed_safe = units_consumed as u32 + 100_000;
-            let compute_limit_instruction = ComputeBudgetInstruction::set_compute_unit_limit(units_consumed_safe);
-            additional_instructions.push(compute_limit_instruction);
-
-            // Helius priority fee logic
-            
-            let api_key = std::env::var("HELIUS_API_KEY")?;
-            let config = helius::config::Config::new(&api_key, helius::types::Cluster::MainnetBeta)?;
-            let request_client = reqwest::Client::new();
-            let client = helius::rpc_client::RpcClient::new(Arc::new(request_client), Arc::new(config))?;   
-
-            let recommended = if priority_level.is_none() {
-                Some(true)
-            } else {
-                None
-            };
-            let request = GetPriorityFeeEstimateRequest {
-                transaction: Some(serialize_transaction_to_base58(&self.transaction)?), // Provide the serialized transaction
-                account_keys: None,                  // Use account keys if you prefer
-                options: Some(GetPriorityFeeEstimateOptions {
-                    priority_level: priority_level, // Adjust priority level as needed
-                    include_all_priority_fee_levels: None,
-                    transaction_encoding: None,
-                    lookback_slots: None,
-                    recommended,
-                    include_vote: None,
-                }),
-            };
-            let priority_fee_estimate_response = client.get_priority_fee_estimate(request).await.map_err(|e| {
-                anyhow::anyhow!("Helius API call failed: {:?}", e)
-            })?;
-            
-            if let Some(fee_estimate) = priority_fee_estimate_response.priority_fee_estimate {
-                println!("Priority fee estimate: {}", fee_estimate);
-                let priority_fee_instruction = ComputeBudgetInstruction::set_compute_unit_price((fee_estimate * 1.5) as u64);
-                additional_instructions.push(priority_fee_instruction);
-            } else {
-                println!("No priority fee estimate available from Helius API.");
-            }
+        if let Some(compute_unit_limit) = policy.compute.limit(simulated_transaction.units_consumed) {
+            additional_instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit));
+            compute_budget.compute_unit_limit = compute_unit_limit;
+        }
 
+        let fee = match policy.fee {
+            FeePolicy::Oracle => FeeOracle::estimate(&self.transaction, &self.instructions, priority_level, kind).await,
+            FeePolicy::Level(priority_fee) => Some(FeeOracle::cap(kind, FeeStrategy::Static(priority_fee), priority_fee.to_micro_lamports())),
+        };
+        compute_budget.fee = fee.map(|fee| match attempt {
+            0 => fee,
+            attempt => FeeOracle::cap(kind, fee.strategy, policy.escalate(fee.micro_lamports, attempt)),
+        });
 
-            // Fetch recent prioritization fees
-            // let start = std::time::Instant::now();
-            // blue!("fetching prioritization fees");
-            // let prioritization_fees = Rpc::call(
-            //     move |client| {
-            //         Box::pin(async move {
-            //             client
-            //                 .get_recent_prioritization_fees(&[address_pubkey])
-            //                 .await
-            //                 .map_err(|e| e.into())
-            //         })
-            //     },
-            //     timeout_ms,
-            //     RpcMode::fast(),
-            // )
-            // .await?;
-            // green!("fetched prioritization fees in {}ms", start.elapsed().as_millis());
-
-            // // Extract and sort prioritization fees
-            // let mut prioritization_fees_array: Vec<u64> = prioritization_fees
-            //     .iter()
-            //     .map(|fee| fee.prioritization_fee)
-            //     .collect();
-            // prioritization_fees_array.sort_unstable();
-
-            // // Calculate the median and add 25%
-            // if let Some(prioritization_fee) = prioritization_fees_array.get(prioritization_fees_array.len() / 2) {
-            //     let adjusted_fee = ((*prioritization_fee as f64) * 1.75).ceil() as u64; // Add 75% and round up
-            //     let minimum_fee = 1_000_000; // Minimum fee in lamports (0.001 SOL)
-            //     let final_fee = adjusted_fee.max(minimum_fee); // Ensure the fee is at least 0.001 SOL
-            
-            //     let priority_fee_instruction = ComputeBudgetInstruction::set_compute_unit_price(final_fee);
-            //     additional_instructions.push(priority_fee_instruction);
-            // }
-            
+        if let Some(fee) = compute_budget.fee {
+            println!("Priority fee from {:?}: {} (attempt {})", fee.strategy, fee.micro_lamports, attempt + 1);
+            let priority_fee_instruction = ComputeBudgetInstruction::set_compute_unit_price(fee.micro_lamports);
+            additional_instructions.push(priority_fee_instruction);
         }
     
-        // Reconstruct the original instructions
-        let mut updated_instructions: Vec<Instruction> = self
-            .transaction
-            .message
-            .instructions
-            .iter()
-            .map(|compiled| {
-                let program_id = self.transaction.message.account_keys[compiled.program_id_index as usize];
-                let accounts = compiled
-                    .accounts
-                    .iter()
-                    .map(|&index| {
-                        let pubkey = self.transaction.message.account_keys[index as usize];
-                        let is_signer = self.transaction.message.is_signer(index as usize);
-                        let is_writable = self.transaction.message.is_maybe_writable(index as usize, None);
-                        solana_sdk::instruction::AccountMeta {
-                            pubkey,
-                            is_signer,
-                            is_writable,
-                        }
-                    })
-                    .collect();
-                solana_sdk::instruction::Instruction {
-                    program_id,
-                    accounts,
-                    data: compiled.data.clone(),
-                }
-            })
-            .collect();
+        let mut updated_instructions = self.instructions.clone();
     
         // Append the additional instructions
         updated_instructions.extend(additional_instructions);
     
         // Return the combined instructions
-        Ok(updated_instructions)
+        Ok((updated_instructions, compute_budget))
     }
     
-    pub fn get_all_signers(additional_signer_strings: Vec<String>) -> anyhow::Result<Vec<Box<dyn Signer>>> {
+    /// Simulates to size compute and priority fees from the operation's policy, then signs with
+    /// the programmatic wallet plus the base64 encoded additional signers and sends. A send whose
+    /// blockhash expires can't land anymore, so it is rebuilt with an escalated fee.
+    pub async fn perform(
+        instructions: Vec<Instruction>,
+        additional_signer_strings: Vec<String>,
+        priority_level: Option<PriorityLevel>,
+        kind: TransactionKind,
+    ) -> anyhow::Result<Signature> {
+        ProgrammaticTransaction::perform_for_position(instructions, additional_signer_strings, priority_level, kind, None).await
+    }
+
+    /// `perform` for a transaction acting on a position, which is noted in the ledger
+    pub async fn perform_for_position(
+        instructions: Vec<Instruction>,
+        additional_signer_strings: Vec<String>,
+        priority_level: Option<PriorityLevel>,
+        kind: TransactionKind,
+        position_address: Option<String>,
+    ) -> anyhow::Result<Signature> {
+        let policy = TransactionPolicy::for_kind(kind);
+        let mut attempt = 0;
+
+        loop {
+            let transaction = ProgrammaticTransaction::new(instructions.clone(), additional_signer_strings.clone()).await?;
+            let (new_instructions, compute_budget) = transaction
+                .simulate_and_update_instructions(Some(20000), priority_level.as_ref(), kind, &policy, attempt)
+                .await?;
+            
+            println!("simulated and adjusted instructions");
+            let signers = ProgrammaticTransaction::get_all_signers(additional_signer_strings.clone())?;
+
+            let start = std::time::Instant::now();
+            blue!("sending transaction");
+            let sent = Rpc::send_and_confirm_transaction_with_config(
+                RpcMode::fast(),
+                new_instructions,
+                signers,
+                Some(60000),
+            ).await?;
+            TransactionLedger::record_sent(&sent, kind, position_address.clone(), Some(&compute_budget));
+
+            attempt += 1;
+            if sent.outcome == TransactionOutcome::Expired && attempt < policy.max_attempts {
+                yellow!("{:?} transaction {} expired, retrying with a higher fee ({}/{})", kind, sent.signature, attempt + 1, policy.max_attempts);
+                continue;
+            }
+
+            // Transactions that land pay their fee even when they fail
+            if sent.outcome != TransactionOutcome::Expired {
+                FeeOracle::record(&sent.signature, kind, compute_budget.fee, compute_budget.compute_unit_limit);
+            }
+
+            let signature = sent.confirmed_signature()?;
+            green!("sent transaction in {}ms", start.elapsed().as_millis());
+
+            return Ok(signature);
+        }
+    }
+
+    pub fn get_all_signers(additional_signer_strings: Vec<String>) -> anyhow::Result<Vec<Box<dyn Signer + Send + Sync>>> {
         // Get the programmatic wallet keypair
         let wallet_keypair = Wallet::get_programmatic_keypair()?;
-        let mut signers: Vec<Box<dyn Signer>> = vec![Box::new(wallet_keypair)];
+        let mut signers: Vec<Box<dyn Signer + Send + Sync>> = vec![Box::new(wallet_keypair)];
+
+        signers.extend(ProgrammaticTransaction::get_additional_signers(additional_signer_strings)?);
     
-        // Decode and create additional keypairs
+        Ok(signers)
+    }
+
+    /// Decodes base64 encoded keypairs, such as new position mints, into signers
+    pub fn get_additional_signers(additional_signer_strings: Vec<String>) -> anyhow::Result<Vec<Box<dyn Signer + Send + Sync>>> {
         let additional_keypairs = additional_signer_strings
             .iter()
             .map(|s| {
                 let decoded = BASE64_STANDARD.decode(s).map_err(|e| {
                     anyhow::anyhow!("Failed to decode signer: {:?}, error: {}", s, e)
                 })?;
-                Keypair::from_bytes(&decoded).map_err(|e| {
+                Keypair::try_from(decoded.as_slice()).map_err(|e| {
                     anyhow::anyhow!("Failed to create Keypair from bytes: {:?}", e)
                 })
             })
             .collect::<Result<Vec<_>, _>>()?;
     
-        // Convert additional keypairs into Box<dyn Signer>
-        signers.extend(
-            additional_keypairs.into_iter().map(|kp| Box::new(kp) as Box<dyn Signer>)
-        );
-    
-        Ok(signers)
+        // Convert additional keypairs into Box<dyn Signer + Send + Sync>
+        Ok(additional_keypairs.into_iter().map(|kp| Box::new(kp) as Box<dyn Signer + Send + Sync>).collect())
     }
-}
\ No newline at end of file
+}
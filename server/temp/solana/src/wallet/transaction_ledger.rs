@@ -0,0 +1,513 @@
+use std::{collections::HashMap, fs::OpenOptions, io::{BufRead, BufReader, Write}, sync::{Arc, Mutex, MutexGuard}, time::Duration};
+
+use chrono::{DateTime, Utc};
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+use solana_sdk::signature::Signature;
+use state::InitCell;
+use tokio::{sync::mpsc::{unbounded_channel, UnboundedSender}, time::sleep};
+
+use crate::rpc::{confirmation::{SentTransaction, TransactionOutcome}, Rpc, RpcMode};
+
+use super::programmatic_transaction::{ComputeBudget, TransactionKind};
+
+const DEFAULT_LEDGER_PATH: &str = "transaction_ledger.jsonl";
+const DEFAULT_QUERY_LIMIT: usize = 100;
+/// getTransaction can lag confirmation by a few slots
+const ENRICH_ATTEMPTS: u32 = 5;
+const ENRICH_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+pub static TRANSACTION_LEDGER: InitCell<Arc<Mutex<TransactionLedger>>> = InitCell::new();
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum LedgerStatus {
+    /// Built for a wallet the server can't sign for
+    Built,
+    /// Signed against a durable nonce and not sent yet
+    Prepared,
+    /// Prepared but dropped without being sent
+    Discarded,
+    Confirmed,
+    Failed,
+    Expired,
+    /// Sent, but whether it landed could not be determined
+    Unresolved,
+}
+
+/// One transaction the server built or sent
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerRecord {
+    /// The signature, or the message hash of a transaction that isn't signed by its payer yet
+    pub id: String,
+    pub purpose: TransactionKind,
+    pub position_address: Option<String>,
+    pub signature: Option<String>,
+    pub slot: Option<u64>,
+    pub status: LedgerStatus,
+    pub error: Option<String>,
+    pub compute_units_consumed: Option<u64>,
+    pub compute_unit_limit: Option<u32>,
+    pub priority_fee_micro_lamports: Option<u64>,
+    /// Base and priority fee the transaction paid
+    pub fee_lamports: Option<u64>,
+    /// Change in the fee payer's balance, negative when the transaction returned lamports
+    pub lamports_spent: Option<i64>,
+    pub rpc_provider: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl LedgerRecord {
+    pub fn new(id: String, purpose: TransactionKind, position_address: Option<String>, status: LedgerStatus) -> Self {
+        LedgerRecord {
+            id,
+            purpose,
+            position_address,
+            signature: None,
+            slot: None,
+            status,
+            error: None,
+            compute_units_consumed: None,
+            compute_unit_limit: None,
+            priority_fee_micro_lamports: None,
+            fee_lamports: None,
+            lamports_spent: None,
+            rpc_provider: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn from_sent(sent: &SentTransaction, purpose: TransactionKind, position_address: Option<String>, compute_budget: Option<&ComputeBudget>) -> Self {
+        let (status, slot, error) = match &sent.outcome {
+            TransactionOutcome::Confirmed { slot } => (LedgerStatus::Confirmed, Some(*slot), None),
+            TransactionOutcome::Failed(err) => (LedgerStatus::Failed, None, Some(err.to_string())),
+            TransactionOutcome::Expired => (LedgerStatus::Expired, None, None),
+        };
+
+        let mut record = LedgerRecord::new(sent.signature.to_string(), purpose, position_address, status);
+        record.signature = Some(sent.signature.to_string());
+        record.slot = slot;
+        record.error = error;
+        record.rpc_provider = sent.provider.clone();
+        record.with_compute_budget(compute_budget)
+    }
+
+    pub fn with_compute_budget(mut self, compute_budget: Option<&ComputeBudget>) -> Self {
+        if let Some(compute_budget) = compute_budget {
+            self.compute_unit_limit = Some(compute_budget.compute_unit_limit);
+            self.priority_fee_micro_lamports = compute_budget.fee.map(|fee| fee.micro_lamports);
+        }
+        self
+    }
+}
+
+/// Filters for `TransactionLedger::query`. Unset filters match everything.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LedgerQuery {
+    pub position_address: Option<String>,
+    pub status: Option<LedgerStatus>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+/// Where ledger records are kept. Saves happen off the async runtime, one at a time and in
+/// the order records changed, so a store can simply let the last save of an id win.
+pub trait LedgerStore: Send + Sync {
+    /// Every record saved, oldest first, possibly several per id
+    fn load(&self) -> anyhow::Result<Vec<LedgerRecord>>;
+
+    fn save(&self, record: &LedgerRecord) -> anyhow::Result<()>;
+}
+
+/// Records appended as JSON lines to a file, `TRANSACTION_LEDGER_PATH` by default. A record is
+/// rewritten as a new line when it changes, and the file is compacted to the latest line of each
+/// record when it is loaded.
+pub struct JsonlLedgerStore {
+    path: String,
+}
+
+impl JsonlLedgerStore {
+    pub fn new(path: String) -> Self {
+        JsonlLedgerStore { path }
+    }
+
+    pub fn from_env() -> Self {
+        JsonlLedgerStore::new(std::env::var("TRANSACTION_LEDGER_PATH").unwrap_or_else(|_| DEFAULT_LEDGER_PATH.to_string()))
+    }
+
+    /// Rewrites the file with one line per record. The new file is written alongside and renamed
+    /// over the old one, so a crash part way leaves the old file in place.
+    fn compact(&self, records: &[LedgerRecord]) -> anyhow::Result<()> {
+        let compacted_path = format!("{}.compacting", self.path);
+        let mut file = std::fs::File::create(&compacted_path)?;
+        for record in records {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&compacted_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl LedgerStore for JsonlLedgerStore {
+    fn load(&self) -> anyhow::Result<Vec<LedgerRecord>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut records: Vec<LedgerRecord> = vec![];
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut line_count = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            line_count += 1;
+            match serde_json::from_str::<LedgerRecord>(&line) {
+                Ok(record) => match index.get(&record.id) {
+                    Some(position) => records[*position] = record,
+                    None => {
+                        index.insert(record.id.clone(), records.len());
+                        records.push(record);
+                    }
+                },
+                Err(e) => yellow!("Skipping unreadable ledger line: {}", e),
+            }
+        }
+
+        if line_count > records.len() {
+            self.compact(&records)?;
+            blue!("Compacted transaction ledger from {} lines to {}", line_count, records.len());
+        }
+
+        Ok(records)
+    }
+
+    fn save(&self, record: &LedgerRecord) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+
+        Ok(())
+    }
+}
+
+/// Every transaction the server builds or sends. Records are served from memory and written
+/// through to a `LedgerStore`, a `JsonlLedgerStore` unless `set_store` gives another one.
+pub struct TransactionLedger {
+    records: Vec<LedgerRecord>,
+    index: HashMap<String, usize>,
+    store: Arc<dyn LedgerStore>,
+    /// Feeds the task saving records in order, started on the first record
+    writer: Option<UnboundedSender<LedgerRecord>>,
+}
+
+impl TransactionLedger {
+    fn lock() -> MutexGuard<'static, TransactionLedger> {
+        if TRANSACTION_LEDGER.try_get().is_none() {
+            let ledger = TransactionLedger::load(Arc::new(JsonlLedgerStore::from_env())).unwrap_or_else(|e| {
+                red!("Failed to load transaction ledger, starting empty: {:?}", e);
+                TransactionLedger::empty(Arc::new(JsonlLedgerStore::from_env()))
+            });
+            TRANSACTION_LEDGER.set(Arc::new(Mutex::new(ledger)));
+        }
+
+        TRANSACTION_LEDGER.get().lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn empty(store: Arc<dyn LedgerStore>) -> TransactionLedger {
+        TransactionLedger {
+            records: vec![],
+            index: HashMap::new(),
+            store,
+            writer: None,
+        }
+    }
+
+    fn load(store: Arc<dyn LedgerStore>) -> anyhow::Result<TransactionLedger> {
+        let records = store.load()?;
+        let mut ledger = TransactionLedger::empty(store);
+        for record in records {
+            ledger.upsert(record);
+        }
+
+        Ok(ledger)
+    }
+
+    /// Moves the ledger onto another store, such as a database, and reloads it from there
+    pub fn set_store(store: Arc<dyn LedgerStore>) -> anyhow::Result<()> {
+        let ledger = TransactionLedger::load(store)?;
+        *TransactionLedger::lock() = ledger;
+
+        Ok(())
+    }
+
+    fn upsert(&mut self, record: LedgerRecord) {
+        match self.index.get(&record.id) {
+            Some(position) => self.records[*position] = record,
+            None => {
+                self.index.insert(record.id.clone(), self.records.len());
+                self.records.push(record);
+            }
+        }
+    }
+
+    /// Sender for the writer task, started on the current runtime if there is none yet. Outside
+    /// a runtime records are saved inline.
+    fn writer(&mut self) -> Option<UnboundedSender<LedgerRecord>> {
+        if self.writer.as_ref().is_some_and(|writer| !writer.is_closed()) {
+            return self.writer.clone();
+        }
+
+        let runtime = tokio::runtime::Handle::try_current().ok()?;
+        let (sender, mut receiver) = unbounded_channel::<LedgerRecord>();
+        let store = self.store.clone();
+
+        runtime.spawn(async move {
+            while let Some(record) = receiver.recv().await {
+                let store = store.clone();
+                let id = record.id.clone();
+                match tokio::task::spawn_blocking(move || store.save(&record)).await {
+                    Ok(Ok(())) => (),
+                    Ok(Err(e)) => red!("Failed to write transaction {} to the ledger: {:?}", id, e),
+                    Err(e) => red!("Ledger write for transaction {} did not finish: {:?}", id, e),
+                }
+            }
+        });
+
+        self.writer = Some(sender);
+        self.writer.clone()
+    }
+
+    /// Adds or replaces the record with the same id. A replaced record keeps its creation time
+    /// and anything the new one leaves unset.
+    pub fn record(mut record: LedgerRecord) {
+        let mut ledger = TransactionLedger::lock();
+
+        if let Some(existing) = ledger.index.get(&record.id).map(|position| &ledger.records[*position]) {
+            record.created_at = existing.created_at;
+            record.position_address = record.position_address.or_else(|| existing.position_address.clone());
+            record.compute_unit_limit = record.compute_unit_limit.or(existing.compute_unit_limit);
+            record.priority_fee_micro_lamports = record.priority_fee_micro_lamports.or(existing.priority_fee_micro_lamports);
+            record.compute_units_consumed = record.compute_units_consumed.or(existing.compute_units_consumed);
+            record.fee_lamports = record.fee_lamports.or(existing.fee_lamports);
+            record.lamports_spent = record.lamports_spent.or(existing.lamports_spent);
+            record.rpc_provider = record.rpc_provider.or_else(|| existing.rpc_provider.clone());
+        }
+        record.updated_at = Utc::now();
+
+        ledger.upsert(record.clone());
+        let writer = ledger.writer();
+        let store = ledger.store.clone();
+        drop(ledger);
+
+        let unsent = match writer {
+            Some(writer) => writer.send(record).err().map(|e| e.0),
+            None => Some(record),
+        };
+        if let Some(record) = unsent {
+            if let Err(e) = store.save(&record) {
+                red!("Failed to write transaction {} to the ledger: {:?}", record.id, e);
+            }
+        }
+    }
+
+    /// Records a sent transaction, filling in its on-chain cost in the background once it landed
+    pub fn record_sent(sent: &SentTransaction, purpose: TransactionKind, position_address: Option<String>, compute_budget: Option<&ComputeBudget>) {
+        let record = LedgerRecord::from_sent(sent, purpose, position_address, compute_budget);
+        let landed = matches!(record.status, LedgerStatus::Confirmed | LedgerStatus::Failed);
+        TransactionLedger::record(record);
+
+        if landed {
+            TransactionLedger::spawn_enrich(sent.signature);
+        }
+    }
+
+    pub fn update_status(id: &str, status: LedgerStatus) {
+        let existing = {
+            let ledger = TransactionLedger::lock();
+            ledger.index.get(id).map(|position| ledger.records[*position].clone())
+        };
+
+        if let Some(mut record) = existing {
+            record.status = status;
+            TransactionLedger::record(record);
+        }
+    }
+
+    /// Reads compute units, fee and the payer's balance change from the landed transaction
+    pub fn spawn_enrich(signature: Signature) {
+        tokio::spawn(async move {
+            for _ in 0..ENRICH_ATTEMPTS {
+                sleep(ENRICH_RETRY_INTERVAL).await;
+
+                let transaction = match Rpc::get_transaction(RpcMode::fast(), signature, Some(10000)).await {
+                    Ok(transaction) => transaction,
+                    Err(_) => continue,
+                };
+                let Some(meta) = transaction.transaction.meta else {
+                    continue;
+                };
+
+                let existing = {
+                    let ledger = TransactionLedger::lock();
+                    ledger.index.get(&signature.to_string()).map(|position| ledger.records[*position].clone())
+                };
+                if let Some(mut record) = existing {
+                    record.slot = Some(transaction.slot);
+                    record.fee_lamports = Some(meta.fee);
+                    record.compute_units_consumed = meta.compute_units_consumed.into();
+                    record.lamports_spent = meta
+                        .pre_balances
+                        .first()
+                        .zip(meta.post_balances.first())
+                        .map(|(pre, post)| *pre as i64 - *post as i64);
+                    TransactionLedger::record(record);
+                }
+
+                return;
+            }
+
+            yellow!("Could not fetch transaction {} to complete its ledger record", signature);
+        });
+    }
+
+    /// Matching records, newest first
+    pub fn query(query: &LedgerQuery) -> Vec<LedgerRecord> {
+        let ledger = TransactionLedger::lock();
+
+        ledger
+            .records
+            .iter()
+            .rev()
+            .filter(|record| query.position_address.as_ref().is_none_or(|address| record.position_address.as_ref() == Some(address)))
+            .filter(|record| query.status.is_none_or(|status| record.status == status))
+            .filter(|record| query.from.is_none_or(|from| record.created_at >= from))
+            .filter(|record| query.to.is_none_or(|to| record.created_at <= to))
+            .take(query.limit.unwrap_or(DEFAULT_QUERY_LIMIT))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serialises tests that swap the process wide ledger's store
+    static LEDGER_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    #[derive(Default)]
+    struct MemoryStore {
+        saved: Mutex<Vec<LedgerRecord>>,
+    }
+
+    impl LedgerStore for MemoryStore {
+        fn load(&self) -> anyhow::Result<Vec<LedgerRecord>> {
+            Ok(self.saved.lock().unwrap().clone())
+        }
+
+        fn save(&self, record: &LedgerRecord) -> anyhow::Result<()> {
+            self.saved.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+    }
+
+    fn memory_ledger() -> Arc<MemoryStore> {
+        let store = Arc::new(MemoryStore::default());
+        TransactionLedger::set_store(store.clone()).unwrap();
+        store
+    }
+
+    #[test]
+    fn record_merges_into_the_existing_record() {
+        let _guard = LEDGER_LOCK.blocking_lock();
+        let store = memory_ledger();
+
+        let mut sent = LedgerRecord::new("sig".to_string(), TransactionKind::Close, Some("position".to_string()), LedgerStatus::Prepared);
+        sent.compute_unit_limit = Some(200_000);
+        sent.rpc_provider = Some("helius".to_string());
+        TransactionLedger::record(sent.clone());
+
+        let mut landed = LedgerRecord::new("sig".to_string(), TransactionKind::Close, None, LedgerStatus::Confirmed);
+        landed.slot = Some(42);
+        TransactionLedger::record(landed);
+
+        let records = TransactionLedger::query(&LedgerQuery::default());
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.status, LedgerStatus::Confirmed);
+        assert_eq!(record.slot, Some(42));
+        assert_eq!(record.created_at, sent.created_at);
+        assert_eq!(record.position_address.as_deref(), Some("position"));
+        assert_eq!(record.compute_unit_limit, Some(200_000));
+        assert_eq!(record.rpc_provider.as_deref(), Some("helius"));
+
+        // Outside a runtime both versions are saved inline, in order
+        let saved = store.saved.lock().unwrap().clone();
+        assert_eq!(saved.iter().map(|record| record.status).collect::<Vec<_>>(), vec![LedgerStatus::Prepared, LedgerStatus::Confirmed]);
+    }
+
+    #[test]
+    fn query_filters_newest_first() {
+        let _guard = LEDGER_LOCK.blocking_lock();
+        memory_ledger();
+
+        for (id, position, status) in [("a", "one", LedgerStatus::Confirmed), ("b", "two", LedgerStatus::Failed), ("c", "one", LedgerStatus::Failed)] {
+            TransactionLedger::record(LedgerRecord::new(id.to_string(), TransactionKind::Swap, Some(position.to_string()), status));
+        }
+        TransactionLedger::update_status("a", LedgerStatus::Expired);
+
+        let ids = |query: LedgerQuery| TransactionLedger::query(&query).into_iter().map(|record| record.id).collect::<Vec<_>>();
+        assert_eq!(ids(LedgerQuery::default()), vec!["c", "b", "a"]);
+        assert_eq!(ids(LedgerQuery { position_address: Some("one".to_string()), ..Default::default() }), vec!["c", "a"]);
+        assert_eq!(ids(LedgerQuery { status: Some(LedgerStatus::Failed), ..Default::default() }), vec!["c", "b"]);
+        assert_eq!(ids(LedgerQuery { status: Some(LedgerStatus::Expired), ..Default::default() }), vec!["a"]);
+        assert_eq!(ids(LedgerQuery { limit: Some(1), ..Default::default() }), vec!["c"]);
+    }
+
+    #[tokio::test]
+    async fn writer_saves_records_in_order() {
+        let _guard = LEDGER_LOCK.lock().await;
+        let store = memory_ledger();
+
+        for status in [LedgerStatus::Prepared, LedgerStatus::Confirmed, LedgerStatus::Expired] {
+            TransactionLedger::record(LedgerRecord::new("sig".to_string(), TransactionKind::Open, None, status));
+        }
+
+        for _ in 0..100 {
+            if store.saved.lock().unwrap().len() == 3 {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        let saved = store.saved.lock().unwrap().clone();
+        assert_eq!(saved.iter().map(|record| record.status).collect::<Vec<_>>(), vec![LedgerStatus::Prepared, LedgerStatus::Confirmed, LedgerStatus::Expired]);
+    }
+
+    #[test]
+    fn jsonl_store_reloads_the_latest_version() {
+        let path = std::env::temp_dir().join(format!("transaction_ledger_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = Arc::new(JsonlLedgerStore::new(path.to_string_lossy().to_string()));
+
+        let mut record = LedgerRecord::new("sig".to_string(), TransactionKind::Swap, None, LedgerStatus::Prepared);
+        store.save(&record).unwrap();
+        record.status = LedgerStatus::Confirmed;
+        store.save(&record).unwrap();
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"not json\n").unwrap();
+
+        let ledger = TransactionLedger::load(store.clone()).unwrap();
+        assert_eq!(ledger.records.len(), 1);
+        assert_eq!(ledger.records[0].status, LedgerStatus::Confirmed);
+
+        // Loading compacted the file to the one line that counts
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert_eq!(store.load().unwrap()[0].status, LedgerStatus::Confirmed);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
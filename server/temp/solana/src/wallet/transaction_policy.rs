@@ -0,0 +1,220 @@
+use kebtech_utils::*;
+use serde::{Deserialize, Serialize};
+
+use crate::rpc::{ComputeUnitLimit, PriorityFee};
+
+use super::programmatic_transaction::TransactionKind;
+
+/// Highest compute limit a transaction can request
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// How a transaction's compute limit is set
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum ComputePolicy {
+    /// Simulated usage plus `margin_percent`, and at least `min_margin` units on top
+    Simulated { margin_percent: u32, min_margin: u32 },
+    Fixed(ComputeUnitLimit),
+}
+
+impl ComputePolicy {
+    /// Parses `simulate:<margin percent>` or `fixed:<high-usage|max|units>`
+    fn parse(value: &str) -> Option<Self> {
+        let (name, argument) = value.trim().split_once(':')?;
+
+        match name.to_lowercase().as_str() {
+            "simulate" => Some(ComputePolicy::Simulated {
+                margin_percent: argument.trim().parse::<u32>().ok()?,
+                min_margin: 0,
+            }),
+            "fixed" => Some(ComputePolicy::Fixed(match argument.trim().to_lowercase().as_str() {
+                "default" => ComputeUnitLimit::Default,
+                "high-usage" => ComputeUnitLimit::HighUsage,
+                "max" => ComputeUnitLimit::Max,
+                units => ComputeUnitLimit::Custom(units.parse::<u32>().ok()?),
+            })),
+            _ => None,
+        }
+    }
+
+    /// Limit to request, or `None` to leave it at the runtime default
+    pub fn limit(&self, units_consumed: Option<u64>) -> Option<u32> {
+        match self {
+            ComputePolicy::Simulated { margin_percent, min_margin } => {
+                let units_consumed = units_consumed? as u32;
+                let margin = (units_consumed as u64 * *margin_percent as u64 / 100) as u32;
+                Some(units_consumed.saturating_add(margin.max(*min_margin)).min(MAX_COMPUTE_UNIT_LIMIT))
+            }
+            ComputePolicy::Fixed(compute_unit_limit) => compute_unit_limit.to_limit(),
+        }
+    }
+}
+
+/// How a transaction's priority fee is set
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum FeePolicy {
+    /// Whatever the fee oracle's strategy chain estimates
+    Oracle,
+    Level(PriorityFee),
+}
+
+impl FeePolicy {
+    /// Parses `oracle`, a `PriorityFee` level or `custom:<micro-lamports>`
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_lowercase();
+
+        Some(match value.as_str() {
+            "oracle" => FeePolicy::Oracle,
+            "none" => FeePolicy::Level(PriorityFee::None),
+            "low" => FeePolicy::Level(PriorityFee::Low),
+            "medium" => FeePolicy::Level(PriorityFee::Medium),
+            "high" => FeePolicy::Level(PriorityFee::High),
+            "highest" => FeePolicy::Level(PriorityFee::Highest),
+            custom => FeePolicy::Level(PriorityFee::Custom(custom.strip_prefix("custom:")?.trim().parse::<u64>().ok()?)),
+        })
+    }
+}
+
+/// Compute and fee settings `ProgrammaticTransaction` applies to one kind of operation. A send
+/// whose blockhash expires is rebuilt up to `max_attempts` times, raising the fee by
+/// `escalation_percent` on each attempt, still within the operation's fee cap.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct TransactionPolicy {
+    pub compute: ComputePolicy,
+    pub fee: FeePolicy,
+    pub escalation_percent: u32,
+    pub max_attempts: u32,
+}
+
+impl TransactionPolicy {
+    fn default_for(kind: TransactionKind) -> Self {
+        // Simulated usage plus 100k matches how limits were always set
+        let compute = ComputePolicy::Simulated { margin_percent: 10, min_margin: 100_000 };
+
+        match kind {
+            TransactionKind::Close => TransactionPolicy { compute, fee: FeePolicy::Oracle, escalation_percent: 50, max_attempts: 3 },
+            TransactionKind::Open | TransactionKind::Swap => TransactionPolicy { compute, fee: FeePolicy::Oracle, escalation_percent: 25, max_attempts: 3 },
+            TransactionKind::Liquidity | TransactionKind::CollectFees => TransactionPolicy { compute, fee: FeePolicy::Oracle, escalation_percent: 25, max_attempts: 2 },
+            TransactionKind::Maintenance => TransactionPolicy { compute, fee: FeePolicy::Oracle, escalation_percent: 0, max_attempts: 2 },
+        }
+    }
+
+    /// Policy for an operation, overridable with `<KIND>_COMPUTE_POLICY`, `<KIND>_FEE_POLICY`,
+    /// `<KIND>_FEE_ESCALATION_PERCENT` and `<KIND>_MAX_ATTEMPTS`, e.g.
+    /// `SWAP_COMPUTE_POLICY=simulate:10` or `CLOSE_FEE_POLICY=custom:250000`
+    pub fn for_kind(kind: TransactionKind) -> Self {
+        TransactionPolicy::from_settings(kind, |setting| std::env::var(format!("{}_{}", kind.env_prefix(), setting)).ok())
+    }
+
+    /// Policy for an operation with overrides looked up by setting name, such as `FEE_POLICY`
+    fn from_settings(kind: TransactionKind, env: impl Fn(&str) -> Option<String>) -> Self {
+        let default = TransactionPolicy::default_for(kind);
+
+        let compute = env("COMPUTE_POLICY").and_then(|value| {
+            let compute = ComputePolicy::parse(&value);
+            if compute.is_none() {
+                yellow!("Ignoring unknown compute policy {} for {:?}", value, kind);
+            }
+            compute
+        });
+        let fee = env("FEE_POLICY").and_then(|value| {
+            let fee = FeePolicy::parse(&value);
+            if fee.is_none() {
+                yellow!("Ignoring unknown fee policy {} for {:?}", value, kind);
+            }
+            fee
+        });
+
+        TransactionPolicy {
+            compute: compute.unwrap_or(default.compute),
+            fee: fee.unwrap_or(default.fee),
+            escalation_percent: env("FEE_ESCALATION_PERCENT")
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or(default.escalation_percent),
+            max_attempts: env("MAX_ATTEMPTS")
+                .and_then(|value| value.parse::<u32>().ok())
+                .filter(|value| *value >= 1)
+                .unwrap_or(default.max_attempts),
+        }
+    }
+
+    /// Fee for a retry, compounding the escalation once per earlier attempt
+    pub fn escalate(&self, micro_lamports: u64, attempt: u32) -> u64 {
+        let multiplier = (1.0 + self.escalation_percent as f64 / 100.0).powi(attempt as i32);
+
+        (micro_lamports as f64 * multiplier).ceil() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::rpc::fee_oracle::{FeeOracle, FeeStrategy};
+
+    #[test]
+    fn escalation_compounds_per_attempt() {
+        let policy = TransactionPolicy::default_for(TransactionKind::Close);
+
+        assert_eq!(policy.escalate(100_000, 0), 100_000);
+        assert_eq!(policy.escalate(100_000, 1), 150_000);
+        assert_eq!(policy.escalate(100_000, 2), 225_000);
+        assert_eq!(policy.escalate(3, 1), 5);
+
+        let maintenance = TransactionPolicy::default_for(TransactionKind::Maintenance);
+        assert_eq!(maintenance.escalate(100_000, 2), 100_000);
+    }
+
+    #[test]
+    fn escalated_fee_stays_under_the_cap() {
+        let policy = TransactionPolicy::default_for(TransactionKind::Liquidity);
+        let max_micro_lamports = FeeOracle::max_micro_lamports(TransactionKind::Liquidity);
+
+        let fee = FeeOracle::cap(TransactionKind::Liquidity, FeeStrategy::Percentile(75), policy.escalate(max_micro_lamports, 1));
+        assert_eq!(fee.micro_lamports, max_micro_lamports);
+        assert!(fee.capped);
+    }
+
+    #[test]
+    fn simulated_limit_adds_the_larger_margin() {
+        let compute = ComputePolicy::Simulated { margin_percent: 10, min_margin: 100_000 };
+        assert_eq!(compute.limit(Some(200_000)), Some(300_000));
+        assert_eq!(compute.limit(Some(1_350_000)), Some(MAX_COMPUTE_UNIT_LIMIT));
+        assert_eq!(compute.limit(None), None);
+
+        let compute = ComputePolicy::Simulated { margin_percent: 20, min_margin: 0 };
+        assert_eq!(compute.limit(Some(200_000)), Some(240_000));
+        assert_eq!(ComputePolicy::Fixed(ComputeUnitLimit::HighUsage).limit(Some(1)), Some(500_000));
+    }
+
+    #[test]
+    fn parses_policies() {
+        assert!(matches!(ComputePolicy::parse("simulate:15"), Some(ComputePolicy::Simulated { margin_percent: 15, min_margin: 0 })));
+        assert!(matches!(ComputePolicy::parse("fixed:max"), Some(ComputePolicy::Fixed(ComputeUnitLimit::Max))));
+        assert!(matches!(ComputePolicy::parse("fixed:350000"), Some(ComputePolicy::Fixed(ComputeUnitLimit::Custom(350_000)))));
+        assert!(ComputePolicy::parse("simulate").is_none());
+
+        assert!(matches!(FeePolicy::parse("Oracle"), Some(FeePolicy::Oracle)));
+        assert!(matches!(FeePolicy::parse("high"), Some(FeePolicy::Level(PriorityFee::High))));
+        assert!(matches!(FeePolicy::parse("custom:250000"), Some(FeePolicy::Level(PriorityFee::Custom(250_000)))));
+        assert!(FeePolicy::parse("fast").is_none());
+    }
+
+    #[test]
+    fn settings_override_the_defaults() {
+        let settings = HashMap::from([
+            ("FEE_POLICY", "custom:5000"),
+            ("FEE_ESCALATION_PERCENT", "40"),
+            ("MAX_ATTEMPTS", "0"),
+            ("COMPUTE_POLICY", "bogus"),
+        ]);
+
+        let policy = TransactionPolicy::from_settings(TransactionKind::CollectFees, |setting| settings.get(setting).map(|value| value.to_string()));
+
+        assert!(matches!(policy.fee, FeePolicy::Level(PriorityFee::Custom(5_000))));
+        assert_eq!(policy.escalation_percent, 40);
+        // Zero attempts would never send, so the default stands
+        assert_eq!(policy.max_attempts, 2);
+        assert!(matches!(policy.compute, ComputePolicy::Simulated { margin_percent: 10, min_margin: 100_000 }));
+    }
+}
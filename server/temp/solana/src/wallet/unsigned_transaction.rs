@@ -0,0 +1,53 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, message::Message, pubkey::Pubkey, transaction::Transaction};
+
+use crate::{rpc::{Rpc, RpcMode}, utils::serialize_transaction_to_base64};
+
+use super::{programmatic_transaction::{ProgrammaticTransaction, TransactionKind}, transaction_ledger::{LedgerRecord, LedgerStatus, TransactionLedger}};
+
+/// Instructions for a wallet the pool manager holds no key for, such as the connected local
+/// wallet, along with the same instructions as a transaction ready for that wallet to sign.
+/// Additional signers have already partially signed it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnsignedTransaction {
+    pub wallet_key: String,
+    pub instructions: Vec<Instruction>,
+    pub additional_signers: Vec<String>,
+    /// Base64 encoded `Transaction` with the wallet as fee payer
+    pub transaction: String,
+    pub recent_blockhash: String,
+}
+
+impl UnsignedTransaction {
+    pub async fn new(
+        wallet_key: &str,
+        instructions: Vec<Instruction>,
+        additional_signer_strings: Vec<String>,
+        purpose: TransactionKind,
+        position_address: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let payer = Pubkey::from_str(wallet_key)?;
+        let recent_blockhash = Rpc::get_latest_blockhash(RpcMode::fast(), None).await?;
+
+        let message = Message::new_with_blockhash(&instructions, Some(&payer), &recent_blockhash);
+        let mut transaction = Transaction::new_unsigned(message);
+
+        if !additional_signer_strings.is_empty() {
+            let signers = ProgrammaticTransaction::get_additional_signers(additional_signer_strings.clone())?;
+            transaction.try_partial_sign(&signers, recent_blockhash)?;
+        }
+
+        // Only the wallet can sign, so the message hash stands in for the signature
+        TransactionLedger::record(LedgerRecord::new(transaction.message.hash().to_string(), purpose, position_address, LedgerStatus::Built));
+
+        Ok(UnsignedTransaction {
+            wallet_key: wallet_key.to_string(),
+            instructions,
+            additional_signers: additional_signer_strings,
+            transaction: serialize_transaction_to_base64(&transaction)?,
+            recent_blockhash: recent_blockhash.to_string(),
+        })
+    }
+}